@@ -0,0 +1,12 @@
+//! The escape-time kernel of src/core.rs on its own in a `no_std` crate,
+//! so that anything it takes from `std` fails the build. Built along with
+//! the tests, or on its own with
+//!
+//!     cargo build --example no_std_core
+#![no_std]
+
+extern crate num;
+
+/// `core` names the standard crate here, so the kernel goes by another
+#[path = "../src/core.rs"]
+pub mod kernel;
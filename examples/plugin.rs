@@ -0,0 +1,48 @@
+//! A plugin with the Burning Ship fractal and a fiery palette, built with
+//!
+//!     cargo build --release --example plugin
+//!
+//! and used by copying target/release/examples/libplugin.so into plugins/:
+//!
+//!     mandelbrot --formula burning-ship ship.png 1000x750 -2.2,1.2 1.2,-1.4
+
+use std::os::raw::c_char;
+
+const NO_ESCAPE: u32 = u32::MAX;
+
+#[no_mangle]
+pub extern "C" fn mandel_plugin_abi() -> u32 {
+    1
+}
+
+#[no_mangle]
+pub extern "C" fn mandel_plugin_name() -> *const c_char {
+    b"burning-ship\0".as_ptr() as *const c_char
+}
+
+/// Iterate `z = (|re z| + i |im z|)^2 + c`, flipped so the ship sails
+/// upright in the usual view
+#[no_mangle]
+pub extern "C" fn mandel_plugin_escape(re: f64, im: f64, limit: u32) -> u32 {
+    let (mut x, mut y) = (0.0f64, 0.0f64);
+    for i in 0 .. limit {
+        let (ax, ay) = (x.abs(), y.abs());
+        x = ax * ax - ay * ay + re;
+        y = 2.0 * ax * ay - im;
+        if x * x + y * y > 4.0 {
+            return i;
+        }
+    }
+    NO_ESCAPE
+}
+
+/// Black through red and yellow to white as points take longer to escape
+#[no_mangle]
+pub extern "C" fn mandel_plugin_color(time: u32, limit: u32) -> u32 {
+    if time == NO_ESCAPE {
+        return 0;
+    }
+    let t = (time as f64 / limit as f64).sqrt();
+    let channel = |start: f64| (((t - start) * 3.0).clamp(0.0, 1.0) * 255.0) as u32;
+    channel(0.0) << 16 | channel(1.0 / 3.0) << 8 | channel(2.0 / 3.0)
+}
@@ -0,0 +1,49 @@
+/* The mandelbrot renderer for C and C++, from src/ffi.rs
+ *
+ * Build the library with
+ *
+ *     cargo rustc --release --lib --features ffi --crate-type cdylib
+ *
+ * or --crate-type staticlib, and link against target/release/libmandelbrot.
+ */
+
+#ifndef MANDELBROT_H
+#define MANDELBROT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Returned by mandel_render when it's done */
+#define MANDEL_OK 0
+/* Returned by mandel_render when given a null pointer */
+#define MANDEL_NULL (-1)
+/* Returned by mandel_render when the view can't be rendered */
+#define MANDEL_INVALID (-2)
+
+/* The view mandel_render renders */
+struct mandel_params {
+    size_t width;
+    size_t height;
+    double top_left_re;
+    double top_left_im;
+    double bot_right_re;
+    double bot_right_im;
+    /* 0 to choose from the zoom */
+    uint32_t iterations;
+    /* 0 for a thread per core */
+    uint32_t threads;
+};
+
+/* Render the view params into out, a gray byte per pixel, row by row,
+ * returning MANDEL_OK or why not. out must hold width * height bytes. */
+int mandel_render(const struct mandel_params *params, uint8_t *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
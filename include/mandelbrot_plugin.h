@@ -0,0 +1,42 @@
+/* What a plugin for the mandelbrot renderer exports, from src/plugin.rs
+ *
+ * A plugin is a shared library in the plugins/ directory exporting these
+ * functions with C linkage. examples/plugin.rs is one written in Rust.
+ */
+
+#ifndef MANDELBROT_PLUGIN_H
+#define MANDELBROT_PLUGIN_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The version of this interface, which mandel_plugin_abi must return */
+#define MANDEL_PLUGIN_ABI 1
+
+/* Escape time of points in the set */
+#define MANDEL_PLUGIN_NO_ESCAPE UINT32_MAX
+
+/* Required: MANDEL_PLUGIN_ABI */
+uint32_t mandel_plugin_abi(void);
+
+/* Required: the name the plugin is chosen by, NUL-terminated and never
+ * freed */
+const char *mandel_plugin_name(void);
+
+/* Optional: the iterations before the point re + i im escaped, below
+ * limit, or MANDEL_PLUGIN_NO_ESCAPE. Called from several threads at
+ * once. */
+uint32_t mandel_plugin_escape(double re, double im, uint32_t limit);
+
+/* Optional: the color of a point with escape time `time` out of `limit`,
+ * as 0xRRGGBB. Called from several threads at once. */
+uint32_t mandel_plugin_color(uint32_t time, uint32_t limit);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
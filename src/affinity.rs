@@ -0,0 +1,134 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::sync::OnceLock;
+
+/// Sets of CPUs render threads are pinned to, in the order they're
+/// handed out
+static SLOTS: OnceLock<Vec<Vec<usize>>> = OnceLock::new();
+
+/// parse_cpu_list(s) : expand a kernel CPU list such as `"0-3,8"`
+fn parse_cpu_list(s: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in s.trim().split(',') {
+        match range.split_once('-') {
+            Some((first, last)) => cpus.extend(first.parse::<usize>().ok()? ..=
+                                               last.parse().ok()?),
+            None => cpus.push(range.parse().ok()?),
+        }
+    }
+    Some(cpus)
+}
+
+#[cfg(target_os = "linux")]
+fn allowed_cpus() -> io::Result<Vec<usize>> {
+    use libc::{cpu_set_t, sched_getaffinity, CPU_ISSET, CPU_SETSIZE};
+    unsafe {
+        let mut set: cpu_set_t = std::mem::zeroed();
+        if sched_getaffinity(0, std::mem::size_of::<cpu_set_t>(), &mut set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((0 .. CPU_SETSIZE as usize).filter(|&cpu| CPU_ISSET(cpu, &set)).collect())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    use libc::{cpu_set_t, sched_setaffinity, CPU_SET};
+    unsafe {
+        let mut set: cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus {
+            CPU_SET(cpu, &mut set);
+        }
+        if sched_setaffinity(0, std::mem::size_of::<cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allowed_cpus() -> io::Result<Vec<usize>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported,
+                       "thread pinning is only supported on Linux"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cpus: &[usize]) -> io::Result<()> {
+    Ok(())
+}
+
+/// Pin every render thread started from now on to a core of its own,
+/// returning how many cores there are to go round
+///
+/// With `skip_smt` only the first hardware thread of each physical core
+/// is used, leaving its SMT siblings idle.
+pub fn configure(skip_smt: bool) -> io::Result<usize> {
+    let mut cores = allowed_cpus()?;
+    if skip_smt {
+        let mut seen = HashSet::new();
+        cores.retain(|cpu| {
+            let path = format!("/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list",
+                               cpu);
+            match fs::read_to_string(path).ok().and_then(|s| parse_cpu_list(&s)) {
+                Some(siblings) => seen.insert(siblings),
+                None => true,
+            }
+        });
+    }
+    set_slots(cores.into_iter().map(|cpu| vec![cpu]).collect())
+}
+
+/// Keep every render thread started from now on within one NUMA node,
+/// returning how many nodes there are to go round
+///
+/// Threads stay free to move between the CPUs of their node. Pixels are
+/// first touched by the thread that renders them, so the kernel places
+/// each band's pages on the node of the thread working on it.
+pub fn configure_numa() -> io::Result<usize> {
+    let allowed: HashSet<usize> = allowed_cpus()?.into_iter().collect();
+    let mut nodes = Vec::new();
+    for entry in fs::read_dir("/sys/devices/system/node")? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let id = name.to_str()
+            .and_then(|n| n.strip_prefix("node"))
+            .and_then(|id| id.parse::<usize>().ok());
+        let cpus = fs::read_to_string(entry.path().join("cpulist")).ok()
+            .and_then(|list| parse_cpu_list(&list));
+        if let (Some(id), Some(cpus)) = (id, cpus) {
+            let cpus: Vec<usize> = cpus.into_iter().filter(|c| allowed.contains(c)).collect();
+            if !cpus.is_empty() {
+                nodes.push((id, cpus));
+            }
+        }
+    }
+    nodes.sort();
+    set_slots(nodes.into_iter().map(|(_, cpus)| cpus).collect())
+}
+
+fn set_slots(slots: Vec<Vec<usize>>) -> io::Result<usize> {
+    let count = slots.len();
+    if SLOTS.set(slots).is_err() {
+        return Err(io::Error::other("thread pinning is already configured"));
+    }
+    Ok(count)
+}
+
+/// Pin the calling thread, the `index`th of its render, if `configure`
+/// or `configure_numa` has been called
+pub fn pin(index: usize) {
+    if let Some(slots) = SLOTS.get().filter(|slots| !slots.is_empty()) {
+        if let Err(e) = set_affinity(&slots[index % slots.len()]) {
+            eprintln!("warning: can't pin thread to CPU: {}", e);
+        }
+    }
+}
+
+#[test]
+fn test_parse_cpu_list() {
+    assert_eq!(parse_cpu_list("0\n"), Some(vec![0]));
+    assert_eq!(parse_cpu_list("0,4"), Some(vec![0, 4]));
+    assert_eq!(parse_cpu_list("0-3,8"), Some(vec![0, 1, 2, 3, 8]));
+    assert_eq!(parse_cpu_list("0-"), None);
+}
@@ -0,0 +1,298 @@
+use affinity;
+use core::{step, INTERIOR_EPSILON};
+use crossbeam;
+use error::MandelError;
+use image::{ImageBuffer, Rgb};
+use num::Complex;
+use std::str::FromStr;
+use {gray, pixel_center, BAILOUT};
+
+/// What an analysis colors points by, instead of how soon they escape
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Analysis {
+    /// the period of the cycle a point in the set is drawn into, which is
+    /// the same all over each bulb and each minibrot's cardioid
+    Period,
+    /// the atom domain of each point, the iteration at which its orbit
+    /// comes closest to 0, whose domains each hold a bulb or minibrot of
+    /// that period
+    Atom,
+}
+
+/// Parsed from `period` or `atom`
+impl FromStr for Analysis {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Analysis, MandelError> {
+        match s {
+            "period" => Ok(Analysis::Period),
+            "atom" => Ok(Analysis::Atom),
+            _ => Err(MandelError::Parse(format!(
+                "bad analysis {:?}, expected period or atom", s))),
+        }
+    }
+}
+
+/// What becomes of the orbit of a point
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Fate {
+    /// it escaped after this many iterations
+    Escaped(u32),
+    /// it was drawn into a cycle of this period
+    Cycle(u32),
+    /// it did neither within the limit, as points close to the edge of
+    /// the set are slow to
+    #[default]
+    Undecided,
+}
+
+/// Longest cycle looked for once an orbit has been drawn into one
+pub const MAX_PERIOD: u32 = 1024;
+
+/// How close an orbit drawn into a cycle has to come back to where it
+/// was to have gone once round it
+const CYCLE_EPSILON: f64 = 1e-9;
+
+/// A color for each period, told apart at a glance, repeating after
+/// the last
+pub const PERIOD_COLORS: [Rgb; 12] = [
+    Rgb([31, 119, 180]),
+    Rgb([255, 127, 14]),
+    Rgb([44, 160, 44]),
+    Rgb([214, 39, 40]),
+    Rgb([148, 103, 189]),
+    Rgb([140, 86, 75]),
+    Rgb([227, 119, 194]),
+    Rgb([188, 189, 34]),
+    Rgb([23, 190, 207]),
+    Rgb([255, 187, 120]),
+    Rgb([152, 223, 138]),
+    Rgb([174, 199, 232]),
+];
+
+/// period(z, c) : the period of the cycle of `c` that `z` is on, going
+/// round it once
+fn period(z: Complex<f64>, c: Complex<f64>) -> Fate {
+    let mut w = z;
+    for period in 1 ..= MAX_PERIOD {
+        w = step(&w, &c);
+        if (w - z).norm_sqr() < CYCLE_EPSILON * CYCLE_EPSILON {
+            return Fate::Cycle(period);
+        }
+    }
+    Fate::Undecided
+}
+
+/// fate(c, l) : whether `c` escapes within `limit` iterations, and after
+/// how many as `escape_time` counts them, or else the period of the cycle
+/// it's drawn into
+///
+/// As in `escape_time_batch`, an orbit is in a cycle once `dz_n/dz_1` has
+/// shrunk past `INTERIOR_EPSILON`, by which time it's as close to the
+/// cycle as makes no difference.
+pub fn fate(c: Complex<f64>, limit: u32) -> Fate {
+    let mut z = c;
+    let mut der = Complex { re: 1.0, im: 0.0 };
+    for i in 0 .. limit {
+        if z.norm_sqr() > BAILOUT * BAILOUT {
+            return Fate::Escaped(i);
+        }
+        if der.norm_sqr() < INTERIOR_EPSILON * INTERIOR_EPSILON {
+            return period(z, c);
+        }
+        der = der * z * 2.0;
+        z = step(&z, &c);
+    }
+    Fate::Undecided
+}
+
+/// atom_domain(c, l) : the `n` of the `Z_n` of `c`, from `Z_1 = c` on,
+/// that's closest to 0, the first of them if several are as close, and
+/// the escape time of `c`, `None` if it doesn't escape within `limit`
+/// iterations
+///
+/// Orbits are followed until they escape or, as in `fate`, are drawn into
+/// a cycle, so points in the set are given the domain they start out in
+/// rather than where their cycle ends up.
+pub fn atom_domain(c: Complex<f64>, limit: u32) -> (u32, Option<u32>) {
+    let mut z = c;
+    let mut der = Complex { re: 1.0, im: 0.0 };
+    let (mut domain, mut closest) = (1, z.norm_sqr());
+    for i in 0 .. limit {
+        if z.norm_sqr() > BAILOUT * BAILOUT {
+            return (domain, Some(i));
+        }
+        if z.norm_sqr() < closest {
+            domain = i + 1;
+            closest = z.norm_sqr();
+        }
+        if der.norm_sqr() < INTERIOR_EPSILON * INTERIOR_EPSILON {
+            break;
+        }
+        der = der * z * 2.0;
+        z = step(&z, &c);
+    }
+    (domain, None)
+}
+
+/// atom_color(d, t) : the color of atom domain `domain` from
+/// `PERIOD_COLORS` for points that escaped after `time`, and black for
+/// points in the set, so each bulb and minibrot shows up black in the
+/// domain of its period
+pub fn atom_color(domain: u32, time: Option<u32>) -> Rgb {
+    match time {
+        Some(_) => PERIOD_COLORS[(domain as usize - 1) % PERIOD_COLORS.len()],
+        None => Rgb([0, 0, 0]),
+    }
+}
+
+/// color(f, l) : gray for points that escaped, as `gray` shades them
+/// out of `limit`, a color from `PERIOD_COLORS` for each period, and
+/// black for points that did neither
+pub fn color(fate: Fate, limit: u32) -> Rgb {
+    match fate {
+        Fate::Escaped(time) => Rgb([gray(Some(time), limit); 3]),
+        Fate::Cycle(period) => PERIOD_COLORS[(period as usize - 1) % PERIOD_COLORS.len()],
+        Fate::Undecided => Rgb([0, 0, 0]),
+    }
+}
+
+/// per_pixel(b, tl, br, t, f) : `analyse` of the center of each pixel of
+/// the `bounds` image between the corners, split into bands on `threads`
+/// threads
+pub fn per_pixel<T, F>(bounds: (usize, usize),
+                       top_left: Complex<f64>,
+                       bot_right: Complex<f64>,
+                       threads: usize,
+                       analyse: F)
+    -> ImageBuffer<T>
+    where T: Clone + Default + Send, F: Fn(Complex<f64>) -> T + Sync
+{
+    let mut image = ImageBuffer::new(bounds);
+    let rows_per_band = bounds.1 / threads + 1;
+    let analyse = &analyse;
+    crossbeam::scope(|spawner| {
+        for (i, band) in image.pixels_mut().chunks_mut(rows_per_band * bounds.0).enumerate() {
+            spawner.spawn(move || {
+                affinity::pin(i);
+                for (index, value) in band.iter_mut().enumerate() {
+                    let pixel = (index % bounds.0, i * rows_per_band + index / bounds.0);
+                    *value = analyse(pixel_center(bounds, pixel, top_left, bot_right));
+                }
+            });
+        }
+    });
+    image
+}
+
+/// Find the fate of each pixel of the `bounds` image between the
+/// corners, split into bands on `threads` threads
+pub fn fates(bounds: (usize, usize),
+             top_left: Complex<f64>,
+             bot_right: Complex<f64>,
+             limit: u32,
+             threads: usize)
+    -> ImageBuffer<Fate>
+{
+    per_pixel(bounds, top_left, bot_right, threads, |c| fate(c, limit))
+}
+
+/// Render the `bounds` image between the corners colored by `analysis`,
+/// on `threads` threads
+pub fn render(analysis: Analysis,
+              bounds: (usize, usize),
+              top_left: Complex<f64>,
+              bot_right: Complex<f64>,
+              limit: u32,
+              threads: usize)
+    -> ImageBuffer<Rgb>
+{
+    match analysis {
+        Analysis::Period => {
+            let fates = fates(bounds, top_left, bot_right, limit, threads);
+            let pixels = fates.pixels().iter().map(|&fate| color(fate, limit)).collect();
+            ImageBuffer::from_pixels(bounds, pixels)
+        }
+        Analysis::Atom => {
+            per_pixel(bounds, top_left, bot_right, threads, |c| {
+                let (domain, time) = atom_domain(c, limit);
+                atom_color(domain, time)
+            })
+        }
+    }
+}
+
+#[test]
+fn test_fate() {
+    // the centers of the main cardioid, the period 2 disc, the rabbit's
+    // bulb, the airplane's minibrot and a period 4 bulb
+    assert_eq!(fate(Complex { re: 0.0, im: 0.0 }, 100), Fate::Cycle(1));
+    assert_eq!(fate(Complex { re: -1.0, im: 0.0 }, 100), Fate::Cycle(2));
+    assert_eq!(fate(Complex { re: -0.1226, im: 0.7449 }, 1000), Fate::Cycle(3));
+    assert_eq!(fate(Complex { re: -1.7549, im: 0.0 }, 1000), Fate::Cycle(3));
+    assert_eq!(fate(Complex { re: -1.3107, im: 0.0 }, 1000), Fate::Cycle(4));
+    assert_eq!(fate(Complex { re: 0.2822, im: 0.5301 }, 1000), Fate::Cycle(4));
+
+    // escaping when `escape_time` says so, and the cusp never settling
+    for &c in &[Complex { re: 1.0, im: 0.0 }, Complex { re: -0.75, im: 0.1 },
+                Complex { re: 0.3, im: 0.0 }]
+    {
+        assert_eq!(fate(c, 1000), Fate::Escaped(::escape_time(c, 1000).unwrap()));
+    }
+    assert_eq!(fate(Complex { re: 0.25, im: 0.0 }, 1000), Fate::Undecided);
+
+    assert_eq!(color(Fate::Cycle(2), 100), PERIOD_COLORS[1]);
+    assert_eq!(color(Fate::Cycle(13), 100), PERIOD_COLORS[0]);
+    assert_eq!(color(Fate::Escaped(0), 100), Rgb([255; 3]));
+    assert_eq!("period".parse::<Analysis>().unwrap(), Analysis::Period);
+    assert!("periods".parse::<Analysis>().is_err());
+}
+
+#[test]
+fn test_atom_domain() {
+    // the centers of components come back to 0 at their period
+    assert_eq!(atom_domain(Complex { re: 0.0, im: 0.0 }, 100), (1, None));
+    assert_eq!(atom_domain(Complex { re: -1.0, im: 0.0 }, 100), (2, None));
+    let rabbit = Complex { re: -0.12256116687665361, im: 0.7448617666197442 };
+    assert_eq!(atom_domain(rabbit, 1000), (3, None));
+    assert_eq!(atom_domain(Complex { re: -1.7548776662466927, im: 0.0 }, 1000), (3, None));
+
+    // just outside the airplane minibrot and the period 2 disc is still
+    // their domain
+    assert_eq!(atom_domain(Complex { re: -1.7549, im: 0.03 }, 1000), (3, Some(9)));
+    assert_eq!(atom_domain(Complex { re: -1.0, im: 0.4 }, 1000), (2, Some(6)));
+    let (domain, time) = atom_domain(Complex { re: 1.0, im: 0.0 }, 1000);
+    assert_eq!((domain, time), (1, ::escape_time(Complex { re: 1.0, im: 0.0 }, 1000)));
+
+    assert_eq!(atom_color(2, Some(5)), PERIOD_COLORS[1]);
+    assert_eq!(atom_color(14, Some(5)), PERIOD_COLORS[1]);
+    assert_eq!(atom_color(1, None), Rgb([0, 0, 0]));
+    assert_eq!("atom".parse::<Analysis>().unwrap(), Analysis::Atom);
+}
+
+#[test]
+fn test_render_periods() {
+    let (top_left, bot_right) = (Complex { re: -2.0, im: 1.25 }, Complex { re: 0.5, im: -1.25 });
+    let bounds = (50, 50);
+    let image = render(Analysis::Period, bounds, top_left, bot_right, 500, 3);
+    let at = |re: f64, im: f64| {
+        let col = ((re - top_left.re) / 2.5 * 50.0) as usize;
+        let row = ((top_left.im - im) / 2.5 * 50.0) as usize;
+        image[(col, row)]
+    };
+    assert_eq!(at(-0.1, 0.0), PERIOD_COLORS[0]);
+    assert_eq!(at(-1.0, 0.0), PERIOD_COLORS[1]);
+    assert_eq!(at(-0.12, 0.74), PERIOD_COLORS[2]);
+    assert_eq!(at(0.45, 1.2), Rgb([255; 3]));
+
+    // the same on any number of threads, and symmetric about the real axis
+    assert_eq!(render(Analysis::Period, bounds, top_left, bot_right, 500, 1), image);
+    for row in image.rows().take(25).zip(image.rows().rev()) {
+        assert_eq!(row.0, row.1);
+    }
+
+    let atoms = render(Analysis::Atom, bounds, top_left, bot_right, 500, 3);
+    assert_eq!(atoms[(38, 25)], Rgb([0, 0, 0]));
+    assert_eq!(atoms[(49, 1)], PERIOD_COLORS[0]);
+    assert_eq!(render(Analysis::Atom, bounds, top_left, bot_right, 500, 1), atoms);
+}
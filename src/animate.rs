@@ -0,0 +1,1214 @@
+use cache::fnv1a;
+use color::{Colorizer, Cycle, EscapeBuffer};
+use crossbeam;
+use easing::Easing;
+use error::MandelError;
+use image::{Blend, ImageBuffer, Rgb};
+use julia;
+use num::Complex;
+use output;
+use renderer::{Renderer, MAX_ITERATIONS};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Condvar, Mutex};
+use viewport::Viewport;
+use {auto_iterations, format_complex, parse_complex, parse_pair, scale_bounds};
+
+/// Height of the plane shown at zoom 1, the whole set
+pub const HOME_HEIGHT: f64 = 2.4;
+
+/// Fewest rows of a frame worth a thread of their own; frames with fewer
+/// per thread are rendered several at a time instead
+const MIN_ROWS_PER_THREAD: usize = 16;
+
+/// How many frames each frame being rendered can run ahead of the last
+/// one written, which bounds the finished frames held waiting their turn
+const FRAMES_AHEAD: usize = 2;
+
+/// File in an animation's directory listing the frames written so far
+pub const FRAME_LOG: &str = "frames.done";
+
+/// File in an animation's directory describing every frame, for tools
+/// working on the frames afterwards
+pub const MANIFEST: &str = "manifest.json";
+
+/// How frames are named unless told otherwise
+pub const FRAME_PATTERN: &str = "frame-%05d.png";
+
+/// Width of the quick renders `Pacing::Detail` measures detail in, or
+/// height for frames taller than wide
+const PROBE_WIDTH: usize = 48;
+
+/// Views along the path `Pacing::Detail` measures the detail of
+const PROBES: usize = 64;
+
+/// Least detail a view counts as having, as a fraction of the average, so
+/// that empty stretches are sped through rather than skipped
+const MIN_DETAIL: f64 = 0.05;
+
+/// A view an animation passes through
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    pub center: Complex<f64>,
+    /// magnification, with 1 showing `HOME_HEIGHT` of the plane top to
+    /// bottom
+    pub zoom: f64,
+    /// `None` to choose from the zoom
+    pub limit: Option<u32>,
+    /// how far round the colors of a `Cycle` have turned
+    pub phase: f64,
+    /// radians the view is turned anticlockwise
+    pub rotation: f64,
+    /// how the values move from here to the next keyframe
+    pub easing: Eases,
+}
+
+/// An `Easing` for each of the values of a keyframe; the iterations
+/// follow the zoom
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Eases {
+    pub zoom: Easing,
+    pub center: Easing,
+    pub phase: Easing,
+    pub rotation: Easing,
+}
+
+/// Parsed from one easing for them all, or from `zoom=EASING`,
+/// `center=EASING`, `phase=EASING` and `rotation=EASING` separated by `/`,
+/// linear for any left out
+impl FromStr for Eases {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Eases, MandelError> {
+        if !s.contains('=') {
+            let easing = s.parse()?;
+            return Ok(Eases { zoom: easing, center: easing, phase: easing, rotation: easing });
+        }
+        let mut eases = Eases::default();
+        for part in s.split('/') {
+            let (name, easing) = part.split_once('=').unwrap_or((part, ""));
+            let field = match name {
+                "zoom" => &mut eases.zoom,
+                "center" => &mut eases.center,
+                "phase" => &mut eases.phase,
+                "rotation" => &mut eases.rotation,
+                _ => return Err(MandelError::Parse(format!(
+                    "bad easing {:?}, expected zoom, center, phase or rotation", name))),
+            };
+            *field = easing.parse()?;
+        }
+        Ok(eases)
+    }
+}
+
+impl Keyframe {
+    /// The top left and bottom right corners of the keyframe's view in a
+    /// `bounds` image
+    pub fn corners(&self, bounds: (usize, usize)) -> (Complex<f64>, Complex<f64>) {
+        let height = HOME_HEIGHT / self.zoom;
+        let half = Complex { re: height * bounds.0 as f64 / bounds.1 as f64, im: height } / 2.0;
+        (Complex { re: self.center.re - half.re, im: self.center.im + half.im },
+         Complex { re: self.center.re + half.re, im: self.center.im - half.im })
+    }
+
+    /// The iteration limit, chosen from the view if the keyframe has none
+    pub fn limit(&self, bounds: (usize, usize)) -> u32 {
+        self.limit.unwrap_or_else(|| {
+            let (top_left, bot_right) = self.corners(bounds);
+            auto_iterations(top_left, bot_right)
+        })
+    }
+
+    /// The view as a JSON object, with the iterations it's rendered with
+    /// in a `bounds` frame and the rotation in degrees
+    pub fn to_json(&self, bounds: (usize, usize)) -> Value {
+        json!({
+            "center": format_complex(self.center),
+            "zoom": self.zoom,
+            "iterations": self.limit(bounds),
+            "phase": self.phase,
+            "rotation": self.rotation.to_degrees(),
+        })
+    }
+}
+
+/// Parsed from `RE,IM:ZOOM[:ITERATIONS[:PHASE[:EASING[:DEGREES]]]]`, with
+/// `auto` or nothing for ITERATIONS to choose them from the zoom, EASING
+/// as `Eases` parses it and DEGREES anticlockwise
+impl FromStr for Keyframe {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Keyframe, MandelError> {
+        let error = || MandelError::Parse(format!(
+            "bad keyframe {:?}, expected RE,IM:ZOOM[:ITERATIONS[:PHASE[:EASING[:DEGREES]]]]",
+            s));
+        let mut fields = s.split(':');
+        let center = fields.next().and_then(|c| parse_complex(c).ok()).ok_or_else(error)?;
+        let zoom = fields.next().and_then(|z| z.parse().ok())
+            .filter(|&z: &f64| z > 0.0 && z.is_finite())
+            .ok_or_else(error)?;
+        let limit = match fields.next() {
+            None | Some("auto") | Some("") => None,
+            Some(n) => Some(n.parse().ok().filter(|&n| n > 0).ok_or_else(error)?),
+        };
+        let phase = match fields.next() {
+            None | Some("") => 0.0,
+            Some(p) => p.parse().map_err(|_| error())?,
+        };
+        let easing = match fields.next() {
+            None | Some("") => Eases::default(),
+            Some(e) => e.parse()?,
+        };
+        let rotation = match fields.next() {
+            None | Some("") => 0.0,
+            Some(d) => d.parse().ok().filter(|d: &f64| d.is_finite()).ok_or_else(error)?,
+        };
+        if fields.next().is_some() {
+            return Err(error());
+        }
+        Ok(Keyframe { center, zoom, limit, phase, rotation: rotation.to_radians(), easing })
+    }
+}
+
+/// How the iterations grow with depth through an animation, for frames
+/// whose keyframes leave them to be chosen: `start` at zoom 1, multiplied
+/// by `growth` each time the zoom doubles, so that shallow frames render
+/// fast and deep ones keep their detail
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ramp {
+    pub start: f64,
+    pub growth: f64,
+}
+
+impl Ramp {
+    /// limit(z) : the iterations at `zoom`, at least 1 and at most
+    /// `MAX_ITERATIONS`
+    pub fn limit(&self, zoom: f64) -> u32 {
+        let limit = self.start * zoom.powf(self.growth.log2());
+        limit.round().clamp(1.0, MAX_ITERATIONS as f64) as u32
+    }
+
+    /// Give each of `views` without a limit of its own the ramp's for its
+    /// zoom
+    pub fn apply(&self, views: &mut [Keyframe]) {
+        for view in views.iter_mut().filter(|view| view.limit.is_none()) {
+            view.limit = Some(self.limit(view.zoom));
+        }
+    }
+}
+
+/// Parsed from `START:GROWTH`, as in `100:1.5`
+impl FromStr for Ramp {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Ramp, MandelError> {
+        match parse_pair::<f64>(s, ':') {
+            Ok((start, growth)) if start > 0.0 && growth > 0.0
+                && start.is_finite() && growth.is_finite() => Ok(Ramp { start, growth }),
+            _ => Err(MandelError::Parse(format!("bad ramp {:?}, expected START:GROWTH", s))),
+        }
+    }
+}
+
+/// between(a, b, s) : the view `s` of the way in time from `a` to `b`,
+/// eased as `a.easing` says
+///
+/// Linearly eased, the zoom changes by the same factor each step, and the
+/// center moves so that `b`'s center slides steadily into place on screen
+/// rather than rushing past while the view is wide. Iterations change
+/// geometrically too, and the phase and rotation linearly.
+pub fn between(a: &Keyframe, b: &Keyframe, s: f64) -> Keyframe {
+    let zoom_at = |s: f64| a.zoom * (b.zoom / a.zoom).powf(s);
+    let zoomed = a.easing.zoom.apply(s);
+    // how far along the center is, by how much of the view's shrinking
+    // would be done were the zoom eased as the center is
+    let moved = a.easing.center.apply(s);
+    let along = if (a.zoom - b.zoom).abs() <= 1e-9 * a.zoom {
+        moved
+    } else {
+        (1.0 / a.zoom - 1.0 / zoom_at(moved)) / (1.0 / a.zoom - 1.0 / b.zoom)
+    };
+    let limit = match (a.limit, b.limit) {
+        (Some(x), Some(y)) => {
+            Some((x as f64 * (y as f64 / x as f64).powf(zoomed)).round() as u32)
+        }
+        _ => None,
+    };
+    Keyframe {
+        center: a.center + (b.center - a.center) * along,
+        zoom: zoom_at(zoomed),
+        limit,
+        phase: a.phase + (b.phase - a.phase) * a.easing.phase.apply(s),
+        rotation: a.rotation + (b.rotation - a.rotation) * a.easing.rotation.apply(s),
+        easing: a.easing,
+    }
+}
+
+/// frames(k, n) : the `count` views of an animation through `keys`,
+/// spaced evenly in time with the keyframes at equal intervals, the first
+/// and last frames on the first and last keyframes
+pub fn frames(keys: &[Keyframe], count: usize) -> Vec<Keyframe> {
+    assert!(!keys.is_empty());
+    let segments = keys.len() - 1;
+    (0 .. count).map(|frame| {
+        if segments == 0 || count < 2 {
+            return keys[0];
+        }
+        let t = frame as f64 / (count - 1) as f64 * segments as f64;
+        let segment = (t.floor() as usize).min(segments - 1);
+        between(&keys[segment], &keys[segment + 1], t - segment as f64)
+    }).collect()
+}
+
+/// How the frames of an animation are spread along its keyframes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pacing {
+    /// the same number of frames from each keyframe to the next, as
+    /// `frames` spreads them
+    Even,
+    /// frames in proportion to how far each leg goes, in doublings of the
+    /// zoom and screens panned, so the zoom grows by the same factor every
+    /// frame of the animation and never seems to crawl
+    Constant,
+    /// `Constant`, slowed where the view has more detail and hurried
+    /// where it has less, by the detail to the power `strength`
+    Detail { strength: f64 },
+}
+
+/// Parsed from `even`, `constant`, or `detail[:STRENGTH]` with 1 if the
+/// strength is left out
+impl FromStr for Pacing {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Pacing, MandelError> {
+        let error = || MandelError::Parse(format!(
+            "bad pacing {:?}, expected even, constant or detail[:STRENGTH]", s));
+        match s {
+            "even" => Ok(Pacing::Even),
+            "constant" => Ok(Pacing::Constant),
+            "detail" => Ok(Pacing::Detail { strength: 1.0 }),
+            _ => {
+                let strength = s.strip_prefix("detail:").and_then(|n| n.parse().ok())
+                    .filter(|&n: &f64| n >= 0.0 && n.is_finite())
+                    .ok_or_else(error)?;
+                Ok(Pacing::Detail { strength })
+            }
+        }
+    }
+}
+
+/// leg_length(a, b) : how far the view goes from `a` to `b`, the doublings
+/// of the zoom plus the screens panned at the wider of the two
+fn leg_length(a: &Keyframe, b: &Keyframe) -> f64 {
+    let doublings = (b.zoom / a.zoom).log2().abs();
+    let screens = (b.center - a.center).norm() * a.zoom.min(b.zoom) / HOME_HEIGHT;
+    doublings + screens
+}
+
+/// along(k, l, p) : the view `position` along `keys`, whose legs are
+/// `lengths` long
+fn along(keys: &[Keyframe], lengths: &[f64], position: f64) -> Keyframe {
+    let mut left = position;
+    for (leg, &length) in lengths.iter().enumerate() {
+        if (left <= length && length > 0.0) || leg + 1 == lengths.len() {
+            let s = if length > 0.0 { (left / length).clamp(0.0, 1.0) } else { 1.0 };
+            return between(&keys[leg], &keys[leg + 1], s);
+        }
+        left -= length;
+    }
+    keys[0]
+}
+
+/// detail(k, b, t) : how much there is to see in the view `key`, as the
+/// fraction of neighboring pixels of a quick render with different escape
+/// times, the render shaped like a `bounds` frame
+fn detail(key: &Keyframe, bounds: (usize, usize), threads: usize) -> Result<f64, MandelError> {
+    // with neighbours both ways, however thin the frame
+    let probe = scale_bounds(bounds, PROBE_WIDTH);
+    let probe = (probe.0.max(2), probe.1.max(2));
+    let (top_left, bot_right) = key.corners(probe);
+    let escapes = Renderer::new()
+        .bounds(probe.0, probe.1)
+        .viewport(top_left, bot_right)
+        .rotation(key.rotation)
+        .iterations(key.limit(bounds))
+        .threads(threads)
+        .escapes()?;
+    let time = |x, y| escapes.times[(x, y)];
+    let (width, height) = probe;
+    let across = (0 .. height).flat_map(|y| (1 .. width).map(move |x| (x, y)))
+        .filter(|&(x, y)| time(x, y) != time(x - 1, y))
+        .count();
+    let down = (1 .. height).flat_map(|y| (0 .. width).map(move |x| (x, y)))
+        .filter(|&(x, y)| time(x, y) != time(x, y - 1))
+        .count();
+    Ok((across + down) as f64 / ((width - 1) * height + width * (height - 1)) as f64)
+}
+
+/// paced(k, n, p, b, t) : the `count` views of an animation through
+/// `keys`, spread along them as `pacing` says, first and last on the first
+/// and last keyframes
+///
+/// `Pacing::Detail` renders a few small views along the way to measure
+/// their detail, on `threads` threads, shaped like `bounds` frames.
+pub fn paced(keys: &[Keyframe],
+             count: usize,
+             pacing: Pacing,
+             bounds: (usize, usize),
+             threads: usize)
+    -> Result<Vec<Keyframe>, MandelError>
+{
+    let lengths: Vec<f64> = keys.windows(2).map(|pair| leg_length(&pair[0], &pair[1])).collect();
+    let total: f64 = lengths.iter().sum();
+    if pacing == Pacing::Even || total == 0.0 || count < 2 {
+        return Ok(frames(keys, count));
+    }
+    let strength = match pacing {
+        Pacing::Detail { strength } => strength,
+        _ => 0.0,
+    };
+
+    // where along the path each frame is: evenly spread, or as evenly
+    // spread in time weighted by detail
+    let mut positions: Vec<f64> = (0 .. count).map(|i| total * i as f64 / (count - 1) as f64)
+        .collect();
+    if strength > 0.0 {
+        let probes: Vec<f64> = (0 .. PROBES).map(|i| total * i as f64 / (PROBES - 1) as f64)
+            .collect();
+        let details = probes.iter()
+            .map(|&position| detail(&along(keys, &lengths, position), bounds, threads))
+            .collect::<Result<Vec<f64>, _>>()?;
+        let mean = details.iter().sum::<f64>() / PROBES as f64;
+        if mean > 0.0 {
+            let weights: Vec<f64> = details.iter()
+                .map(|&detail| (detail / mean).max(MIN_DETAIL).powf(strength))
+                .collect();
+            // the time taken to each probe, by the trapezium rule
+            let mut time = vec![0.0; PROBES];
+            for i in 1 .. PROBES {
+                time[i] = time[i - 1] + (weights[i - 1] + weights[i]) / 2.0;
+            }
+            positions = (0 .. count).map(|i| {
+                let t = time[PROBES - 1] * i as f64 / (count - 1) as f64;
+                let j = time.iter().rposition(|&taken| taken <= t).unwrap_or(0).min(PROBES - 2);
+                let s = ((t - time[j]) / (time[j + 1] - time[j])).clamp(0.0, 1.0);
+                probes[j] + (probes[j + 1] - probes[j]) * s
+            }).collect();
+        }
+    }
+    Ok(positions.iter().map(|&position| along(keys, &lengths, position)).collect())
+}
+
+/// shutter(v, i, n) : `subframes` views spread evenly over the time frame
+/// `index` of `views` is on screen, from halfway back to the frame before
+/// to halfway on to the next, just the frame's own view for one subframe
+pub fn shutter(views: &[Keyframe], index: usize, subframes: usize) -> Vec<Keyframe> {
+    let view = Keyframe { easing: Eases::default(), ..views[index] };
+    (0 .. subframes).map(|j| {
+        let offset = (j as f64 + 0.5) / subframes as f64 - 0.5;
+        let toward = if offset < 0.0 { index.checked_sub(1) } else { Some(index + 1) };
+        match toward.and_then(|other| views.get(other)) {
+            Some(other) if offset != 0.0 => between(&view, other, offset.abs()),
+            _ => views[index],
+        }
+    }).collect()
+}
+
+/// average(i) : the mean of `images`, all of one size, a channel at a time
+fn average(images: &[ImageBuffer<Rgb>]) -> ImageBuffer<Rgb> {
+    let pixels = (0 .. images[0].pixels().len()).map(|i| {
+        Rgb::mean(&images.iter().map(|image| image.pixels()[i]).collect::<Vec<_>>())
+    }).collect();
+    ImageBuffer::from_pixels(images[0].bounds(), pixels)
+}
+
+/// Where an animation's frames are written: into `dir`, named by a
+/// printf-style pattern with one `%d`, or `%0Nd` to pad the number with
+/// zeros to N digits, numbered from `start`
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameFiles {
+    pub dir: PathBuf,
+    pub start: usize,
+    /// the pattern either side of the number, and the digits it's padded
+    /// to
+    before: String,
+    width: usize,
+    after: String,
+}
+
+impl FrameFiles {
+    /// Frames in `dir` named by `FRAME_PATTERN`, from 0
+    pub fn new<P: Into<PathBuf>>(dir: P) -> FrameFiles {
+        FrameFiles::new_unchecked(dir.into(), FRAME_PATTERN)
+    }
+
+    fn new_unchecked(dir: PathBuf, pattern: &str) -> FrameFiles {
+        let (before, width, after) = split_pattern(pattern).unwrap();
+        FrameFiles { dir, start: 0, before, width, after }
+    }
+
+    /// Name the frames by `pattern`, or say what's wrong with it
+    pub fn pattern(self, pattern: &str) -> Result<FrameFiles, MandelError> {
+        let (before, width, after) = split_pattern(pattern).ok_or_else(|| {
+            MandelError::Parse(format!("bad frame pattern {:?}, expected one %d or %0Nd, \
+                                        with %% for a %", pattern))
+        })?;
+        Ok(FrameFiles { before, width, after, ..self })
+    }
+
+    /// Number the first frame `start`
+    pub fn start(self, start: usize) -> FrameFiles {
+        FrameFiles { start, ..self }
+    }
+
+    /// name(i) : the file name of frame `index`, counting from 0 whatever
+    /// the number it's given
+    pub fn name(&self, index: usize) -> String {
+        format!("{}{:0width$}{}", self.before, self.start + index, self.after, width = self.width)
+    }
+
+    /// path(i) : where frame `index` goes
+    pub fn path(&self, index: usize) -> String {
+        self.dir.join(self.name(index)).to_string_lossy().into_owned()
+    }
+}
+
+/// split_pattern(p) : the text of `pattern` before and after its one `%d`
+/// or `%0Nd`, with `%%` read as `%`, and the N
+fn split_pattern(pattern: &str) -> Option<(String, usize, String)> {
+    let (mut before, mut after) = (String::new(), String::new());
+    let mut width = None;
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        let text = if width.is_none() { &mut before } else { &mut after };
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '%' => text.push('%'),
+            'd' if width.is_none() => width = Some(0),
+            '0' if width.is_none() => {
+                let mut digits = String::new();
+                while let Some(&digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    digits.push(digit);
+                    chars.next();
+                }
+                if chars.next() != Some('d') {
+                    return None;
+                }
+                width = Some(digits.parse().ok()?);
+            }
+            _ => return None,
+        }
+    }
+    if before.contains('/') || after.contains('/') {
+        return None;
+    }
+    Some((before, width?, after))
+}
+
+/// split_threads(b, t) : how many `bounds` frames to render at once, and
+/// on how many threads each, to keep `threads` threads busy
+///
+/// Each frame gets as many threads as have `MIN_ROWS_PER_THREAD` rows to
+/// work on, so big frames are rendered one at a time over every thread
+/// and small ones side by side.
+pub fn split_threads(bounds: (usize, usize), threads: usize) -> (usize, usize) {
+    let per_frame = (bounds.1 / MIN_ROWS_PER_THREAD).clamp(1, threads.max(1));
+    (threads.max(1) / per_frame, per_frame)
+}
+
+/// Make the `count` frames of an animation with `render`, given a frame's
+/// index and the threads it can use, `at_once` at a time on `per_frame`
+/// threads each as `split` has them, handing each to `write` in order
+///
+/// The first error from either stops the rest and is returned.
+fn in_frames<T, R, W>(count: usize,
+                      split: (usize, usize),
+                      render: R,
+                      mut write: W)
+    -> Result<(), MandelError>
+    where T: Send,
+          R: Fn(usize, usize) -> Result<T, MandelError> + Sync,
+          W: FnMut(usize, T) -> Result<(), MandelError>
+{
+    let (at_once, per_frame) = split;
+    let next = AtomicUsize::new(0);
+    // frames written so far, or `count` once writing has stopped
+    let written = (Mutex::new(0), Condvar::new());
+    let (send, finished) = mpsc::channel();
+    crossbeam::scope(|spawner| {
+        for _ in 0 .. at_once.min(count) {
+            let (next, written, render, send) = (&next, &written, &render, send.clone());
+            spawner.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                if index >= count {
+                    return;
+                }
+                let (ref done, ref wake) = *written;
+                let mut done = done.lock().unwrap();
+                while index >= *done + at_once * FRAMES_AHEAD {
+                    done = wake.wait(done).unwrap();
+                }
+                if *done >= count {
+                    return;
+                }
+                drop(done);
+                if send.send((index, render(index, per_frame))).is_err() {
+                    return;
+                }
+            });
+        }
+        drop(send);
+
+        let mut waiting = BTreeMap::new();
+        let mut turn = 0;
+        let mut result = Ok(());
+        for (index, frame) in finished.iter() {
+            waiting.insert(index, frame);
+            while let Some(frame) = waiting.remove(&turn) {
+                result = frame.and_then(|frame| write(turn, frame));
+                if result.is_err() {
+                    break;
+                }
+                turn += 1;
+                let (ref done, ref wake) = written;
+                *done.lock().unwrap() = turn;
+                wake.notify_all();
+            }
+            if result.is_err() {
+                break;
+            }
+        }
+        if result.is_err() {
+            let (ref done, ref wake) = written;
+            *done.lock().unwrap() = count;
+            wake.notify_all();
+            drop(finished);
+        }
+        result
+    })
+}
+
+/// The frames of an animation written to a directory so far, each with a
+/// hash of what it shows, so that rendering it again after a crash can
+/// skip them
+///
+/// A frame is added once its file is complete, so one cut off part way
+/// through is rendered again, as is one whose settings or name have
+/// changed.
+pub struct FrameLog {
+    file: File,
+    /// the hash of each file name's frame
+    done: HashMap<String, u64>,
+}
+
+impl FrameLog {
+    /// The log in `dir`, begun if there's none yet
+    pub fn open(dir: &Path) -> Result<FrameLog, MandelError> {
+        let path = dir.join(FRAME_LOG);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        // each line is `HASH NAME`; a line cut off by a crash is ignored
+        let complete = text.rfind('\n').map_or("", |end| &text[.. end]);
+        let done = complete.lines()
+            .filter_map(|line| {
+                let (hash, name) = line.split_once(' ').filter(|(hash, _)| hash.len() == 16)?;
+                Some((name.to_string(), u64::from_str_radix(hash, 16).ok()?))
+            })
+            .collect();
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        if !text.is_empty() && !text.ends_with('\n') {
+            writeln!(file)?;
+        }
+        Ok(FrameLog { file, done })
+    }
+
+    /// done(f, i, h) : whether frame `index` of `files` is there already,
+    /// rendered from settings hashing to `hash`
+    pub fn done(&self, files: &FrameFiles, index: usize, hash: u64) -> bool {
+        self.done.get(&files.name(index)) == Some(&hash) && Path::new(&files.path(index)).exists()
+    }
+
+    /// Note that the frame called `name` has been written from settings
+    /// hashing to `hash`
+    pub fn record(&mut self, name: &str, hash: u64) -> Result<(), MandelError> {
+        writeln!(self.file, "{:016x} {}", hash, name)?;
+        self.done.insert(name.to_string(), hash);
+        Ok(())
+    }
+}
+
+/// Render `bounds` frames with `render` into `files`, as `in_frames` does
+/// with `split`, one for each of `params`, the JSON objects of what
+/// decides them
+///
+/// The frames are listed with their parameters in the directory's
+/// `MANIFEST` first. Any its `FrameLog` has already, from the same
+/// parameters, are skipped, and how many is returned.
+fn write_frames<R, F>(files: &FrameFiles,
+                      bounds: (usize, usize),
+                      params: &[Value],
+                      split: (usize, usize),
+                      render: R,
+                      mut on_frame: F)
+    -> Result<usize, MandelError>
+    where R: Fn(usize, usize) -> Result<ImageBuffer<Rgb>, MandelError> + Sync,
+          F: FnMut(usize)
+{
+    let frames: Vec<Value> = params.iter().enumerate().map(|(index, params)| {
+        let mut frame = json!({"file": files.name(index), "number": files.start + index});
+        frame.as_object_mut().unwrap().extend(params.as_object().cloned().unwrap_or_default());
+        frame
+    }).collect();
+    let manifest = json!({"width": bounds.0, "height": bounds.1, "frames": frames});
+    fs::write(files.dir.join(MANIFEST), ::serde_json::to_string_pretty(&manifest).unwrap())?;
+
+    let hashes: Vec<u64> = params.iter().map(|params| {
+        fnv1a(json!({"width": bounds.0, "height": bounds.1, "frame": params}).to_string()
+              .as_bytes())
+    }).collect();
+    let mut log = FrameLog::open(&files.dir)?;
+    let todo: Vec<usize> = (0 .. hashes.len())
+        .filter(|&index| !log.done(files, index, hashes[index]))
+        .collect();
+    in_frames(todo.len(), split, |n, threads| render(todo[n], threads), |n, image| {
+        let index = todo[n];
+        output::write_rgb(&files.path(index), &image)?;
+        log.record(&files.name(index), hashes[index])?;
+        on_frame(index);
+        Ok(())
+    })?;
+    Ok(hashes.len() - todo.len())
+}
+
+/// Render the `count` frames of an animation through `keys` into `files`,
+/// colored with a `Cycle` turned to each frame's phase,
+/// on `threads` threads, several frames at a time if they're small
+///
+/// Each frame is the average of `subframes` renders spread over its time
+/// on screen, as `shutter` picks them, blurring the motion so that fine
+/// detail doesn't shimmer and crawl from frame to frame; 1 renders each
+/// frame's view alone.
+///
+/// Frames already there from an earlier run with the same settings, as the
+/// directory's `FrameLog` has them, are skipped, and how many is returned.
+/// `on_frame` is called with the index of each other frame once it's
+/// written, in order.
+pub fn render<F>(files: &FrameFiles,
+                 bounds: (usize, usize),
+                 keys: &[Keyframe],
+                 count: usize,
+                 subframes: usize,
+                 threads: usize,
+                 on_frame: F)
+    -> Result<usize, MandelError>
+    where F: FnMut(usize)
+{
+    render_views(files, bounds, &frames(keys, count), subframes, threads, on_frame)
+}
+
+/// Render a frame of each of `views` into `files`, as `render` does
+pub fn render_views<F>(files: &FrameFiles,
+                       bounds: (usize, usize),
+                       views: &[Keyframe],
+                       subframes: usize,
+                       threads: usize,
+                       on_frame: F)
+    -> Result<usize, MandelError>
+    where F: FnMut(usize)
+{
+    let subframes = subframes.max(1);
+    let params: Vec<Value> = views.iter().map(|key| {
+        let mut params = key.to_json(bounds);
+        if subframes > 1 {
+            params["subframes"] = json!(subframes);
+        }
+        params
+    }).collect();
+    write_frames(files, bounds, &params, split_threads(bounds, threads), |index, threads| {
+        let images = shutter(views, index, subframes).iter().map(|key| {
+            let (top_left, bot_right) = key.corners(bounds);
+            Renderer::new()
+                .bounds(bounds.0, bounds.1)
+                .viewport(top_left, bot_right)
+                .rotation(key.rotation)
+                .iterations(key.limit(bounds))
+                .threads(threads)
+                .render_with(&Cycle { phase: key.phase, ..Cycle::default() })
+        }).collect::<Result<Vec<_>, _>>()?;
+        Ok(average(&images))
+    }, on_frame)
+}
+
+/// Write `count` frames of `escapes` into `files`, each colored with a
+/// `Cycle` turned a step further, so that the colors go once round and
+/// loop back to the first frame
+///
+/// The escape times are found once, beforehand, so each frame costs only
+/// its coloring and writing; `threads` frames are done at a time, skipping
+/// those already there as `render` does, and `on_frame` is called with the
+/// index of each once it's written, in order.
+pub fn cycle<F>(files: &FrameFiles,
+                escapes: &EscapeBuffer,
+                count: usize,
+                threads: usize,
+                on_frame: F)
+    -> Result<usize, MandelError>
+    where F: FnMut(usize)
+{
+    let times: Vec<u8> = escapes.times.pixels().iter().flat_map(|t| t.to_le_bytes()).collect();
+    let escapes_hash = format!("{:016x}", fnv1a(&times));
+    let phases: Vec<f64> = (0 .. count).map(|index| index as f64 / count as f64).collect();
+    let params: Vec<Value> = phases.iter()
+        .map(|phase| json!({"escapes": escapes_hash, "iterations": escapes.limit, "phase": phase}))
+        .collect();
+    // coloring a frame is one thread's work however big it is
+    write_frames(files, escapes.bounds(), &params, (threads.max(1), 1), |index, _| {
+        Ok(escapes.colorize(&Cycle { phase: phases[index], ..Cycle::default() }))
+    }, on_frame)
+}
+
+/// The path the constant `c` takes through a Julia morph
+#[derive(Clone, Debug, PartialEq)]
+pub enum Morph {
+    /// once round the main cardioid of the Mandelbrot set: a `radius`
+    /// of 1 follows its edge, less stays inside, more outside
+    Cardioid { radius: f64 },
+    /// along straight lines through the points in turn, at a steady speed
+    Polyline(Vec<Complex<f64>>),
+}
+
+impl Morph {
+    /// point(t) : where `c` is `t` of the way along the path, from 0 to 1
+    pub fn point(&self, t: f64) -> Complex<f64> {
+        match *self {
+            Morph::Cardioid { radius } => {
+                // the image of the circle `|mu| = radius` under the map
+                // taking the unit disc onto the main cardioid
+                let mu = Complex::from_polar(radius, 2.0 * PI * t);
+                mu / 2.0 - mu * mu / 4.0
+            }
+            Morph::Polyline(ref points) => {
+                let lengths: Vec<f64> = points.windows(2).map(|pair| (pair[1] - pair[0]).norm())
+                    .collect();
+                let mut left = t.clamp(0.0, 1.0) * lengths.iter().sum::<f64>();
+                for (pair, &length) in points.windows(2).zip(&lengths) {
+                    if left <= length && length > 0.0 {
+                        return pair[0] + (pair[1] - pair[0]) * (left / length);
+                    }
+                    left -= length;
+                }
+                points[points.len() - 1]
+            }
+        }
+    }
+
+    /// closed() : whether the path ends where it starts, so that a morph
+    /// along it loops
+    pub fn closed(&self) -> bool {
+        match *self {
+            Morph::Cardioid { .. } => true,
+            Morph::Polyline(_) => false,
+        }
+    }
+
+    /// frames(n) : the `c` of each of `count` frames along the path, the
+    /// last frame stopping one step short of the start on a closed path
+    pub fn frames(&self, count: usize) -> Vec<Complex<f64>> {
+        let steps = if self.closed() { count } else { count.saturating_sub(1).max(1) };
+        (0 .. count).map(|frame| self.point(frame as f64 / steps as f64)).collect()
+    }
+}
+
+/// Parsed from `cardioid[:RADIUS]`, or from points `RE,IM` separated by
+/// spaces or semicolons for a polyline
+impl FromStr for Morph {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Morph, MandelError> {
+        let error = || MandelError::Parse(format!(
+            "bad path {:?}, expected cardioid[:RADIUS] or RE,IM;RE,IM...", s));
+        if let Some(rest) = s.strip_prefix("cardioid") {
+            let radius = match rest.strip_prefix(':') {
+                None if rest.is_empty() => 1.0,
+                None => return Err(error()),
+                Some(r) => r.parse().ok().filter(|&r: &f64| r > 0.0).ok_or_else(error)?,
+            };
+            return Ok(Morph::Cardioid { radius });
+        }
+        let points = s.split([';', ' ']).filter(|point| !point.is_empty())
+            .map(|point| parse_complex(point).ok())
+            .collect::<Option<Vec<_>>>()
+            .filter(|points| !points.is_empty())
+            .ok_or_else(error)?;
+        Ok(Morph::Polyline(points))
+    }
+}
+
+/// Render the `count` frames of the Julia set of each `c` along `path`
+/// into `files`, run on `threads` as `render` does them, skipping those
+/// already there, showing the square every Julia set fits in, widened to
+/// `bounds`
+pub fn morph<F>(files: &FrameFiles,
+                bounds: (usize, usize),
+                path: &Morph,
+                count: usize,
+                limit: u32,
+                threads: usize,
+                on_frame: F)
+    -> Result<usize, MandelError>
+    where F: FnMut(usize)
+{
+    let view = Viewport::new(bounds, julia::TOP_LEFT, julia::BOT_RIGHT).fit();
+    let points = path.frames(count);
+    let params: Vec<Value> = points.iter()
+        .map(|c| json!({"c": format_complex(*c), "iterations": limit}))
+        .collect();
+    write_frames(files, bounds, &params, split_threads(bounds, threads), |index, threads| {
+        let mut times = vec![0; bounds.0 * bounds.1];
+        julia::escape_times(&mut times, bounds, view.top_left, view.bot_right, points[index],
+                            limit, threads);
+        let mut image = ImageBuffer::new(bounds);
+        Cycle::default().colorize(&times, limit, image.pixels_mut());
+        Ok(image)
+    }, on_frame)
+}
+
+#[test]
+fn test_keyframe_parse() {
+    let key: Keyframe = "-0.75,0.1:1e3:500:0.25".parse().unwrap();
+    assert_eq!(key, Keyframe {
+        center: Complex { re: -0.75, im: 0.1 },
+        zoom: 1000.0,
+        limit: Some(500),
+        phase: 0.25,
+        rotation: 0.0,
+        easing: Eases::default(),
+    });
+    let key: Keyframe = "0,0:2".parse().unwrap();
+    assert_eq!((key.limit, key.phase), (None, 0.0));
+    assert_eq!("0,0:2:auto:1".parse::<Keyframe>().unwrap().limit, None);
+    let eased: Keyframe = "0,0:2:::ease-in-out".parse().unwrap();
+    assert_eq!(eased.easing.phase, Easing::EaseInOut);
+    let eased: Keyframe = "0,0:2:auto:0:zoom=bezier(0.4,0,0.6,1)/phase=ease-in-out".parse()
+        .unwrap();
+    assert_eq!(eased.easing, Eases {
+        zoom: Easing::Bezier(0.4, 0.0, 0.6, 1.0),
+        center: Easing::Linear,
+        phase: Easing::EaseInOut,
+        rotation: Easing::Linear,
+    });
+    let turned: Keyframe = "0,0:2::::90".parse().unwrap();
+    assert_eq!((turned.rotation, turned.easing), (PI / 2.0, Eases::default()));
+    for bad in &["", "0,0", "0:1", "0,0:0", "0,0:-1", "0,0:x", "0,0:1:0", "0,0:1:5:x",
+                 "0,0:1:5:0:fast", "0,0:1:5:0:spin=linear", "0,0:1:5:0:linear:x",
+                 "0,0:1:5:0:linear:9:9"] {
+        assert!(matches!(bad.parse::<Keyframe>(), Err(MandelError::Parse(_))), "{}", bad);
+    }
+
+    assert_eq!(key.corners((400, 200)),
+               (Complex { re: -1.2, im: 0.6 }, Complex { re: 1.2, im: -0.6 }));
+}
+
+#[test]
+fn test_between() {
+    let a: Keyframe = "0,0:1:100:0".parse().unwrap();
+    let b: Keyframe = "-1,0.5:100:400:1".parse().unwrap();
+    assert_eq!(between(&a, &b, 0.0), a);
+    let end = between(&a, &b, 1.0);
+    assert!((end.center - b.center).norm() < 1e-12 && (end.zoom - b.zoom).abs() < 1e-9);
+
+    // halfway in time is halfway in log zoom, with most of the move done
+    let half = between(&a, &b, 0.5);
+    assert!((half.zoom - 10.0).abs() < 1e-9);
+    assert_eq!((half.limit, half.phase), (Some(200), 0.5));
+    let spin = Keyframe { rotation: PI, ..b };
+    assert_eq!(between(&a, &spin, 0.25).rotation, PI / 4.0);
+    let along = (half.center - a.center).norm() / (b.center - a.center).norm();
+    assert!((along - 10.0 / 11.0).abs() < 1e-12);
+
+    // at a steady zoom the center moves linearly
+    let pan = between(&a, &Keyframe { zoom: 1.0, ..b }, 0.25);
+    assert_eq!(pan.center, Complex { re: -0.25, im: 0.125 });
+    assert_eq!(between(&a, &Keyframe { limit: None, ..b }, 0.5).limit, None);
+
+    // eased, the zoom starts slowly and the center keeps gliding in step
+    // with it, while the phase still turns steadily
+    let eased = Keyframe { easing: "zoom=ease-in-out/center=ease-in-out".parse().unwrap(), ..a };
+    let early = between(&eased, &b, 0.25);
+    assert!((early.zoom - 100f64.powf(0.15625)).abs() < 1e-9);
+    assert_eq!(early.phase, 0.25);
+    let along = (1.0 - 1.0 / early.zoom) / (1.0 - 1.0 / b.zoom);
+    assert!((early.center - (a.center + (b.center - a.center) * along)).norm() < 1e-12);
+    let half = between(&eased, &b, 0.5);
+    assert!((half.zoom - 10.0).abs() < 1e-9 && half.limit == Some(200));
+    assert_eq!(between(&eased, &b, 1.0).easing, eased.easing);
+}
+
+#[test]
+fn test_ramp() {
+    let ramp: Ramp = "100:1.5".parse().unwrap();
+    assert_eq!(ramp, Ramp { start: 100.0, growth: 1.5 });
+    assert_eq!((ramp.limit(1.0), ramp.limit(2.0), ramp.limit(16.0)), (100, 150, 506));
+    assert_eq!(ramp.limit(1e300), MAX_ITERATIONS);
+    assert_eq!(Ramp { start: 0.5, growth: 2.0 }.limit(1.0), 1);
+
+    // only the frames left to choose follow the ramp
+    let mut views: Vec<Keyframe> = ["0,0:4", "0,0:4:30"].iter()
+        .map(|key| key.parse().unwrap())
+        .collect();
+    ramp.apply(&mut views);
+    assert_eq!((views[0].limit, views[1].limit), (Some(225), Some(30)));
+    for bad in &["", "100", "0:1.5", "100:0", "100:-1", "100:x", "inf:2"] {
+        assert!(matches!(bad.parse::<Ramp>(), Err(MandelError::Parse(_))), "{}", bad);
+    }
+}
+
+#[test]
+fn test_frames() {
+    let keys: Vec<Keyframe> = ["0,0:1:100", "0,0:4:100", "0,0:16:100"].iter()
+        .map(|key| key.parse().unwrap())
+        .collect();
+    let zooms: Vec<f64> = frames(&keys, 5).iter().map(|key| key.zoom).collect();
+    assert_eq!(zooms, [1.0, 2.0, 4.0, 8.0, 16.0]);
+    assert_eq!(frames(&keys[.. 1], 3), [keys[0]; 3]);
+    assert_eq!(frames(&keys, 1), [keys[0]]);
+    assert!(frames(&keys, 0).is_empty());
+
+    let dir = ::std::env::temp_dir().join(format!("mandel-animate-{}", ::std::process::id()));
+    ::std::fs::create_dir_all(&dir).unwrap();
+    let files = FrameFiles::new(&dir);
+    let mut written = Vec::new();
+    assert_eq!(render(&files, (16, 12), &keys, 3, 1, 2, |index| written.push(index)).unwrap(), 0);
+    assert_eq!(written, [0, 1, 2]);
+    assert!((0 .. 3).all(|index| Path::new(&files.path(index)).exists()));
+
+    // run again, only what's missing or changed is rendered
+    ::std::fs::remove_file(files.path(1)).unwrap();
+    let mut changed = keys.clone();
+    changed[2].zoom = 32.0;
+    written.clear();
+    let skipped = render(&files, (16, 12), &changed, 3, 1, 2, |index| written.push(index));
+    assert_eq!(skipped.unwrap(), 1);
+    assert_eq!(written, [1, 2]);
+    assert_eq!(render(&files, (16, 12), &changed, 3, 1, 2, |_| panic!()).unwrap(), 3);
+    ::std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_paced() {
+    let keys: Vec<Keyframe> = ["0,0:1:100", "0,0:4:100", "0,0:64:100"].iter()
+        .map(|key| key.parse().unwrap())
+        .collect();
+    let zooms = |pacing| -> Vec<f64> {
+        paced(&keys, 7, pacing, (16, 12), 2).unwrap().iter().map(|key| key.zoom).collect()
+    };
+    // the second leg zooms twice as far, so gets twice the frames
+    let constant = zooms(Pacing::Constant);
+    let doubling = [1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0];
+    assert!(constant.iter().zip(&doubling).all(|(zoom, two)| (zoom - two).abs() < 1e-9));
+    assert_eq!(zooms(Pacing::Even), frames(&keys, 7).iter().map(|k| k.zoom).collect::<Vec<_>>());
+    let none = zooms(Pacing::Detail { strength: 0.0 });
+    assert!(none.iter().zip(&constant).all(|(a, b)| (a - b).abs() < 1e-9));
+
+    // panning from inside the set, where there's nothing to see, to its
+    // edge, the frames hurry through the inside and linger at the edge
+    let pan: Vec<Keyframe> = ["-0.2,0:50:100", "-0.75,0.05:50:100"].iter()
+        .map(|key| key.parse().unwrap())
+        .collect();
+    let views = paced(&pan, 9, Pacing::Detail { strength: 1.0 }, (16, 12), 2).unwrap();
+    assert_eq!((views[0], views[8]), (pan[0], pan[1]));
+    assert!(views.windows(2).all(|pair| pair[1].center.re <= pair[0].center.re));
+    assert!(views[4].center.re < -0.475, "{:?}", views[4].center);
+
+    assert_eq!("constant".parse::<Pacing>().unwrap(), Pacing::Constant);
+    assert_eq!("detail".parse::<Pacing>().unwrap(), Pacing::Detail { strength: 1.0 });
+    assert_eq!("detail:0.5".parse::<Pacing>().unwrap(), Pacing::Detail { strength: 0.5 });
+    for bad in &["", "fast", "detail:", "detail:-1", "constant:2"] {
+        assert!(matches!(bad.parse::<Pacing>(), Err(MandelError::Parse(_))), "{}", bad);
+    }
+}
+
+#[test]
+fn test_shutter() {
+    let keys: Vec<Keyframe> = ["0,0:1:100", "0,0:16:100"].iter()
+        .map(|key| key.parse().unwrap())
+        .collect();
+    let views = frames(&keys, 5);
+    assert_eq!(shutter(&views, 2, 1), [views[2]]);
+
+    // four subframes a quarter of a frame apart, centered on the frame,
+    // and at the ends only what's on screen
+    let zooms: Vec<f64> = shutter(&views, 2, 4).iter().map(|key| key.zoom).collect();
+    let expected = [-0.375, -0.125, 0.125, 0.375].iter().map(|d| 4f64 * 2f64.powf(*d));
+    assert!(zooms.iter().zip(expected).all(|(zoom, expected)| (zoom - expected).abs() < 1e-9));
+    let first: Vec<f64> = shutter(&views, 0, 2).iter().map(|key| key.zoom).collect();
+    assert!(first[0] == 1.0 && (first[1] - 2f64.powf(0.25)).abs() < 1e-9);
+
+    let gray = |v| ImageBuffer::from_pixels((1, 1), vec![Rgb([v, 0, 255])]);
+    assert_eq!(average(&[gray(10), gray(21)]).pixels(), [Rgb([16, 0, 255])]);
+
+    let dir = ::std::env::temp_dir().join(format!("mandel-shutter-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let files = FrameFiles::new(&dir);
+    let edge: Vec<Keyframe> = ["-0.75,0.1:1:100", "-0.75,0.1:16:100"].iter()
+        .map(|key| key.parse().unwrap())
+        .collect();
+    render(&files, (16, 12), &edge, 2, 1, 2, |_| {}).unwrap();
+    let sharp = fs::read(files.path(1)).unwrap();
+    assert_eq!(render(&files, (16, 12), &edge, 2, 3, 2, |_| {}).unwrap(), 0);
+    assert_ne!(fs::read(files.path(1)).unwrap(), sharp);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_morph() {
+    let circle = Morph::Cardioid { radius: 1.0 };
+    assert_eq!(circle.point(0.0), Complex { re: 0.25, im: 0.0 });
+    assert!((circle.point(0.5) - Complex { re: -0.75, im: 0.0 }).norm() < 1e-12);
+    assert_eq!(circle.frames(4).len(), 4);
+    assert!((circle.frames(4)[2] - circle.point(0.5)).norm() < 1e-12);
+    assert_eq!("cardioid".parse::<Morph>().unwrap(), circle);
+    assert_eq!("cardioid:0.9".parse::<Morph>().unwrap(), Morph::Cardioid { radius: 0.9 });
+
+    let line: Morph = "-1,0;0,0 0,0.5".parse().unwrap();
+    assert!(!line.closed());
+    assert_eq!(line.frames(4), [Complex { re: -1.0, im: 0.0 }, Complex { re: -0.5, im: 0.0 },
+                                Complex { re: 0.0, im: 0.0 }, Complex { re: 0.0, im: 0.5 }]);
+    assert_eq!(Morph::Polyline(vec![Complex { re: 1.0, im: 1.0 }]).frames(2),
+               [Complex { re: 1.0, im: 1.0 }; 2]);
+    for bad in &["", "cardioid:", "cardioid:0", "cardioids", "1,0;x"] {
+        assert!(matches!(bad.parse::<Morph>(), Err(MandelError::Parse(_))), "{}", bad);
+    }
+
+    let dir = ::std::env::temp_dir().join(format!("mandel-morph-{}", ::std::process::id()));
+    ::std::fs::create_dir_all(&dir).unwrap();
+    let files = FrameFiles::new(&dir);
+    let mut written = Vec::new();
+    morph(&files, (16, 12), &circle, 2, 100, 2, |index| written.push(index)).unwrap();
+    assert_eq!(written, [0, 1]);
+    assert_eq!(morph(&files, (16, 12), &circle, 2, 100, 2, |_| panic!()).unwrap(), 2);
+    assert_eq!(morph(&files, (16, 12), &circle, 2, 200, 2, |_| {}).unwrap(), 0);
+    assert!(Path::new(&files.path(1)).exists());
+    ::std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_in_frames() {
+    use std::thread;
+    use std::time::Duration;
+
+    assert_eq!(split_threads((640, 480), 64), (2, 30));
+    assert_eq!(split_threads((160, 120), 64), (9, 7));
+    assert_eq!(split_threads((4000, 3000), 8), (1, 8));
+    assert_eq!(split_threads((10, 10), 0), (1, 1));
+
+    // early frames take longest, so they finish out of order, yet are
+    // written in order, by however many threads there are
+    let slow = |index: usize, threads: usize| {
+        thread::sleep(Duration::from_millis(((8 - index % 8) * 2) as u64));
+        Ok((index, threads))
+    };
+    let mut written = Vec::new();
+    in_frames(20, split_threads((100, 16), 4), slow, |index, frame| {
+        written.push((index, frame));
+        Ok(())
+    }).unwrap();
+    assert_eq!(written, (0 .. 20).map(|i| (i, (i, 1))).collect::<Vec<_>>());
+
+    // an error writing or rendering stops the rest
+    let mut written = 0;
+    let failed = in_frames(50, split_threads((100, 16), 4), slow, |index, _| {
+        written += 1;
+        if index == 5 { Err(MandelError::Cancelled) } else { Ok(()) }
+    });
+    assert!(matches!(failed, Err(MandelError::Cancelled)) && written == 6);
+    let failed = in_frames(50, (4, 1), |index, _| {
+        if index == 3 { Err(MandelError::Cancelled) } else { Ok(index) }
+    }, |_, _| Ok(()));
+    assert!(matches!(failed, Err(MandelError::Cancelled)));
+}
+
+#[test]
+fn test_cycle() {
+    let escapes = Renderer::new().bounds(16, 12).iterations(100).threads(2).escapes().unwrap();
+    let dir = ::std::env::temp_dir().join(format!("mandel-cycle-{}", ::std::process::id()));
+    ::std::fs::create_dir_all(&dir).unwrap();
+    let files = FrameFiles::new(&dir);
+    let mut written = Vec::new();
+    cycle(&files, &escapes, 3, 2, |index| written.push(index)).unwrap();
+    assert_eq!(written, [0, 1, 2]);
+    assert!((0 .. 3).all(|index| Path::new(&files.path(index)).exists()));
+    let first = ::std::fs::read(files.path(0)).unwrap();
+    assert_ne!(::std::fs::read(files.path(1)).unwrap(), first);
+    ::std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_frame_log() {
+    let dir = ::std::env::temp_dir().join(format!("mandel-log-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let files = FrameFiles::new(&dir);
+    let mut log = FrameLog::open(&dir).unwrap();
+    log.record(&files.name(0), 0xabc).unwrap();
+    fs::write(files.path(0), b"").unwrap();
+    assert!(log.done(&files, 0, 0xabc) && !log.done(&files, 0, 0xabd));
+    assert!(!log.done(&files, 1, 0xabc) && !log.done(&files.clone().start(5), 0, 0xabc));
+
+    // a line cut off part way is skipped, and the next starts afresh
+    drop(log);
+    let mut file = OpenOptions::new().append(true).open(dir.join(FRAME_LOG)).unwrap();
+    write!(file, "00000000000000ab frame-0").unwrap();
+    let mut log = FrameLog::open(&dir).unwrap();
+    assert_eq!(log.done, [(files.name(0), 0xabc)].iter().cloned().collect());
+    log.record("other.png", 7).unwrap();
+    assert_eq!(FrameLog::open(&dir).unwrap().done.get("other.png"), Some(&7));
+    fs::remove_dir_all(&dir).unwrap();
+
+}
+
+#[test]
+fn test_frame_files() {
+    let files = FrameFiles::new("frames");
+    assert_eq!(files.name(7), "frame-00007.png");
+    assert_eq!(Path::new(&files.path(7)), Path::new("frames/frame-00007.png"));
+    let files = files.pattern("shot_%06d.png").unwrap().start(100);
+    assert_eq!(files.name(0), "shot_000100.png");
+    assert_eq!(files.name(12), "shot_000112.png");
+    let files = files.pattern("100%%-%d.png").unwrap();
+    assert_eq!(files.name(1), "100%-101.png");
+    assert_eq!(files.pattern("%02d").unwrap().name(2000), "2100");
+    for bad in &["frame.png", "%d-%d.png", "%5d.png", "%s.png", "%0xd", "frame%", "a/%d.png"] {
+        assert!(matches!(FrameFiles::new(".").pattern(bad), Err(MandelError::Parse(_))), "{}", bad);
+    }
+
+    // the manifest lists every frame with what it shows
+    let dir = ::std::env::temp_dir().join(format!("mandel-manifest-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let files = FrameFiles::new(&dir).pattern("f%03d.png").unwrap().start(1);
+    let keys: Vec<Keyframe> = ["0,0:1:50:::90", "0.5,0:2:50"].iter()
+        .map(|key| key.parse().unwrap())
+        .collect();
+    render(&files, (8, 6), &keys, 2, 1, 1, |_| {}).unwrap();
+    assert!(Path::new(&dir.join("f002.png")).exists());
+    let manifest: Value = ::serde_json::from_slice(&fs::read(dir.join(MANIFEST)).unwrap())
+        .unwrap();
+    assert_eq!(manifest, json!({"width": 8, "height": 6, "frames": [
+        {"file": "f001.png", "number": 1, "center": "0,0", "zoom": 1.0, "iterations": 50,
+         "phase": 0.0, "rotation": 90.0},
+        {"file": "f002.png", "number": 2, "center": "0.5,0", "zoom": 2.0, "iterations": 50,
+         "phase": 0.0, "rotation": 0.0},
+    ]}));
+    fs::remove_dir_all(&dir).unwrap();
+}
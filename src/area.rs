@@ -0,0 +1,122 @@
+use affinity;
+use crossbeam;
+use escape_time_batch;
+use num::Complex;
+use random::Rng;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use NO_ESCAPE;
+
+/// A rectangle the whole set fits in, `-2 <= re <= 0.5` and
+/// `|im| <= 1.25`
+pub const TOP_LEFT: Complex<f64> = Complex { re: -2.0, im: 1.25 };
+pub const BOT_RIGHT: Complex<f64> = Complex { re: 0.5, im: -1.25 };
+
+/// Samples drawn from each `Rng::stream`, so that however many threads
+/// share them out the same points are sampled
+const CHUNK: u64 = 4096;
+
+/// How many standard errors either side of an estimate a 95% confidence
+/// interval reaches
+pub const Z_95: f64 = 1.96;
+
+/// How much of a rectangle of the plane is in the set, from sampling it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Estimate {
+    /// the area of the rectangle sampled
+    pub rect: f64,
+    pub samples: u64,
+    /// samples that didn't escape
+    pub inside: u64,
+}
+
+impl Estimate {
+    pub fn area(&self) -> f64 {
+        self.rect * self.inside as f64 / self.samples as f64
+    }
+
+    /// standard_error() : how far `area` is likely to be off, the spread
+    /// of the binomial count of samples inside, as an area
+    pub fn standard_error(&self) -> f64 {
+        let p = self.inside as f64 / self.samples as f64;
+        self.rect * (p * (1.0 - p) / self.samples as f64).sqrt()
+    }
+
+    /// interval(z) : the area `z` standard errors either side of the
+    /// estimate, such as `Z_95` of them for 95% confidence
+    pub fn interval(&self, z: f64) -> (f64, f64) {
+        let (area, error) = (self.area(), self.standard_error());
+        ((area - z * error).max(0.0), (area + z * error).min(self.rect))
+    }
+}
+
+/// Estimate the area of the set between the corners from `samples` points
+/// spread uniformly at random over them, drawn from `seed`, counting
+/// those that don't escape in `limit` iterations, on `threads` threads
+///
+/// Points that would escape after more than `limit` are counted in, so
+/// the estimate is a little high, less so the higher `limit` is.
+pub fn estimate(top_left: Complex<f64>,
+                bot_right: Complex<f64>,
+                samples: u64,
+                limit: u32,
+                seed: u64,
+                threads: usize)
+    -> Estimate
+{
+    let size = Complex { re: bot_right.re - top_left.re, im: top_left.im - bot_right.im };
+    let chunks = samples.div_ceil(CHUNK);
+    let next = AtomicUsize::new(0);
+    let inside = AtomicU64::new(0);
+    crossbeam::scope(|spawner| {
+        for index in 0 .. threads {
+            let (next, inside) = (&next, &inside);
+            spawner.spawn(move || {
+                affinity::pin(index);
+                let mut times = Vec::new();
+                loop {
+                    let chunk = next.fetch_add(1, Ordering::SeqCst) as u64;
+                    if chunk >= chunks {
+                        break;
+                    }
+                    let mut rng = Rng::stream(seed, chunk);
+                    let points: Vec<_> = (chunk * CHUNK .. samples.min((chunk + 1) * CHUNK))
+                        .map(|_| Complex {
+                            re: top_left.re + size.re * rng.unit(),
+                            im: top_left.im - size.im * rng.unit(),
+                        })
+                        .collect();
+                    times.resize(points.len(), 0);
+                    escape_time_batch(&points, limit, &mut times);
+                    let count = times.iter().filter(|&&time| time == NO_ESCAPE).count();
+                    inside.fetch_add(count as u64, Ordering::SeqCst);
+                }
+            });
+        }
+    });
+
+    Estimate { rect: size.re * size.im, samples, inside: inside.into_inner() }
+}
+
+#[test]
+fn test_estimate() {
+    // the area of the set is about 1.50659, and points escaping after
+    // 1000 iterations make it come out a little over that
+    let whole = estimate(TOP_LEFT, BOT_RIGHT, 200_000, 1000, 1, 4);
+    let (low, high) = whole.interval(4.0);
+    assert!(low < 1.5066 && 1.5066 < high + 0.01, "{:?}", whole);
+    assert!(whole.standard_error() < 0.01);
+
+    // the same points whatever the threads, other points for another seed
+    let few = estimate(TOP_LEFT, BOT_RIGHT, 10_000, 100, 1, 4);
+    assert_eq!(estimate(TOP_LEFT, BOT_RIGHT, 10_000, 100, 1, 1), few);
+    assert_ne!(estimate(TOP_LEFT, BOT_RIGHT, 10_000, 100, 2, 4), few);
+
+    // wholly inside the main cardioid, and wholly outside the set
+    let cardioid = estimate(Complex { re: -0.2, im: 0.2 }, Complex { re: 0.0, im: 0.0 },
+                            10_000, 100, 1, 3);
+    assert_eq!((cardioid.inside, cardioid.standard_error()), (10_000, 0.0));
+    assert!((cardioid.area() - 0.04).abs() < 1e-12);
+    let outside = estimate(Complex { re: 1.0, im: 1.0 }, Complex { re: 2.0, im: 0.0 },
+                           5000, 100, 1, 3);
+    assert_eq!((outside.samples, outside.area(), outside.interval(Z_95)), (5000, 0.0, (0.0, 0.0)));
+}
@@ -0,0 +1,116 @@
+use color::EscapeBuffer;
+use error::MandelError;
+use num::Complex;
+use serde_json::{self, json};
+use std::fs;
+use std::path::Path;
+use viewport::Viewport;
+
+/// pixels(e) : the pixels in the set with a neighbour across or down from
+/// them that isn't, which trace the edge of the set, row by row
+pub fn pixels(escapes: &EscapeBuffer) -> Vec<(usize, usize)> {
+    let (width, height) = escapes.bounds();
+    let inside = |col: usize, row: usize| escapes.time((col, row)).is_none();
+    let mut edge = Vec::new();
+    for row in 0 .. height {
+        for col in 0 .. width {
+            if !inside(col, row) {
+                continue;
+            }
+            let neighbours = [(col.wrapping_sub(1), row), (col + 1, row),
+                              (col, row.wrapping_sub(1)), (col, row + 1)];
+            if neighbours.iter().any(|&(x, y)| x < width && y < height && !inside(x, y)) {
+                edge.push((col, row));
+            }
+        }
+    }
+    edge
+}
+
+/// points(e, v) : the points sampled at the edge `pixels` of `escapes`, a
+/// render of `view`
+pub fn points(escapes: &EscapeBuffer, view: &Viewport) -> Vec<Complex<f64>> {
+    pixels(escapes).into_iter().map(|pixel| view.sample(pixel)).collect()
+}
+
+/// to_csv(p) : `points` as CSV, a header then `re,im` on each line
+pub fn to_csv(points: &[Complex<f64>]) -> String {
+    let mut csv = String::from("re,im\n");
+    for point in points {
+        csv.push_str(&format!("{},{}\n", point.re, point.im));
+    }
+    csv
+}
+
+/// to_geojson(p) : `points` as a GeoJSON `MultiPoint` feature, the real
+/// part as x and the imaginary part as y
+pub fn to_geojson(points: &[Complex<f64>]) -> String {
+    let coordinates: Vec<[f64; 2]> = points.iter().map(|point| [point.re, point.im]).collect();
+    let feature = json!({
+        "type": "Feature",
+        "geometry": { "type": "MultiPoint", "coordinates": coordinates },
+        "properties": { "name": "boundary" },
+    });
+    serde_json::to_string(&feature).unwrap() + "\n"
+}
+
+/// Write `points` to `filename`, as CSV or GeoJSON by its extension,
+/// `.csv` or `.geojson` or `.json`
+pub fn write(filename: &str, points: &[Complex<f64>]) -> Result<(), MandelError> {
+    let extension = Path::new(filename).extension().and_then(|e| e.to_str());
+    let text = match extension.map(|e| e.to_ascii_lowercase()).as_deref() {
+        Some("csv") => to_csv(points),
+        Some("geojson") | Some("json") => to_geojson(points),
+        _ => return Err(MandelError::InvalidParams(format!(
+            "can't tell what to write {} as, expected .csv, .geojson or .json", filename))),
+    };
+    Ok(fs::write(filename, text)?)
+}
+
+#[test]
+fn test_boundary() {
+    use image::ImageBuffer;
+    use NO_ESCAPE;
+
+    // a 2x2 block of interior in a 5x4 image, touching its right edge
+    let times = ImageBuffer::from_pixels((5, 4), vec![
+        1, 1, 1, 1, 1,
+        1, 1, 1, NO_ESCAPE, NO_ESCAPE,
+        1, 1, 1, NO_ESCAPE, NO_ESCAPE,
+        1, NO_ESCAPE, 1, 1, 1,
+    ]);
+    let escapes = EscapeBuffer { limit: 10, times };
+    assert_eq!(pixels(&escapes), [(3, 1), (4, 1), (3, 2), (4, 2), (1, 3)]);
+
+    let times = ImageBuffer::from_pixels((2, 2), vec![NO_ESCAPE; 4]);
+    let all = EscapeBuffer { limit: 10, times };
+    assert_eq!(pixels(&all), []);
+
+    let view = Viewport::new((5, 4), Complex { re: 0.0, im: 4.0 }, Complex { re: 5.0, im: 0.0 });
+    let points = points(&escapes, &view);
+    assert_eq!(points[0], Complex { re: 3.5, im: 2.5 });
+    assert_eq!(to_csv(&points[.. 2]), "re,im\n3.5,2.5\n4.5,2.5\n");
+    assert_eq!(to_geojson(&points[.. 1]),
+               "{\"geometry\":{\"coordinates\":[[3.5,2.5]],\"type\":\"MultiPoint\"},\
+                \"properties\":{\"name\":\"boundary\"},\"type\":\"Feature\"}\n");
+    assert!(matches!(write("edge.txt", &points), Err(MandelError::InvalidParams(_))));
+}
+
+#[test]
+fn test_boundary_of_set() {
+    // the edge of the whole set is in it, within the circle of radius 2
+    let (top_left, bot_right) = (Complex { re: -2.2, im: 1.2 }, Complex { re: 0.8, im: -1.05 });
+    let escapes = ::Renderer::new()
+        .bounds(120, 90)
+        .viewport(top_left, bot_right)
+        .iterations(500)
+        .escapes()
+        .unwrap();
+    let view = Viewport::new((120, 90), top_left, bot_right);
+    let edge = points(&escapes, &view);
+    assert!(edge.len() > 100);
+    for point in &edge {
+        assert!(point.norm() <= 2.0);
+        assert!(::escape_time(*point, 500).is_none());
+    }
+}
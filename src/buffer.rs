@@ -0,0 +1,82 @@
+use memmap2::MmapMut;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+/// Pixel buffer held in memory, or in a memory-mapped file for images
+/// too big to fit in RAM
+pub enum PixelBuffer {
+    Memory(Vec<u8>),
+    Mapped(MmapMut),
+}
+
+impl PixelBuffer {
+    /// Zeroed memory comes straight from the kernel and isn't touched
+    /// until it's rendered into, so each page is placed on the NUMA node
+    /// of the thread that renders it.
+    pub fn in_memory(len: usize) -> PixelBuffer {
+        PixelBuffer::Memory(vec![0; len])
+    }
+
+    /// Back the buffer with a scratch file at `path`, which is unlinked
+    /// straight away: on Unix the mapping outlives the directory entry, so
+    /// nothing is left behind even if the render is killed.
+    pub fn mapped(len: usize, path: &Path) -> io::Result<PixelBuffer> {
+        let file = OpenOptions::new()
+            .read(true).write(true).create_new(true)
+            .open(path)?;
+        file.set_len(len as u64)?;
+        let map = unsafe { MmapMut::map_mut(&file)? };
+        let _ = fs::remove_file(path);
+        Ok(PixelBuffer::Mapped(map))
+    }
+}
+
+impl Deref for PixelBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match *self {
+            PixelBuffer::Memory(ref v) => v,
+            PixelBuffer::Mapped(ref m) => m,
+        }
+    }
+}
+
+impl DerefMut for PixelBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match *self {
+            PixelBuffer::Memory(ref mut v) => v,
+            PixelBuffer::Mapped(ref mut m) => m,
+        }
+    }
+}
+
+/// available_memory() : bytes of RAM the kernel says are free for use
+///
+/// Returns `None` where that isn't known (anywhere but Linux).
+pub fn available_memory() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|l| l.starts_with("MemAvailable:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[test]
+fn test_mapped_buffer() {
+    let path = std::env::temp_dir()
+        .join(format!("mandelbrot-buffer-test-{}", std::process::id()));
+    let mut buffer = PixelBuffer::mapped(4096, &path).unwrap();
+    assert!(!path.exists());
+
+    assert_eq!(buffer.len(), 4096);
+    assert!(buffer.iter().all(|&p| p == 0));
+    for (chunk, value) in buffer.chunks_mut(1024).zip(1..) {
+        for p in chunk.iter_mut() {
+            *p = value;
+        }
+    }
+    assert_eq!(buffer[0], 1);
+    assert_eq!(buffer[4095], 4);
+}
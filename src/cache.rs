@@ -0,0 +1,206 @@
+use affinity;
+use crossbeam;
+use num::Complex;
+use precision::Precision;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use {escape_time_batch, escaped, gray, NO_ESCAPE};
+
+pub const TILE_SIZE: usize = 64;
+
+/// On-disk cache of per-tile escape times
+///
+/// Tiles sit on a grid anchored at the origin of the complex plane rather
+/// than at the image corner, so a view panned by whole pixels (or
+/// re-rendered with only the shading changed) finds the same tiles again.
+pub struct TileCache {
+    dir: PathBuf,
+}
+
+impl TileCache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> io::Result<TileCache> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(TileCache { dir })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{:016x}.tile", fnv1a(key.as_bytes())))
+    }
+
+    /// The key is stored in the file too, so a hash collision is a miss
+    fn load(&self, key: &str) -> Option<Vec<u32>> {
+        let mut reader = BufReader::new(File::open(self.path(key)).ok()?);
+        let mut header = String::new();
+        reader.read_line(&mut header).ok()?;
+        if header.trim_end() != key {
+            return None;
+        }
+
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).ok()?;
+        if bytes.len() != TILE_SIZE * TILE_SIZE * 4 {
+            return None;
+        }
+        Some(bytes.chunks(4)
+             .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+             .collect())
+    }
+
+    fn store(&self, key: &str, times: &[u32]) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(key.len() + 1 + times.len() * 4);
+        writeln!(bytes, "{}", key)?;
+        for t in times {
+            bytes.extend_from_slice(&t.to_le_bytes());
+        }
+        File::create(self.path(key))?.write_all(&bytes)
+    }
+}
+
+/// 64-bit FNV-1a, chosen over `DefaultHasher` for being stable across
+/// Rust releases
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn tile_key(scale: (f64, f64), tile: (i64, i64), limit: u32, precision: Precision)
+    -> String
+{
+    format!("mandelbrot centers {} {} {:016x} {:016x} {} {}",
+            precision, limit, scale.0.to_bits(), scale.1.to_bits(),
+            tile.0, tile.1)
+}
+
+fn render_tile(scale: (f64, f64), tile: (i64, i64), limit: u32, precision: Precision)
+    -> Vec<u32>
+{
+    let points: Vec<_> = (0 .. TILE_SIZE)
+        .flat_map(|row| (0 .. TILE_SIZE).map(move |col| {
+            let gx = tile.0 * TILE_SIZE as i64 + col as i64;
+            let gy = tile.1 * TILE_SIZE as i64 + row as i64;
+            Complex { re: (gx as f64 + 0.5) * scale.0, im: -(gy as f64 + 0.5) * scale.1 }
+        }))
+        .collect();
+
+    let mut times = vec![NO_ESCAPE; TILE_SIZE * TILE_SIZE];
+    match precision {
+        Precision::F32 => {
+            let points: Vec<_> = points.iter()
+                .map(|pt| Complex { re: pt.re as f32, im: pt.im as f32 })
+                .collect();
+            escape_time_batch(&points, limit, &mut times);
+        }
+        _ => escape_time_batch(&points, limit, &mut times),
+    }
+    times
+}
+
+/// Render like `render`, reusing cached tiles where possible
+///
+/// The view is snapped to the nearest whole pixel of the tile grid.
+#[allow(clippy::too_many_arguments)]
+pub fn render(pixels: &mut [u8],
+              bounds: (usize, usize),
+              top_left: Complex<f64>,
+              bot_right: Complex<f64>,
+              limit: u32,
+              precision: Precision,
+              cache: &TileCache,
+              threads: usize)
+{
+    assert!(pixels.len() == bounds.0 * bounds.1);
+
+    let scale = ((bot_right.re - top_left.re) / bounds.0 as f64,
+                 (top_left.im - bot_right.im) / bounds.1 as f64);
+    let origin = ((top_left.re / scale.0).round() as i64,
+                  (-top_left.im / scale.1).round() as i64);
+
+    let tile = TILE_SIZE as i64;
+    let first = (origin.0.div_euclid(tile), origin.1.div_euclid(tile));
+    let last = ((origin.0 + bounds.0 as i64 - 1).div_euclid(tile),
+                (origin.1 + bounds.1 as i64 - 1).div_euclid(tile));
+    let tiles: Vec<(i64, i64)> = (first.1 ..= last.1)
+        .flat_map(|ty| (first.0 ..= last.0).map(move |tx| (tx, ty)))
+        .collect();
+
+    let next = AtomicUsize::new(0);
+    let done = Mutex::new(Vec::with_capacity(tiles.len()));
+    crossbeam::scope(|spawner| {
+        for index in 0..threads {
+            let (tiles, next, done) = (&tiles, &next, &done);
+            spawner.spawn(move || {
+                affinity::pin(index);
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let t = match tiles.get(i) {
+                        Some(&t) => t,
+                        None => break,
+                    };
+                    let key = tile_key(scale, t, limit, precision);
+                    let times = cache.load(&key).unwrap_or_else(|| {
+                        let times = render_tile(scale, t, limit, precision);
+                        if let Err(e) = cache.store(&key, &times) {
+                            eprintln!("warning: can't write tile cache: {}", e);
+                        }
+                        times
+                    });
+                    done.lock().unwrap().push((t, times));
+                }
+            });
+        }
+    });
+
+    for (t, times) in done.into_inner().unwrap() {
+        for row in 0 .. TILE_SIZE {
+            for col in 0 .. TILE_SIZE {
+                let x = t.0 * tile + col as i64 - origin.0;
+                let y = t.1 * tile + row as i64 - origin.1;
+                if x < 0 || y < 0 || x >= bounds.0 as i64 || y >= bounds.1 as i64 {
+                    continue;
+                }
+                let time = escaped(times[row * TILE_SIZE + col]);
+                pixels[y as usize * bounds.0 + x as usize] = gray(time, limit);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_fnv1a() {
+    assert_eq!(fnv1a(b""), 0xcbf29ce484222325);
+    assert_eq!(fnv1a(b"a"), 0xaf63dc4c8601ec8c);
+}
+
+#[test]
+fn test_cache_reuses_panned_tiles() {
+    let dir = std::env::temp_dir()
+        .join(format!("mandelbrot-cache-test-{}", std::process::id()));
+    let cache = TileCache::new(&dir).unwrap();
+    let bounds = (100, 80);
+    let tl = Complex { re: -1.0, im: 0.5 };
+    let br = Complex { re: 0.0, im: -0.3 };
+
+    let mut first = vec![0; bounds.0 * bounds.1];
+    render(&mut first, bounds, tl, br, 255, Precision::F64, &cache, 4);
+    let stored = fs::read_dir(&dir).unwrap().count();
+
+    // pan right by exactly 10 pixels: everything but the new edge is cached
+    let step = 0.01;
+    let mut panned = vec![0; bounds.0 * bounds.1];
+    render(&mut panned, bounds,
+           Complex { re: tl.re + 10.0 * step, ..tl },
+           Complex { re: br.re + 10.0 * step, ..br },
+           255, Precision::F64, &cache, 4);
+    for row in 0 .. bounds.1 {
+        assert_eq!(panned[row * bounds.0 .. row * bounds.0 + 90],
+                   first[row * bounds.0 + 10 .. (row + 1) * bounds.0]);
+    }
+    assert!(fs::read_dir(&dir).unwrap().count() <= stored + 2);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,269 @@
+use animate::{between, Eases, Keyframe, Ramp};
+use error::MandelError;
+use parse_complex;
+use serde_json::{Map, Value};
+use std::fs;
+
+/// invalid(r) : the error for a path that can't be followed, and why
+fn invalid<S: Into<String>>(reason: S) -> MandelError {
+    MandelError::InvalidParams(reason.into())
+}
+
+/// Frames a second of a path that doesn't say
+pub const DEFAULT_FPS: f64 = 30.0;
+
+/// Fields a waypoint can have
+const WAYPOINT_FIELDS: [&str; 10] = ["center", "zoom", "iterations", "phase", "rotation",
+                                     "easing", "time", "after", "speed", "hold"];
+
+/// A view the camera passes through, at a time in seconds from the start
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Waypoint {
+    pub time: f64,
+    /// seconds the camera stays here before moving on
+    pub hold: f64,
+    pub key: Keyframe,
+}
+
+impl Waypoint {
+    /// leaves() : when the camera moves on from here
+    pub fn leaves(&self) -> f64 {
+        self.time + self.hold
+    }
+}
+
+/// A camera's flight through views over time, for animations with more
+/// to them than keyframes evenly spaced, read from JSON such as:
+///
+/// ```json
+/// {"fps": 30, "ramp": {"start": 100, "growth": 1.4}, "waypoints": [
+///     {"center": "-0.5,0", "zoom": 1, "iterations": 200, "hold": 1},
+///     {"center": "-0.7436,0.1318", "zoom": 1e4, "speed": 2, "easing": "ease-in-out"},
+///     {"after": 4, "center": "-0.7436,0.1318", "zoom": 1e4, "rotation": 180}
+/// ]}
+/// ```
+///
+/// Each waypoint is a view as a `Keyframe` has it, `rotation` in degrees,
+/// with `easing` for the way on to the next. The first is at time 0 and
+/// each after it at its `time` in seconds, `after` seconds once the camera
+/// leaves the one before, or as soon as zooming at `speed` doublings a
+/// second gets there. The camera `hold`s still at a waypoint for that many
+/// seconds before leaving. Frames between waypoints without `iterations`
+/// follow the `ramp` if there is one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraPath {
+    pub fps: f64,
+    pub ramp: Option<Ramp>,
+    pub waypoints: Vec<Waypoint>,
+}
+
+impl CameraPath {
+    /// The path in the JSON object `json`, or what's wrong with it
+    pub fn from_json(json: &Value) -> Result<CameraPath, MandelError> {
+        let fields = json.as_object().ok_or_else(|| invalid("a path must be a JSON object"))?;
+        let known = ["fps", "ramp", "waypoints"];
+        if let Some(unknown) = fields.keys().find(|key| !known.contains(&&key[..])) {
+            return Err(invalid(format!("unknown field '{}'", unknown)));
+        }
+        let fps = match fields.get("fps") {
+            None => DEFAULT_FPS,
+            Some(n) => n.as_f64().filter(|&n| n > 0.0)
+                .ok_or_else(|| invalid("'fps' must be a positive number"))?,
+        };
+        let ramp = match fields.get("ramp") {
+            None => None,
+            Some(ramp) => {
+                let part = |name| ramp.get(name).and_then(Value::as_f64)
+                    .filter(|&n| n > 0.0 && n.is_finite());
+                match (ramp.as_object().map(|ramp| ramp.len()), part("start"), part("growth")) {
+                    (Some(2), Some(start), Some(growth)) => Some(Ramp { start, growth }),
+                    _ => return Err(invalid("'ramp' must be {\"start\": N, \"growth\": N} \
+                                             with both positive")),
+                }
+            }
+        };
+        let points = fields.get("waypoints").and_then(Value::as_array)
+            .filter(|points| !points.is_empty())
+            .ok_or_else(|| invalid("'waypoints' must be a list of at least one"))?;
+
+        let mut waypoints: Vec<Waypoint> = Vec::with_capacity(points.len());
+        for (i, point) in points.iter().enumerate() {
+            let point = point.as_object()
+                .ok_or_else(|| invalid(format!("waypoint {} must be a JSON object", i)))?;
+            let waypoint = parse_waypoint(point, waypoints.last())
+                .map_err(|e| invalid(format!("waypoint {}: {}", i, e)))?;
+            waypoints.push(waypoint);
+        }
+        Ok(CameraPath { fps, ramp, waypoints })
+    }
+
+    /// The path in the JSON file `filename`
+    pub fn read(filename: &str) -> Result<CameraPath, MandelError> {
+        let text = fs::read_to_string(filename)?;
+        let json: Value = ::serde_json::from_str(&text)
+            .map_err(|e| MandelError::Parse(format!("{} isn't JSON: {}", filename, e)))?;
+        CameraPath::from_json(&json)
+    }
+
+    /// duration() : seconds from the start to leaving the last waypoint
+    pub fn duration(&self) -> f64 {
+        self.waypoints.last().map_or(0.0, Waypoint::leaves)
+    }
+
+    /// at(t) : the view `t` seconds in
+    pub fn at(&self, t: f64) -> Keyframe {
+        let last = self.waypoints.iter().rposition(|point| point.time <= t).unwrap_or(0);
+        let here = &self.waypoints[last];
+        match self.waypoints.get(last + 1) {
+            Some(next) if t > here.leaves() => {
+                let s = (t - here.leaves()) / (next.time - here.leaves());
+                between(&here.key, &next.key, s)
+            }
+            _ => here.key,
+        }
+    }
+
+    /// frames() : the view of each frame, `fps` a second from the start
+    /// to the end of the path, with its iterations from the ramp if it
+    /// needs them
+    pub fn frames(&self) -> Vec<Keyframe> {
+        let count = (self.duration() * self.fps + 1e-9).floor() as usize + 1;
+        let mut views: Vec<Keyframe> = (0 .. count)
+            .map(|frame| self.at(frame as f64 / self.fps))
+            .collect();
+        if let Some(ref ramp) = self.ramp {
+            ramp.apply(&mut views);
+        }
+        views
+    }
+}
+
+/// parse_waypoint(p, b) : the waypoint in the fields `point`, timed from
+/// the one `before` it
+fn parse_waypoint(point: &Map<String, Value>, before: Option<&Waypoint>)
+    -> Result<Waypoint, String>
+{
+    if let Some(unknown) = point.keys().find(|key| !WAYPOINT_FIELDS.contains(&&key[..])) {
+        return Err(format!("unknown field '{}'", unknown));
+    }
+    let number = |name: &str| match point.get(name) {
+        None => Ok(None),
+        Some(n) => n.as_f64().filter(|n| n.is_finite()).map(Some)
+            .ok_or_else(|| format!("'{}' must be a number", name)),
+    };
+
+    let center = point.get("center").and_then(Value::as_str).and_then(|c| parse_complex(c).ok())
+        .ok_or("'center' must be \"re,im\"")?;
+    let zoom = number("zoom")?.filter(|&zoom| zoom > 0.0)
+        .ok_or("'zoom' must be a positive number")?;
+    let limit = match point.get("iterations") {
+        None => None,
+        Some(Value::String(s)) if s == "auto" => None,
+        Some(n) => Some(n.as_u64().filter(|&n| n > 0 && n <= u32::MAX as u64)
+            .ok_or("'iterations' must be \"auto\" or a positive whole number")? as u32),
+    };
+    let easing: Eases = match point.get("easing") {
+        None => Eases::default(),
+        Some(e) => e.as_str().ok_or("'easing' must be a string")?
+            .parse().map_err(|e: MandelError| e.to_string())?,
+    };
+    let key = Keyframe {
+        center,
+        zoom,
+        limit,
+        phase: number("phase")?.unwrap_or(0.0),
+        rotation: number("rotation")?.unwrap_or(0.0).to_radians(),
+        easing,
+    };
+
+    let hold = number("hold")?.unwrap_or(0.0);
+    if hold < 0.0 {
+        return Err("'hold' can't be negative".to_string());
+    }
+    let (time, after, speed) = (number("time")?, number("after")?, number("speed")?);
+    let time = match (before, time, after, speed) {
+        (None, None, None, None) => 0.0,
+        (None, _, _, _) => return Err("the first waypoint is at time 0".to_string()),
+        (Some(_), Some(time), None, None) => time,
+        (Some(before), None, Some(after), None) => before.leaves() + after,
+        (Some(before), None, None, Some(speed)) => {
+            let doublings = (zoom / before.key.zoom).log2().abs();
+            if speed <= 0.0 || doublings == 0.0 {
+                return Err("'speed' must be positive, between different zooms".to_string());
+            }
+            before.leaves() + doublings / speed
+        }
+        (Some(_), None, None, None) => return Err("needs a 'time', 'after' or 'speed'".to_string()),
+        (Some(_), _, _, _) => return Err("only one of 'time', 'after' and 'speed'".to_string()),
+    };
+    if let Some(before) = before {
+        if time < before.leaves() {
+            return Err(format!("at {}s, before the camera leaves the waypoint before at {}s",
+                               time, before.leaves()));
+        }
+    }
+    Ok(Waypoint { time, hold, key })
+}
+
+#[test]
+fn test_camera_path() {
+    use num::Complex;
+    use serde_json::json;
+
+    let path = CameraPath::from_json(&json!({"fps": 2, "waypoints": [
+        {"center": "0,0", "zoom": 1, "iterations": 100, "hold": 1},
+        {"center": "0,0", "zoom": 16, "iterations": 100, "speed": 2, "easing": "ease-in-out"},
+        {"center": "1,0", "zoom": 16, "after": 1, "rotation": 90, "hold": 0.5},
+    ]})).unwrap();
+    let times: Vec<(f64, f64)> = path.waypoints.iter().map(|p| (p.time, p.hold)).collect();
+    assert_eq!(times, [(0.0, 1.0), (3.0, 0.0), (4.0, 0.5)]);
+    assert_eq!(path.duration(), 4.5);
+
+    // held, then four doublings at two a second, then a pan while turning
+    assert_eq!(path.at(0.5).zoom, 1.0);
+    assert_eq!(path.at(1.0).zoom, 1.0);
+    assert!((path.at(2.0).zoom - 4.0).abs() < 1e-9);
+    assert_eq!(path.at(3.5).center, Complex { re: 0.5, im: 0.0 });
+    assert_eq!(path.at(3.5).rotation, 45f64.to_radians());
+    assert_eq!(path.at(4.25), path.waypoints[2].key);
+    assert_eq!(path.at(9.0), path.waypoints[2].key);
+    let frames = path.frames();
+    assert_eq!(frames.len(), 10);
+    assert_eq!((frames[0], frames[9]), (path.waypoints[0].key, path.waypoints[2].key));
+
+    let one = CameraPath::from_json(&json!({"waypoints": [{"center": "0,0", "zoom": 1}]}))
+        .unwrap();
+    assert_eq!((one.fps, one.frames().len(), one.frames()[0].limit), (DEFAULT_FPS, 1, None));
+    let ramped = CameraPath::from_json(&json!({"ramp": {"start": 50, "growth": 2},
+                                               "waypoints": [{"center": "0,0", "zoom": 4}]}))
+        .unwrap();
+    assert_eq!(ramped.frames()[0].limit, Some(200));
+
+    let point = |extra: Value| {
+        let mut second = json!({"center": "0,0", "zoom": 2});
+        second.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        json!({"waypoints": [{"center": "0,0", "zoom": 1, "hold": 1}, second]})
+    };
+    assert!(CameraPath::from_json(&point(json!({"time": 1.5}))).is_ok());
+    for bad in [json!([]),
+                json!({"waypoints": []}),
+                json!({"waypoints": [{"center": "0,0", "zoom": 1}], "speed": 1}),
+                json!({"fps": 0, "waypoints": [{"center": "0,0", "zoom": 1}]}),
+                json!({"ramp": {"start": 50}, "waypoints": [{"center": "0,0", "zoom": 1}]}),
+                json!({"ramp": {"start": 50, "growth": 0},
+                       "waypoints": [{"center": "0,0", "zoom": 1}]}),
+                json!({"waypoints": [{"center": "0,0", "zoom": 1, "time": 2}]}),
+                json!({"waypoints": [{"center": "0", "zoom": 1}]}),
+                json!({"waypoints": [{"center": "0,0", "zoom": 0}]}),
+                point(json!({})),
+                point(json!({"time": 0.5})),
+                point(json!({"time": 2, "after": 1})),
+                point(json!({"speed": 0})),
+                point(json!({"after": 1, "hold": -1})),
+                point(json!({"after": 1, "easing": "fast"})),
+                point(json!({"after": 1, "iterations": 0})),
+                point(json!({"after": 1, "colour": 1}))] {
+        assert!(matches!(CameraPath::from_json(&bad), Err(MandelError::InvalidParams(_))),
+                "{}", bad);
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A way to stop a render from another thread; clones share the same
+/// switch, so one can be kept while another is handed to the render
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Ask every render holding a clone of this token to stop
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// is_cancelled() : whether `cancel` has been called on any clone
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Tokens are equal when they're clones of each other
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &CancelToken) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[test]
+fn test_cancel_token() {
+    let token = CancelToken::new();
+    let held = token.clone();
+    assert_eq!(token, held);
+    assert_ne!(token, CancelToken::new());
+    assert!(!held.is_cancelled());
+    token.cancel();
+    assert!(held.is_cancelled());
+}
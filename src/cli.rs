@@ -0,0 +1,672 @@
+use analysis;
+use animate;
+use boundary;
+use buffer::{self, PixelBuffer};
+use cache;
+use color::Gray;
+use distance;
+use distributed;
+use error::MandelError;
+use filter;
+use fixed::{self, Fixed};
+use guess;
+use image::{ImageBuffer, Rgb, Rgba};
+use interrupt::{self, Checkpoint};
+use num::Complex;
+use output;
+use overlay;
+use pan;
+#[cfg(feature = "plugins")]
+use plugin;
+use precision::{self, Precision};
+use progressive;
+use ray;
+use renderer::Renderer;
+use stats;
+use std::io;
+use timing;
+use viewport::{Fit, Viewport};
+use {auto_iterations, format_complex, parse_complex, render_bands, render_streaming,
+     write_image};
+
+/// How far from square, as a fraction, the pixels of a view can be before
+/// it's pointed out that the image will look squashed
+const DISTORTION_TOLERANCE: f64 = 0.01;
+
+/// Results whose errors should say what was being done
+trait Context<T> {
+    /// context(what) : the value, or else the error, saying `what` went
+    /// wrong
+    fn context(self, what: &str) -> Result<T, MandelError>;
+}
+
+impl<T, E: Into<io::Error>> Context<T> for Result<T, E> {
+    fn context(self, what: &str) -> Result<T, MandelError> {
+        self.map_err(|e| {
+            let e = e.into();
+            MandelError::Io(io::Error::new(e.kind(), format!("{}: {}", what, e)))
+        })
+    }
+}
+
+/// The ways an image can be rendered, each picked by the flags that ask
+/// for it, in the order `Mode::pick` checks them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Formula,
+    Stats,
+    Field,
+    Analysis,
+    Overlay,
+    Sampled,
+    Workers,
+    Cache,
+    Reuse,
+    Guess,
+    Progressive,
+    Timing,
+    Plain,
+}
+
+/// The flags that pick a `Mode` or change how it renders: each flag, the
+/// mode it asks for and the other modes that take it into account, in the
+/// order `Mode::pick` checks them
+pub const MODE_FLAGS: [(&str, Mode, &[Mode]); 25] = {
+    use self::Mode::*;
+    [("--formula", Formula, &[]),
+     ("--stats", Stats, &[]),
+     ("--boundary", Stats, &[]),
+     ("--distance", Field, &[]),
+     ("--potential", Field, &[]),
+     ("--channels", Field, &[]),
+     ("--analysis", Analysis, &[]),
+     ("--orbit", Overlay, &[Analysis]),
+     ("--ray", Overlay, &[Analysis]),
+     ("--axes", Overlay, &[Analysis]),
+     ("--caption", Overlay, &[Analysis]),
+     ("--text", Overlay, &[Analysis]),
+     ("--watermark", Overlay, &[Analysis]),
+     ("--filter", Overlay, &[Analysis]),
+     ("--antialias", Sampled, &[Overlay]),
+     ("--jitter", Sampled, &[Overlay]),
+     ("--bailout", Sampled, &[Stats, Overlay]),
+     ("--workers", Workers, &[]),
+     ("--cache", Cache, &[]),
+     ("--reuse", Reuse, &[]),
+     ("--guess", Guess, &[]),
+     ("--progressive", Progressive, &[]),
+     ("--timing", Timing, &[]),
+     ("--heatmap", Timing, &[]),
+     ("--resume", Plain, &[])]
+};
+
+impl Mode {
+    /// given(a) : the flags of `MODE_FLAGS` among the arguments `args`
+    pub fn given(args: &[String]) -> Vec<&'static str> {
+        MODE_FLAGS.iter()
+            .map(|&(name, _, _)| name)
+            .filter(|name| args.iter().any(|arg| {
+                arg.strip_prefix(name).is_some_and(|rest| rest.is_empty() || rest.starts_with('='))
+            }))
+            .collect()
+    }
+
+    /// pick(g) : the mode asked for by the first of the flags `given` in
+    /// `MODE_FLAGS`
+    pub fn pick(given: &[&str]) -> Mode {
+        MODE_FLAGS.iter()
+            .find(|&&(name, _, _)| given.contains(&name))
+            .map_or(Mode::Plain, |&(_, mode, _)| mode)
+    }
+
+    /// How the mode renders, after the flags that ask for it
+    fn how(self) -> &'static str {
+        match self {
+            Mode::Formula => "with its plugin",
+            Mode::Stats | Mode::Field | Mode::Analysis => "a sample to a pixel",
+            Mode::Overlay => "in color",
+            Mode::Sampled => "here",
+            Mode::Workers => "on the workers",
+            Mode::Cache => "from the tile cache",
+            Mode::Reuse => "from the old image",
+            Mode::Guess | Mode::Progressive | Mode::Timing | Mode::Plain => "",
+        }
+    }
+
+    /// How the mode renders, for the note on flags it leaves out
+    pub fn describe(self) -> String {
+        let flags: Vec<&str> = MODE_FLAGS.iter()
+            .filter(|&&(_, mode, _)| mode == self && mode != Mode::Plain)
+            .map(|&(name, _, _)| name)
+            .collect();
+        let (flags, render) = match flags[..] {
+            [] => ("this view".to_string(), "renders"),
+            [flag] => (flag.to_string(), "renders"),
+            [ref rest @ .., last] => (format!("{} and {}", rest.join(", "), last), "render"),
+        };
+        format!("{} {} {}", flags, render, self.how()).trim_end().to_string()
+    }
+
+    /// ignored(g) : the flags of `given` this mode renders without
+    pub fn ignored<'a>(self, given: &[&'a str]) -> Vec<&'a str> {
+        given.iter().cloned()
+            .filter(|flag| {
+                !MODE_FLAGS.iter().any(|&(name, mode, others)| {
+                    name == *flag && (mode == self || others.contains(&self))
+                })
+            })
+            .collect()
+    }
+}
+
+
+/// The view of an image to render, from its PIXELS, TOP_LEFT and BOT_RIGHT
+pub struct View {
+    pub bounds: (usize, usize),
+    /// the corners as written, with as many digits as were given
+    pub corners: (String, String),
+    pub top_left: Complex<f64>,
+    pub bot_right: Complex<f64>,
+    /// the fixed-point corners of views too deep for f64
+    pub deep: Option<(Complex<Fixed>, Complex<Fixed>)>,
+    pub precision: Precision,
+    pub limit: u32,
+    /// the view as `pan::describe` writes it, to record with the image
+    pub description: String,
+}
+
+impl View {
+    /// new(b, tl, br, p, i, f) : the view of `bounds` pixels between the
+    /// corners `top_left` and `bot_right`, fitted as `fit` says, in the
+    /// `requested` precision or whichever can resolve it, to the
+    /// `iterations` asked for or 255
+    pub fn new(bounds: (usize, usize),
+               top_left: &str,
+               bot_right: &str,
+               requested: Precision,
+               iterations: Option<&str>,
+               fit: Option<Fit>)
+        -> Result<View, MandelError>
+    {
+        let error = |what: &str, e: MandelError| {
+            MandelError::Parse(format!("error parsing {}: {}", what, e))
+        };
+        let mut corners = (top_left.to_string(), bot_right.to_string());
+        let top_left = parse_complex(top_left).map_err(|e| error("TOP_LEFT", e))?;
+        let bot_right = parse_complex(bot_right).map_err(|e| error("BOT_RIGHT", e))?;
+
+        // corners with more digits than f64 holds are left as they're given
+        let given = Viewport::new(bounds, top_left, bot_right);
+        let shallow = given.check(None).is_ok()
+            && !matches!(precision::select(requested, bounds, &corners.0, &corners.1),
+                         Ok(Precision::Fixed(_)));
+        let distortion = given.distortion().max(1.0 / given.distortion());
+        let (top_left, bot_right) = match fit {
+            Some(fit) if fit != Fit::Stretch && shallow => {
+                let fitted = given.fitted(fit);
+                corners = (format_complex(fitted.top_left), format_complex(fitted.bot_right));
+                (fitted.top_left, fitted.bot_right)
+            }
+            Some(fit) if fit != Fit::Stretch => {
+                eprintln!("note: --fit leaves views too deep for f64 as they are");
+                (top_left, bot_right)
+            }
+            None if shallow && distortion - 1.0 > DISTORTION_TOLERANCE => {
+                let (long, short) = if given.distortion() > 1.0 { ("wide", "tall") }
+                                    else { ("tall", "wide") };
+                eprintln!("note: this view's pixels are {:.3} times as {} as they're {}, so \
+                           the image is squashed; --fit contain or cover keeps them square, \
+                           --fit stretch as they are", distortion, long, short);
+                (top_left, bot_right)
+            }
+            _ => (top_left, bot_right),
+        };
+
+        let limit = match iterations {
+            None => 255,
+            Some("auto") => auto_iterations(top_left, bot_right),
+            Some(n) => n.parse().map_err(|e| {
+                MandelError::Parse(format!("error parsing --iterations: {}", e))
+            })?,
+        };
+
+        // a view that can't be rendered is explained before anything else
+        let viewport = Viewport::new(bounds, top_left, bot_right);
+        let precision = precision::select(requested, bounds, &corners.0, &corners.1)
+            .or_else(|e| {
+                viewport.check(None)?;
+                Err(error("TOP_LEFT/BOT_RIGHT", e))
+            })?;
+        // past ~1e14 magnification f64 can't tell pixels apart, so switch to
+        // fixed-point corners parsed straight from the decimal arguments
+        let deep = match precision {
+            Precision::Fixed(bits) => {
+                let top_left = fixed::parse_complex(&corners.0, bits)
+                    .map_err(|e| error("TOP_LEFT", e))?;
+                let bot_right = fixed::parse_complex(&corners.1, bits)
+                    .map_err(|e| error("BOT_RIGHT", e))?;
+                Some((top_left, bot_right))
+            }
+            _ => None
+        };
+        viewport.check(deep.as_ref())?;
+        if precision != requested {
+            eprintln!("note: {} can't resolve this view, using {}", requested, precision);
+        }
+
+        let description = pan::describe(limit, precision, &corners.0, &corners.1);
+        Ok(View { bounds, corners, top_left, bot_right, deep, precision, limit, description })
+    }
+}
+
+/// What the flags of an image render ask for, beyond its view
+pub struct Flags {
+    pub mmap: bool,
+    pub antialias: usize,
+    pub jitter: usize,
+    pub seed: Option<u64>,
+    pub bailout: f64,
+    pub resume: bool,
+    pub threads: usize,
+    pub timing: Option<String>,
+    pub heatmap: Option<String>,
+    pub stats: Option<String>,
+    pub boundary: Option<String>,
+    pub distance: Option<String>,
+    pub potential: Option<String>,
+    pub channels: Option<String>,
+    pub analysis: Option<analysis::Analysis>,
+    pub orbits: Option<Vec<Complex<f64>>>,
+    pub rays: Option<Vec<ray::Angle>>,
+    pub axes: bool,
+    pub caption: Option<overlay::Placement>,
+    pub filters: Vec<filter::Filter>,
+    pub text: Option<String>,
+    pub watermark: Option<ImageBuffer<Rgba>>,
+    pub stamp: Option<overlay::Stamp>,
+    pub reuse: Option<String>,
+    pub formula: Option<String>,
+    pub workers: Option<Vec<String>>,
+    pub cache: Option<cache::TileCache>,
+}
+
+/// allocate(len, filename, mmap) : full pixel buffer for renderers that
+/// can't stream, mapped from a scratch file next to `filename` if asked
+/// to or if it wouldn't fit in RAM
+fn allocate(len: usize, filename: &str, mmap: bool) -> Result<PixelBuffer, MandelError> {
+    let too_big = buffer::available_memory().is_some_and(|free| len as u64 > free);
+    if too_big && !mmap {
+        eprintln!("note: {} pixels won't fit in the free memory, rendering into a file",
+                  len);
+    }
+    if mmap || too_big {
+        let scratch = format!("{}.pixels", filename);
+        PixelBuffer::mapped(len, scratch.as_ref())
+            .context(&format!("can't map a {}-byte scratch file {}", len, scratch))
+    } else {
+        Ok(PixelBuffer::in_memory(len))
+    }
+}
+
+/// Render `filename` with the formula of the plugin called `name` from
+/// `plugin::PLUGIN_DIR`, in gray
+#[cfg(feature = "plugins")]
+fn render_formula(name: &str, filename: &str, view: &View, threads: usize)
+    -> Result<(), MandelError>
+{
+    let mut names = Vec::new();
+    for plugin in plugin::discover(plugin::PLUGIN_DIR.as_ref()) {
+        match plugin {
+            Ok(ref plugin) if plugin.name() == name => {
+                let formula = plugin.formula().ok_or_else(|| {
+                    MandelError::InvalidParams(format!("plugin {} has no formula", name))
+                })?;
+                let escapes = formula.escapes(view.bounds, view.top_left, view.bot_right,
+                                              view.limit, threads);
+                let image: ImageBuffer<u8> = escapes.colorize(&Gray);
+                return write_image(filename, image.pixels(), view.bounds, None)
+                    .context("error writing PNG file");
+            }
+            Ok(plugin) => names.push(plugin.name().to_string()),
+            Err(e) => eprintln!("warning: {}", e),
+        }
+    }
+    Err(MandelError::InvalidParams(format!("no plugin called {} in {}/, found: {}",
+                                           name, plugin::PLUGIN_DIR, names.join(", "))))
+}
+
+#[cfg(not(feature = "plugins"))]
+fn render_formula(_: &str, _: &str, _: &View, _: usize) -> Result<(), MandelError> {
+    Err(MandelError::InvalidParams("this build can't load plugins, rebuild with --features \
+                                    plugins".to_string()))
+}
+
+/// resume(f, v, r) : the rows of `filename` already rendered of `view`,
+/// from the checkpoint an interrupted render left, if `resume` was asked
+/// for and they can be used
+fn resume(filename: &str, view: &View, resume: bool) -> Result<Vec<u8>, MandelError> {
+    let bounds = view.bounds;
+    let checkpoint = Checkpoint::load(filename).context("error reading checkpoint")?;
+    Ok(match checkpoint {
+        Some(ref checkpoint) if resume && checkpoint.view == view.description => {
+            let image = output::read_image(filename).context("error reading partial image")?;
+            if image.bounds != bounds {
+                eprintln!("note: {} is a different size, starting over", filename);
+                Vec::new()
+            } else if checkpoint.rows > bounds.1 {
+                eprintln!("note: checkpoint has {} rows of {}, starting over",
+                          checkpoint.rows, bounds.1);
+                Vec::new()
+            } else {
+                eprintln!("resuming from row {}", checkpoint.rows);
+                image.pixels[.. checkpoint.rows * bounds.0].to_vec()
+            }
+        }
+        Some(_) if resume => {
+            eprintln!("note: checkpoint is for a different view, starting over");
+            Vec::new()
+        }
+        _ => Vec::new(),
+    })
+}
+
+/// render(f, v, fl, g) : write the image `filename` of `view`, in the mode
+/// the `MODE_FLAGS` among `given` pick, with what `flags` ask for
+///
+/// A plain render stopped by `interrupt` saves a checkpoint to resume from
+/// and fails with `MandelError::Cancelled`.
+pub fn render(filename: &str, view: &View, flags: &Flags, given: &[&str])
+    -> Result<(), MandelError>
+{
+    let View { bounds, top_left, bot_right, ref deep, precision, limit, .. } = *view;
+    let (ref corner_tl, ref corner_br) = view.corners;
+    let description = &view.description;
+    let threads = flags.threads;
+    let len = bounds.0 * bounds.1;
+
+    // the caption is written from the fixed-point corners of deep views,
+    // whose centers and widths f64 can't hold
+    let caption = flags.caption.map(|placement| {
+        let (center, (width, height)) = match *deep {
+            Some((ref top_left, ref bot_right)) => {
+                let size = ((&bot_right.re - &top_left.re).to_f64(),
+                            (&top_left.im - &bot_right.im).to_f64());
+                let places = overlay::places(size.0 / bounds.0 as f64);
+                let middle = |a: &Fixed, b: &Fixed| (a + b).scale(1, 2).to_decimal(places);
+                ((middle(&top_left.re, &bot_right.re), middle(&top_left.im, &bot_right.im)), size)
+            }
+            None => {
+                let viewport = Viewport::new(bounds, top_left, bot_right);
+                let (center, size) = (viewport.center(), viewport.size());
+                let places = overlay::places(size.0 / bounds.0 as f64);
+                ((format!("{:.*}", places, center.re), format!("{:.*}", places, center.im)), size)
+            }
+        };
+        let zoom = animate::HOME_HEIGHT / height.abs();
+        (overlay::Caption { center, zoom, width }, placement)
+    });
+
+    // the filters asked for over a color render, then the gridlines,
+    // orbits, rays, caption, text and watermark, kept sharp on top
+    let finish = |image: &mut ImageBuffer<Rgb>| {
+        filter::apply(&flags.filters, image);
+        let viewport = Viewport::new(bounds, top_left, bot_right);
+        if flags.axes {
+            overlay::draw_axes(image, &viewport);
+        }
+        for &c in flags.orbits.iter().flatten() {
+            overlay::draw_orbit(image, &viewport, c, limit.min(overlay::ORBIT_STEPS),
+                                overlay::ORBIT_COLOR);
+        }
+        for &angle in flags.rays.iter().flatten() {
+            let points = ray::trace(angle, ray::DEPTH);
+            overlay::draw_path(image, &viewport, &points, overlay::RAY_COLOR);
+        }
+        if let Some((ref caption, placement)) = caption {
+            overlay::draw_caption(image, caption, placement);
+        }
+        if flags.text.is_some() || flags.watermark.is_some() {
+            overlay::draw_stamp(image, flags.text.as_deref(), flags.watermark.as_ref(),
+                                flags.stamp.unwrap_or_default());
+        }
+    };
+
+    // the tile cache and reused images hold f64 renders only
+    let mut given = given.to_vec();
+    if deep.is_some() {
+        for &name in &["--cache", "--reuse"] {
+            if given.contains(&name) {
+                eprintln!("note: {} only covers f32/f64 renders", name);
+                given.retain(|&flag| flag != name);
+            }
+        }
+    }
+    let mode = Mode::pick(&given);
+    if let [ref rest @ .., last] = mode.ignored(&given)[..] {
+        let flags = if rest.is_empty() { last.to_string() }
+                    else { format!("{} or {}", rest.join(", "), last) };
+        eprintln!("note: {}, without {}", mode.describe(), flags);
+    }
+
+    let written = match mode {
+        Mode::Formula => {
+            if let Precision::Fixed(_) = precision {
+                eprintln!("note: plugins render in f64, which can't resolve this view, so it \
+                           will be blocky");
+            }
+            return render_formula(flags.formula.as_ref().unwrap(), filename, view, threads);
+        }
+        Mode::Stats => {
+            let escapes = Renderer::new()
+                .bounds(bounds.0, bounds.1)
+                .corners(corner_tl, corner_br)
+                .iterations(limit)
+                .precision(precision)
+                .bailout(flags.bailout)
+                .threads(threads)
+                .escapes()?;
+            if let Some(ref filename) = flags.stats {
+                let counted = stats::Stats::of(&escapes);
+                eprintln!("{}", counted.summary());
+                counted.write_json(filename).context("error writing --stats")?;
+            }
+            if let Some(ref filename) = flags.boundary {
+                if let Precision::Fixed(_) = precision {
+                    eprintln!("note: --boundary writes points as f64, which can't tell this \
+                               view's pixels apart");
+                }
+                let view = Viewport::new(bounds, top_left, bot_right);
+                let points = boundary::points(&escapes, &view);
+                eprintln!("{} points on the boundary", points.len());
+                boundary::write(filename, &points)?;
+            }
+            write_image(filename, escapes.colorize::<u8, _>(&Gray).pixels(), bounds,
+                        Some(description))
+        }
+        Mode::Field => {
+            if precision != Precision::F64 {
+                eprintln!("note: --distance, --potential and --channels render in f64");
+            }
+            if let Some(ref filename) = flags.potential {
+                let potentials = distance::potentials(bounds, top_left, bot_right, limit,
+                                                      threads);
+                distance::write(filename, &potentials)?;
+            }
+            if let Some(ref filename) = flags.channels {
+                let image = distance::channels(bounds, top_left, bot_right, limit, threads);
+                distance::write_channels(filename, &distance::CHANNELS, &image)?;
+            }
+            // the image shows the distance field, whichever was asked for
+            let distances = distance::field(bounds, top_left, bot_right, limit, threads);
+            if let Some(ref filename) = flags.distance {
+                distance::write(filename, &distances)?;
+            }
+            let pixels: Vec<u8> = distances.pixels().iter().map(|&d| distance::gray(d)).collect();
+            write_image(filename, &pixels, bounds, Some(description))
+        }
+        Mode::Analysis => {
+            if precision != Precision::F64 {
+                eprintln!("note: --analysis renders in f64");
+            }
+            let mut image = analysis::render(flags.analysis.unwrap(), bounds, top_left,
+                                             bot_right, limit, threads);
+            finish(&mut image);
+            output::write_rgb(filename, &image)
+        }
+        Mode::Overlay => {
+            if matches!(precision, Precision::Fixed(_))
+                && (flags.orbits.is_some() || flags.rays.is_some() || flags.axes)
+            {
+                eprintln!("note: orbits, rays and gridlines are placed in f64, which can't \
+                           resolve this view, so they may be drawn in the wrong place");
+            }
+            Renderer::new()
+                .bounds(bounds.0, bounds.1)
+                .corners(corner_tl, corner_br)
+                .iterations(limit)
+                .precision(precision)
+                .antialias(flags.antialias)
+                .jitter(flags.jitter)
+                .seed(flags.seed.unwrap_or(0))
+                .bailout(flags.bailout)
+                .threads(threads)
+                .render_with::<Rgb, _>(&Gray)
+                .and_then(|mut image| {
+                    finish(&mut image);
+                    output::write_rgb(filename, &image)
+                })
+        }
+        Mode::Sampled => {
+            Renderer::new()
+                .bounds(bounds.0, bounds.1)
+                .corners(corner_tl, corner_br)
+                .iterations(limit)
+                .precision(precision)
+                .antialias(flags.antialias)
+                .jitter(flags.jitter)
+                .seed(flags.seed.unwrap_or(0))
+                .bailout(flags.bailout)
+                .threads(threads)
+                .write(filename)
+        }
+        Mode::Workers => {
+            let workers = flags.workers.as_ref().unwrap();
+            let mut pixels = allocate(len, filename, flags.mmap)?;
+            distributed::render(&mut pixels, bounds, corner_tl, corner_br,
+                                precision, limit, workers, threads)?;
+            write_image(filename, &pixels, bounds, Some(description))
+        }
+        Mode::Cache => {
+            let cache = flags.cache.as_ref().unwrap();
+            let mut pixels = allocate(len, filename, flags.mmap)?;
+            cache::render(&mut pixels, bounds, top_left, bot_right,
+                          limit, precision, cache, threads);
+            // the view was snapped to the tile grid, so don't record it
+            write_image(filename, &pixels, bounds, None)
+        }
+        Mode::Reuse => {
+            let reuse = flags.reuse.as_ref().unwrap();
+            let mut old = output::read_image(reuse).context("error reading --reuse image")?;
+            if Checkpoint::load(reuse).ok().flatten().is_some() {
+                eprintln!("note: {} is only partly rendered, not reusing it", reuse);
+                old.view = None;
+            }
+            let new = pan::View { bounds, limit, precision, top_left, bot_right };
+            let mut pixels = allocate(len, filename, flags.mmap)?;
+            match old.view.as_ref().and_then(|text| pan::View::parse(old.bounds, text)) {
+                Some(view) => {
+                    let reused = pan::render(&mut pixels, &new, &view, &old.pixels, threads);
+                    eprintln!("reused {} of {} pixels", reused, len);
+                }
+                None => {
+                    eprintln!("note: --reuse image doesn't record a view it can share");
+                    render_bands(&mut pixels, bounds, 0, top_left, bot_right,
+                                 None, precision, limit, threads);
+                }
+            }
+            write_image(filename, &pixels, bounds, Some(description))
+        }
+        Mode::Guess => {
+            let mut pixels = allocate(len, filename, flags.mmap)?;
+            let computed = guess::render(&mut pixels, bounds, top_left, bot_right,
+                                         deep.as_ref(), precision, limit, threads,
+                                         guess::BLOCK_SIZE);
+            eprintln!("computed {} of {} pixels", computed, len);
+            // guessed pixels aren't exact, so don't offer them for reuse
+            write_image(filename, &pixels, bounds, None)
+        }
+        Mode::Progressive => {
+            // rewrite the output after every pass as a growing preview
+            let mut pixels = allocate(len, filename, flags.mmap)?;
+            let mut written = Ok(());
+            progressive::render(&mut pixels, bounds, top_left, bot_right,
+                                deep.as_ref(), precision, limit, threads,
+                                |step, pixels| {
+                eprintln!("pass at 1/{} resolution done", step);
+                // only the last pass is complete enough to be reused
+                let view = if step == 1 { Some(description.as_str()) } else { None };
+                written = write_image(filename, pixels, bounds, view);
+                written.is_ok()
+            });
+            written
+        }
+        Mode::Timing => {
+            let mut pixels = allocate(len, filename, flags.mmap)?;
+            let times = timing::render(&mut pixels, bounds, top_left, bot_right,
+                                       deep.as_ref(), precision, limit, threads);
+            if let Some(ref filename) = flags.timing {
+                timing::write_json(filename, bounds, &times)
+                    .context("error writing timing report")?;
+            }
+            if let Some(ref filename) = flags.heatmap {
+                timing::write_heatmap(filename, bounds, &times)
+                    .context("error writing heatmap")?;
+            }
+            write_image(filename, &pixels, bounds, Some(description))
+        }
+        Mode::Plain => {
+            let done = resume(filename, view, flags.resume)?;
+            interrupt::install();
+            let rows = render_streaming(filename, bounds, top_left, bot_right,
+                                        deep.as_ref(), precision, limit, threads,
+                                        Some(description), &done)
+                .context("error writing PNG file")?;
+            if rows == bounds.1 {
+                return Checkpoint::remove(filename).context("error removing checkpoint");
+            }
+            Checkpoint { rows, view: description.clone() }.save(filename)
+                .context("error writing checkpoint")?;
+            eprintln!("interrupted: saved {} of {} rows, run again with --resume to finish",
+                      rows, bounds.1);
+            return Err(MandelError::Cancelled);
+        }
+    };
+    written.context("error writing PNG file")
+}
+
+#[test]
+fn test_mode() {
+    assert_eq!(Mode::pick(&[]), Mode::Plain);
+    assert_eq!(Mode::pick(&["--workers", "--orbit"]), Mode::Overlay);
+    assert_eq!(Mode::pick(&["--heatmap", "--stats", "--formula"]), Mode::Formula);
+
+    // each flag picks a mode that takes it
+    for &(name, mode, _) in &MODE_FLAGS {
+        assert_eq!(Mode::pick(&[name]), mode);
+        assert!(mode.ignored(&[name]).is_empty(), "{}", name);
+    }
+
+    assert_eq!(Mode::Stats.ignored(&["--stats", "--bailout", "--jitter", "--cache"]),
+               ["--jitter", "--cache"]);
+    assert_eq!(Mode::Overlay.ignored(&["--caption", "--antialias", "--workers"]), ["--workers"]);
+    assert_eq!(Mode::pick(&["--resume", "--guess"]), Mode::Guess);
+    assert_eq!(Mode::Guess.ignored(&["--resume", "--guess"]), ["--resume"]);
+
+    let args: Vec<String> = ["prog", "--cache=dir", "--axes", "--text", "hi", "--timings"]
+        .iter().map(|s| s.to_string()).collect();
+    assert_eq!(Mode::given(&args), ["--axes", "--text", "--cache"]);
+    assert_eq!(Mode::Stats.describe(), "--stats and --boundary render a sample to a pixel");
+    assert_eq!(Mode::Formula.describe(), "--formula renders with its plugin");
+    assert_eq!(Mode::Timing.describe(), "--timing and --heatmap render");
+    assert_eq!(Mode::Plain.describe(), "this view renders");
+}
@@ -0,0 +1,154 @@
+use image::{ImageBuffer, Rgb, Rgba};
+use {escaped, gray};
+
+/// How escape times are shown: each scheme maps a pixel's escape time to
+/// its color, a `P`, leaving the render itself alone
+pub trait Colorizer<P = u8>: Sync {
+    /// color(t, l) : color of a pixel that escaped after `t` of `l`
+    /// iterations, `None` if it never did
+    fn color(&self, time: Option<u32>, limit: u32) -> P;
+
+    /// Color `times`, as `escape_time_batch` records them, into `pixels`
+    fn colorize(&self, times: &[u32], limit: u32, pixels: &mut [P]) {
+        for (pixel, &time) in pixels.iter_mut().zip(times) {
+            *pixel = self.color(escaped(time), limit);
+        }
+    }
+}
+
+/// White for points that escape at once, fading to black for those that
+/// never do, at 8 or 16 bits, as a fraction of white or in color
+pub struct Gray;
+
+impl Colorizer for Gray {
+    fn color(&self, time: Option<u32>, limit: u32) -> u8 {
+        gray(time, limit)
+    }
+}
+
+impl Colorizer<u16> for Gray {
+    fn color(&self, time: Option<u32>, limit: u32) -> u16 {
+        match time {
+            None => 0,
+            Some(i) => 65535 - (i as u64 * 65535 / limit as u64) as u16,
+        }
+    }
+}
+
+impl Colorizer<f32> for Gray {
+    fn color(&self, time: Option<u32>, limit: u32) -> f32 {
+        match time {
+            None => 0.0,
+            Some(i) => 1.0 - (i as f64 / limit as f64) as f32,
+        }
+    }
+}
+
+impl Colorizer<Rgb> for Gray {
+    fn color(&self, time: Option<u32>, limit: u32) -> Rgb {
+        let shade = gray(time, limit);
+        Rgb([shade; 3])
+    }
+}
+
+impl Colorizer<Rgba> for Gray {
+    fn color(&self, time: Option<u32>, limit: u32) -> Rgba {
+        let shade = gray(time, limit);
+        Rgba([shade, shade, shade, 255])
+    }
+}
+
+/// A rainbow repeating every `period` iterations, started `phase` of the
+/// way round, with the interior black; stepping the phase from frame to
+/// frame cycles the colors
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cycle {
+    pub period: f64,
+    pub phase: f64,
+}
+
+/// The `Cycle` of `CYCLE_PERIOD` iterations at phase zero
+impl Default for Cycle {
+    fn default() -> Cycle {
+        Cycle { period: CYCLE_PERIOD, phase: 0.0 }
+    }
+}
+
+/// Iterations a `Cycle` goes once round its colors in by default
+pub const CYCLE_PERIOD: f64 = 64.0;
+
+impl Colorizer<Rgb> for Cycle {
+    fn color(&self, time: Option<u32>, _: u32) -> Rgb {
+        let time = match time {
+            None => return Rgb([0, 0, 0]),
+            Some(time) => time,
+        };
+        let turn = time as f64 / self.period + self.phase;
+        // each channel a cosine, a third of a turn behind the last
+        let channel = |offset: f64| {
+            let wave = (2.0 * ::std::f64::consts::PI * (turn + offset)).cos();
+            (127.5 + 127.5 * wave).round() as u8
+        };
+        Rgb([channel(0.0), channel(-1.0 / 3.0), channel(-2.0 / 3.0)])
+    }
+}
+
+/// The escape time of every pixel of an image before it's colored
+#[derive(Clone, Debug, PartialEq)]
+pub struct EscapeBuffer {
+    pub limit: u32,
+    /// as `escape_time_batch` records them, `NO_ESCAPE` for the interior
+    pub times: ImageBuffer<u32>,
+}
+
+impl EscapeBuffer {
+    pub fn bounds(&self) -> (usize, usize) {
+        self.times.bounds()
+    }
+
+    /// time(p) : when the point at pixel `p` escaped, if it did
+    pub fn time(&self, pixel: (usize, usize)) -> Option<u32> {
+        escaped(self.times[pixel])
+    }
+
+    /// The image as colored by `colorizer`
+    pub fn colorize<P, C>(&self, colorizer: &C) -> ImageBuffer<P>
+        where P: Clone + Default, C: Colorizer<P> + ?Sized
+    {
+        let mut image = ImageBuffer::new(self.bounds());
+        colorizer.colorize(self.times.pixels(), self.limit, image.pixels_mut());
+        image
+    }
+}
+
+#[test]
+fn test_colorize() {
+    /// every tenth iteration white, for telling bands of escape times apart
+    struct Bands;
+    impl Colorizer for Bands {
+        fn color(&self, time: Option<u32>, _: u32) -> u8 {
+            match time {
+                Some(t) if t % 10 == 0 => 255,
+                _ => 0,
+            }
+        }
+    }
+
+    let times = ImageBuffer::from_pixels((2, 2), vec![0, 10, 50, ::NO_ESCAPE]);
+    let escapes = EscapeBuffer { limit: 100, times };
+    assert_eq!(escapes.time((1, 1)), None);
+    assert_eq!(escapes.time((0, 1)), Some(50));
+    assert_eq!(escapes.colorize::<u8, _>(&Gray).pixels(), [255, 230, 128, 0]);
+    assert_eq!(escapes.colorize::<u16, _>(&Gray).pixels(), [65535, 58982, 32768, 0]);
+    assert_eq!(escapes.colorize::<f32, _>(&Gray).pixels(), [1.0, 0.9, 0.5, 0.0]);
+    assert_eq!(escapes.colorize::<Rgba, _>(&Gray)[(0, 1)], Rgba([128, 128, 128, 255]));
+    assert_eq!(escapes.colorize(&Bands).pixels(), [255, 255, 255, 0]);
+
+    let cycle = Cycle::default();
+    assert_eq!(cycle.color(Some(0), 100), Rgb([255, 64, 64]));
+    assert_eq!(cycle.color(Some(64), 100), cycle.color(Some(0), 100));
+    assert_eq!(cycle.color(None, 100), Rgb([0, 0, 0]));
+    // a third of a turn on, each channel takes the place of the one before
+    let turned = Cycle { phase: 1.0 / 3.0, ..cycle };
+    assert_eq!(turned.color(Some(0), 100), Rgb([64, 255, 64]));
+}
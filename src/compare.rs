@@ -0,0 +1,167 @@
+use distance;
+use error::MandelError;
+use image::{ImageBuffer, Rgb};
+use output;
+use std::fs;
+use std::path::Path;
+
+/// The values of each pixel of a render, however it was written
+#[derive(Clone, Debug, PartialEq)]
+pub struct Raster {
+    pub bounds: (usize, usize),
+    /// values to a pixel, 1 for a gray image or a field
+    pub channels: usize,
+    /// the values of each pixel in turn, row by row
+    pub values: Vec<f64>,
+}
+
+/// load(f) : the render in `filename`, a grayscale PNG as a render writes
+/// or a `.npy` dump of floats as `--distance` or `--channels` writes
+pub fn load(filename: &str) -> Result<Raster, MandelError> {
+    let extension = Path::new(filename).extension().and_then(|e| e.to_str());
+    if extension.map(|e| e.to_ascii_lowercase()).as_deref() == Some("npy") {
+        let (shape, values) = distance::from_npy(&fs::read(filename)?)?;
+        let (bounds, channels) = match shape[..] {
+            [height, width] => ((width, height), 1),
+            [height, width, channels] => ((width, height), channels),
+            _ => return Err(MandelError::InvalidParams(format!(
+                "{} isn't an image, its shape is {:?}", filename, shape))),
+        };
+        let values = values.into_iter().map(|value| value as f64).collect();
+        return Ok(Raster { bounds, channels, values });
+    }
+    let image = output::read_image(filename)?;
+    let values = image.pixels.into_iter().map(|value| value as f64).collect();
+    Ok(Raster { bounds: image.bounds, channels: 1, values })
+}
+
+/// How two renders of the same size differ
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comparison {
+    pub bounds: (usize, usize),
+    /// the most any value of each pixel differs by, row by row
+    pub differences: Vec<f64>,
+}
+
+/// difference(a, b) : how far apart `a` and `b` are, 0 if both are NaN
+/// and infinitely far if only one is
+fn difference(a: f64, b: f64) -> f64 {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => 0.0,
+        (false, false) => (a - b).abs(),
+        _ => f64::INFINITY,
+    }
+}
+
+/// compare(a, b) : how `b` differs from `a` pixel by pixel, which have to
+/// be the same size with as many values to a pixel
+pub fn compare(a: &Raster, b: &Raster) -> Result<Comparison, MandelError> {
+    if (a.bounds, a.channels) != (b.bounds, b.channels) {
+        return Err(MandelError::InvalidParams(format!(
+            "can't compare a {}x{} render of {} values to a pixel with a {}x{} one of {}",
+            a.bounds.0, a.bounds.1, a.channels, b.bounds.0, b.bounds.1, b.channels)));
+    }
+    let differences = a.values.chunks(a.channels.max(1))
+        .zip(b.values.chunks(b.channels.max(1)))
+        .map(|(a, b)| a.iter().zip(b).map(|(&a, &b)| difference(a, b)).fold(0.0, f64::max))
+        .collect();
+    Ok(Comparison { bounds: a.bounds, differences })
+}
+
+impl Comparison {
+    /// differing() : each pixel that differs and how much by, row by row
+    pub fn differing(&self) -> impl Iterator<Item = ((usize, usize), f64)> + '_ {
+        let width = self.bounds.0;
+        self.differences.iter().enumerate()
+            .filter(|&(_, &difference)| difference > 0.0)
+            .map(move |(index, &difference)| ((index % width, index / width), difference))
+    }
+
+    /// most() : the most any pixel differs by, 0 if they're the same
+    pub fn most(&self) -> f64 {
+        self.differences.iter().cloned().fold(0.0, f64::max)
+    }
+
+    /// mean() : how much pixels differ by on average
+    pub fn mean(&self) -> f64 {
+        if self.differences.is_empty() {
+            return 0.0;
+        }
+        self.differences.iter().sum::<f64>() / self.differences.len() as f64
+    }
+
+    /// summary() : how many pixels differ and how much by, in a line
+    pub fn summary(&self) -> String {
+        let count = self.differing().count();
+        if count == 0 {
+            return format!("all {} pixels are the same", self.differences.len());
+        }
+        format!("{} of {} pixels differ ({:.3}%), by at most {} and {:.3} on average",
+                count, self.differences.len(),
+                100.0 * count as f64 / self.differences.len() as f64, self.most(), self.mean())
+    }
+
+    /// heatmap() : an image of the differences, black where pixels are the
+    /// same and from dark red through red to yellow for the most, so that
+    /// however little a pixel differs it stands out
+    pub fn heatmap(&self) -> ImageBuffer<Rgb> {
+        let most = self.most();
+        let pixels = self.differences.iter().map(|&difference| {
+            if difference == 0.0 {
+                return Rgb([0, 0, 0]);
+            }
+            let t = if most.is_finite() { difference / most } else { 1.0 };
+            Rgb([(128.0 + 254.0 * t).min(255.0) as u8, (255.0 * (2.0 * t - 1.0)).max(0.0) as u8, 0])
+        });
+        ImageBuffer::from_pixels(self.bounds, pixels.collect())
+    }
+}
+
+#[test]
+fn test_compare() {
+    let gray = |values: Vec<f64>| Raster { bounds: (3, 2), channels: 1, values };
+    let a = gray(vec![0.0, 10.0, 20.0, 30.0, 40.0, 50.0]);
+    let same = compare(&a, &a).unwrap();
+    assert_eq!((same.differing().count(), same.most(), same.mean()), (0, 0.0, 0.0));
+    assert_eq!(same.summary(), "all 6 pixels are the same");
+    assert!(same.heatmap().pixels().iter().all(|&pixel| pixel == Rgb([0, 0, 0])));
+
+    let b = gray(vec![0.0, 12.0, 20.0, 30.0, 40.0, 46.0]);
+    let changed = compare(&a, &b).unwrap();
+    assert_eq!(changed.differing().collect::<Vec<_>>(), [((1, 0), 2.0), ((2, 1), 4.0)]);
+    assert_eq!(changed.summary(),
+               "2 of 6 pixels differ (33.333%), by at most 4 and 1.000 on average");
+    let heatmap = changed.heatmap();
+    assert_eq!((heatmap[(0, 0)], heatmap[(1, 0)], heatmap[(2, 1)]),
+               (Rgb([0, 0, 0]), Rgb([255, 0, 0]), Rgb([255, 255, 0])));
+
+    // channels count apart by the one that differs most, and NaNs only
+    // when one side has them
+    let pairs = |values: Vec<f64>| Raster { bounds: (2, 1), channels: 2, values };
+    let nan = f64::NAN;
+    let compared = compare(&pairs(vec![1.0, 2.0, nan, 0.0]), &pairs(vec![1.5, 1.0, nan, nan]));
+    assert_eq!(compared.unwrap().differences, [1.0, f64::INFINITY]);
+    assert!(matches!(compare(&a, &pairs(vec![0.0; 4])), Err(MandelError::InvalidParams(_))));
+}
+
+#[test]
+fn test_load() {
+    let dir = std::env::temp_dir();
+    let path = |name: &str| {
+        dir.join(format!("mandelbrot-compare-test-{}-{}", std::process::id(), name))
+            .to_str().unwrap().to_string()
+    };
+
+    let (png, npy) = (path("a.png"), path("b.npy"));
+    ::write_image(&png, &[0, 64, 128, 255], (2, 2), None).unwrap();
+    let field = ImageBuffer::from_pixels((2, 2), vec![0.0, 64.0, 128.0, 255.5]);
+    distance::write(&npy, &field).unwrap();
+
+    let (a, b) = (load(&png).unwrap(), load(&npy).unwrap());
+    assert_eq!(a, Raster { bounds: (2, 2), channels: 1, values: vec![0.0, 64.0, 128.0, 255.0] });
+    assert_eq!(compare(&a, &b).unwrap().differing().collect::<Vec<_>>(), [((1, 1), 0.5)]);
+    assert!(load(&path("missing.png")).is_err());
+
+    fs::remove_file(&png).unwrap();
+    fs::remove_file(&npy).unwrap();
+}
@@ -0,0 +1,483 @@
+use num::traits::float::FloatCore;
+use num::Complex;
+
+/// A point whose orbit shrinks perturbations below this is in the set
+pub const INTERIOR_EPSILON: f64 = 1e-12;
+
+/// Radius past which an orbit has escaped, the least that's sure to: once
+/// `|z| > 2` it goes off to infinity
+pub const BAILOUT: f64 = 2.0;
+
+/// Escape time recorded for points that never escaped
+pub const NO_ESCAPE: u32 = u32::MAX;
+
+/// Points iterated side by side by `escape_time_batch`
+const LANES: usize = 8;
+
+/// Rounds of a step of every lane between asking whether the iterating
+/// has been cancelled
+const CANCEL_ROUNDS: u32 = 1024;
+
+/// The arithmetic the kernel needs from a number type, so that one
+/// implementation iterates `f32`, `f64`, `fixed::Fixed` or any other type
+/// implementing it
+///
+/// Constants are made `like` a value already at hand, for types such as
+/// `Fixed` that carry their precision with them. For floats each operation
+/// rounds on its own, never fused into a multiply-add, so escape times come
+/// out the same on every machine.
+pub trait Real: Clone + PartialOrd {
+    /// like(x) : `x` in the same type and precision as `self`
+    fn like(&self, x: f64) -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn sub(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+    /// scale(n, d) : `self * n / d`
+    ///
+    /// Taking `u64`s, pixel arithmetic such as `2 * x + 1` can be done
+    /// without overflowing even where `usize` is 32 bits.
+    fn scale(&self, num: u64, den: u64) -> Self;
+}
+
+impl<T: FloatCore> Real for T {
+    fn like(&self, x: f64) -> T {
+        T::from(x).unwrap()
+    }
+
+    fn add(&self, other: &T) -> T {
+        *self + *other
+    }
+
+    fn sub(&self, other: &T) -> T {
+        *self - *other
+    }
+
+    fn mul(&self, other: &T) -> T {
+        *self * *other
+    }
+
+    fn scale(&self, num: u64, den: u64) -> T {
+        T::from(num).unwrap() * *self / T::from(den).unwrap()
+    }
+}
+
+/// step(z, c) : one iteration, `z^2 + c`
+pub fn step<T: Real>(z: &Complex<T>, c: &Complex<T>) -> Complex<T> {
+    let reim = z.re.mul(&z.im);
+    Complex {
+        re: z.re.mul(&z.re).sub(&z.im.mul(&z.im)).add(&c.re),
+        im: reim.add(&reim).add(&c.im),
+    }
+}
+
+fn norm_sqr<T: Real>(z: &Complex<T>) -> T {
+    z.re.mul(&z.re).add(&z.im.mul(&z.im))
+}
+
+/// escaped(t) : a time from `escape_time_batch`, `None` for `NO_ESCAPE`
+pub fn escaped(time: u32) -> Option<u32> {
+    match time {
+        NO_ESCAPE => None,
+        t => Some(t),
+    }
+}
+
+/// An orbit in flight in `escape_time_batch`
+#[derive(Clone)]
+struct Lane<T> {
+    index: usize,
+    c: Complex<T>,
+    z: Complex<T>,
+    der: Complex<T>,
+    i: u32,
+}
+
+/// escape_time_batch(cs, l, ts) : check which of `cs` are in Mandelbrot
+/// with up to `l` iterations
+///
+/// Stores in `ts`:
+///     `i` if the point left within `i` iterations, `i` < `l`
+///     `NO_ESCAPE` otherwise
+///
+/// Alongside `z` each orbit tracks `der = dz_n/dz_1`, which tends to zero
+/// when the orbit is drawn into an attracting cycle, so most interior
+/// points are recognised long before `l` iterations.
+///
+/// Up to `LANES` orbits are stepped in turn, each lane taking the next
+/// point as soon as its own is decided, so the independent iterations can
+/// overlap in the pipeline instead of waiting on each other.
+pub fn escape_time_batch<T: Real>(points: &[Complex<T>], limit: u32, times: &mut [u32]) {
+    iterate_batch(points, None, limit, BAILOUT, &|| false, times)
+}
+
+/// Iterate `z = z^2 + c` for each of `points`, as `c` starting from `z = 0`
+/// or, given `julia`, as `z` with that `c`, until `|z|` passes `bailout`
+///
+/// With `z_0 = 0` for the Mandelbrot set the first step doesn't depend on
+/// `z`, so `der` only starts counting from `z_1`. A `bailout` past
+/// `BAILOUT` makes points take a little longer to escape, but leaves
+/// alone which do.
+///
+/// Once `cancelled` returns true it stops within `CANCEL_ROUNDS` rounds,
+/// leaving the times of points not yet decided as they were. It's a
+/// closure rather than a `CancelToken`, so the kernel needs nothing from
+/// `std`.
+pub fn iterate_batch<T: Real>(points: &[Complex<T>],
+                              julia: Option<Complex<T>>,
+                              limit: u32,
+                              bailout: f64,
+                              cancelled: &dyn Fn() -> bool,
+                              times: &mut [u32])
+{
+    assert!(points.len() == times.len());
+    let first = match points.first() {
+        Some(first) => &first.re,
+        None => return,
+    };
+
+    let zero = Complex { re: first.like(0.0), im: first.like(0.0) };
+    let one = Complex { re: first.like(1.0), im: first.like(0.0) };
+    let escape = first.like(bailout * bailout);
+    let interior = first.like(INTERIOR_EPSILON * INTERIOR_EPSILON);
+
+    let mut lanes: [Option<Lane<T>>; LANES] = Default::default();
+    let mut next = 0;
+    let mut until_check = CANCEL_ROUNDS;
+    loop {
+        until_check -= 1;
+        if until_check == 0 {
+            if cancelled() {
+                return;
+            }
+            until_check = CANCEL_ROUNDS;
+        }
+        let mut busy = false;
+        for slot in lanes.iter_mut() {
+            if slot.is_none() && next < points.len() {
+                let (z, c) = match julia {
+                    None => (zero.clone(), points[next].clone()),
+                    Some(ref c) => (points[next].clone(), c.clone()),
+                };
+                *slot = Some(Lane { index: next, c, z, der: one.clone(), i: 0 });
+                next += 1;
+            }
+            let lane = match *slot {
+                Some(ref mut lane) => lane,
+                None => continue,
+            };
+            busy = true;
+
+            let time = if lane.i >= limit {
+                Some(NO_ESCAPE)
+            } else {
+                if lane.i > 0 || julia.is_some() {
+                    // der * z * 2, doubled by adding so it's exact
+                    let der = Complex {
+                        re: lane.der.re.mul(&lane.z.re).sub(&lane.der.im.mul(&lane.z.im)),
+                        im: lane.der.re.mul(&lane.z.im).add(&lane.der.im.mul(&lane.z.re)),
+                    };
+                    lane.der = Complex { re: der.re.add(&der.re), im: der.im.add(&der.im) };
+                }
+                lane.z = step(&lane.z, &lane.c);
+                if norm_sqr(&lane.z) > escape {
+                    Some(lane.i)
+                } else if norm_sqr(&lane.der) < interior {
+                    Some(NO_ESCAPE)
+                } else {
+                    lane.i += 1;
+                    None
+                }
+            };
+            if let Some(time) = time {
+                times[lane.index] = time;
+                *slot = None;
+            }
+        }
+        if !busy {
+            return;
+        }
+    }
+}
+
+/// escape_time(c, l) : `escape_time_batch` of the single point `c`
+pub fn escape_time<T: Real>(c: Complex<T>, limit: u32) -> Option<u32> {
+    let mut time = [NO_ESCAPE];
+    escape_time_batch(&[c], limit, &mut time);
+    escaped(time[0])
+}
+
+/// The points a `c` visits under `z = z^2 + c`, from `orbit_iter`
+#[derive(Clone, Debug)]
+pub struct Orbit<T> {
+    c: Complex<T>,
+    /// the next point, `None` once the orbit is over
+    z: Option<Complex<T>>,
+    steps_left: u32,
+}
+
+/// orbit_iter(c, l) : `Z_0 = 0, Z_1 .. Z_n` of `c`, stopping after `l`
+/// iterations or once `Z_n` escapes
+pub fn orbit_iter<T: Real>(c: Complex<T>, limit: u32) -> Orbit<T> {
+    let zero = Complex { re: c.re.like(0.0), im: c.re.like(0.0) };
+    Orbit { c, z: Some(zero), steps_left: limit }
+}
+
+impl<T: Real> Iterator for Orbit<T> {
+    type Item = Complex<T>;
+
+    fn next(&mut self) -> Option<Complex<T>> {
+        let z = self.z.take()?;
+        if self.steps_left > 0 && norm_sqr(&z) <= z.re.like(4.0) {
+            self.steps_left -= 1;
+            self.z = Some(step(&z, &self.c));
+        }
+        Some(z)
+    }
+}
+
+/// pixel_to_point(b, p, tl, br) : the point at the top left of pixel `p`
+/// of the `bounds` image of `top_left` to `bot_right`
+pub fn pixel_to_point<T: Real>(bounds: (usize, usize),
+                               pixel: (usize, usize),
+                               top_left: Complex<T>,
+                               bot_right: Complex<T>)
+    -> Complex<T>
+{
+    let tl = top_left;
+    let br = bot_right;
+    let (width, height) = (br.re.sub(&tl.re), tl.im.sub(&br.im));
+
+    Complex {
+        re: tl.re.add(&width.scale(pixel.0 as u64, bounds.0 as u64)),
+        im: tl.im.sub(&height.scale(pixel.1 as u64, bounds.1 as u64)),
+    }
+}
+
+/// pixel_center(b, p, tl, br) : the point at the center of pixel `p` of the
+/// `bounds` image of `top_left` to `bot_right`, where the pixel is sampled
+///
+/// Sampling centers rather than the corners `pixel_to_point` gives keeps
+/// the image from sitting half a pixel up and left of the view, so a view
+/// symmetric about the real axis renders symmetric.
+pub fn pixel_center<T: Real>(bounds: (usize, usize),
+                             pixel: (usize, usize),
+                             top_left: Complex<T>,
+                             bot_right: Complex<T>)
+    -> Complex<T>
+{
+    let tl = top_left;
+    let br = bot_right;
+    let (width, height) = (br.re.sub(&tl.re), tl.im.sub(&br.im));
+
+    Complex {
+        re: tl.re.add(&width.scale(2 * pixel.0 as u64 + 1, 2 * bounds.0 as u64)),
+        im: tl.im.sub(&height.scale(2 * pixel.1 as u64 + 1, 2 * bounds.1 as u64)),
+    }
+}
+
+/// The map from the pixels of an image to points of the plane, for views
+/// turned about their center as well as those lined up with the axes
+#[derive(Clone, Debug, PartialEq)]
+pub struct Affine<T> {
+    /// the point at the top left of pixel `(0, 0)`
+    pub origin: Complex<T>,
+    /// the steps one pixel right and one pixel down take in the plane
+    pub across: Complex<T>,
+    pub down: Complex<T>,
+}
+
+impl<T: Real> Affine<T> {
+    /// The `bounds` image of `top_left` to `bot_right` turned anticlockwise
+    /// about its center by the angle whose cosine and sine are `turn`,
+    /// worked out by the caller since `no_std` has no trigonometry
+    pub fn new(bounds: (usize, usize),
+               top_left: Complex<T>,
+               bot_right: Complex<T>,
+               turn: (f64, f64))
+        -> Affine<T>
+    {
+        let (tl, br) = (top_left, bot_right);
+        let zero = tl.re.like(0.0);
+        let (cos, sin) = (tl.re.like(turn.0), tl.re.like(turn.1));
+        let (width, height) = (br.re.sub(&tl.re), tl.im.sub(&br.im));
+        let (dx, dy) = (width.scale(1, bounds.0 as u64), height.scale(1, bounds.1 as u64));
+
+        // the top left corner, turned about the center
+        let (half_w, half_h) = (width.scale(1, 2), height.scale(1, 2));
+        let center = Complex { re: tl.re.add(&half_w), im: tl.im.sub(&half_h) };
+        let origin = Complex {
+            re: center.re.sub(&half_w.mul(&cos)).sub(&half_h.mul(&sin)),
+            im: center.im.sub(&half_w.mul(&sin)).add(&half_h.mul(&cos)),
+        };
+        Affine {
+            origin,
+            across: Complex { re: dx.mul(&cos), im: dx.mul(&sin) },
+            down: Complex { re: dy.mul(&sin), im: zero.sub(&dy.mul(&cos)) },
+        }
+    }
+
+    /// point(p) : the point at the top left of pixel `p`
+    pub fn point(&self, pixel: (usize, usize)) -> Complex<T> {
+        let (x, y) = (pixel.0 as u64, pixel.1 as u64);
+        Complex {
+            re: self.origin.re.add(&self.across.re.scale(x, 1)).add(&self.down.re.scale(y, 1)),
+            im: self.origin.im.add(&self.across.im.scale(x, 1)).add(&self.down.im.scale(y, 1)),
+        }
+    }
+
+    /// center(p) : the point at the center of pixel `p`, where it's sampled
+    pub fn center(&self, pixel: (usize, usize)) -> Complex<T> {
+        let (x, y) = (2 * pixel.0 as u64 + 1, 2 * pixel.1 as u64 + 1);
+        Complex {
+            re: self.origin.re.add(&self.across.re.scale(x, 2)).add(&self.down.re.scale(y, 2)),
+            im: self.origin.im.add(&self.across.im.scale(x, 2)).add(&self.down.im.scale(y, 2)),
+        }
+    }
+
+    /// at(x, y) : the point `x` pixels right and `y` down from the top left
+    /// of the image, fractions of a pixel included
+    pub fn at(&self, x: f64, y: f64) -> Complex<T> {
+        let (x, y) = (self.origin.re.like(x), self.origin.re.like(y));
+        Complex {
+            re: self.origin.re.add(&self.across.re.mul(&x)).add(&self.down.re.mul(&y)),
+            im: self.origin.im.add(&self.across.im.mul(&x)).add(&self.down.im.mul(&y)),
+        }
+    }
+}
+
+/// gray(t, l) : shade for escape time `t` out of `l`, black for no escape
+pub fn gray(time: Option<u32>, limit: u32) -> u8 {
+    match time {
+        None => 0,
+        Some(i) => 255 - (i as u64 * 255 / limit as u64) as u8
+    }
+}
+
+#[test]
+fn test_escape_time_f32() {
+    for &(re, im) in &[(0.3, 0.5), (-0.75, 0.1), (-1.0, 0.0), (0.26, 0.0)] {
+        assert_eq!(escape_time(Complex { re: re as f32, im: im as f32 }, 255),
+                   escape_time(Complex { re, im }, 255));
+    }
+}
+
+#[test]
+fn test_escape_time_batch() {
+    // the textbook loop, one point at a time with no interior check
+    fn scalar(c: Complex<f64>, limit: u32) -> Option<u32> {
+        let mut z = Complex { re: 0.0, im: 0.0 };
+        for i in 0 .. limit {
+            z = z * z + c;
+            if z.norm_sqr() > BAILOUT * BAILOUT {
+                return Some(i);
+            }
+        }
+        None
+    }
+
+    // a count of points that doesn't fill the last round of lanes
+    let points: Vec<_> = (0 .. 41)
+        .flat_map(|i| (0 .. 29).map(move |j| {
+            Complex { re: -2.0 + i as f64 * 0.065, im: -1.2 + j as f64 * 0.085 }
+        }))
+        .collect();
+    for &limit in &[1, 20, 500] {
+        let mut times = vec![0; points.len()];
+        escape_time_batch(&points, limit, &mut times);
+        for (&pt, &time) in points.iter().zip(&times) {
+            assert_eq!(escaped(time), scalar(pt, limit), "{} at {}", pt, limit);
+        }
+        assert!(times.contains(&NO_ESCAPE));
+    }
+
+    escape_time_batch::<f64>(&[], 500, &mut []);
+}
+
+#[test]
+fn test_iterate_batch_cancel() {
+    // 1/4 is on the boundary, so neither escapes nor is caught as
+    // interior: without being cancelled this would run for ~4 billion steps
+    let points = vec![Complex { re: 0.25, im: 0.0 }; 3];
+    let mut times = [7; 3];
+    iterate_batch(&points, None, u32::MAX, BAILOUT, &|| true, &mut times);
+    assert_eq!(times, [7; 3]);
+}
+
+#[test]
+fn test_bailout() {
+    let points = [Complex { re: 0.3, im: 0.5 }, Complex { re: -0.75, im: 0.1 },
+                  Complex { re: -1.0, im: 0.0 }, Complex { re: 1.0, im: 1.0 }];
+    let mut near = [0; 4];
+    let mut far = [0; 4];
+    iterate_batch(&points, None, 500, BAILOUT, &|| false, &mut near);
+    iterate_batch(&points, None, 500, 1e6, &|| false, &mut far);
+    for (&near, &far) in near.iter().zip(&far) {
+        assert_eq!(near == NO_ESCAPE, far == NO_ESCAPE);
+        assert!(far >= near);
+    }
+    // |z_1| = |1 + i| is past 2 but not 1e6, which takes four more steps
+    assert_eq!((near[3], far[3]), (1, 5));
+}
+
+#[test]
+fn test_escape_time_interior() {
+    // without interior detection these would run for ~4 billion iterations
+    assert_eq!(escape_time(Complex { re: -0.1, im: 0.1 }, u32::MAX), None);
+    assert_eq!(escape_time(Complex { re: -1.1, im: 0.05 }, u32::MAX), None);
+    assert_eq!(escape_time(Complex { re: -0.12f32, im: 0.75 }, u32::MAX), None);
+    assert_eq!(escape_time(Complex { re: 0.26, im: 0.0 }, u32::MAX), Some(29));
+}
+
+#[test]
+fn test_orbit_iter() {
+    let orbit: [Complex<f64>; 4] = [Complex { re: 0.0, im: 0.0 }, Complex { re: -1.0, im: 0.0 },
+                                    Complex { re: 0.0, im: 0.0 }, Complex { re: -1.0, im: 0.0 }];
+    assert!(orbit_iter(Complex { re: -1.0, im: 0.0 }, 3).eq(orbit.iter().cloned()));
+    assert_eq!(orbit_iter(Complex { re: 1.0f32, im: 0.0 }, 255).count(), 4);
+    assert_eq!(orbit_iter(Complex { re: 0.26, im: 0.0 }, 1000).count() as u32 - 1,
+               escape_time(Complex { re: 0.26, im: 0.0 }, 1000).unwrap() + 1);
+}
+
+#[test]
+fn test_gray() {
+    assert_eq!(gray(None, 255), 0);
+    assert_eq!(gray(Some(0), 255), 255);
+    assert_eq!(gray(Some(10), 255), 245);
+    assert_eq!(gray(Some(500), 1000), 128);
+}
+
+#[test]
+fn test_pixel_to_point() {
+    assert_eq!(pixel_to_point((100,100), (25,75),
+                              Complex { re: -1.0, im:  1.0 },
+                              Complex { re:  1.0, im: -1.0 }),
+               Complex { re: -0.5, im: -0.5 });
+
+    // centers sit half a pixel in from the corners, so the first and last
+    // pixels of a row are as far in from each edge
+    let (tl, br) = (Complex { re: -2.0, im: 1.0 }, Complex { re: 2.0, im: -1.0 });
+    assert_eq!(pixel_center((4, 2), (0, 0), tl, br), Complex { re: -1.5, im: 0.5 });
+    assert_eq!(pixel_center((4, 2), (3, 1), tl, br), Complex { re: 1.5, im: -0.5 });
+    assert_eq!(pixel_center((4, 2), (1, 0), tl, br), pixel_to_point((8, 4), (3, 1), tl, br));
+}
+
+#[test]
+fn test_affine() {
+    let (top_left, bot_right) = (Complex { re: -2.0, im: 1.0 }, Complex { re: 2.0, im: -1.0 });
+    let level = Affine::new((4, 2), top_left, bot_right, (1.0, 0.0));
+    for &pixel in &[(0, 0), (1, 1), (3, 0), (4, 2)] {
+        assert_eq!(level.point(pixel), pixel_to_point((4, 2), pixel, top_left, bot_right));
+    }
+
+    // a quarter turn anticlockwise takes the top left corner to the
+    // bottom left, and right across the image to up the plane
+    let turned = Affine::new((4, 2), top_left, bot_right, (0.0, 1.0));
+    assert_eq!(turned.origin, Complex { re: -1.0, im: -2.0 });
+    assert_eq!(turned.point((4, 0)), Complex { re: -1.0, im: 2.0 });
+    assert_eq!(turned.point((4, 2)), Complex { re: 1.0, im: 2.0 });
+    assert_eq!(turned.point((2, 1)), Complex { re: 0.0, im: 0.0 });
+    assert_eq!(turned.at(2.0, 1.0), turned.point((2, 1)));
+    assert_eq!(turned.center((1, 0)), turned.at(1.5, 0.5));
+    assert_eq!(level.center((3, 1)), pixel_center((4, 2), (3, 1), top_left, bot_right));
+    assert_eq!(level.at(0.5, 1.5), Complex { re: -1.5, im: -0.5 });
+}
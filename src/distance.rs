@@ -0,0 +1,401 @@
+use analysis::per_pixel;
+use error::MandelError;
+use image::ImageBuffer;
+use num::Complex;
+use std::fs;
+use std::path::Path;
+
+/// Radius orbits are followed out to before the distance or potential is
+/// worked out, far past `BAILOUT` so that `|z|` has grown enough for them
+/// to hold
+pub const RADIUS: f64 = 1e5;
+
+/// escape(c, l) : the first `Z_n` of the orbit of `c` past `RADIUS`, with
+/// its derivative by `c` and `n`, `None` if it doesn't get there in
+/// `limit` iterations
+fn escape(c: Complex<f64>, limit: u32) -> Option<(Complex<f64>, Complex<f64>, u32)> {
+    let mut z = Complex { re: 0.0, im: 0.0 };
+    let mut der = Complex { re: 0.0, im: 0.0 };
+    for n in 1 ..= limit {
+        // dZ_n+1/dc = 2 Z_n dZ_n/dc + 1
+        der = 2.0 * z * der + 1.0;
+        z = z * z + c;
+        if z.norm() > RADIUS {
+            return Some((z, der, n));
+        }
+    }
+    None
+}
+
+/// estimate(c, l) : how far `c` is from the set, from the derivative of
+/// its orbit by `c`, `None` if it doesn't escape in `limit` iterations
+///
+/// The estimate is `|z| ln |z| / |dz/dc|`, which is within a factor of 4
+/// of the true distance either way, and closer the nearer `c` is.
+pub fn estimate(c: Complex<f64>, limit: u32) -> Option<f64> {
+    escape(c, limit).map(|(z, der, _)| z.norm() * z.norm().ln() / der.norm())
+}
+
+/// potential(c, l) : the Douady-Hubbard potential of `c`, `ln |Z_n| / 2^n-1`
+/// once its orbit is far out, `None` if it doesn't escape in `limit`
+/// iterations
+///
+/// It's 0 on the set and about `ln |c|` far from it, and smooth between:
+/// its level curves are the equipotentials and external rays cross them
+/// at right angles. Points that take over 1000 iterations to escape have
+/// a potential too small for `f64`, and come out 0.
+pub fn potential(c: Complex<f64>, limit: u32) -> Option<f64> {
+    escape(c, limit).map(|(z, _, n)| z.norm().ln() / 2f64.powi(n as i32 - 1))
+}
+
+/// Estimate the distance to the set from the center of each pixel of the
+/// `bounds` image between the corners, in pixel widths, so that the same
+/// field comes out at any zoom, 0 for pixels in the set, on `threads`
+/// threads
+pub fn field(bounds: (usize, usize),
+             top_left: Complex<f64>,
+             bot_right: Complex<f64>,
+             limit: u32,
+             threads: usize)
+    -> ImageBuffer<f32>
+{
+    let width = (bot_right.re - top_left.re) / bounds.0 as f64;
+    per_pixel(bounds, top_left, bot_right, threads, |c| {
+        estimate(c, limit).map_or(0.0, |distance| (distance / width) as f32)
+    })
+}
+
+/// Work out the `potential` at the center of each pixel of the `bounds`
+/// image between the corners, 0 for pixels in the set, on `threads`
+/// threads
+pub fn potentials(bounds: (usize, usize),
+                  top_left: Complex<f64>,
+                  bot_right: Complex<f64>,
+                  limit: u32,
+                  threads: usize)
+    -> ImageBuffer<f32>
+{
+    per_pixel(bounds, top_left, bot_right, threads, |c| {
+        potential(c, limit).unwrap_or(0.0) as f32
+    })
+}
+
+/// The channels `sample` works out for a point, in the alphabetical order
+/// OpenEXR keeps them in
+pub const CHANNELS: [&str; 5] = ["argument", "distance", "dwell", "modulus", "smooth"];
+
+/// sample(c, l, w) : the `CHANNELS` of `c`, from one orbit followed out to
+/// `RADIUS` for up to `limit` iterations: the argument of its last `Z_n`,
+/// the `estimate` of its distance from the set in widths of `width`, its
+/// dwell `n`, the modulus of `Z_n`, and the smooth dwell, which grows from
+/// one dwell to the next without steps; all 0 if it doesn't escape
+pub fn sample(c: Complex<f64>, limit: u32, width: f64) -> [f32; 5] {
+    match escape(c, limit) {
+        Some((z, der, n)) => {
+            let norm = z.norm();
+            let distance = norm * norm.ln() / der.norm() / width;
+            let smooth = n as f64 + 1.0 - norm.ln().log2();
+            [z.arg() as f32, distance as f32, n as f32, norm as f32, smooth as f32]
+        }
+        None => [0.0; 5],
+    }
+}
+
+/// Work out the `CHANNELS` at the center of each pixel of the `bounds`
+/// image between the corners, the distance in pixel widths, on `threads`
+/// threads
+pub fn channels(bounds: (usize, usize),
+                top_left: Complex<f64>,
+                bot_right: Complex<f64>,
+                limit: u32,
+                threads: usize)
+    -> ImageBuffer<[f32; 5]>
+{
+    let width = (bot_right.re - top_left.re) / bounds.0 as f64;
+    per_pixel(bounds, top_left, bot_right, threads, |c| sample(c, limit, width))
+}
+
+/// gray(d) : a pixel at distance `d` from the set shaded black within a
+/// pixel of it, fading to white a few pixels out, tracing its edge
+pub fn gray(distance: f32) -> u8 {
+    (255.0 * (distance / 4.0).min(1.0)).round() as u8
+}
+
+/// npy(s, v) : `values` as a NumPy `.npy` array of little-endian `f32`
+/// with `shape`, its last axis varying fastest
+fn npy<I: Iterator<Item = f32>>(shape: &[usize], values: I) -> Vec<u8> {
+    let axes: Vec<_> = shape.iter().map(|n| n.to_string()).collect();
+    let comma = if shape.len() == 1 { "," } else { "" };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({}{}), }}",
+                             axes.join(", "), comma);
+    // the magic, version and length take 10 bytes, and the data starts on
+    // a multiple of 64 after the newline ending the header
+    while (10 + header.len() + 1) % 64 != 0 {
+        header.push(' ');
+    }
+    header.push('\n');
+    let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+    npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    npy.extend_from_slice(header.as_bytes());
+    for value in values {
+        npy.extend_from_slice(&value.to_le_bytes());
+    }
+    npy
+}
+
+/// to_npy(f) : `field` as a NumPy `.npy` array of little-endian `f32`,
+/// its rows first
+pub fn to_npy(field: &ImageBuffer<f32>) -> Vec<u8> {
+    let (width, height) = field.bounds();
+    npy(&[height, width], field.pixels().iter().cloned())
+}
+
+/// to_npy_channels(i) : `image` as a NumPy `.npy` array of little-endian
+/// `f32`, shaped rows by columns by channels
+pub fn to_npy_channels<const N: usize>(image: &ImageBuffer<[f32; N]>) -> Vec<u8> {
+    let (width, height) = image.bounds();
+    npy(&[height, width, N], image.pixels().iter().flatten().cloned())
+}
+
+/// exr(n, b, v) : an uncompressed OpenEXR image of `bounds` with a `f32`
+/// channel for each of `names`, in the order they're given, which OpenEXR
+/// has be alphabetical, the value of each at a pixel from `value` of the
+/// index of the channel and of the pixel
+fn exr<F: Fn(usize, usize) -> f32>(names: &[&str], bounds: (usize, usize), value: F) -> Vec<u8> {
+    assert!(names.windows(2).all(|pair| pair[0] < pair[1]));
+    let (width, height) = bounds;
+    let mut exr = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        for text in &[name, kind] {
+            exr.extend_from_slice(text.as_bytes());
+            exr.push(0);
+        }
+        exr.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        exr.extend_from_slice(value);
+    };
+    let ints = |values: &[i32]| values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<_>>();
+    // each channel of pixel type 2, f32, linear off, sampled every pixel
+    let mut channels = Vec::new();
+    for name in names {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend(ints(&[2, 0, 1, 1]));
+    }
+    channels.push(0);
+    let window = ints(&[0, 0, width as i32 - 1, height as i32 - 1]);
+    attribute("channels", "chlist", &channels);
+    attribute("compression", "compression", &[0]);
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+    exr.push(0);
+
+    // a table of where each row starts, then the rows, each with its
+    // index and length, then its values a channel at a time
+    let row_bytes = 4 * width * names.len();
+    let first = exr.len() + 8 * height;
+    for row in 0 .. height {
+        exr.extend_from_slice(&((first + row * (8 + row_bytes)) as u64).to_le_bytes());
+    }
+    for row in 0 .. height {
+        exr.extend(ints(&[row as i32, row_bytes as i32]));
+        for channel in 0 .. names.len() {
+            for index in row * width .. (row + 1) * width {
+                exr.extend_from_slice(&value(channel, index).to_le_bytes());
+            }
+        }
+    }
+    exr
+}
+
+/// to_exr(f) : `field` as an uncompressed OpenEXR image with a single
+/// `f32` luminance channel, `Y`
+pub fn to_exr(field: &ImageBuffer<f32>) -> Vec<u8> {
+    exr(&["Y"], field.bounds(), |_, index| field.pixels()[index])
+}
+
+/// to_exr_channels(n, i) : `image` as an uncompressed OpenEXR image with
+/// a `f32` channel for each of `names`, which must be in alphabetical
+/// order
+pub fn to_exr_channels<const N: usize>(names: &[&str; N], image: &ImageBuffer<[f32; N]>)
+    -> Vec<u8>
+{
+    exr(names, image.bounds(), |channel, index| image.pixels()[index][channel])
+}
+
+/// by_extension(f, n, e) : the bytes `npy` or `exr` makes, by whether
+/// `filename` ends `.npy` or `.exr`, written to it
+fn by_extension<N, E>(filename: &str, npy: N, exr: E) -> Result<(), MandelError>
+    where N: FnOnce() -> Vec<u8>, E: FnOnce() -> Vec<u8>
+{
+    let extension = Path::new(filename).extension().and_then(|e| e.to_str());
+    let bytes = match extension.map(|e| e.to_ascii_lowercase()).as_deref() {
+        Some("npy") => npy(),
+        Some("exr") => exr(),
+        _ => return Err(MandelError::InvalidParams(format!(
+            "can't tell what to write {} as, expected .npy or .exr", filename))),
+    };
+    Ok(fs::write(filename, bytes)?)
+}
+
+/// Write `field` to `filename`, as NumPy or OpenEXR by its extension,
+/// `.npy` or `.exr`
+pub fn write(filename: &str, field: &ImageBuffer<f32>) -> Result<(), MandelError> {
+    by_extension(filename, || to_npy(field), || to_exr(field))
+}
+
+/// Write the channels of `image`, named `names` in alphabetical order, to
+/// `filename`, as NumPy or OpenEXR by its extension, `.npy` or `.exr`
+pub fn write_channels<const N: usize>(filename: &str,
+                                      names: &[&str; N],
+                                      image: &ImageBuffer<[f32; N]>)
+    -> Result<(), MandelError>
+{
+    by_extension(filename, || to_npy_channels(image), || to_exr_channels(names, image))
+}
+
+/// from_npy(b) : the shape and values of a NumPy array of little-endian
+/// `f32` in `bytes`, such as `to_npy` and `to_npy_channels` make
+pub fn from_npy(bytes: &[u8]) -> Result<(Vec<usize>, Vec<f32>), MandelError> {
+    let error = |why: &str| MandelError::Parse(format!("bad .npy file, {}", why));
+    if bytes.len() < 10 || !bytes.starts_with(b"\x93NUMPY") {
+        return Err(error("it doesn't start as one does"));
+    }
+    // version 1 has a 2 byte length before the header, later ones 4
+    let (length, start) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        _ if bytes.len() >= 12 => {
+            (u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize, 12)
+        }
+        _ => return Err(error("it doesn't start as one does")),
+    };
+    let header = bytes.get(start .. start + length)
+        .and_then(|header| std::str::from_utf8(header).ok())
+        .ok_or_else(|| error("its header is cut short"))?;
+    if !header.contains("'descr': '<f4'") || !header.contains("'fortran_order': False") {
+        return Err(error("expected little-endian f32 in rows"));
+    }
+    let shape = header.split_once("'shape': (")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .ok_or_else(|| error("it has no shape"))?
+        .0
+        .split(',')
+        .map(str::trim)
+        .filter(|axis| !axis.is_empty())
+        .map(|axis| axis.parse::<usize>().map_err(|_| error("its shape isn't numbers")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let data = &bytes[start + length ..];
+    if data.len() != 4 * shape.iter().product::<usize>() {
+        return Err(error("it holds more or less than its shape"));
+    }
+    let values = data.chunks(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect();
+    Ok((shape, values))
+}
+
+#[test]
+fn test_estimate() {
+    // the nearest points of the set to 1 and -2.5 are 0.25 and -2
+    for &(c, distance) in &[(1.0, 0.75), (-2.5, 0.5)] {
+        let estimate = estimate(Complex { re: c, im: 0.0 }, 1000).unwrap();
+        assert!(distance / 4.0 < estimate && estimate < distance * 4.0, "{} {}", c, estimate);
+    }
+    // closer in, closer to the truth
+    let near = estimate(Complex { re: -2.0001, im: 0.0 }, 1000).unwrap();
+    assert!(near < 1e-4 * 2.0 && near > 1e-4 / 2.0, "{}", near);
+    assert_eq!(estimate(Complex { re: -1.0, im: 0.0 }, 1000), None);
+
+    // a pixel width is 0.5, so 1 is 1.5 pixels from the set, and 0 is in it
+    let field = field((6, 1), Complex { re: -1.75, im: 0.25 }, Complex { re: 1.25, im: -0.25 },
+                      1000, 2);
+    assert_eq!(field.pixels()[3], 0.0);
+    assert!(field.pixels()[5] > 1.5 / 4.0 && field.pixels()[5] < 1.5 * 4.0);
+    assert_eq!((gray(0.0), gray(2.0), gray(100.0)), (0, 128, 255));
+}
+
+#[test]
+fn test_potential() {
+    // about `ln |c|` far out, whichever way
+    for &c in &[Complex { re: 1000.0, im: 0.0 }, Complex { re: -300.0, im: 400.0 }] {
+        assert!((potential(c, 100).unwrap() - c.norm().ln()).abs() < 1e-3);
+    }
+    // the same however far the orbit is followed, falling towards the set
+    let falling: Vec<_> = [1.0, 0.5, 0.3, 0.26].iter()
+        .map(|&re| potential(Complex { re, im: 0.0 }, 10_000).unwrap())
+        .collect();
+    assert!(falling.windows(2).all(|pair| pair[0] > pair[1] && pair[1] > 0.0));
+    let (z, _, n) = escape(Complex { re: 0.5, im: 0.5 }, 100).unwrap();
+    let further = (z * z + Complex { re: 0.5, im: 0.5 }).norm().ln() / 2f64.powi(n as i32);
+    assert!((potential(Complex { re: 0.5, im: 0.5 }, 100).unwrap() - further).abs() < 1e-9);
+    assert_eq!(potential(Complex { re: -1.0, im: 0.0 }, 1000), None);
+
+    let field = potentials((6, 1), Complex { re: -1.75, im: 0.25 },
+                           Complex { re: 1.25, im: -0.25 }, 1000, 2);
+    assert_eq!(field.pixels()[3], 0.0);
+    assert!(field.pixels()[5] > field.pixels()[4] && field.pixels()[4] > 0.0);
+}
+
+#[test]
+fn test_sample() {
+    // 1000, then a million and a thousand, past `RADIUS`
+    let c = Complex { re: 1000.0, im: 0.0 };
+    let [argument, distance, dwell, modulus, smooth] = sample(c, 100, 0.5);
+    assert_eq!((argument, dwell, modulus), (0.0, 2.0, 1_001_000.0));
+    assert_eq!(distance, (estimate(c, 100).unwrap() / 0.5) as f32);
+    assert!((smooth as f64 - (3.0 - 1_001_000f64.ln().log2())).abs() < 1e-5);
+    assert_eq!(sample(Complex { re: -1.0, im: 0.0 }, 1000, 0.5), [0.0; 5]);
+
+    // the dwell steps along the real axis, the smooth dwell doesn't
+    let samples: Vec<_> = (0 .. 7000)
+        .map(|i| sample(Complex { re: 1.0 - i as f64 * 1e-4, im: 0.0 }, 1000, 1.0))
+        .collect();
+    assert!(samples.windows(2).any(|pair| pair[1][2] > pair[0][2]));
+    assert!(samples.windows(2).all(|pair| (pair[1][4] - pair[0][4]).abs() < 0.05));
+
+    let image = channels((6, 1), Complex { re: -1.75, im: 0.25 },
+                         Complex { re: 1.25, im: -0.25 }, 1000, 2);
+    assert_eq!(image.pixels()[3], [0.0; 5]);
+    assert_eq!(image.pixels()[5], sample(Complex { re: 1.0, im: 0.0 }, 1000, 0.5));
+}
+
+#[test]
+fn test_write_field() {
+    let field = ImageBuffer::from_pixels((3, 2), vec![0.0, 1.0, 2.0, 0.5, 0.25, 0.0]);
+
+    let npy = to_npy(&field);
+    assert!(npy.starts_with(b"\x93NUMPY\x01\x00\x76\x00{'descr': '<f4', 'fortran_order': \
+                              False, 'shape': (2, 3), }"));
+    assert_eq!((npy.len(), npy[127]), (128 + 6 * 4, b'\n'));
+    assert_eq!(&npy[132 .. 136], &1f32.to_le_bytes());
+
+    let exr = to_exr(&field);
+    assert_eq!(&exr[.. 8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+    // the offsets of the two rows, each 8 bytes ahead of its 12 of data
+    let end = exr.len() - 2 * (8 + 12);
+    assert_eq!(&exr[end - 16 .. end - 8], &(end as u64).to_le_bytes());
+    assert_eq!(&exr[end - 8 .. end], &(end as u64 + 20).to_le_bytes());
+    assert_eq!(&exr[end + 20 .. end + 24], &[1, 0, 0, 0]);
+    assert_eq!(&exr[exr.len() - 8 .. exr.len() - 4], &0.25f32.to_le_bytes());
+
+    assert!(matches!(write("field.png", &field), Err(MandelError::InvalidParams(_))));
+
+    // channels side by side in NumPy, one after another along a row in
+    // OpenEXR
+    let image = ImageBuffer::from_pixels((2, 1), vec![[1.0, 2.0], [3.0, 4.0]]);
+    let npy = to_npy_channels(&image);
+    assert!(npy[10 ..].starts_with(b"{'descr': '<f4', 'fortran_order': False, \
+                                     'shape': (1, 2, 2), }"));
+    let flat = ImageBuffer::from_pixels((4, 1), vec![1.0, 2.0, 3.0, 4.0]);
+    assert_eq!(&npy[128 ..], &to_npy(&flat)[128 ..]);
+    assert_eq!(from_npy(&npy).unwrap(), (vec![1, 2, 2], vec![1.0, 2.0, 3.0, 4.0]));
+    assert_eq!(from_npy(&to_npy(&field)).unwrap().0, [2, 3]);
+    for bad in &[&npy[.. 130], &npy[.. 20], b"P5 1 1 255 0"] {
+        assert!(matches!(from_npy(bad), Err(MandelError::Parse(_))));
+    }
+    let exr = to_exr_channels(&["a", "b"], &image);
+    let values: Vec<_> = [1.0f32, 3.0, 2.0, 4.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+    assert!(exr.ends_with(&values));
+    assert!(exr.windows(19).any(|w| w == b"a\0\x02\0\0\0\0\0\0\0\x01\0\0\0\x01\0\0\0b"));
+}
@@ -0,0 +1,318 @@
+use crossbeam;
+use error::MandelError;
+use fixed;
+use precision::Precision;
+use renderer::MAX_ITERATIONS;
+use server::{Slot, MAX_RENDER_PIXELS};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::AtomicUsize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use {copy_rect, parse_complex, parse_pair, render_rect};
+
+/// Side of the square tiles handed out to workers
+const TILE_SIZE: usize = 128;
+
+/// How long a worker waits on a quiet or stalled coordinator before
+/// dropping the connection
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Longest line either side reads, room for corners of a few hundred
+/// digits each
+const MAX_LINE: usize = 4 << 10;
+
+/// Most coordinator connections a worker serves at once; more are closed
+/// as they come in
+const MAX_CONNECTIONS: usize = 256;
+
+/// One tile of a render, as sent over the wire
+///
+/// The corners travel as the decimal strings the user typed so that
+/// workers can re-parse them in fixed point for deep zooms.
+#[derive(Clone, Debug, PartialEq)]
+struct Job {
+    precision: Precision,
+    limit: u32,
+    bounds: (usize, usize),
+    top_left: String,
+    bot_right: String,
+    rect: (usize, usize, usize, usize),
+}
+
+impl Job {
+    fn to_line(&self) -> String {
+        let precision = self.precision.name();
+        format!("RENDER {} {} {}x{} {} {} {} {} {} {}\n",
+                precision, self.limit, self.bounds.0, self.bounds.1,
+                self.top_left, self.bot_right,
+                self.rect.0, self.rect.1, self.rect.2, self.rect.3)
+    }
+
+    fn parse(line: &str) -> Option<Job> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.len() != 10 || words[0] != "RENDER" {
+            return None;
+        }
+        let precision = Precision::from_name(words[1]).ok()?;
+        let rect: (usize, usize, usize, usize) =
+            (words[6].parse().ok()?, words[7].parse().ok()?,
+             words[8].parse().ok()?, words[9].parse().ok()?);
+        let bounds: (usize, usize) = parse_pair(words[3], 'x').ok()?;
+        let limit = words[2].parse().ok()?;
+        // the tile must be a nonempty part of the image no bigger than a
+        // server would render, and iterated no more than one would
+        if limit > MAX_ITERATIONS
+            || rect.2 == 0 || rect.3 == 0
+            || rect.0.saturating_add(rect.2) > bounds.0
+            || rect.1.saturating_add(rect.3) > bounds.1
+            || rect.2.saturating_mul(rect.3) > MAX_RENDER_PIXELS
+        {
+            return None;
+        }
+        Some(Job {
+            precision,
+            limit,
+            bounds,
+            top_left: words[4].to_string(),
+            bot_right: words[5].to_string(),
+            rect,
+        })
+    }
+
+    fn render(&self) -> Result<Vec<u8>, MandelError> {
+        let top_left = parse_complex(&self.top_left)?;
+        let bot_right = parse_complex(&self.bot_right)?;
+        let deep = match self.precision {
+            Precision::Fixed(bits) => {
+                Some((fixed::parse_complex(&self.top_left, bits)?,
+                      fixed::parse_complex(&self.bot_right, bits)?))
+            }
+            _ => None
+        };
+
+        let mut pixels = vec![0; self.rect.2 * self.rect.3];
+        render_rect(&mut pixels, self.bounds, self.rect, top_left, bot_right,
+                    deep.as_ref(), self.precision, self.limit);
+        Ok(pixels)
+    }
+}
+
+/// Worker side: render jobs from every connection until it closes
+pub fn serve(listener: TcpListener) -> io::Result<()> {
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let connection = match Slot::take(&connections, MAX_CONNECTIONS) {
+            Some(connection) => connection,
+            None => continue,
+        };
+        std::thread::spawn(move || {
+            let _connection = connection;
+            if let Err(e) = handle(stream) {
+                eprintln!("worker: connection dropped: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// read_line(r, l) : `read_line` of at most `MAX_LINE` bytes, failing on a
+/// line any longer
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<usize> {
+    let read = reader.take(MAX_LINE as u64).read_line(line)?;
+    if read == MAX_LINE && !line.ends_with('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("line longer than {} bytes", MAX_LINE)));
+    }
+    Ok(read)
+}
+
+fn handle(stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut line = String::new();
+    while read_line(&mut reader, &mut line)? > 0 {
+        match Job::parse(&line).map(|job| job.render()) {
+            Some(Ok(pixels)) => {
+                writeln!(writer, "OK {}", pixels.len())?;
+                writer.write_all(&pixels)?;
+            }
+            Some(Err(e)) => writeln!(writer, "ERR {}", e)?,
+            None => writeln!(writer, "ERR bad request")?,
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+/// Send `job` to the worker behind `reader` and wait for its pixels
+fn request(reader: &mut BufReader<TcpStream>, job: &Job) -> io::Result<Vec<u8>> {
+    reader.get_mut().write_all(job.to_line().as_bytes())?;
+
+    let mut status = String::new();
+    read_line(reader, &mut status)?;
+    let len = status.trim_end().strip_prefix("OK ")
+        .and_then(|len| len.parse::<usize>().ok())
+        .filter(|&len| len == job.rect.2 * job.rect.3)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData,
+                                      format!("worker replied {:?}", status)))?;
+
+    let mut pixels = vec![0; len];
+    reader.read_exact(&mut pixels)?;
+    Ok(pixels)
+}
+
+/// Coordinator side: render the image on `workers`, each given
+/// `connections` tiles at a time
+///
+/// A worker that fails is dropped and its tile goes back on the queue;
+/// anything left once every worker is gone is rendered locally, which
+/// fails if the corners can't be read.
+#[allow(clippy::too_many_arguments)]
+pub fn render(pixels: &mut [u8],
+              bounds: (usize, usize),
+              top_left: &str,
+              bot_right: &str,
+              precision: Precision,
+              limit: u32,
+              workers: &[String],
+              connections: usize)
+    -> Result<(), MandelError>
+{
+    assert!(pixels.len() == bounds.0 * bounds.1);
+
+    let mut jobs = Vec::new();
+    for y in (0 .. bounds.1).step_by(TILE_SIZE) {
+        for x in (0 .. bounds.0).step_by(TILE_SIZE) {
+            jobs.push(Job {
+                precision,
+                limit,
+                bounds,
+                top_left: top_left.to_string(),
+                bot_right: bot_right.to_string(),
+                rect: (x, y,
+                       TILE_SIZE.min(bounds.0 - x), TILE_SIZE.min(bounds.1 - y)),
+            });
+        }
+    }
+    // hand out tiles top to bottom
+    jobs.reverse();
+
+    let queue = Mutex::new(jobs);
+    let done = Mutex::new(Vec::new());
+    crossbeam::scope(|spawner| {
+        for worker in workers {
+            for _ in 0..connections {
+                let (queue, done) = (&queue, &done);
+                spawner.spawn(move || {
+                    let stream = match TcpStream::connect(worker.as_str()) {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            eprintln!("can't reach worker {}: {}", worker, e);
+                            return;
+                        }
+                    };
+                    let mut reader = BufReader::new(stream);
+                    loop {
+                        let job = match queue.lock().unwrap().pop() {
+                            Some(job) => job,
+                            None => return,
+                        };
+                        match request(&mut reader, &job) {
+                            Ok(tile) => done.lock().unwrap().push((job.rect, tile)),
+                            Err(e) => {
+                                eprintln!("worker {} failed: {}", worker, e);
+                                queue.lock().unwrap().push(job);
+                                return;
+                            }
+                        }
+                    }
+                });
+            }
+        }
+    });
+
+    let mut done = done.into_inner().unwrap();
+    for job in queue.into_inner().unwrap() {
+        let tile = job.render()?;
+        done.push((job.rect, tile));
+    }
+
+    for (rect, tile) in done {
+        copy_rect(pixels, bounds, rect, &tile);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_job_round_trip() {
+    let job = Job {
+        precision: Precision::Fixed(128),
+        limit: 1000,
+        bounds: (640, 480),
+        top_left: "-0.75,0.1".to_string(),
+        bot_right: "-0.74,0.09".to_string(),
+        rect: (128, 256, 128, 96),
+    };
+    assert_eq!(Job::parse(&job.to_line()), Some(job.clone()));
+
+    let job = Job { precision: Precision::F32, ..job };
+    assert_eq!(Job::parse(&job.to_line()), Some(job));
+    assert_eq!(Job::parse("RENDER f64 255"), None);
+    assert_eq!(Job::parse("RENDER fixed:100000000 255 640x400 -2,1 1,-1 0 0 1 1"), None);
+    let line = format!("RENDER f64 {} 640x400 -2,1 1,-1 0 0 1 1", MAX_ITERATIONS + 1);
+    assert_eq!(Job::parse(&line), None);
+
+    // tiles that are empty, stray outside the image or are too big
+    for rect in ["0 0 0 96", "600 0 128 96", "0 0 640 480", "0 18446744073709551615 1 1"] {
+        let line = format!("RENDER f64 255 640x400 -2,1 1,-1 {}", rect);
+        assert_eq!(Job::parse(&line), None, "{}", rect);
+    }
+    let huge = format!("RENDER f64 255 {0}x{0} -2,1 1,-1 0 0 {0} {0}", 1 << 16);
+    assert_eq!(Job::parse(&huge), None);
+    assert!(Job::parse("RENDER f64 255 640x400 -2,1 1,-1 512 384 128 16").is_some());
+}
+
+#[test]
+fn test_render_matches_local() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    std::thread::spawn(move || serve(listener));
+
+    let bounds = (300, 200);
+    let (top_left, bot_right) = ("-1.2,0.35", "-1,0.2");
+    let mut remote = vec![0; bounds.0 * bounds.1];
+    // the second worker is unreachable and must not lose any tiles
+    render(&mut remote, bounds, top_left, bot_right, Precision::F64, 255,
+           &[addr, "127.0.0.1:1".to_string()], 2).unwrap();
+
+    // with no workers at all, every tile is rendered locally
+    let mut local = vec![0; bounds.0 * bounds.1];
+    render(&mut local, bounds, top_left, bot_right, Precision::F64, 255, &[], 2).unwrap();
+    assert!(remote == local);
+}
+
+#[test]
+fn test_read_line() {
+    let mut line = String::new();
+    let short = "RENDER f64\n".repeat(2);
+    assert_eq!(read_line(&mut short.as_bytes(), &mut line).unwrap(), 11);
+    line.clear();
+    let long = "9".repeat(MAX_LINE * 4);
+    assert!(read_line(&mut long.as_bytes(), &mut line).is_err());
+    assert!(line.len() <= MAX_LINE);
+
+    // a worker hangs up on a line that doesn't end
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || serve(listener));
+    let mut stream = TcpStream::connect(addr).unwrap();
+    // with the rest unread the hang up can come as a reset
+    let _ = stream.write_all(long.as_bytes());
+    let mut reply = Vec::new();
+    let _ = stream.read_to_end(&mut reply);
+    assert!(reply.is_empty());
+}
@@ -0,0 +1,112 @@
+use error::MandelError;
+use std::str::FromStr;
+
+/// Bisection steps when solving a `Bezier` for its time, each halving the
+/// error, far past what a frame can show
+const BEZIER_STEPS: u32 = 40;
+
+/// How a value moves between two keyframes: the fraction of the change
+/// made by each fraction of the time
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Easing {
+    /// at a steady pace
+    #[default]
+    Linear,
+    /// starting and stopping gently, fastest halfway
+    EaseInOut,
+    /// along the cubic Bézier from (0, 0) to (1, 1) with control points
+    /// `(x1, y1)` and `(x2, y2)`, as CSS's `cubic-bezier` draws it
+    Bezier(f64, f64, f64, f64),
+}
+
+/// bezier(a, b, t) : one coordinate of the curve with control points `a`
+/// and `b` at `t`
+fn bezier(a: f64, b: f64, t: f64) -> f64 {
+    let u = 1.0 - t;
+    3.0 * u * u * t * a + 3.0 * u * t * t * b + t * t * t
+}
+
+impl Easing {
+    /// apply(s) : how much of the change is made `s` of the way through
+    /// the time, 0 at 0 and 1 at 1
+    pub fn apply(&self, s: f64) -> f64 {
+        if s <= 0.0 || s >= 1.0 {
+            return s.clamp(0.0, 1.0);
+        }
+        match *self {
+            Easing::Linear => s,
+            Easing::EaseInOut => s * s * (3.0 - 2.0 * s),
+            Easing::Bezier(x1, y1, x2, y2) => {
+                // x never turns back with x1 and x2 in 0 ..= 1, so the t
+                // giving x = s can be found by halving
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0 .. BEZIER_STEPS {
+                    let mid = (low + high) / 2.0;
+                    if bezier(x1, x2, mid) < s {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                bezier(y1, y2, (low + high) / 2.0)
+            }
+        }
+    }
+}
+
+/// Parsed from `linear`, `ease-in-out`, CSS's other names `ease-in` and
+/// `ease-out`, or `bezier(X1,Y1,X2,Y2)`
+impl FromStr for Easing {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Easing, MandelError> {
+        let error = || MandelError::Parse(format!(
+            "bad easing {:?}, expected linear, ease-in, ease-out, ease-in-out or \
+             bezier(X1,Y1,X2,Y2)", s));
+        match s {
+            "linear" => return Ok(Easing::Linear),
+            "ease-in-out" => return Ok(Easing::EaseInOut),
+            "ease-in" => return Ok(Easing::Bezier(0.42, 0.0, 1.0, 1.0)),
+            "ease-out" => return Ok(Easing::Bezier(0.0, 0.0, 0.58, 1.0)),
+            _ => {}
+        }
+        let args = s.strip_prefix("bezier(").and_then(|rest| rest.strip_suffix(')'))
+            .ok_or_else(error)?;
+        let numbers = args.split(',').map(|n| n.trim().parse().ok())
+            .collect::<Option<Vec<f64>>>()
+            .ok_or_else(error)?;
+        match numbers[..] {
+            [x1, y1, x2, y2] if (0.0 ..= 1.0).contains(&x1) && (0.0 ..= 1.0).contains(&x2) => {
+                Ok(Easing::Bezier(x1, y1, x2, y2))
+            }
+            _ => Err(error()),
+        }
+    }
+}
+
+#[test]
+fn test_easing() {
+    for easing in &[Easing::Linear, Easing::EaseInOut, Easing::Bezier(0.42, 0.0, 0.58, 1.0)] {
+        assert!(easing.apply(0.0) == 0.0 && easing.apply(1.0) == 1.0);
+        assert!((easing.apply(0.5) - 0.5).abs() < 1e-9, "{:?}", easing);
+        assert!(easing.apply(2.0) == easing.apply(1.0) && easing.apply(-1.0) == 0.0);
+    }
+    assert_eq!(Easing::Linear.apply(0.25), 0.25);
+    assert_eq!(Easing::EaseInOut.apply(0.25), 0.15625);
+
+    // a straight-line Bézier is linear, and ease-in starts slowly
+    let straight = Easing::Bezier(1.0 / 3.0, 1.0 / 3.0, 2.0 / 3.0, 2.0 / 3.0);
+    assert!((straight.apply(0.3) - 0.3).abs() < 1e-9);
+    let ease_in: Easing = "ease-in".parse().unwrap();
+    assert!(ease_in.apply(0.25) < 0.1 && ease_in.apply(0.9) > 0.8);
+    let pairs = (1 .. 100).map(|i| ease_in.apply(i as f64 / 100.0)).collect::<Vec<_>>();
+    assert!(pairs.windows(2).all(|pair| pair[0] < pair[1]));
+
+    assert_eq!("bezier(0.1, 0.2,0.3,0.4)".parse::<Easing>().unwrap(),
+               Easing::Bezier(0.1, 0.2, 0.3, 0.4));
+    assert_eq!("linear".parse::<Easing>().unwrap(), Easing::default());
+    for bad in &["", "fast", "bezier(0,0,1)", "bezier(2,0,1,1)", "bezier(0,0,1,1",
+                 "bezier(a,0,1,1)"] {
+        assert!(matches!(bad.parse::<Easing>(), Err(MandelError::Parse(_))), "{}", bad);
+    }
+}
@@ -0,0 +1,48 @@
+#[cfg(feature = "native")]
+use png;
+use std::io;
+use thiserror::Error;
+
+/// Everything that can go wrong in the library, for callers to tell apart
+#[derive(Debug, Error)]
+pub enum MandelError {
+    /// text that should have been a number, corner or precision wasn't
+    #[error("{0}")]
+    Parse(String),
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    /// an image couldn't be encoded as PNG
+    #[cfg(feature = "native")]
+    #[error("error encoding image: {0}")]
+    Encode(#[from] png::EncodingError),
+    /// the explorer couldn't open its window or draw in it
+    #[error("window error: {0}")]
+    Gpu(String),
+    /// a plugin couldn't be loaded, or isn't for this version
+    #[error("error loading plugin {0}")]
+    Plugin(String),
+    /// the render was stopped through its `CancelToken`
+    #[error("render cancelled")]
+    Cancelled,
+    /// settings that can't be rendered, such as an empty image
+    #[error("{0}")]
+    InvalidParams(String),
+}
+
+/// As `io::Error::other`, for code that only deals in IO errors, unless
+/// it was one to start with
+impl From<MandelError> for io::Error {
+    fn from(e: MandelError) -> io::Error {
+        match e {
+            MandelError::Io(e) => e,
+            e => io::Error::other(e),
+        }
+    }
+}
+
+#[test]
+fn test_mandel_error() {
+    let e = MandelError::from(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+    assert_eq!(io::Error::from(e).kind(), io::ErrorKind::NotFound);
+    assert_eq!(io::Error::from(MandelError::Cancelled).to_string(), "render cancelled");
+}
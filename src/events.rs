@@ -0,0 +1,76 @@
+use renderer::TileRect;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A piece of a render starting or finishing, as told to the hook set by
+/// `Renderer::on_event`
+///
+/// The pieces are a thread's band of a strip of rows when rendering whole
+/// images, and `Renderer::tiles`'s tiles, so `rect` is one or the other.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RenderEvent {
+    /// a thread has begun on `rect`
+    Started { rect: TileRect },
+    /// a thread has finished `rect`, which took `elapsed`, `total` after
+    /// the render started
+    Finished { rect: TileRect, elapsed: Duration, total: Duration },
+}
+
+impl RenderEvent {
+    pub fn rect(&self) -> TileRect {
+        match *self {
+            RenderEvent::Started { rect } | RenderEvent::Finished { rect, .. } => rect,
+        }
+    }
+}
+
+/// A shared function called with every `RenderEvent` of a render, from
+/// whichever thread the piece is rendered on
+#[derive(Clone)]
+pub struct EventHook(Arc<dyn Fn(RenderEvent) + Send + Sync>);
+
+impl EventHook {
+    pub fn new<F>(hook: F) -> EventHook
+        where F: Fn(RenderEvent) + Send + Sync + 'static
+    {
+        EventHook(Arc::new(hook))
+    }
+
+    pub fn call(&self, event: RenderEvent) {
+        (self.0)(event)
+    }
+}
+
+impl fmt::Debug for EventHook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("EventHook")
+    }
+}
+
+/// Hooks are equal when they're clones of each other
+impl PartialEq for EventHook {
+    fn eq(&self, other: &EventHook) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[test]
+fn test_event_hook() {
+    use std::sync::Mutex;
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+        let seen = seen.clone();
+        EventHook::new(move |event| seen.lock().unwrap().push(event.rect()))
+    };
+    assert_eq!(hook, hook.clone());
+    assert_ne!(hook, EventHook::new(|_| {}));
+    hook.call(RenderEvent::Started { rect: (0, 0, 4, 4) });
+    hook.clone().call(RenderEvent::Finished {
+        rect: (4, 0, 4, 4),
+        elapsed: Duration::from_millis(1),
+        total: Duration::from_millis(2),
+    });
+    assert_eq!(*seen.lock().unwrap(), [(0, 0, 4, 4), (4, 0, 4, 4)]);
+}
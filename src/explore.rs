@@ -0,0 +1,230 @@
+use num::Complex;
+use pan;
+use precision::Precision;
+use random::Rng;
+use std::fs;
+use std::io;
+use {auto_iterations, escape_time_batch, escaped, format_complex, pixel_center, pixel_to_point};
+
+/// Samples across and down a view when looking for somewhere to go
+const GRID: (usize, usize) = (48, 36);
+
+/// The whole set, in the shape of `GRID`
+pub const TOP_LEFT: Complex<f64> = Complex { re: -2.25, im: 1.125 };
+pub const BOT_RIGHT: Complex<f64> = Complex { re: 0.75, im: -1.125 };
+
+/// Side of the squares of samples whose escape counts are compared
+const CELL: usize = 4;
+
+/// How many of the most varied squares to choose between
+const CHOICES: usize = 3;
+
+/// variance(c) : how spread out the escape counts `counts` are
+fn variance(counts: &[f64]) -> f64 {
+    let mean = counts.iter().sum::<f64>() / counts.len() as f64;
+    counts.iter().map(|count| (count - mean) * (count - mean)).sum::<f64>() / counts.len() as f64
+}
+
+/// cells(tl, br, l) : the squares of `CELL` samples of the view whose
+/// escape counts vary, by the top left sample of each, most varied first,
+/// with the variance of each
+///
+/// Points that don't escape count as `limit`.
+fn cells(top_left: Complex<f64>, bot_right: Complex<f64>, limit: u32)
+    -> Vec<(f64, (usize, usize))>
+{
+    let points: Vec<_> = (0 .. GRID.0 * GRID.1)
+        .map(|i| pixel_center(GRID, (i % GRID.0, i / GRID.0), top_left, bot_right))
+        .collect();
+    let mut times = vec![0; points.len()];
+    escape_time_batch(&points, limit, &mut times);
+    let counts: Vec<f64> = times.iter()
+        .map(|&time| escaped(time).unwrap_or(limit) as f64)
+        .collect();
+
+    let mut cells = Vec::new();
+    for row in (0 .. GRID.1).step_by(CELL) {
+        for col in (0 .. GRID.0).step_by(CELL) {
+            let cell: Vec<f64> = (row .. row + CELL)
+                .flat_map(|y| counts[y * GRID.0 + col .. y * GRID.0 + col + CELL].to_vec())
+                .collect();
+            cells.push((variance(&cell), (col, row)));
+        }
+    }
+    cells.retain(|&(spread, _)| spread > 0.0);
+    cells.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    cells
+}
+
+/// interesting(tl, br, l, r) : the middle of one of the parts of the view
+/// where escape counts vary the most, which is where the boundary has
+/// detail worth zooming into
+///
+/// Points that don't escape count as `limit`. Returns `None` if the view
+/// is all one count, with nowhere to go.
+pub fn interesting(top_left: Complex<f64>,
+                   bot_right: Complex<f64>,
+                   limit: u32,
+                   rng: &mut Rng)
+    -> Option<Complex<f64>>
+{
+    let mut cells = cells(top_left, bot_right, limit);
+    cells.truncate(CHOICES);
+    if cells.is_empty() {
+        return None;
+    }
+    let (_, (col, row)) = cells[rng.below(cells.len())];
+    Some(pixel_center(GRID, (col + CELL / 2, row + CELL / 2), top_left, bot_right))
+}
+
+/// A part of a view with detail worth zooming into
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spot {
+    pub top_left: Complex<f64>,
+    pub bot_right: Complex<f64>,
+    /// the variance of the escape counts where it was first found, by
+    /// which spots are ranked
+    pub score: f64,
+}
+
+/// cell_view(tl, br, c) : the corners of a view the shape of this one
+/// and as wide as a cell, around the cell whose top left sample is `cell`
+fn cell_view(top_left: Complex<f64>, bot_right: Complex<f64>, cell: (usize, usize))
+    -> (Complex<f64>, Complex<f64>)
+{
+    let center = pixel_to_point(GRID, (cell.0 + CELL / 2, cell.1 + CELL / 2), top_left, bot_right);
+    let half = (bot_right - top_left) * (CELL as f64 / GRID.0 as f64 / 2.0);
+    (center - half, center + half)
+}
+
+/// spots(tl, br, l, n, d) : up to `count` spots of the view, the most
+/// varied first and none next to another, each narrowed down `depth`
+/// times more to the most varied part of it
+///
+/// Spots next to the reflection of another in the real axis are left out
+/// too, the set being the same either side of it.
+///
+/// Each step down is `GRID.0 / CELL` times closer, and looked at with
+/// `limit` iterations or `auto_iterations` if that's more.
+pub fn spots(top_left: Complex<f64>,
+             bot_right: Complex<f64>,
+             limit: u32,
+             count: usize,
+             depth: usize)
+    -> Vec<Spot>
+{
+    let center = |cell: (usize, usize)| {
+        pixel_to_point(GRID, (cell.0 + CELL / 2, cell.1 + CELL / 2), top_left, bot_right)
+    };
+    let (width, height) = ((bot_right.re - top_left.re) * (CELL as f64 / GRID.0 as f64),
+                           (top_left.im - bot_right.im) * (CELL as f64 / GRID.1 as f64));
+    let mut chosen: Vec<(f64, (usize, usize))> = Vec::new();
+    for (score, cell) in cells(top_left, bot_right, limit) {
+        if chosen.len() == count {
+            break;
+        }
+        // within a cell of each other, rather than two or more apart
+        let near = |&(_, other): &(f64, (usize, usize))| {
+            let (a, b) = (center(cell), center(other));
+            [b, b.conj()].iter().any(|b| {
+                (a.re - b.re).abs() < 1.5 * width && (a.im - b.im).abs() < 1.5 * height
+            })
+        };
+        if !chosen.iter().any(near) {
+            chosen.push((score, cell));
+        }
+    }
+
+    chosen.into_iter()
+        .map(|(score, cell)| {
+            let (mut top_left, mut bot_right) = cell_view(top_left, bot_right, cell);
+            for _ in 0 .. depth {
+                let limit = limit.max(auto_iterations(top_left, bot_right));
+                match cells(top_left, bot_right, limit).first() {
+                    Some(&(_, cell)) => {
+                        let view = cell_view(top_left, bot_right, cell);
+                        top_left = view.0;
+                        bot_right = view.1;
+                    }
+                    None => break,
+                }
+            }
+            Spot { top_left, bot_right, score }
+        })
+        .collect()
+}
+
+/// Write `spots` to `path` as lines of a bookmarks file, each with the
+/// iterations it needs, for the explorer to open; the first is named
+/// `NAME 1`, the next `NAME 2` and so on
+pub fn write_spots(path: &str, spots: &[Spot], limit: u32, name: &str) -> io::Result<()> {
+    let mut text = String::new();
+    for (index, spot) in spots.iter().enumerate() {
+        let limit = limit.max(auto_iterations(spot.top_left, spot.bot_right));
+        text.push_str(&pan::describe(limit, Precision::F64, &format_complex(spot.top_left),
+                                     &format_complex(spot.bot_right)));
+        text.push_str(&format!(" {} {}\n", name, index + 1));
+    }
+    fs::write(path, text)
+}
+
+#[test]
+fn test_interesting() {
+    let mut rng = Rng::new(42);
+
+    // deep inside the main cardioid there's nothing to find
+    let inside = interesting(Complex { re: -0.1, im: 0.1 }, Complex { re: 0.1, im: -0.1 },
+                             200, &mut rng);
+    assert_eq!(inside, None);
+
+    // across the whole set, somewhere near the boundary, where a small
+    // step changes the escape count
+    let top_left = Complex { re: -2.0, im: 1.5 };
+    let bot_right = Complex { re: 1.0, im: -1.5 };
+    for _ in 0 .. 5 {
+        let point = interesting(top_left, bot_right, 200, &mut rng).unwrap();
+        assert!(point.norm() < 2.0);
+        let step = 3.0 / GRID.0 as f64;
+        let around = [point, point + step, point - step,
+                      point + Complex { re: 0.0, im: step }, point - Complex { re: 0.0, im: step }];
+        let mut times = vec![0; around.len()];
+        escape_time_batch(&around, 200, &mut times);
+        assert!(times.iter().any(|&time| time != times[0]), "{}", point);
+    }
+}
+
+#[test]
+fn test_spots() {
+    let top_left = Complex { re: -2.0, im: 1.5 };
+    let bot_right = Complex { re: 1.0, im: -1.5 };
+    let found = spots(top_left, bot_right, 200, 5, 2);
+    assert_eq!(found.len(), 5);
+    for (spot, next) in found.iter().zip(&found[1 ..]) {
+        assert!(spot.score >= next.score);
+    }
+    for spot in &found {
+        // two steps down from a cell, 12 times smaller each time
+        let width = spot.bot_right.re - spot.top_left.re;
+        assert!((width - 3.0 / 12.0 / 144.0).abs() < 1e-12, "{:?}", spot);
+        assert!(!cells(spot.top_left, spot.bot_right, 1000).is_empty(), "{:?}", spot);
+    }
+    for (i, a) in found.iter().enumerate() {
+        for b in &found[i + 1 ..] {
+            assert!((a.top_left - b.top_left).norm() > 3.0 / 12.0, "{:?} {:?}", a, b);
+        }
+    }
+
+    let inside = spots(Complex { re: -0.1, im: 0.1 }, Complex { re: 0.1, im: -0.1 }, 200, 5, 2);
+    assert_eq!(inside, []);
+}
+
+#[test]
+fn test_write_spots() {
+    let path = std::env::temp_dir().join(format!("spots-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+    let spots = [Spot { top_left: Complex { re: -0.75, im: 0.1 },
+                        bot_right: Complex { re: -0.74, im: 0.09 }, score: 1.0 }];
+    write_spots(path, &spots, 1000, "seahorse").unwrap();
+    assert_eq!(fs::read_to_string(path).unwrap(), "1000 f64 -0.75,0.1 -0.74,0.09 seahorse 1\n");
+    fs::remove_file(path).unwrap();
+}
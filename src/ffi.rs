@@ -0,0 +1,90 @@
+use num::Complex;
+use renderer::Renderer;
+use std::os::raw::c_int;
+use std::panic::{self, AssertUnwindSafe};
+use std::slice;
+
+/// The view `mandel_render` renders, laid out as `struct mandel_params`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MandelParams {
+    pub width: usize,
+    pub height: usize,
+    pub top_left_re: f64,
+    pub top_left_im: f64,
+    pub bot_right_re: f64,
+    pub bot_right_im: f64,
+    /// 0 to choose from the zoom
+    pub iterations: u32,
+    /// 0 for a thread per core
+    pub threads: u32,
+}
+
+/// Returned by `mandel_render` when it's done
+pub const MANDEL_OK: c_int = 0;
+
+/// Returned by `mandel_render` when given a null pointer
+pub const MANDEL_NULL: c_int = -1;
+
+/// Returned by `mandel_render` when the view can't be rendered
+pub const MANDEL_INVALID: c_int = -2;
+
+/// Render the view `params` into `out`, a gray byte per pixel, row by
+/// row, returning `MANDEL_OK` or why not
+///
+/// # Safety
+///
+/// `params` must point to a `MandelParams` and `out` to at least
+/// `width * height` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mandel_render(params: *const MandelParams, out: *mut u8) -> c_int {
+    if params.is_null() || out.is_null() {
+        return MANDEL_NULL;
+    }
+    let params = *params;
+    let renderer = Renderer::new()
+        .bounds(params.width, params.height)
+        .viewport(Complex { re: params.top_left_re, im: params.top_left_im },
+                  Complex { re: params.bot_right_re, im: params.bot_right_im });
+    let renderer = match params.iterations {
+        0 => renderer,
+        limit => renderer.iterations(limit),
+    };
+    let renderer = match params.threads {
+        0 => renderer,
+        threads => renderer.threads(threads as usize),
+    };
+
+    // a panic mustn't unwind into C, and there's no event hook here for
+    // it to leave half-updated
+    match panic::catch_unwind(AssertUnwindSafe(|| renderer.render())) {
+        Ok(Ok(image)) => {
+            let out = slice::from_raw_parts_mut(out, params.width * params.height);
+            out.copy_from_slice(image.pixels());
+            MANDEL_OK
+        }
+        _ => MANDEL_INVALID,
+    }
+}
+
+#[test]
+fn test_mandel_render() {
+    let mut params = MandelParams {
+        width: 40,
+        height: 30,
+        top_left_re: -1.2,
+        top_left_im: 0.35,
+        bot_right_re: -1.0,
+        bot_right_im: 0.2,
+        iterations: 0,
+        threads: 2,
+    };
+    let mut out = vec![0; 40 * 30];
+    assert_eq!(unsafe { mandel_render(&params, out.as_mut_ptr()) }, MANDEL_OK);
+    let expected = Renderer::new().bounds(40, 30).corners("-1.2,0.35", "-1,0.2").render();
+    assert_eq!(out, expected.unwrap().into_pixels());
+
+    assert_eq!(unsafe { mandel_render(&params, std::ptr::null_mut()) }, MANDEL_NULL);
+    params.bot_right_re = -1.4;
+    assert_eq!(unsafe { mandel_render(&params, out.as_mut_ptr()) }, MANDEL_INVALID);
+}
@@ -0,0 +1,375 @@
+use error::MandelError;
+use image::{ImageBuffer, Rgb};
+use std::str::FromStr;
+
+/// How many standard deviations out a Gaussian kernel reaches, past which
+/// its weights are too small to show in 8 bits
+const KERNEL_REACH: f64 = 3.0;
+
+/// Widest a filter's Gaussian can be, in pixels, well past any look worth
+/// having and short of a kernel too big to run
+pub const MAX_SIGMA: f64 = 256.0;
+
+/// Widest `Filter::Denoise` can be: it weighs every pixel of a square
+/// `2 * KERNEL_REACH * sigma` across around each one, with no blur's
+/// shortcut of one pass along each axis, so it costs `sigma^2` a pixel
+pub const MAX_DENOISE_SIGMA: f64 = 16.0;
+
+/// Weights of the red, green and blue channels in how bright a color looks
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// A filter for a finished color image, its sizes in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    /// a Gaussian blur of standard deviation `sigma`
+    Blur { sigma: f64 },
+    /// an unsharp mask, adding `amount` times the difference from a blur
+    /// of `sigma` back on, to stand edges out
+    Sharpen { sigma: f64, amount: f64 },
+    /// a glow around what's brighter than `threshold`, from 0 to 1, blurred
+    /// by `sigma` and laid over the image with `strength`, the way light
+    /// blooms around the bright filaments near the set
+    Bloom { sigma: f64, strength: f64, threshold: f64 },
+    /// each channel through a tone curve, lifting the dim parts of
+    /// sparsely sampled renders without blowing out the bright ones
+    Tone(Tone),
+    /// a bilateral filter, averaging pixels within about `sigma` of each
+    /// other weighted by how close their colors are too, with colors
+    /// `range` apart of 0 to 1 counting for little, so noise smooths out
+    /// and edges stay
+    Denoise { sigma: f64, range: f64 },
+}
+
+/// A tone curve, taking 0 to 0 and 1 to 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tone {
+    /// `ln(1 + gain x) / ln(1 + gain)`
+    Log { gain: f64 },
+    /// `sqrt(x)`
+    Sqrt,
+    /// Reinhard's `e x / (1 + e x)` of `exposure` times the value, scaled
+    /// back up so that 1 stays 1
+    Reinhard { exposure: f64 },
+}
+
+impl Tone {
+    /// map(x) : `x` through the curve, clamped to 0 to 1 first
+    pub fn map(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        match *self {
+            Tone::Log { gain } => (gain as f32 * x).ln_1p() / (gain as f32).ln_1p(),
+            Tone::Sqrt => x.sqrt(),
+            Tone::Reinhard { exposure } => {
+                let e = exposure as f32;
+                x * (1.0 + e) / (1.0 + e * x)
+            }
+        }
+    }
+}
+
+/// Parsed from `blur:SIGMA`, `sharpen:SIGMA[:AMOUNT]`,
+/// `bloom:SIGMA[:STRENGTH[:THRESHOLD]]`, `tone:log[:GAIN]`, `tone:sqrt`,
+/// `tone:reinhard[:EXPOSURE]` or `denoise:SIGMA[:RANGE]`, an amount and
+/// strength of 1, a threshold of 0.5, a gain of 100, an exposure of 4 and
+/// a range of 0.1 if left out
+impl FromStr for Filter {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Filter, MandelError> {
+        let error = || MandelError::Parse(format!(
+            "bad filter {:?}, expected blur:SIGMA, sharpen:SIGMA[:AMOUNT], \
+             bloom:SIGMA[:STRENGTH[:THRESHOLD]], tone:log[:GAIN], tone:sqrt, \
+             tone:reinhard[:EXPOSURE] or denoise:SIGMA[:RANGE]", s));
+        let mut parts = s.split(':');
+        let mut name = parts.next().unwrap_or("").to_string();
+        if name == "tone" {
+            name = format!("tone:{}", parts.next().unwrap_or(""));
+        }
+        let numbers = parts.map(|part| part.parse::<f64>().ok().filter(|n| n.is_finite()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(error)?;
+        let filter = match (name.as_str(), &numbers[..]) {
+            ("blur", &[sigma]) => Filter::Blur { sigma },
+            ("sharpen", &[sigma]) => Filter::Sharpen { sigma, amount: 1.0 },
+            ("sharpen", &[sigma, amount]) => Filter::Sharpen { sigma, amount },
+            ("bloom", &[sigma]) => Filter::Bloom { sigma, strength: 1.0, threshold: 0.5 },
+            ("bloom", &[sigma, strength]) => Filter::Bloom { sigma, strength, threshold: 0.5 },
+            ("bloom", &[sigma, strength, threshold]) => {
+                Filter::Bloom { sigma, strength, threshold }
+            }
+            ("tone:log", &[]) => Filter::Tone(Tone::Log { gain: 100.0 }),
+            ("tone:log", &[gain]) => Filter::Tone(Tone::Log { gain }),
+            ("tone:sqrt", &[]) => Filter::Tone(Tone::Sqrt),
+            ("tone:reinhard", &[]) => Filter::Tone(Tone::Reinhard { exposure: 4.0 }),
+            ("tone:reinhard", &[exposure]) => Filter::Tone(Tone::Reinhard { exposure }),
+            ("denoise", &[sigma]) => Filter::Denoise { sigma, range: 0.1 },
+            ("denoise", &[sigma, range]) => Filter::Denoise { sigma, range },
+            _ => return Err(error()),
+        };
+        let sized = |sigma: f64| sigma > 0.0 && sigma <= MAX_SIGMA;
+        let valid = match filter {
+            Filter::Blur { sigma } => sized(sigma),
+            Filter::Sharpen { sigma, amount } => sized(sigma) && amount >= 0.0,
+            Filter::Bloom { sigma, strength, threshold } => {
+                sized(sigma) && strength >= 0.0 && (0.0 .. 1.0).contains(&threshold)
+            }
+            Filter::Tone(Tone::Log { gain }) => gain > 0.0,
+            Filter::Tone(Tone::Sqrt) => true,
+            Filter::Tone(Tone::Reinhard { exposure }) => exposure > 0.0,
+            Filter::Denoise { sigma, range } => {
+                sized(sigma) && sigma <= MAX_DENOISE_SIGMA && range > 0.0
+            }
+        };
+        if valid { Ok(filter) } else { Err(error()) }
+    }
+}
+
+/// kernel(s) : the weights of a Gaussian of standard deviation `sigma`
+/// from its middle out to `KERNEL_REACH` of them each way, adding to 1
+fn kernel(sigma: f64) -> Vec<f32> {
+    let radius = (KERNEL_REACH * sigma).ceil() as i64;
+    let weights: Vec<f64> = (-radius ..= radius)
+        .map(|x| (-(x * x) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = weights.iter().sum();
+    weights.iter().map(|&weight| (weight / total) as f32).collect()
+}
+
+/// blur(p, b, s) : `pixels` of a `bounds` image blurred by a Gaussian of
+/// `sigma`, across and then down, the pixels at the edges carrying on past
+/// them
+fn blur(pixels: &[[f32; 3]], bounds: (usize, usize), sigma: f64) -> Vec<[f32; 3]> {
+    let (width, height) = bounds;
+    let weights = kernel(sigma);
+    let radius = (weights.len() / 2) as i64;
+    let pass = |pixels: &[[f32; 3]], across: bool| {
+        let mut out = vec![[0.0; 3]; pixels.len()];
+        for y in 0 .. height {
+            for x in 0 .. width {
+                let mut sum = [0.0f32; 3];
+                for (offset, &weight) in (-radius ..= radius).zip(&weights) {
+                    let (nx, ny) = if across {
+                        ((x as i64 + offset).clamp(0, width as i64 - 1) as usize, y)
+                    } else {
+                        (x, (y as i64 + offset).clamp(0, height as i64 - 1) as usize)
+                    };
+                    let pixel = pixels[ny * width + nx];
+                    for (sum, channel) in sum.iter_mut().zip(pixel) {
+                        *sum += weight * channel;
+                    }
+                }
+                out[y * width + x] = sum;
+            }
+        }
+        out
+    };
+    pass(&pass(pixels, true), false)
+}
+
+/// denoise(p, b, s, r) : `pixels` of a `bounds` image through a bilateral
+/// filter of `sigma` pixels and `range` apart in color
+fn denoise(pixels: &[[f32; 3]], bounds: (usize, usize), sigma: f64, range: f64)
+    -> Vec<[f32; 3]>
+{
+    let (width, height) = bounds;
+    let radius = (KERNEL_REACH * sigma).ceil() as i64;
+    let near = |d2: f32| (-d2 / (2.0 * (sigma * sigma) as f32)).exp();
+    let alike = |d2: f32| (-d2 / (2.0 * (range * range) as f32)).exp();
+    let mut out = vec![[0.0; 3]; pixels.len()];
+    for y in 0 .. height as i64 {
+        for x in 0 .. width as i64 {
+            let middle = pixels[y as usize * width + x as usize];
+            let (mut sum, mut total) = ([0.0f32; 3], 0.0f32);
+            for ny in (y - radius).max(0) ..= (y + radius).min(height as i64 - 1) {
+                for nx in (x - radius).max(0) ..= (x + radius).min(width as i64 - 1) {
+                    let pixel = pixels[ny as usize * width + nx as usize];
+                    let apart: f32 = pixel.iter().zip(&middle).map(|(a, b)| (a - b) * (a - b))
+                        .sum();
+                    let weight = near(((nx - x).pow(2) + (ny - y).pow(2)) as f32) * alike(apart);
+                    for (sum, channel) in sum.iter_mut().zip(pixel) {
+                        *sum += weight * channel;
+                    }
+                    total += weight;
+                }
+            }
+            out[y as usize * width + x as usize] = sum.map(|sum| sum / total);
+        }
+    }
+    out
+}
+
+impl Filter {
+    /// Filter `pixels` of a `bounds` image, each channel from 0 to 1 but
+    /// free to stray past either end until the last filter is done
+    fn run(&self, pixels: &mut [[f32; 3]], bounds: (usize, usize)) {
+        match *self {
+            Filter::Blur { sigma } => {
+                let blurred = blur(pixels, bounds, sigma);
+                pixels.copy_from_slice(&blurred);
+            }
+            Filter::Sharpen { sigma, amount } => {
+                let blurred = blur(pixels, bounds, sigma);
+                for (pixel, blurred) in pixels.iter_mut().zip(blurred) {
+                    for (channel, blurred) in pixel.iter_mut().zip(blurred) {
+                        *channel += amount as f32 * (*channel - blurred);
+                    }
+                }
+            }
+            Filter::Bloom { sigma, strength, threshold } => {
+                let threshold = threshold as f32;
+                let bright: Vec<[f32; 3]> = pixels.iter().map(|&pixel| {
+                    let luma: f32 = pixel.iter().zip(&LUMA).map(|(c, w)| c * w).sum();
+                    let over = ((luma - threshold) / (1.0 - threshold)).clamp(0.0, 1.0);
+                    pixel.map(|channel| channel.clamp(0.0, 1.0) * over)
+                }).collect();
+                let glow = blur(&bright, bounds, sigma);
+                // screened on, so it lightens without ever going past white
+                for (pixel, glow) in pixels.iter_mut().zip(glow) {
+                    for (channel, glow) in pixel.iter_mut().zip(glow) {
+                        let glow = (strength as f32 * glow).min(1.0);
+                        *channel = 1.0 - (1.0 - *channel) * (1.0 - glow);
+                    }
+                }
+            }
+            Filter::Tone(tone) => {
+                for channel in pixels.iter_mut().flatten() {
+                    *channel = tone.map(*channel);
+                }
+            }
+            Filter::Denoise { sigma, range } => {
+                let denoised = denoise(pixels, bounds, sigma, range);
+                pixels.copy_from_slice(&denoised);
+            }
+        }
+    }
+}
+
+/// Run each of `filters` over `image` in turn, keeping the pixels as
+/// floats in between so that rounding doesn't build up along the chain
+pub fn apply(filters: &[Filter], image: &mut ImageBuffer<Rgb>) {
+    if filters.is_empty() {
+        return;
+    }
+    let bounds = image.bounds();
+    let mut pixels: Vec<[f32; 3]> = image.pixels().iter()
+        .map(|pixel| pixel.0.map(|channel| channel as f32 / 255.0))
+        .collect();
+    for filter in filters {
+        filter.run(&mut pixels, bounds);
+    }
+    for (pixel, filtered) in image.pixels_mut().iter_mut().zip(pixels) {
+        *pixel = Rgb(filtered.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8));
+    }
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!("blur:2".parse::<Filter>().unwrap(), Filter::Blur { sigma: 2.0 });
+    assert_eq!("sharpen:1".parse::<Filter>().unwrap(),
+               Filter::Sharpen { sigma: 1.0, amount: 1.0 });
+    assert_eq!("bloom:4:0.5".parse::<Filter>().unwrap(),
+               Filter::Bloom { sigma: 4.0, strength: 0.5, threshold: 0.5 });
+    assert_eq!("bloom:4:2:0.8".parse::<Filter>().unwrap(),
+               Filter::Bloom { sigma: 4.0, strength: 2.0, threshold: 0.8 });
+    assert_eq!("tone:log".parse::<Filter>().unwrap(), Filter::Tone(Tone::Log { gain: 100.0 }));
+    assert_eq!("tone:reinhard:2".parse::<Filter>().unwrap(),
+               Filter::Tone(Tone::Reinhard { exposure: 2.0 }));
+    assert_eq!("denoise:2".parse::<Filter>().unwrap(), Filter::Denoise { sigma: 2.0, range: 0.1 });
+    assert!(format!("denoise:{}", MAX_DENOISE_SIGMA).parse::<Filter>().is_ok());
+    for bad in &["", "blur", "blur:0", "blur:-1", "blur:1:2", "sharpen:1:-1", "bloom:1:1:1",
+                 "glow:1", "blur:x", "blur:inf", "blur:1000", "tone", "tone:gamma", "tone:sqrt:2",
+                 "tone:log:0", "denoise:1:0", "denoise", "denoise:17", "denoise:256"] {
+        assert!(bad.parse::<Filter>().is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_blur() {
+    let weights = kernel(1.0);
+    assert_eq!(weights.len(), 7);
+    assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+    assert!(weights[3] > weights[2] && weights[2] == weights[4]);
+
+    // a dot spreads out evenly and keeps its light; a flat image stays flat
+    let mut pixels = vec![[0.0; 3]; 81];
+    pixels[40] = [1.0; 3];
+    let blurred = blur(&pixels, (9, 9), 1.0);
+    assert!((blurred.iter().map(|p| p[0]).sum::<f32>() - 1.0).abs() < 1e-5);
+    assert_eq!((blurred[39], blurred[31]), (blurred[41], blurred[49]));
+    assert!(blurred[40][0] < 1.0 && blurred[39][0] > blurred[38][0]);
+    let flat = blur(&[[0.5; 3]; 12], (4, 3), 2.0);
+    assert!(flat.iter().flatten().all(|&c| (c - 0.5).abs() < 1e-6));
+}
+
+#[test]
+fn test_apply() {
+    let gray = |value: u8| Rgb([value; 3]);
+    // half black and half white, split down the middle
+    let edge = ImageBuffer::from_pixels((8, 1), (0 .. 8).map(|x| gray(if x < 4 { 0 } else { 200 }))
+        .collect());
+
+    let mut blurred = edge.clone();
+    apply(&[Filter::Blur { sigma: 1.0 }], &mut blurred);
+    assert!(blurred[(3, 0)].0[0] > 0 && blurred[(4, 0)].0[0] < 200);
+
+    // sharpening overshoots either side of the edge, and leaves flat
+    // parts be
+    let mut sharpened = edge.clone();
+    apply(&[Filter::Sharpen { sigma: 1.0, amount: 1.0 }], &mut sharpened);
+    assert_eq!((sharpened[(0, 0)], sharpened[(3, 0)]), (gray(0), gray(0)));
+    assert!(sharpened[(4, 0)].0[0] > 200 && sharpened[(7, 0)] == gray(200));
+
+    // bloom lights up the dark side near the bright one, and does nothing
+    // with nothing over its threshold
+    let mut bloomed = edge.clone();
+    apply(&[Filter::Bloom { sigma: 1.0, strength: 1.0, threshold: 0.5 }], &mut bloomed);
+    assert!(bloomed[(3, 0)].0[0] > bloomed[(0, 0)].0[0] && bloomed[(0, 0)] == gray(0));
+    assert!(bloomed[(4, 0)].0[0] > 200);
+    let mut dim = ImageBuffer::from_pixels((4, 1), vec![gray(0), gray(100), gray(0), gray(60)]);
+    let before = dim.clone();
+    apply(&[Filter::Bloom { sigma: 1.0, strength: 1.0, threshold: 0.5 }], &mut dim);
+    assert_eq!(dim, before);
+
+    // filters run in the order given
+    let (mut a, mut b) = (edge.clone(), edge.clone());
+    let bloom = Filter::Bloom { sigma: 1.0, strength: 1.0, threshold: 0.5 };
+    apply(&[Filter::Blur { sigma: 1.0 }, bloom], &mut a);
+    apply(&[bloom, Filter::Blur { sigma: 1.0 }], &mut b);
+    assert_ne!(a, b);
+    let mut same = edge.clone();
+    apply(&[], &mut same);
+    assert_eq!(same, edge);
+}
+
+#[test]
+fn test_tone() {
+    for tone in &[Tone::Log { gain: 100.0 }, Tone::Sqrt, Tone::Reinhard { exposure: 4.0 }] {
+        assert_eq!((tone.map(-1.0), tone.map(0.0)), (0.0, 0.0));
+        assert!((tone.map(1.0) - 1.0).abs() < 1e-6 && tone.map(2.0) == tone.map(1.0));
+        // dim values come up the most
+        let values: Vec<f32> = (0 ..= 10).map(|x| tone.map(x as f32 / 10.0)).collect();
+        assert!(values.windows(2).all(|pair| pair[1] > pair[0]), "{:?}", tone);
+        assert!(tone.map(0.1) > 0.3, "{:?}", tone);
+    }
+    assert_eq!(Tone::Sqrt.map(0.25), 0.5);
+    assert_eq!(Tone::Reinhard { exposure: 1.0 }.map(0.5), 0.5 * 2.0 / 1.5);
+}
+
+#[test]
+fn test_denoise() {
+    // speckles on a flat field die down, and an edge between two fields
+    // stays where a blur would spread it
+    let gray = |value: u8| Rgb([value; 3]);
+    let mut pixels: Vec<Rgb> = (0 .. 16 * 8).map(|i| gray(if i % 16 < 8 { 40 } else { 220 }))
+        .collect();
+    pixels[3 * 16 + 3] = gray(70);
+    pixels[5 * 16 + 12] = gray(190);
+    let noisy = ImageBuffer::from_pixels((16, 8), pixels);
+    let mut denoised = noisy.clone();
+    apply(&[Filter::Denoise { sigma: 2.0, range: 0.2 }], &mut denoised);
+    assert!(denoised[(3, 3)].0[0] < 50 && denoised[(12, 5)].0[0] > 210);
+    assert_eq!((denoised[(7, 0)], denoised[(8, 0)]), (gray(40), gray(220)));
+    let mut blurred = noisy.clone();
+    apply(&[Filter::Blur { sigma: 2.0 }], &mut blurred);
+    assert!(blurred[(7, 0)].0[0] > 60);
+}
@@ -0,0 +1,262 @@
+use core::Real;
+use error::MandelError;
+use num::{BigInt, Complex, Float, Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::ops::{Add, Mul, Sub};
+
+/// Fixed-point real number with arbitrary precision, `mant / 2^bits`
+///
+/// Used for zooms where neighbouring pixels are no longer distinguishable
+/// as `f64` coordinates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fixed {
+    mant: BigInt,
+    bits: usize,
+}
+
+impl Fixed {
+    pub fn zero(bits: usize) -> Fixed {
+        Fixed { mant: BigInt::zero(), bits }
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+
+    pub fn from_f64(x: f64, bits: usize) -> Fixed {
+        let (m, e, sign) = x.integer_decode();
+        let mant = BigInt::from(m) * sign as i64;
+        let shift = e as isize + bits as isize;
+        let mant = if shift >= 0 {
+            mant << shift as usize
+        } else {
+            mant >> (-shift) as usize
+        };
+        Fixed { mant, bits }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // keep the top 64 bits so the conversion can't overflow
+        let len = self.mant.bits();
+        let shift = len.saturating_sub(64);
+        let m = (&self.mant >> shift).to_f64().unwrap();
+        // scale in two steps: 2^e alone may underflow when m * 2^e doesn't
+        let e = shift as i32 - self.bits as i32;
+        m * 2.0.powi(e / 2) * 2.0.powi(e - e / 2)
+    }
+
+    /// Parse a decimal string such as `-0.743643887037158704752`
+    /// or `1.5e-20` without going through `f64`.
+    pub fn parse(s: &str, bits: usize) -> Result<Fixed, MandelError> {
+        let error = |why: &str| MandelError::Parse(format!("'{}' {}", s, why));
+        let (negative, rest) = match s.as_bytes().first() {
+            Some(&b'-') => (true, &s[1..]),
+            Some(&b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (digits, exp) = match rest.find(['e', 'E']) {
+            Some(index) => {
+                let exp = rest[index+1..].parse::<i64>()
+                    .map_err(|_| error("has a bad exponent"))?;
+                (&rest[..index], exp)
+            }
+            None => (rest, 0),
+        };
+        let (int, frac) = match digits.find('.') {
+            Some(index) => (&digits[..index], &digits[index+1..]),
+            None => (digits, ""),
+        };
+        let all = format!("{}{}", int, frac);
+        if all.is_empty() || !all.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(error("isn't a decimal number"));
+        }
+        // digits past the decimal place of 2^-bits, and a guard digit, only
+        // cost time, so they're dropped, however many there are and whether
+        // it's the exponent or the digits themselves that puts them there
+        let places = (bits as f64 * 2f64.log10()).ceil() as i64 + 1;
+        let exp = exp.saturating_sub(frac.len() as i64);
+        let keep = exp.saturating_add(places).saturating_add(all.len() as i64)
+            .clamp(0, all.len() as i64) as usize;
+        let exp = exp.saturating_add((all.len() - keep) as i64);
+        let kept = all[..keep].trim_start_matches('0');
+        if kept.is_empty() {
+            return Ok(Fixed::zero(bits));
+        }
+        // and a value with more whole digits than a 2^bits fraction has is
+        // no coordinate
+        if exp.saturating_add(kept.len() as i64) > (bits / 3 + 1) as i64 {
+            return Err(error("is too big to be a coordinate"));
+        }
+        let value = BigInt::parse_bytes(kept.as_bytes(), 10)
+            .ok_or_else(|| error("isn't a decimal number"))?;
+        let ten = BigInt::from(10);
+        let mant = if exp >= 0 {
+            (value * num::pow(ten, exp as usize)) << bits
+        } else {
+            (value << bits) / num::pow(ten, (-exp) as usize)
+        };
+        let mant = if negative { -mant } else { mant };
+        Ok(Fixed { mant, bits })
+    }
+
+    /// to_decimal(p) : the value written out to `places` decimal places,
+    /// rounded to the nearest
+    pub fn to_decimal(&self, places: usize) -> String {
+        let scaled: BigInt = self.mant.abs() * num::pow(BigInt::from(10), places);
+        let half: BigInt = (BigInt::from(1) << self.bits) >> 1;
+        let digits = ((scaled + half) >> self.bits).to_string();
+        let digits = format!("{:0>width$}", digits, width = places + 1);
+        let (int, frac) = digits.split_at(digits.len() - places);
+        let sign = if self.mant.is_negative() && digits.bytes().any(|b| b != b'0') { "-" }
+                   else { "" };
+        if places == 0 {
+            format!("{}{}", sign, int)
+        } else {
+            format!("{}{}.{}", sign, int, frac)
+        }
+    }
+
+    /// `self * num / den`, exact up to the last bit
+    pub fn scale(&self, num: u64, den: u64) -> Fixed {
+        Fixed {
+            mant: &self.mant * BigInt::from(num) / BigInt::from(den),
+            bits: self.bits,
+        }
+    }
+}
+
+/// Values of the same precision compare by value
+impl PartialOrd for Fixed {
+    fn partial_cmp(&self, other: &Fixed) -> Option<Ordering> {
+        debug_assert_eq!(self.bits, other.bits);
+        self.mant.partial_cmp(&other.mant)
+    }
+}
+
+impl Real for Fixed {
+    fn like(&self, x: f64) -> Fixed {
+        Fixed::from_f64(x, self.bits)
+    }
+
+    fn add(&self, other: &Fixed) -> Fixed {
+        self + other
+    }
+
+    fn sub(&self, other: &Fixed) -> Fixed {
+        self - other
+    }
+
+    fn mul(&self, other: &Fixed) -> Fixed {
+        self * other
+    }
+
+    fn scale(&self, num: u64, den: u64) -> Fixed {
+        Fixed::scale(self, num, den)
+    }
+}
+
+impl Add<&Fixed> for &Fixed {
+    type Output = Fixed;
+
+    fn add(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed { mant: &self.mant + &other.mant, bits: self.bits }
+    }
+}
+
+impl Sub<&Fixed> for &Fixed {
+    type Output = Fixed;
+
+    fn sub(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed { mant: &self.mant - &other.mant, bits: self.bits }
+    }
+}
+
+impl Mul<&Fixed> for &Fixed {
+    type Output = Fixed;
+
+    fn mul(self, other: &Fixed) -> Fixed {
+        debug_assert_eq!(self.bits, other.bits);
+        Fixed { mant: (&self.mant * &other.mant) >> self.bits, bits: self.bits }
+    }
+}
+
+/// parse_complex(s, b) : the complex number written as `re,im`, in fixed
+/// point with `bits` bits
+pub fn parse_complex(s: &str, bits: usize) -> Result<Complex<Fixed>, MandelError> {
+    let (re, im) = super::parse_pair::<String>(s, ',')?;
+    Ok(Complex { re: Fixed::parse(&re, bits)?, im: Fixed::parse(&im, bits)? })
+}
+
+pub fn to_f64(z: &Complex<Fixed>) -> Complex<f64> {
+    Complex { re: z.re.to_f64(), im: z.im.to_f64() }
+}
+
+/// format_complex(z, p) : `z` written as `re,im` to `places` decimal
+/// places, for `parse_complex` to read back
+pub fn format_complex(z: &Complex<Fixed>, places: usize) -> String {
+    format!("{},{}", z.re.to_decimal(places), z.im.to_decimal(places))
+}
+
+#[test]
+fn test_fixed_parse() {
+    assert_eq!(Fixed::parse("1.25", 64).unwrap().to_f64(), 1.25);
+    assert_eq!(Fixed::parse("-0.0625", 64).unwrap().to_f64(), -0.0625);
+    assert_eq!(Fixed::parse("3e-2", 64).unwrap().to_f64(), 0.03);
+    assert_eq!(Fixed::parse("1.5e-20", 128).unwrap().to_f64(), 1.5e-20);
+    assert!(Fixed::parse("", 64).is_err());
+    assert!(Fixed::parse("1.2.3", 64).is_err());
+    assert!(Fixed::parse("-", 64).is_err());
+    assert_eq!(Fixed::parse("1e", 64).unwrap_err().to_string(), "'1e' has a bad exponent");
+    assert_eq!(Fixed::parse("0x1", 64).unwrap_err().to_string(), "'0x1' isn't a decimal number");
+
+    // exponents and digits past the precision give 0 or nothing, without
+    // working out the power of 10
+    assert_eq!(Fixed::parse("1e-100000000", 64).unwrap(), Fixed::zero(64));
+    assert_eq!(Fixed::parse("-9.99e-40", 64).unwrap(), Fixed::zero(64));
+    assert!(Fixed::parse("1e100000000", 64).is_err());
+    assert!(Fixed::parse("1e30", 64).is_err());
+    assert_eq!(Fixed::parse(&format!("0.{}1", "0".repeat(1000)), 64).unwrap(),
+               Fixed::zero(64));
+    let long = format!("-0.7436438870371587047{}", "3".repeat(10_000));
+    assert_eq!(Fixed::parse(&long, 64).unwrap().to_decimal(18),
+               Fixed::parse("-0.7436438870371587047", 64).unwrap().to_decimal(18));
+    assert_eq!(Fixed::parse(&format!("7.5{}e-20", "9".repeat(100)), 64).unwrap(),
+               Fixed::parse(&format!("0.000000000000000000075{}", "9".repeat(100)), 64)
+                   .unwrap());
+    assert!(Fixed::parse("1e-19", 64).unwrap().to_f64() > 0.0);
+}
+
+#[test]
+fn test_fixed_to_decimal() {
+    assert_eq!(Fixed::from_f64(1.25, 64).to_decimal(3), "1.250");
+    assert_eq!(Fixed::from_f64(-0.0625, 64).to_decimal(3), "-0.063");
+    assert_eq!(Fixed::from_f64(-0.0004, 64).to_decimal(3), "0.000");
+    assert_eq!(Fixed::from_f64(-2.5, 64).to_decimal(0), "-3");
+    let digits = "-0.7436438870371587047522397827022759";
+    let x = Fixed::parse(digits, 160).unwrap();
+    assert_eq!(x.to_decimal(34), digits);
+    assert_eq!(Fixed::parse(&x.to_decimal(40), 160).unwrap().to_decimal(34), digits);
+    let z = parse_complex("0.5,-1e-30", 128).unwrap();
+    assert_eq!(format_complex(&z, 31), "0.5000000000000000000000000000000,\
+                                        -0.0000000000000000000000000000010");
+}
+
+#[test]
+fn test_fixed_arithmetic() {
+    let a = Fixed::from_f64(1.5, 64);
+    let b = Fixed::from_f64(-0.25, 64);
+    assert_eq!((&a * &b).to_f64(), -0.375);
+    assert_eq!((&a + &b).to_f64(), 1.25);
+    assert_eq!((&a - &b).to_f64(), 1.75);
+    assert_eq!(a.scale(3, 4).to_f64(), 1.125);
+}
+
+#[test]
+fn test_fixed_escape_time_matches_f64() {
+    for &(re, im) in &[(0.3, 0.5), (-0.75, 0.1), (-1.0, 0.0), (0.26, 0.0)] {
+        let c = Complex { re: Fixed::from_f64(re, 96), im: Fixed::from_f64(im, 96) };
+        assert_eq!(::escape_time(c, 255), ::escape_time(Complex { re, im }, 255));
+    }
+}
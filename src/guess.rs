@@ -0,0 +1,127 @@
+use fixed::Fixed;
+use num::Complex;
+use precision::Precision;
+use render_points_parallel;
+
+/// Side of the blocks the image is first divided into, a power of two
+pub const BLOCK_SIZE: usize = 16;
+
+/// corners(b, blk) : the pixels at the corners of `blk`, pulled in to
+/// the last row and column where the block runs off the image
+fn corners(bounds: (usize, usize), block: (usize, usize, usize)) -> [(usize, usize); 4] {
+    let (x, y, size) = block;
+    let right = (x + size).min(bounds.0 - 1);
+    let bottom = (y + size).min(bounds.1 - 1);
+    [(x, y), (right, y), (x, bottom), (right, bottom)]
+}
+
+/// Render by solid guessing, as Fractint does
+///
+/// The corners of every `block_size` square are computed first. A block
+/// whose corners all came out the same shade is filled with it unseen;
+/// the rest are split in four and tried again, down to single pixels.
+/// Thin features crossing a block without touching its corners are
+/// lost, which is the price of skipping most of the interior.
+///
+/// Returns how many pixels were actually computed.
+#[allow(clippy::too_many_arguments)]
+pub fn render(pixels: &mut [u8],
+              bounds: (usize, usize),
+              top_left: Complex<f64>,
+              bot_right: Complex<f64>,
+              deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+              precision: Precision,
+              limit: u32,
+              threads: usize,
+              block_size: usize)
+    -> usize
+{
+    assert!(pixels.len() == bounds.0 * bounds.1);
+    assert!(block_size.is_power_of_two());
+
+    let mut computed = vec![false; pixels.len()];
+    let mut count = 0;
+    let mut blocks: Vec<_> = (0 .. bounds.1).step_by(block_size)
+        .flat_map(|y| (0 .. bounds.0).step_by(block_size).map(move |x| (x, y, block_size)))
+        .collect();
+
+    while !blocks.is_empty() {
+        let mut points: Vec<_> = blocks.iter()
+            .flat_map(|&block| corners(bounds, block).to_vec())
+            .filter(|&(col, row)| !computed[row * bounds.0 + col])
+            .collect();
+        points.sort_by_key(|&(col, row)| (row, col));
+        points.dedup();
+
+        let shades = render_points_parallel(&points, bounds, top_left, bot_right,
+                                            deep, precision, limit, threads);
+        for (&(col, row), &shade) in points.iter().zip(&shades) {
+            pixels[row * bounds.0 + col] = shade;
+            computed[row * bounds.0 + col] = true;
+        }
+        count += points.len();
+
+        let mut split = Vec::new();
+        for block in blocks {
+            let (x, y, size) = block;
+            let shades = corners(bounds, block).map(|(col, row)| pixels[row * bounds.0 + col]);
+            if shades.iter().all(|&shade| shade == shades[0]) {
+                for row in y .. (y + size).min(bounds.1) {
+                    for col in x .. (x + size).min(bounds.0) {
+                        if !computed[row * bounds.0 + col] {
+                            pixels[row * bounds.0 + col] = shades[0];
+                        }
+                    }
+                }
+            } else if size > 1 {
+                let half = size / 2;
+                for &(dx, dy) in &[(0, 0), (half, 0), (0, half), (half, half)] {
+                    if x + dx < bounds.0 && y + dy < bounds.1 {
+                        split.push((x + dx, y + dy, half));
+                    }
+                }
+            }
+        }
+        blocks = split;
+    }
+    count
+}
+
+#[test]
+fn test_single_pixel_blocks_are_exact() {
+    let bounds = (37, 29);
+    let top_left = Complex { re: -1.2, im: 0.35 };
+    let bot_right = Complex { re: -1.0, im: 0.2 };
+
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    let count = render(&mut pixels, bounds, top_left, bot_right,
+                       None, Precision::F64, 255, 3, 1);
+    assert_eq!(count, bounds.0 * bounds.1);
+
+    let all: Vec<_> = (0 .. bounds.1)
+        .flat_map(|row| (0 .. bounds.0).map(move |col| (col, row)))
+        .collect();
+    assert_eq!(pixels, ::render_points(&all, bounds, top_left, bot_right,
+                                       None, Precision::F64, 255));
+}
+
+#[test]
+fn test_guessing_skips_solid_areas() {
+    // the whole set, with large areas of both interior and exterior
+    let bounds = (200, 150);
+    let top_left = Complex { re: -2.0, im: 1.125 };
+    let bot_right = Complex { re: 1.0, im: -1.125 };
+
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    let count = render(&mut pixels, bounds, top_left, bot_right,
+                       None, Precision::F64, 255, 3, BLOCK_SIZE);
+    assert!(count < bounds.0 * bounds.1 / 2);
+
+    let all: Vec<_> = (0 .. bounds.1)
+        .flat_map(|row| (0 .. bounds.0).map(move |col| (col, row)))
+        .collect();
+    let exact = ::render_points(&all, bounds, top_left, bot_right,
+                                None, Precision::F64, 255);
+    let wrong = pixels.iter().zip(&exact).filter(|(a, b)| a != b).count();
+    assert!(wrong < bounds.0 * bounds.1 / 100);
+}
@@ -0,0 +1,115 @@
+use pan;
+use precision::Precision;
+use std::fs;
+use std::io;
+
+/// Where the explorer keeps its bookmarks between runs
+pub const FILE: &str = "bookmarks.txt";
+
+/// A view saved under a name, with the corners as they were shown
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    pub limit: u32,
+    pub precision: Precision,
+    pub top_left: String,
+    pub bot_right: String,
+}
+
+impl Bookmark {
+    /// The bookmark's line in a bookmarks file: the view as `pan::describe`
+    /// writes it, then the name, which may have spaces in it
+    fn line(&self) -> String {
+        format!("{} {}",
+                pan::describe(self.limit, self.precision, &self.top_left, &self.bot_right),
+                self.name)
+    }
+}
+
+/// parse(t) : the bookmarks in the text of a bookmarks file
+///
+/// Blank lines, lines starting with `#` and lines that don't parse are
+/// skipped, so files can be written and annotated by hand.
+pub fn parse(text: &str) -> Vec<Bookmark> {
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let words: Vec<&str> = line.trim().splitn(5, ' ').collect();
+            if words.len() != 5 || words[4].trim().is_empty() {
+                return None;
+            }
+            Some(Bookmark {
+                name: words[4].trim().to_string(),
+                limit: words[0].parse().ok()?,
+                precision: words[1].parse().ok()?,
+                top_left: words[2].to_string(),
+                bot_right: words[3].to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Read the bookmarks in `path`, of which there are none if it doesn't
+/// exist yet
+pub fn load(path: &str) -> io::Result<Vec<Bookmark>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(parse(&text)),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Write `bookmarks` to `path`, one to a line
+pub fn save(path: &str, bookmarks: &[Bookmark]) -> io::Result<()> {
+    let mut text = String::new();
+    for bookmark in bookmarks {
+        text.push_str(&bookmark.line());
+        text.push('\n');
+    }
+    fs::write(path, text)
+}
+
+/// Add `bookmark` to `bookmarks`, in place of any with the same name
+pub fn add(bookmarks: &mut Vec<Bookmark>, bookmark: Bookmark) {
+    match bookmarks.iter().position(|b| b.name == bookmark.name) {
+        Some(i) => bookmarks[i] = bookmark,
+        None => bookmarks.push(bookmark),
+    }
+}
+
+#[test]
+fn test_parse_bookmarks() {
+    let seahorse = Bookmark {
+        name: "seahorse valley".to_string(),
+        limit: 1000,
+        precision: Precision::F64,
+        top_left: "-0.75,0.1".to_string(),
+        bot_right: "-0.74,0.09".to_string(),
+    };
+    assert_eq!(seahorse.line(), "1000 f64 -0.75,0.1 -0.74,0.09 seahorse valley");
+    let text = format!("# shared by a friend\n\n{}\n255 f64 -2,1\n\
+                        255 f16 -2,1 1,-1 broken\n  64 f32 -2,1 1,-1 whole  \n",
+                       seahorse.line());
+    let mut bookmarks = parse(&text);
+    assert_eq!(bookmarks.len(), 2);
+    assert_eq!(bookmarks[0], seahorse);
+    assert_eq!(bookmarks[1].name, "whole");
+    assert_eq!(bookmarks[1].precision, Precision::F32);
+
+    add(&mut bookmarks, Bookmark { limit: 64, ..seahorse.clone() });
+    assert_eq!(bookmarks.len(), 2);
+    assert_eq!(bookmarks[0].limit, 64);
+    add(&mut bookmarks, Bookmark { name: "again".to_string(), ..seahorse });
+    assert_eq!(bookmarks.len(), 3);
+}
+
+#[test]
+fn test_save_and_load_bookmarks() {
+    let path = std::env::temp_dir().join(format!("bookmarks-{}.txt", std::process::id()));
+    let path = path.to_str().unwrap();
+    assert_eq!(load(path).unwrap(), []);
+    let bookmarks = parse("300 f32 -2,1.5 1,-1.5 the whole set\n");
+    save(path, &bookmarks).unwrap();
+    assert_eq!(load(path).unwrap(), bookmarks);
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,577 @@
+mod bookmarks;
+mod panel;
+pub mod screensaver;
+
+use error::MandelError;
+use num::Complex;
+use history::History;
+use julia;
+use pan;
+use fixed::Fixed;
+use pixels::{Pixels, SurfaceTexture};
+use precision::{self, Precision};
+use progressive;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{ElementState, Event, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{Key, ModifiersState, NamedKey};
+use winit::event_loop::{EventLoopBuilder, EventLoopProxy};
+use winit::window::WindowBuilder;
+use self::bookmarks::Bookmark;
+use self::panel::{Action, Controls, Panel};
+use viewport::Viewport;
+use {format_complex, parse_complex, render_streaming};
+
+/// How much one notch of the scroll wheel zooms in
+const ZOOM_STEP: f64 = 0.8;
+
+/// Fraction of the view an arrow key pans by, and with shift held
+const PAN_STEP: f64 = 0.1;
+const FINE_PAN_STEP: f64 = 0.01;
+
+/// How much `+` zooms in, and with shift held
+const KEY_ZOOM_STEP: f64 = 0.5;
+const FINE_KEY_ZOOM_STEP: f64 = 0.9;
+
+/// Pixels the mouse must move with the button down to start a selection
+/// rather than count as a click
+const DRAG_THRESHOLD: f64 = 4.0;
+
+/// Side of the Julia set shown in the panel
+const JULIA_SIZE: usize = 200;
+
+/// Shown in the panel once the view is too deep to hold in `f64`
+const TOO_DEEP: &str = "zoomed in past what f64 can place, the view is off; zoom out";
+
+/// pixel(p) : the pixel under the cursor at `p`, clamped to the window's
+/// top and left edges
+fn pixel(p: PhysicalPosition<f64>) -> (usize, usize) {
+    (p.x.max(0.0) as usize, p.y.max(0.0) as usize)
+}
+
+/// Everything undo takes the explorer back to
+#[derive(Clone, Copy, PartialEq)]
+struct Step {
+    view: Viewport,
+    limit: u32,
+    precision: Precision,
+}
+
+/// Copy gray `pixels` into pane `pane` of the RGBA `frame`, which holds
+/// `panes` panes of `bounds` side by side
+fn draw(frame: &mut [u8], bounds: (usize, usize), pane: usize, panes: usize, pixels: &[u8]) {
+    let stride = 4 * bounds.0 * panes;
+    for (row, line) in pixels.chunks(bounds.0).enumerate() {
+        let start = row * stride + 4 * pane * bounds.0;
+        for (rgba, &gray) in frame[start .. start + 4 * bounds.0].chunks_exact_mut(4).zip(line) {
+            rgba.copy_from_slice(&[gray, gray, gray, 0xff]);
+        }
+    }
+}
+
+/// Panes the window is split into
+fn pane_count(controls: &Controls) -> usize {
+    if controls.split { 2 } else { 1 }
+}
+
+/// pane_bounds(s, p) : the size of each of `panes` side by side in a
+/// window of `size`
+fn pane_bounds(size: PhysicalSize<u32>, panes: usize) -> (usize, usize) {
+    ((size.width as usize / panes).max(1), (size.height as usize).max(1))
+}
+
+/// Something for the window to show, sent from another thread
+enum Message {
+    /// one pass of a render of one pane
+    Frame { generation: u64, bounds: (usize, usize), pane: usize, pixels: Vec<u8> },
+    /// an export finished, with how it went
+    Exported(String),
+    /// the Julia set of `c`, `JULIA_SIZE` square
+    Julia { c: Complex<f64>, pixels: Vec<u8> },
+}
+
+/// A view to render, once for each pane with its precision and limit
+struct Job {
+    generation: u64,
+    view: Viewport,
+    panes: Vec<(Precision, u32)>,
+}
+
+/// Render every job sent down the returned channel on a thread of its
+/// own, passing each pass back through `proxy`
+///
+/// A render is dropped between passes as soon as a newer job is asked
+/// for, so the window never waits on a view it has moved away from.
+fn spawn_renderer(proxy: EventLoopProxy<Message>, latest: Arc<AtomicU64>, threads: usize)
+    -> mpsc::Sender<Job>
+{
+    let (sender, jobs) = mpsc::channel::<Job>();
+    thread::spawn(move || {
+        while let Ok(mut job) = jobs.recv() {
+            while let Ok(newer) = jobs.try_recv() {
+                job = newer;
+            }
+            let Job { generation, view, panes } = job;
+            for (pane, &(precision, limit)) in panes.iter().enumerate() {
+                if latest.load(Ordering::SeqCst) != generation {
+                    break;
+                }
+                // perturbation works from the corners in fixed point
+                let deep = match precision {
+                    Precision::Fixed(bits) => {
+                        let to_fixed = |z: Complex<f64>| Complex {
+                            re: Fixed::from_f64(z.re, bits),
+                            im: Fixed::from_f64(z.im, bits),
+                        };
+                        Some((to_fixed(view.top_left), to_fixed(view.bot_right)))
+                    }
+                    _ => None,
+                };
+                let mut pixels = vec![0; view.bounds.0 * view.bounds.1];
+                progressive::render(&mut pixels, view.bounds, view.top_left, view.bot_right,
+                                    deep.as_ref(), precision, limit, threads,
+                                    |_, pixels| {
+                    let frame = Message::Frame {
+                        generation,
+                        bounds: view.bounds,
+                        pane,
+                        pixels: pixels.to_vec(),
+                    };
+                    proxy.send_event(frame).is_ok()
+                        && latest.load(Ordering::SeqCst) == generation
+                });
+            }
+        }
+    });
+    sender
+}
+
+/// Render the Julia set of each `c` sent down the returned channel, with
+/// its iteration limit, on a thread of its own
+///
+/// Only the latest point asked for is rendered, so sweeping the cursor
+/// over the set doesn't queue up sets nobody will see.
+fn spawn_julia(proxy: EventLoopProxy<Message>, threads: usize)
+    -> mpsc::Sender<(Complex<f64>, u32)>
+{
+    let (sender, points) = mpsc::channel::<(Complex<f64>, u32)>();
+    thread::spawn(move || {
+        while let Ok(mut point) = points.recv() {
+            while let Ok(newer) = points.try_recv() {
+                point = newer;
+            }
+            let (c, limit) = point;
+            let mut pixels = vec![0; JULIA_SIZE * JULIA_SIZE];
+            julia::render(&mut pixels, (JULIA_SIZE, JULIA_SIZE),
+                          julia::TOP_LEFT, julia::BOT_RIGHT, c, limit, threads);
+            if proxy.send_event(Message::Julia { c, pixels }).is_err() {
+                return;
+            }
+        }
+    });
+    sender
+}
+
+/// Render `view` at `width` pixels across into `filename` on a thread of
+/// its own, reporting back through `proxy` when done
+fn spawn_export(proxy: EventLoopProxy<Message>,
+                view: Viewport,
+                width: usize,
+                filename: String,
+                precision: Precision,
+                limit: u32,
+                threads: usize)
+{
+    thread::spawn(move || {
+        let bounds = (width, (width * view.bounds.1 / view.bounds.0).max(1));
+        let description = pan::describe(limit, precision, &format_complex(view.top_left),
+                                        &format_complex(view.bot_right));
+        let status = match render_streaming(&filename, bounds, view.top_left, view.bot_right,
+                                            None, precision, limit, threads,
+                                            Some(&description), &[]) {
+            Ok(_) => format!("exported {}x{} to {}", bounds.0, bounds.1, filename),
+            Err(e) => format!("error exporting {}: {}", filename, e),
+        };
+        let _ = proxy.send_event(Message::Exported(status));
+    });
+}
+
+/// Write the panel's bookmarks back to their file, saying so in the panel
+/// if that fails
+fn keep_bookmarks(controls: &mut Controls) {
+    if let Err(e) = bookmarks::save(bookmarks::FILE, &controls.bookmarks) {
+        controls.status = format!("error saving bookmarks to {}: {}", bookmarks::FILE, e);
+    }
+}
+
+/// Open a window on the view and let the user explore from there
+///
+/// Scrolling zooms about the cursor, dragging out a rectangle zooms in on
+/// it and clicking recentres on the cursor. The arrow keys pan and `+` and
+/// `-` zoom about the centre, in finer steps with shift held, and
+/// backspace undoes the last change of view or settings, shift-backspace
+/// redoing it. The view can be split in two to compare settings, both
+/// halves following the mouse as if it were over the left one. Each
+/// change first stretches the image already on screen to fit, then
+/// starts a fresh progressive render, shown pass by pass. Holding Ctrl
+/// over a point shows the Julia set for it in the panel. A side
+/// panel holds the settings, a button to export the view and bookmarks,
+/// kept in `bookmarks::FILE` and shared by importing and exporting files
+/// of them.
+pub fn run(bounds: (usize, usize),
+           top_left: Complex<f64>,
+           bot_right: Complex<f64>,
+           precision: Precision,
+           limit: u32,
+           threads: usize)
+    -> Result<(), MandelError>
+{
+    let event_loop = EventLoopBuilder::<Message>::with_user_event().build()
+        .map_err(|e| MandelError::Gpu(e.to_string()))?;
+    let window = WindowBuilder::new()
+        .with_title("mandelbrot")
+        .with_inner_size(PhysicalSize::new(bounds.0 as u32, bounds.1 as u32))
+        .build(&event_loop)
+        .map_err(|e| MandelError::Gpu(e.to_string()))?;
+    let window = Arc::new(window);
+
+    let size = window.inner_size();
+    let mut view = Viewport::new(bounds, top_left, bot_right)
+        .resize((size.width.max(1) as usize, size.height.max(1) as usize));
+    let surface = SurfaceTexture::new(size.width, size.height, window.clone());
+    let mut screen = Pixels::new(view.bounds.0 as u32, view.bounds.1 as u32, surface)
+        .map_err(|e| MandelError::Gpu(e.to_string()))?;
+    let (saved, status) = match bookmarks::load(bookmarks::FILE) {
+        Ok(saved) => (saved, String::new()),
+        Err(e) => (Vec::new(), format!("error reading {}: {}", bookmarks::FILE, e)),
+    };
+    let mut panel = Panel::new(&window, screen.device(), screen.render_texture_format(),
+                               Controls {
+        limit,
+        precision,
+        top_left: String::new(),
+        bot_right: String::new(),
+        export_width: 4 * bounds.0,
+        export_file: "mandel.png".to_string(),
+        bookmark_name: String::new(),
+        bookmarks: saved,
+        share_file: "shared-bookmarks.txt".to_string(),
+        split: false,
+        right_limit: limit,
+        right_precision: precision,
+        status,
+    });
+
+    let latest = Arc::new(AtomicU64::new(0));
+    let proxy = event_loop.create_proxy();
+    let renderer = spawn_renderer(proxy.clone(), latest.clone(), threads);
+    let julia = spawn_julia(proxy.clone(), threads);
+    let requested = latest.clone();
+    let show = move |view: Viewport, controls: &mut Controls| {
+        controls.top_left = format_complex(view.top_left);
+        controls.bot_right = format_complex(view.bot_right);
+        if precision::too_deep_for_f64(view.top_left, view.bot_right) {
+            controls.status = TOO_DEEP.to_string();
+        } else if controls.status == TOO_DEEP {
+            controls.status.clear();
+        }
+        let generation = requested.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut panes = vec![(controls.precision, controls.limit)];
+        if controls.split {
+            panes.push((controls.right_precision, controls.right_limit));
+        }
+        let _ = renderer.send(Job { generation, view, panes });
+    };
+    show(view, &mut panel.controls);
+
+    let mut cursor = PhysicalPosition::new(0.0, 0.0);
+    let mut drag = None;
+    let mut modifiers = ModifiersState::default();
+    // the view on screen, and the pixels of each pane, which stand in for
+    // a new view until its render catches up
+    let mut drawn = view;
+    let mut shown = vec![vec![0; view.bounds.0 * view.bounds.1]];
+    let mut history = History::new();
+    let mut visited = Step { view, limit, precision };
+    event_loop.run(move |event, target| {
+        match event {
+            Event::UserEvent(Message::Frame { generation, bounds, pane, pixels })
+                if generation == latest.load(Ordering::SeqCst) && bounds == view.bounds
+                    && pane < shown.len() => {
+                draw(screen.frame_mut(), bounds, pane, shown.len(), &pixels);
+                drawn = view;
+                shown[pane] = pixels;
+                window.request_redraw();
+            }
+            Event::UserEvent(Message::Exported(status)) => {
+                panel.controls.status = status;
+                window.request_redraw();
+            }
+            Event::UserEvent(Message::Julia { c, pixels }) => {
+                panel.show_julia(c, JULIA_SIZE, &pixels);
+                window.request_redraw();
+            }
+            Event::WindowEvent { event, .. } => {
+                let response = panel.handle_event(&window, &event);
+                if response.repaint {
+                    window.request_redraw();
+                }
+                match event {
+                    WindowEvent::CloseRequested => target.exit(),
+                    WindowEvent::RedrawRequested => {
+                        let selection = drag.map(|start| [start, cursor]);
+                        for action in panel.prepare(&window, selection) {
+                            let action = match action {
+                                Action::GoTo(i) => {
+                                    let bookmark = panel.controls.bookmarks[i].clone();
+                                    let controls = &mut panel.controls;
+                                    controls.limit = bookmark.limit;
+                                    controls.precision = bookmark.precision;
+                                    controls.top_left = bookmark.top_left;
+                                    controls.bot_right = bookmark.bot_right;
+                                    Action::Jump
+                                }
+                                action => action,
+                            };
+                            match action {
+                                Action::Rerender => show(view, &mut panel.controls),
+                                Action::Split => {
+                                    let panes = pane_count(&panel.controls);
+                                    view = view.resize(pane_bounds(window.inner_size(), panes));
+                                    visited.view = view;
+                                    shown = vec![shown[0].clone(); panes];
+                                    let resized = screen.resize_buffer(
+                                        (view.bounds.0 * panes) as u32, view.bounds.1 as u32);
+                                    if let Err(e) = resized {
+                                        eprintln!("error resizing window: {}", e);
+                                        target.exit();
+                                    }
+                                    show(view, &mut panel.controls);
+                                }
+                                Action::Jump => {
+                                    let corners = (parse_complex(&panel.controls.top_left),
+                                                   parse_complex(&panel.controls.bot_right));
+                                    match corners {
+                                        (Ok(top_left), Ok(bot_right)) => {
+                                            view = Viewport { top_left, bot_right, ..view }
+                                                .fit();
+                                            show(view, &mut panel.controls);
+                                        }
+                                        (Err(e), _) | (_, Err(e)) => {
+                                            panel.controls.status = e.to_string();
+                                        }
+                                    }
+                                }
+                                Action::Export => {
+                                    let controls = &mut panel.controls;
+                                    controls.status =
+                                        format!("exporting to {}...", controls.export_file);
+                                    spawn_export(proxy.clone(), view, controls.export_width,
+                                                 controls.export_file.clone(),
+                                                 controls.precision, controls.limit, threads);
+                                }
+                                Action::Bookmark => {
+                                    let controls = &mut panel.controls;
+                                    let name = controls.bookmark_name.trim().to_string();
+                                    if name.is_empty() {
+                                        controls.status = "name the bookmark first".to_string();
+                                        continue;
+                                    }
+                                    controls.status = format!("bookmarked {}", name);
+                                    bookmarks::add(&mut controls.bookmarks, Bookmark {
+                                        name,
+                                        limit: controls.limit,
+                                        precision: controls.precision,
+                                        top_left: format_complex(view.top_left),
+                                        bot_right: format_complex(view.bot_right),
+                                    });
+                                    keep_bookmarks(controls);
+                                }
+                                Action::GoTo(_) => unreachable!(),
+                                Action::Forget(i) => {
+                                    let controls = &mut panel.controls;
+                                    let forgotten = controls.bookmarks.remove(i);
+                                    controls.status = format!("deleted {}", forgotten.name);
+                                    keep_bookmarks(controls);
+                                }
+                                Action::ImportBookmarks => {
+                                    let controls = &mut panel.controls;
+                                    match fs::read_to_string(&controls.share_file) {
+                                        Ok(text) => {
+                                            let imported = bookmarks::parse(&text);
+                                            controls.status = format!(
+                                                "imported {} bookmarks from {}",
+                                                imported.len(), controls.share_file);
+                                            for bookmark in imported {
+                                                bookmarks::add(&mut controls.bookmarks, bookmark);
+                                            }
+                                            keep_bookmarks(controls);
+                                        }
+                                        Err(e) => {
+                                            controls.status = format!(
+                                                "error importing {}: {}", controls.share_file, e);
+                                        }
+                                    }
+                                }
+                                Action::ExportBookmarks => {
+                                    let controls = &mut panel.controls;
+                                    controls.status = match bookmarks::save(
+                                        &controls.share_file, &controls.bookmarks) {
+                                        Ok(()) => format!("exported {} bookmarks to {}",
+                                                          controls.bookmarks.len(),
+                                                          controls.share_file),
+                                        Err(e) => format!("error exporting {}: {}",
+                                                          controls.share_file, e),
+                                    };
+                                }
+                            }
+                        }
+                        let rendered = screen.render_with(|encoder, target, context| {
+                            context.scaling_renderer.render(encoder, target);
+                            panel.render(encoder, target, context);
+                            Ok(())
+                        });
+                        if let Err(e) = rendered {
+                            eprintln!("error drawing window: {}", e);
+                            target.exit();
+                        }
+                    }
+                    WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                        let panes = pane_count(&panel.controls);
+                        view = view.resize(pane_bounds(size, panes));
+                        let resized = screen.resize_surface(size.width, size.height)
+                            .and_then(|_| screen.resize_buffer((view.bounds.0 * panes) as u32,
+                                                               view.bounds.1 as u32));
+                        if let Err(e) = resized {
+                            eprintln!("error resizing window: {}", e);
+                            target.exit();
+                        }
+                        visited.view = view;
+                        show(view, &mut panel.controls);
+                    }
+                    WindowEvent::CursorMoved { position, .. } => {
+                        // over either half of a split view, as if over the left one
+                        cursor = PhysicalPosition::new(position.x % view.bounds.0 as f64,
+                                                       position.y);
+                        if drag.is_some() {
+                            window.request_redraw();
+                        }
+                        if modifiers.control_key() && !response.consumed {
+                            let _ = julia.send((view.point(pixel(cursor)), panel.controls.limit));
+                        }
+                    }
+                    WindowEvent::MouseInput {
+                        state: ElementState::Released,
+                        button: MouseButton::Left,
+                        ..
+                    } if drag.is_some() => {
+                        let start: PhysicalPosition<f64> = drag.take().unwrap();
+                        let moved = (cursor.x - start.x).abs().max((cursor.y - start.y).abs());
+                        view = if moved < DRAG_THRESHOLD {
+                            view.recenter(view.point(pixel(cursor)))
+                        } else {
+                            view.select(view.point(pixel(start)), view.point(pixel(cursor)))
+                        };
+                        show(view, &mut panel.controls);
+                        window.request_redraw();
+                    }
+                    WindowEvent::ModifiersChanged(changed) => {
+                        modifiers = changed.state();
+                        if modifiers.control_key() {
+                            let _ = julia.send((view.point(pixel(cursor)), panel.controls.limit));
+                        }
+                    }
+                    _ if response.consumed => {}
+                    WindowEvent::KeyboardInput {
+                        event: KeyEvent { logical_key, state: ElementState::Pressed, .. },
+                        ..
+                    } => {
+                        let (step, zoom) = if modifiers.shift_key() {
+                            (FINE_PAN_STEP, FINE_KEY_ZOOM_STEP)
+                        } else {
+                            (PAN_STEP, KEY_ZOOM_STEP)
+                        };
+                        if logical_key == Key::Named(NamedKey::Backspace) {
+                            let step = if modifiers.shift_key() {
+                                history.redo(visited)
+                            } else {
+                                history.undo(visited)
+                            };
+                            if let Some(step) = step {
+                                view = step.view.resize(view.bounds);
+                                panel.controls.limit = step.limit;
+                                panel.controls.precision = step.precision;
+                                visited = Step { view, ..step };
+                                show(view, &mut panel.controls);
+                            }
+                        }
+                        let moved = match logical_key.as_ref() {
+                            Key::Named(NamedKey::ArrowLeft) => view.pan(-step, 0.0),
+                            Key::Named(NamedKey::ArrowRight) => view.pan(step, 0.0),
+                            Key::Named(NamedKey::ArrowUp) => view.pan(0.0, step),
+                            Key::Named(NamedKey::ArrowDown) => view.pan(0.0, -step),
+                            // with shift, + is on the = key of most layouts
+                            Key::Character("+") | Key::Character("=") => {
+                                view.zoom(view.center(), zoom)
+                            }
+                            Key::Character("-") | Key::Character("_") => {
+                                view.zoom(view.center(), 1.0 / zoom)
+                            }
+                            _ => view,
+                        };
+                        if moved != view {
+                            view = moved;
+                            show(view, &mut panel.controls);
+                        }
+                    }
+                    WindowEvent::MouseWheel { delta, .. } => {
+                        let notches = match delta {
+                            MouseScrollDelta::LineDelta(_, y) => y as f64,
+                            MouseScrollDelta::PixelDelta(p) => p.y / 40.0,
+                        };
+                        view = view.zoom(view.point(pixel(cursor)), ZOOM_STEP.powf(notches));
+                        show(view, &mut panel.controls);
+                    }
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button: MouseButton::Left,
+                        ..
+                    } => drag = Some(cursor),
+                    _ => {}
+                }
+                let now = Step {
+                    view,
+                    limit: panel.controls.limit,
+                    precision: panel.controls.precision,
+                };
+                if now != visited {
+                    history.push(visited);
+                    visited = now;
+                }
+                if view != drawn {
+                    let panes = shown.len();
+                    for (pane, pixels) in shown.iter_mut().enumerate() {
+                        *pixels = view.resample(&drawn, pixels);
+                        draw(screen.frame_mut(), view.bounds, pane, panes, pixels);
+                    }
+                    drawn = view;
+                    window.request_redraw();
+                }
+            }
+            _ => {}
+        }
+    }).map_err(|e| MandelError::Gpu(e.to_string()))
+}
+
+#[test]
+fn test_draw_panes() {
+    let mut frame = vec![0; 4 * 2 * 2 * 2];
+    draw(&mut frame, (2, 2), 1, 2, &[1, 2, 3, 4]);
+    let grays: Vec<u8> = frame.chunks(4).map(|rgba| rgba[0]).collect();
+    assert_eq!(grays, [0, 0, 1, 2, 0, 0, 3, 4]);
+    assert_eq!(&frame[8 .. 12], [1, 1, 1, 0xff]);
+}
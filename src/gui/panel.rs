@@ -0,0 +1,293 @@
+use egui;
+use gui::bookmarks::Bookmark;
+use egui_wgpu::{Renderer, ScreenDescriptor};
+use num::Complex;
+use egui_winit::{EventResponse, State};
+use pixels::{wgpu, PixelsContext};
+use precision::Precision;
+use winit::dpi::PhysicalPosition;
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+/// Bits of the reference orbit when the right half of a split view is
+/// rendered by perturbation
+pub const PERTURB_BITS: usize = 128;
+
+/// Settings the panel lets the user change, as shown in it
+pub struct Controls {
+    pub limit: u32,
+    pub precision: Precision,
+    /// corners as `re,im`, applied with the "Go" button
+    pub top_left: String,
+    pub bot_right: String,
+    pub export_width: usize,
+    pub export_file: String,
+    /// name the view is bookmarked under with the "Save" button
+    pub bookmark_name: String,
+    pub bookmarks: Vec<Bookmark>,
+    /// file bookmarks are imported from and exported to for sharing
+    pub share_file: String,
+    /// whether the window shows the view twice, the right half rendered
+    /// with the settings below
+    pub split: bool,
+    pub right_limit: u32,
+    pub right_precision: Precision,
+    /// last thing worth telling the user, such as how an export went
+    pub status: String,
+}
+
+/// What the user asked for in the panel since it was last drawn
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    /// iterations or precision changed
+    Rerender,
+    /// go to the corners typed in
+    Jump,
+    /// split view turned on or off
+    Split,
+    Export,
+    /// bookmark the view under `bookmark_name`
+    Bookmark,
+    /// go to the bookmark at this index, or delete it
+    GoTo(usize),
+    Forget(usize),
+    ImportBookmarks,
+    ExportBookmarks,
+}
+
+/// egui side panel drawn over the render
+pub struct Panel {
+    ctx: egui::Context,
+    state: State,
+    renderer: Renderer,
+    screen: ScreenDescriptor,
+    paint_jobs: Vec<egui::ClippedPrimitive>,
+    textures: egui::TexturesDelta,
+    /// the Julia set last shown and the point it's for
+    julia: Option<(Complex<f64>, egui::TextureHandle)>,
+    pub controls: Controls,
+}
+
+impl Panel {
+    pub fn new(window: &Window, device: &wgpu::Device, format: wgpu::TextureFormat,
+               controls: Controls)
+        -> Panel
+    {
+        let ctx = egui::Context::default();
+        let max_texture_side = device.limits().max_texture_dimension_2d as usize;
+        let state = State::new(ctx.clone(), egui::ViewportId::ROOT, window,
+                               Some(window.scale_factor() as f32), Some(max_texture_side));
+        let size = window.inner_size();
+        Panel {
+            ctx,
+            state,
+            renderer: Renderer::new(device, format, None, 1),
+            screen: ScreenDescriptor {
+                size_in_pixels: [size.width, size.height],
+                pixels_per_point: window.scale_factor() as f32,
+            },
+            paint_jobs: Vec::new(),
+            textures: egui::TexturesDelta::default(),
+            julia: None,
+            controls,
+        }
+    }
+
+    /// Pass a window event to egui, which says whether it used it up
+    pub fn handle_event(&mut self, window: &Window, event: &WindowEvent) -> EventResponse {
+        match *event {
+            WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                self.screen.size_in_pixels = [size.width, size.height];
+            }
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.screen.pixels_per_point = scale_factor as f32;
+            }
+            _ => {}
+        }
+        self.state.on_window_event(window, event)
+    }
+
+    /// Show the `size` square grayscale `pixels` as the Julia set of `c`
+    pub fn show_julia(&mut self, c: Complex<f64>, size: usize, pixels: &[u8]) {
+        let image = egui::ColorImage::from_gray([size, size], pixels);
+        match self.julia {
+            Some((ref mut shown, ref mut texture)) => {
+                *shown = c;
+                texture.set(image, egui::TextureOptions::default());
+            }
+            None => {
+                let texture = self.ctx.load_texture("julia", image, Default::default());
+                self.julia = Some((c, texture));
+            }
+        }
+    }
+
+    /// Lay the panel out for the next frame, along with the outline of
+    /// the rectangle being dragged out if any, returning what was clicked
+    pub fn prepare(&mut self, window: &Window, selection: Option<[PhysicalPosition<f64>; 2]>)
+        -> Vec<Action>
+    {
+        let input = self.state.take_egui_input(window);
+        let mut actions = Vec::new();
+        let controls = &mut self.controls;
+        let julia = &self.julia;
+        let output = self.ctx.run(input, |ctx| {
+            if let Some([a, b]) = selection {
+                let scale = ctx.pixels_per_point();
+                let point = |p: PhysicalPosition<f64>| {
+                    egui::pos2(p.x as f32 / scale, p.y as f32 / scale)
+                };
+                let layer = egui::LayerId::new(egui::Order::Foreground,
+                                               egui::Id::new("selection"));
+                ctx.layer_painter(layer).rect_stroke(
+                    egui::Rect::from_two_pos(point(a), point(b)),
+                    0.0,
+                    egui::Stroke::new(1.0, egui::Color32::RED));
+            }
+
+            egui::SidePanel::left("controls").show(ctx, |ui| {
+                ui.heading("View");
+                let limit = ui.horizontal(|ui| {
+                    ui.label("Iterations");
+                    ui.add(egui::DragValue::new(&mut controls.limit)
+                        .clamp_range(1 ..= 1_000_000).speed(10))
+                }).inner;
+                let precision = ui.horizontal(|ui| {
+                    ui.label("Precision");
+                    ui.selectable_value(&mut controls.precision, Precision::F32, "f32")
+                        | ui.selectable_value(&mut controls.precision, Precision::F64, "f64")
+                }).inner;
+                if limit.changed() || precision.changed() {
+                    actions.push(Action::Rerender);
+                }
+
+                ui.separator();
+                ui.label("Top left");
+                ui.text_edit_singleline(&mut controls.top_left);
+                ui.label("Bottom right");
+                ui.text_edit_singleline(&mut controls.bot_right);
+                if ui.button("Go").clicked() {
+                    actions.push(Action::Jump);
+                }
+
+                ui.separator();
+                ui.heading("Compare");
+                if ui.checkbox(&mut controls.split, "Split view").changed() {
+                    actions.push(Action::Split);
+                }
+                if controls.split {
+                    ui.label("Right half");
+                    let limit = ui.horizontal(|ui| {
+                        ui.label("Iterations");
+                        ui.add(egui::DragValue::new(&mut controls.right_limit)
+                            .clamp_range(1 ..= 1_000_000).speed(10))
+                    }).inner;
+                    let precision = ui.horizontal(|ui| {
+                        let precision = &mut controls.right_precision;
+                        ui.selectable_value(precision, Precision::F32, "f32")
+                            | ui.selectable_value(precision, Precision::F64, "f64")
+                            | ui.selectable_value(precision, Precision::Fixed(PERTURB_BITS),
+                                                  "perturbation")
+                    }).inner;
+                    if limit.changed() || precision.changed() {
+                        actions.push(Action::Rerender);
+                    }
+                }
+
+                ui.separator();
+                ui.heading("Export");
+                ui.horizontal(|ui| {
+                    ui.label("Width");
+                    ui.add(egui::DragValue::new(&mut controls.export_width)
+                        .clamp_range(1 ..= 100_000).speed(10));
+                });
+                ui.text_edit_singleline(&mut controls.export_file);
+                if ui.button("Export at high resolution").clicked() {
+                    actions.push(Action::Export);
+                }
+
+                ui.separator();
+                ui.heading("Bookmarks");
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(&mut controls.bookmark_name);
+                    if ui.button("Save").clicked() {
+                        actions.push(Action::Bookmark);
+                    }
+                });
+                for (i, bookmark) in controls.bookmarks.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.button("x").on_hover_text("delete").clicked() {
+                            actions.push(Action::Forget(i));
+                        }
+                        if ui.link(&bookmark.name).clicked() {
+                            actions.push(Action::GoTo(i));
+                        }
+                    });
+                }
+                ui.text_edit_singleline(&mut controls.share_file);
+                ui.horizontal(|ui| {
+                    if ui.button("Import").clicked() {
+                        actions.push(Action::ImportBookmarks);
+                    }
+                    if ui.button("Export").clicked() {
+                        actions.push(Action::ExportBookmarks);
+                    }
+                });
+
+                ui.separator();
+                ui.heading("Julia set");
+                match *julia {
+                    Some((c, ref texture)) => {
+                        ui.label(format!("c = {:.6},{:.6}", c.re, c.im));
+                        ui.image(texture);
+                    }
+                    None => {
+                        ui.label("hold Ctrl over a point to see its Julia set");
+                    }
+                }
+
+                ui.separator();
+                ui.label(&controls.status);
+            });
+        });
+
+        self.state.handle_platform_output(window, output.platform_output);
+        self.textures.append(output.textures_delta);
+        self.paint_jobs = self.ctx.tessellate(output.shapes, output.pixels_per_point);
+        actions
+    }
+
+    /// Draw the panel laid out by `prepare` on top of `target`
+    pub fn render(&mut self,
+                  encoder: &mut wgpu::CommandEncoder,
+                  target: &wgpu::TextureView,
+                  context: &PixelsContext)
+    {
+        for (id, delta) in &self.textures.set {
+            self.renderer.update_texture(&context.device, &context.queue, *id, delta);
+        }
+        self.renderer.update_buffers(&context.device, &context.queue, encoder,
+                                     &self.paint_jobs, &self.screen);
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("egui"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            self.renderer.render(&mut pass, &self.paint_jobs, &self.screen);
+        }
+        for id in &self.textures.free {
+            self.renderer.free_texture(id);
+        }
+        self.textures.clear();
+    }
+}
@@ -0,0 +1,150 @@
+use explore;
+use error::MandelError;
+use num::Complex;
+use pixels::{Pixels, SurfaceTexture};
+use precision::Precision;
+use random::Rng;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use winit::dpi::PhysicalSize;
+use winit::event::{ElementState, Event, KeyEvent, WindowEvent};
+use winit::event_loop::{EventLoopBuilder, EventLoopProxy};
+use winit::window::{Fullscreen, WindowBuilder};
+use viewport::Viewport;
+use super::draw;
+use {auto_iterations, render_bands};
+
+/// How much each frame zooms in
+const ZOOM_PER_FRAME: f64 = 0.97;
+
+/// Frames between choosing where to head for next
+const RETARGET_FRAMES: u32 = 45;
+
+/// Shortest time a frame is shown, which keeps the zoom steady when
+/// frames are quick to render
+const FRAME_TIME: Duration = Duration::from_millis(33);
+
+/// Width of a pixel, relative to the view's distance from the origin,
+/// below which `f64` turns blocky and the tour starts over
+const DEEPEST: f64 = 1e-13;
+
+/// A rendered frame and the size it was rendered at
+type Frame = ((usize, usize), Vec<u8>);
+
+/// The whole set, fitted to `bounds`
+fn home(bounds: (usize, usize)) -> Viewport {
+    Viewport::new(bounds, Complex { re: -2.2, im: 1.2 }, Complex { re: 0.8, im: -1.2 }).fit()
+}
+
+/// Zoom forever on a thread of its own, choosing where to go by `seed`,
+/// sending each frame through `proxy` and taking the window's size from
+/// `sizes`
+fn spawn_tour(proxy: EventLoopProxy<Frame>,
+              sizes: mpsc::Receiver<(usize, usize)>,
+              bounds: (usize, usize),
+              seed: u64,
+              threads: usize)
+{
+    thread::spawn(move || {
+        let mut rng = Rng::new(seed);
+        let mut view = home(bounds);
+        let mut target = None;
+        for frame in 0 .. {
+            let started = Instant::now();
+            while let Ok(bounds) = sizes.try_recv() {
+                view = view.resize(bounds);
+            }
+
+            let limit = auto_iterations(view.top_left, view.bot_right);
+            if frame % RETARGET_FRAMES == 0 || target.is_none() {
+                target = explore::interesting(view.top_left, view.bot_right, limit, &mut rng);
+            }
+            let pixel = view.pixel_size().0;
+            let deepest = DEEPEST * view.center().norm().max(1.0);
+            view = match target {
+                Some(target) if pixel > deepest => view.zoom(target, ZOOM_PER_FRAME),
+                _ => {
+                    target = None;
+                    home(view.bounds)
+                }
+            };
+
+            let mut pixels = vec![0; view.bounds.0 * view.bounds.1];
+            render_bands(&mut pixels, view.bounds, 0, view.top_left, view.bot_right,
+                         None, Precision::F64, limit, threads);
+            if proxy.send_event((view.bounds, pixels)).is_err() {
+                return;
+            }
+            if let Some(rest) = FRAME_TIME.checked_sub(started.elapsed()) {
+                thread::sleep(rest);
+            }
+        }
+    });
+}
+
+/// Fill the screen with an endless zoom into the set, heading each time
+/// for wherever the escape counts vary the most, until a key or button
+/// is pressed
+///
+/// The same `seed` takes the same tour every time; without one it's
+/// taken from the clock.
+pub fn run(seed: Option<u64>, threads: usize) -> Result<(), MandelError> {
+    let seed = seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_nanos() as u64)
+    });
+    let event_loop = EventLoopBuilder::<Frame>::with_user_event().build()
+        .map_err(|e| MandelError::Gpu(e.to_string()))?;
+    let window = WindowBuilder::new()
+        .with_title("mandelbrot")
+        .with_fullscreen(Some(Fullscreen::Borderless(None)))
+        .build(&event_loop)
+        .map_err(|e| MandelError::Gpu(e.to_string()))?;
+    window.set_cursor_visible(false);
+    let window = Arc::new(window);
+
+    let size = window.inner_size();
+    let bounds = (size.width.max(1) as usize, size.height.max(1) as usize);
+    let surface = SurfaceTexture::new(size.width, size.height, window.clone());
+    let mut screen = Pixels::new(bounds.0 as u32, bounds.1 as u32, surface)
+        .map_err(|e| MandelError::Gpu(e.to_string()))?;
+
+    let (resize, sizes) = mpsc::channel();
+    spawn_tour(event_loop.create_proxy(), sizes, bounds, seed, threads);
+    let mut bounds = bounds;
+    event_loop.run(move |event, target| {
+        match event {
+            Event::UserEvent((rendered, pixels)) if rendered == bounds => {
+                draw(screen.frame_mut(), bounds, 0, 1, &pixels);
+                window.request_redraw();
+            }
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested
+                | WindowEvent::KeyboardInput {
+                    event: KeyEvent { state: ElementState::Pressed, .. },
+                    ..
+                }
+                | WindowEvent::MouseInput { state: ElementState::Pressed, .. } => target.exit(),
+                WindowEvent::RedrawRequested => {
+                    if let Err(e) = screen.render() {
+                        eprintln!("error drawing window: {}", e);
+                        target.exit();
+                    }
+                }
+                WindowEvent::Resized(PhysicalSize { width, height }) if width > 0 && height > 0 => {
+                    bounds = (width as usize, height as usize);
+                    let resized = screen.resize_surface(width, height)
+                        .and_then(|_| screen.resize_buffer(width, height));
+                    if let Err(e) = resized {
+                        eprintln!("error resizing window: {}", e);
+                        target.exit();
+                    }
+                    let _ = resize.send(bounds);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }).map_err(|e| MandelError::Gpu(e.to_string()))
+}
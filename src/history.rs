@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+/// Most steps kept to go back through; older ones are forgotten
+const MAX_STEPS: usize = 1000;
+
+/// Where an explorer has been, to step back and forth through
+pub struct History<T> {
+    back: VecDeque<T>,
+    forward: Vec<T>,
+}
+
+impl<T: PartialEq> History<T> {
+    pub fn new() -> History<T> {
+        History { back: VecDeque::new(), forward: Vec::new() }
+    }
+
+    /// Record leaving `current` for somewhere new, which leaves nothing to
+    /// redo
+    pub fn push(&mut self, current: T) {
+        if self.back.back() != Some(&current) {
+            if self.back.len() == MAX_STEPS {
+                self.back.pop_front();
+            }
+            self.back.push_back(current);
+        }
+        self.forward.clear();
+    }
+
+    /// undo(c) : the step before `current`, which can then be redone
+    pub fn undo(&mut self, current: T) -> Option<T> {
+        let previous = self.back.pop_back()?;
+        self.forward.push(current);
+        Some(previous)
+    }
+
+    /// redo(c) : the step last undone to get to `current`
+    pub fn redo(&mut self, current: T) -> Option<T> {
+        let next = self.forward.pop()?;
+        self.back.push_back(current);
+        Some(next)
+    }
+}
+
+#[test]
+fn test_history() {
+    let mut history = History::new();
+    assert_eq!(history.undo(1), None);
+    history.push(1);
+    history.push(2);
+    history.push(2);
+    assert_eq!(history.undo(3), Some(2));
+    assert_eq!(history.undo(2), Some(1));
+    assert_eq!(history.undo(1), None);
+    assert_eq!(history.redo(1), Some(2));
+    assert_eq!(history.redo(2), Some(3));
+    assert_eq!(history.redo(3), None);
+
+    // going somewhere new forgets what could have been redone
+    assert_eq!(history.undo(3), Some(2));
+    history.push(2);
+    assert_eq!(history.redo(4), None);
+    assert_eq!(history.undo(4), Some(2));
+
+    for step in 0 .. MAX_STEPS + 10 {
+        history.push(step);
+    }
+    assert_eq!(history.back.len(), MAX_STEPS);
+    assert_eq!(history.back.front(), Some(&10));
+}
@@ -0,0 +1,241 @@
+use std::ops::{Index, IndexMut};
+use std::slice::Chunks;
+
+/// A color with red, green and blue channels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rgb(pub [u8; 3]);
+
+/// A color with red, green, blue and alpha channels
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rgba(pub [u8; 4]);
+
+/// A kind of pixel that can be mixed, for anti-aliasing
+pub trait Blend: Sized {
+    /// mean(p) : the average of `pixels`, of which there's at least one
+    fn mean(pixels: &[Self]) -> Self;
+
+    /// contrast(o) : how far apart the pixel and `other` look, from 0 when
+    /// they're the same to 1 for black against white
+    fn contrast(&self, other: &Self) -> f64;
+}
+
+/// mean_channels(c) : the rounded average of each of the `N` channels of
+/// `pixels`
+fn mean_channels<const N: usize>(pixels: &[[u8; N]]) -> [u8; N] {
+    let count = pixels.len() as u32;
+    let mut sums = [0u32; N];
+    for pixel in pixels {
+        for (sum, &channel) in sums.iter_mut().zip(pixel) {
+            *sum += channel as u32;
+        }
+    }
+    sums.map(|sum| ((sum + count / 2) / count) as u8)
+}
+
+/// contrast_channels(a, b) : the biggest difference between a channel of
+/// `a` and of `b`, as a fraction of the whole range
+fn contrast_channels(a: &[u8], b: &[u8]) -> f64 {
+    a.iter().zip(b).map(|(&a, &b)| a.abs_diff(b)).max().unwrap_or(0) as f64 / 255.0
+}
+
+impl Blend for u8 {
+    fn mean(pixels: &[u8]) -> u8 {
+        let count = pixels.len() as u32;
+        ((pixels.iter().map(|&p| p as u32).sum::<u32>() + count / 2) / count) as u8
+    }
+
+    fn contrast(&self, other: &u8) -> f64 {
+        self.abs_diff(*other) as f64 / 255.0
+    }
+}
+
+impl Blend for u16 {
+    fn mean(pixels: &[u16]) -> u16 {
+        let count = pixels.len() as u64;
+        ((pixels.iter().map(|&p| p as u64).sum::<u64>() + count / 2) / count) as u16
+    }
+
+    fn contrast(&self, other: &u16) -> f64 {
+        self.abs_diff(*other) as f64 / 65535.0
+    }
+}
+
+impl Blend for f32 {
+    fn mean(pixels: &[f32]) -> f32 {
+        pixels.iter().sum::<f32>() / pixels.len() as f32
+    }
+
+    fn contrast(&self, other: &f32) -> f64 {
+        (self - other).abs() as f64
+    }
+}
+
+impl Blend for Rgb {
+    fn mean(pixels: &[Rgb]) -> Rgb {
+        Rgb(mean_channels(&pixels.iter().map(|p| p.0).collect::<Vec<_>>()))
+    }
+
+    fn contrast(&self, other: &Rgb) -> f64 {
+        contrast_channels(&self.0, &other.0)
+    }
+}
+
+impl Blend for Rgba {
+    fn mean(pixels: &[Rgba]) -> Rgba {
+        Rgba(mean_channels(&pixels.iter().map(|p| p.0).collect::<Vec<_>>()))
+    }
+
+    fn contrast(&self, other: &Rgba) -> f64 {
+        contrast_channels(&self.0, &other.0)
+    }
+}
+
+/// The pixels of an image, row by row, in whatever type a pixel is kept
+/// in: `u8` or `u16` gray, `f32`, `Rgb`, `Rgba`, or `u32` escape times
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageBuffer<T> {
+    bounds: (usize, usize),
+    pixels: Vec<T>,
+}
+
+/// A rectangle of an image, borrowed from it
+pub struct Tile<'a, T: 'a> {
+    pixels: &'a [T],
+    width: usize,
+    rect: (usize, usize, usize, usize),
+}
+
+impl<T: Clone + Default> ImageBuffer<T> {
+    /// An image of `bounds` with every pixel the default, black for colors
+    pub fn new(bounds: (usize, usize)) -> ImageBuffer<T> {
+        ImageBuffer { bounds, pixels: vec![T::default(); bounds.0 * bounds.1] }
+    }
+}
+
+impl<T> ImageBuffer<T> {
+    /// The image of `bounds` whose rows are laid end to end in `pixels`
+    pub fn from_pixels(bounds: (usize, usize), pixels: Vec<T>) -> ImageBuffer<T> {
+        assert!(pixels.len() == bounds.0 * bounds.1);
+        ImageBuffer { bounds, pixels }
+    }
+
+    pub fn bounds(&self) -> (usize, usize) {
+        self.bounds
+    }
+
+    pub fn pixels(&self) -> &[T] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [T] {
+        &mut self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<T> {
+        self.pixels
+    }
+
+    pub fn row(&self, row: usize) -> &[T] {
+        &self.pixels[row * self.bounds.0 .. (row + 1) * self.bounds.0]
+    }
+
+    pub fn row_mut(&mut self, row: usize) -> &mut [T] {
+        &mut self.pixels[row * self.bounds.0 .. (row + 1) * self.bounds.0]
+    }
+
+    pub fn rows(&self) -> Chunks<'_, T> {
+        self.pixels.chunks(self.bounds.0.max(1))
+    }
+
+    /// The `(x, y, width, height)` rectangle `rect` of the image
+    pub fn tile(&self, rect: (usize, usize, usize, usize)) -> Tile<'_, T> {
+        let (x, y, width, height) = rect;
+        assert!(x + width <= self.bounds.0 && y + height <= self.bounds.1);
+        Tile { pixels: &self.pixels, width: self.bounds.0, rect }
+    }
+}
+
+impl<T: Clone> ImageBuffer<T> {
+    /// Copy `tile`, the pixels of the `(x, y, width, height)` rectangle
+    /// `rect`, into its place in the image
+    pub fn copy_tile(&mut self, rect: (usize, usize, usize, usize), tile: &[T]) {
+        let (x, _, width, _) = rect;
+        for (row, tile_row) in (rect.1 ..).zip(tile.chunks(width.max(1))) {
+            self.row_mut(row)[x .. x + width].clone_from_slice(tile_row);
+        }
+    }
+}
+
+/// The pixel at `(col, row)`
+impl<T> Index<(usize, usize)> for ImageBuffer<T> {
+    type Output = T;
+
+    fn index(&self, (col, row): (usize, usize)) -> &T {
+        &self.pixels[row * self.bounds.0 + col]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for ImageBuffer<T> {
+    fn index_mut(&mut self, (col, row): (usize, usize)) -> &mut T {
+        &mut self.pixels[row * self.bounds.0 + col]
+    }
+}
+
+impl<'a, T> Tile<'a, T> {
+    /// The `(x, y, width, height)` of the tile in its image
+    pub fn rect(&self) -> (usize, usize, usize, usize) {
+        self.rect
+    }
+
+    /// row(r) : row `r` of the tile, counting from its top
+    pub fn row(&self, row: usize) -> &'a [T] {
+        let (x, y, width, _) = self.rect;
+        let start = (y + row) * self.width + x;
+        &self.pixels[start .. start + width]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &'a [T]> + '_ {
+        (0 .. self.rect.3).map(move |row| self.row(row))
+    }
+}
+
+impl<'a, T: Clone> Tile<'a, T> {
+    /// The tile's pixels, row by row, as an image of its own
+    pub fn to_image(&self) -> ImageBuffer<T> {
+        let pixels = self.rows().flat_map(|row| row.iter().cloned()).collect();
+        ImageBuffer::from_pixels((self.rect.2, self.rect.3), pixels)
+    }
+}
+
+#[test]
+fn test_image_buffer() {
+    let mut image = ImageBuffer::from_pixels((4, 3), (0 .. 12).collect::<Vec<u16>>());
+    assert_eq!(image[(1, 2)], 9);
+    assert_eq!(image.row(1), [4, 5, 6, 7]);
+    assert_eq!(image.rows().count(), 3);
+
+    let tile = image.tile((1, 1, 2, 2));
+    assert_eq!(tile.rows().collect::<Vec<_>>(), [&[5, 6][..], &[9, 10][..]]);
+    assert_eq!(tile.to_image(), ImageBuffer::from_pixels((2, 2), vec![5, 6, 9, 10]));
+
+    image.copy_tile((2, 0, 2, 2), &[20, 21, 22, 23]);
+    image[(0, 0)] = 30;
+    assert_eq!(image.into_pixels(), [30, 1, 20, 21, 4, 5, 22, 23, 8, 9, 10, 11]);
+
+    let blank: ImageBuffer<Rgb> = ImageBuffer::new((2, 1));
+    assert_eq!(blank.pixels(), [Rgb([0, 0, 0]); 2]);
+}
+
+#[test]
+fn test_blend() {
+    assert_eq!(u8::mean(&[0, 255]), 128);
+    assert_eq!(u16::mean(&[10, 20, 40]), 23);
+    assert_eq!(f32::mean(&[0.25, 0.75]), 0.5);
+    assert_eq!(Rgb::mean(&[Rgb([0, 10, 255]), Rgb([255, 20, 255])]), Rgb([128, 15, 255]));
+    assert_eq!(Rgba::mean(&[Rgba([1, 2, 3, 4])]), Rgba([1, 2, 3, 4]));
+
+    assert_eq!(0u8.contrast(&255), 1.0);
+    assert_eq!(0u16.contrast(&0), 0.0);
+    assert_eq!(0.25f32.contrast(&0.75), 0.5);
+    assert_eq!(Rgb([0, 0, 0]).contrast(&Rgb([0, 51, 0])), 0.2);
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(target_os = "linux")]
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        // a second Ctrl-C means stop right now
+        unsafe { libc::_exit(130) }
+    }
+}
+
+/// Make Ctrl-C ask the render to stop at the next strip instead of
+/// killing the process outright
+#[cfg(target_os = "linux")]
+pub fn install() {
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn install() {}
+
+/// requested() : whether Ctrl-C has been pressed since `install`
+pub fn requested() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// How far an interrupted render got, saved next to its image
+#[derive(Debug, PartialEq)]
+pub struct Checkpoint {
+    /// rows at the top of the image that are complete
+    pub rows: usize,
+    /// the view being rendered, as given by `pan::describe`
+    pub view: String,
+}
+
+impl Checkpoint {
+    fn path(filename: &str) -> String {
+        format!("{}.resume", filename)
+    }
+
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        fs::write(Checkpoint::path(filename), format!("{}\n{}\n", self.rows, self.view))
+    }
+
+    /// The checkpoint left by an interrupted render of `filename`, if any
+    pub fn load(filename: &str) -> io::Result<Option<Checkpoint>> {
+        let text = match fs::read_to_string(Checkpoint::path(filename)) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut lines = text.lines();
+        let rows = lines.next().and_then(|rows| rows.parse().ok());
+        match (rows, lines.next()) {
+            (Some(rows), Some(view)) => Ok(Some(Checkpoint { rows, view: view.to_string() })),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                    format!("malformed checkpoint {}",
+                                            Checkpoint::path(filename)))),
+        }
+    }
+
+    pub fn remove(filename: &str) -> io::Result<()> {
+        match fs::remove_file(Checkpoint::path(filename)) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[test]
+fn test_checkpoint_round_trip() {
+    let path = std::env::temp_dir()
+        .join(format!("mandelbrot-interrupt-test-{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    assert_eq!(Checkpoint::load(filename).unwrap(), None);
+    let checkpoint = Checkpoint { rows: 512, view: "255 f64 -1.2,0.35 -1,0.2".to_string() };
+    checkpoint.save(filename).unwrap();
+    assert_eq!(Checkpoint::load(filename).unwrap(), Some(checkpoint));
+
+    Checkpoint::remove(filename).unwrap();
+    assert_eq!(Checkpoint::load(filename).unwrap(), None);
+    Checkpoint::remove(filename).unwrap();
+}
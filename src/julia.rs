@@ -0,0 +1,79 @@
+use affinity;
+use crossbeam;
+use num::Complex;
+use {escaped, gray, iterate_batch, pixel_center, BAILOUT};
+
+/// The square every Julia set fits in, since orbits leaving `|z| <= 2`
+/// never come back
+pub const TOP_LEFT: Complex<f64> = Complex { re: -2.0, im: 2.0 };
+pub const BOT_RIGHT: Complex<f64> = Complex { re: 2.0, im: -2.0 };
+
+/// Find the escape times of the `bounds` image of the Julia set of `c`
+/// between the corners, as `escape_time_batch` records them, split into
+/// bands on `threads` threads
+pub fn escape_times(times: &mut [u32],
+                    bounds: (usize, usize),
+                    top_left: Complex<f64>,
+                    bot_right: Complex<f64>,
+                    c: Complex<f64>,
+                    limit: u32,
+                    threads: usize)
+{
+    assert!(times.len() == bounds.0 * bounds.1);
+
+    let rows_per_band = bounds.1 / threads + 1;
+    crossbeam::scope(|spawner| {
+        for (i, band) in times.chunks_mut(rows_per_band * bounds.0).enumerate() {
+            spawner.spawn(move || {
+                affinity::pin(i);
+                let points: Vec<_> = (0 .. band.len())
+                    .map(|index| {
+                        let pixel = (index % bounds.0, i * rows_per_band + index / bounds.0);
+                        pixel_center(bounds, pixel, top_left, bot_right)
+                    })
+                    .collect();
+                iterate_batch(&points, Some(c), limit, BAILOUT, &|| false, band);
+            });
+        }
+    })
+}
+
+/// Render the filled Julia set of `c` between the corners into `pixels`,
+/// split into bands on `threads` threads
+pub fn render(pixels: &mut [u8],
+              bounds: (usize, usize),
+              top_left: Complex<f64>,
+              bot_right: Complex<f64>,
+              c: Complex<f64>,
+              limit: u32,
+              threads: usize)
+{
+    let mut times = vec![0; pixels.len()];
+    escape_times(&mut times, bounds, top_left, bot_right, c, limit, threads);
+    for (pixel, &time) in pixels.iter_mut().zip(&times) {
+        *pixel = gray(escaped(time), limit);
+    }
+}
+
+#[test]
+fn test_render_julia() {
+    let bounds = (40, 40);
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+
+    // c = 0 gives the unit disc, all of it interior
+    render(&mut pixels, bounds, TOP_LEFT, BOT_RIGHT, Complex { re: 0.0, im: 0.0 }, 1000, 3);
+    for row in 0 .. bounds.1 {
+        for col in 0 .. bounds.0 {
+            // points on the circle itself could round either way
+            let z = pixel_center(bounds, (col, row), TOP_LEFT, BOT_RIGHT);
+            if (z.norm() - 1.0).abs() > 1e-6 {
+                assert_eq!(pixels[row * bounds.0 + col] == 0, z.norm() < 1.0, "{}", z);
+            }
+        }
+    }
+
+    // the basilica, c = -1, has 0 in its interior and 2 outside it
+    render(&mut pixels, bounds, TOP_LEFT, BOT_RIGHT, Complex { re: -1.0, im: 0.0 }, 1000, 2);
+    assert_eq!(pixels[20 * bounds.0 + 20], 0);
+    assert!(pixels[20 * bounds.0 + 39] > 0);
+}
@@ -0,0 +1,763 @@
+//! Render the Mandelbrot set
+//!
+//! The functions here shade whole images, rectangles of them or scattered
+//! pixels in `f32`, `f64` or, for deep zooms, fixed point with
+//! perturbation. The modules build on them: faster and resumable ways to
+//! render, the tile cache, workers and server, and the explorers.
+//!
+//! With the default `native` feature off only the kernel is built, for
+//! `wasm32-unknown-unknown`.
+
+// the browser build leaves some of the kernel's helpers unused
+#![cfg_attr(not(feature = "native"), allow(dead_code))]
+
+#[cfg(feature = "native")]
+extern crate crossbeam;
+#[cfg(feature = "gui")]
+extern crate egui;
+#[cfg(feature = "gui")]
+extern crate egui_wgpu;
+#[cfg(feature = "gui")]
+extern crate egui_winit;
+#[cfg(target_os = "linux")]
+extern crate libc;
+#[cfg(feature = "plugins")]
+extern crate libloading;
+#[cfg(feature = "native")]
+extern crate memmap2;
+extern crate num;
+#[cfg(feature = "gui")]
+extern crate pixels;
+#[cfg(test)]
+extern crate pollster;
+#[cfg(feature = "native")]
+extern crate png;
+#[cfg(feature = "tui")]
+extern crate ratatui;
+#[cfg(feature = "native")]
+extern crate serde;
+#[cfg(feature = "native")]
+extern crate serde_json;
+extern crate thiserror;
+#[cfg(feature = "native")]
+extern crate tungstenite;
+#[cfg(feature = "gui")]
+extern crate winit;
+
+/// Pinning render threads to cores or NUMA nodes
+#[cfg(feature = "native")]
+pub mod affinity;
+/// Coloring points by what their orbits do, such as the period of the
+/// cycle they fall into
+#[cfg(feature = "native")]
+pub mod analysis;
+/// Zoom animations through keyframed views
+#[cfg(feature = "native")]
+pub mod animate;
+/// Estimating the area of the set by sampling it
+#[cfg(feature = "native")]
+pub mod area;
+/// Tracing the edge of the set in a render, as points for other tools
+#[cfg(feature = "native")]
+pub mod boundary;
+/// Pixel buffers in memory or mapped from a file
+#[cfg(feature = "native")]
+pub mod buffer;
+/// On-disk cache of rendered tiles
+#[cfg(feature = "native")]
+pub mod cache;
+/// Camera paths for animations, read from JSON
+#[cfg(feature = "native")]
+pub mod camera;
+/// Stopping a render from another thread
+pub mod cancel;
+/// The image renders of the `mandelbrot` program: the view its arguments
+/// give, the mode its flags pick, and resuming interrupted renders
+#[cfg(feature = "native")]
+pub mod cli;
+/// Coloring escape times into images
+pub mod color;
+/// Comparing two renders pixel by pixel, to check one way of rendering
+/// against another
+#[cfg(feature = "native")]
+pub mod compare;
+/// The escape-time kernel and the math mapping pixels to points, using
+/// nothing from `std` and allocating nothing, so it builds under `no_std`,
+/// as `examples/no_std_core.rs` checks
+pub mod core;
+/// Estimating how far points are from the set and their potential, as
+/// fields for other tools
+#[cfg(feature = "native")]
+pub mod distance;
+/// Rendering on worker processes over TCP
+#[cfg(feature = "native")]
+pub mod distributed;
+/// Curves for easing animated values between keyframes
+pub mod easing;
+/// The errors the library returns
+pub mod error;
+/// Hooks called as each band or tile of a render starts and finishes
+#[cfg(feature = "native")]
+pub mod events;
+/// Looking for the parts of a view with the most detail, to zoom into
+#[cfg(feature = "native")]
+pub mod explore;
+/// The renderer for C and C++, declared in `include/mandelbrot.h`
+#[cfg(feature = "ffi")]
+pub mod ffi;
+/// Blurring, sharpening, glowing, tone mapping and denoising a finished
+/// color image, in a chain of filters
+pub mod filter;
+/// Arbitrary-precision fixed point for views past `f64`
+pub mod fixed;
+/// Rendering only the edges of blocks whose insides can be guessed
+#[cfg(feature = "native")]
+pub mod guess;
+/// The explorer window
+#[cfg(feature = "gui")]
+pub mod gui;
+#[cfg(any(feature = "gui", feature = "tui"))]
+mod history;
+/// Images of any kind of pixel, with views of their rows and tiles
+pub mod image;
+/// Stopping renders on Ctrl-C and resuming them later
+#[cfg(feature = "native")]
+pub mod interrupt;
+/// Filled Julia sets
+#[cfg(feature = "native")]
+pub mod julia;
+/// Finding the nuclei of minibrots and Misiurewicz points near a point
+#[cfg(feature = "native")]
+pub mod locate;
+/// Reading and writing grayscale PNGs
+#[cfg(feature = "native")]
+pub mod output;
+/// Drawing the orbits of points over an image of the set
+pub mod overlay;
+/// Reusing the pixels of an earlier render of an overlapping view
+#[cfg(feature = "native")]
+pub mod pan;
+/// Deep zooms by perturbation against a high-precision reference orbit
+pub mod perturb;
+/// Formulas and colorizers loaded from shared libraries at runtime
+#[cfg(feature = "plugins")]
+pub mod plugin;
+/// Choosing the number type a view is rendered in
+pub mod precision;
+/// Rendering in passes from coarse to fine
+#[cfg(feature = "native")]
+pub mod progressive;
+/// Seeded random numbers, the same on every run, for the stochastic
+/// renders
+pub mod random;
+/// Tracing external rays in from far out to where they land on the set
+#[cfg(feature = "native")]
+pub mod ray;
+/// Setting up a render one option at a time
+#[cfg(feature = "native")]
+pub mod renderer;
+/// Rendering the tiles of an image on several backends at once, each
+/// taking as many as its throughput earns it
+#[cfg(feature = "native")]
+pub mod schedule;
+/// HTTP server for map tiles and whole images
+#[cfg(feature = "native")]
+pub mod server;
+/// Turning orbits into sound, written out as WAV files
+#[cfg(feature = "native")]
+pub mod sound;
+/// Histograms and other statistics of escape times
+#[cfg(feature = "native")]
+pub mod stats;
+/// Renders that can be awaited from async code
+#[cfg(feature = "native")]
+pub mod task;
+/// Timing each tile of a render
+#[cfg(feature = "native")]
+pub mod timing;
+/// The explorer in a terminal
+#[cfg(feature = "tui")]
+pub mod tui;
+/// The rectangle of the plane an image shows, and moving it about
+pub mod viewport;
+#[cfg(not(feature = "native"))]
+mod web;
+
+pub use error::MandelError;
+#[cfg(feature = "native")]
+pub use renderer::Renderer;
+
+pub use core::{escape_time, escape_time_batch, escaped, gray, iterate_batch, orbit_iter,
+               pixel_center, pixel_to_point, Affine, Orbit, Real, BAILOUT, NO_ESCAPE};
+
+use cancel::CancelToken;
+use color::{Colorizer, Gray};
+use core::INTERIOR_EPSILON;
+use num::Complex;
+use num::traits::float::FloatCore;
+use fixed::Fixed;
+#[cfg(feature = "native")]
+use output::ImageWriter;
+use precision::Precision;
+#[cfg(feature = "native")]
+use std::ops::Range;
+use std::str::FromStr;
+
+/// parse_pair(s, sep) : the two values either side of `sep` in `s`, such
+/// as the `1000` and `750` of `"1000x750"`
+///
+/// Space around either value is skipped, and a letter separator can be
+/// either case, so `" 1000 X 750 "` is read the same.
+pub fn parse_pair<T: FromStr>(s: &str, separator: char) -> Result<(T, T), MandelError> {
+    let error = |why: String| MandelError::Parse(format!("'{}' isn't a pair: {}", s, why));
+    let is_separator = |c: char| c.to_lowercase().eq(separator.to_lowercase());
+    let mut values = s.split(is_separator).map(str::trim);
+    match (values.next(), values.next(), values.next()) {
+        (Some(left), Some(right), None) => {
+            let parse = |value: &str| {
+                T::from_str(value).map_err(|_| error(format!("can't read '{}'", value)))
+            };
+            Ok((parse(left)?, parse(right)?))
+        }
+        (_, None, _) => Err(error(format!("no '{}' between the values", separator))),
+        _ => Err(error(format!("more than one '{}'", separator))),
+    }
+}
+
+/// parse_complex(s) : the complex number written as `re,im`
+pub fn parse_complex(s: &str) -> Result<Complex<f64>, MandelError> {
+    parse_pair(s, ',').map(|(re, im)| Complex { re, im })
+}
+
+/// format_complex(z) : `z` written as `re,im`, with as many digits as it
+/// takes for `parse_complex` to read back exactly `z`
+pub fn format_complex(z: Complex<f64>) -> String {
+    format!("{},{}", z.re, z.im)
+}
+
+/// auto_iterations(tl, br) : iteration limit growing with the square root
+/// of the magnification relative to the full set
+pub fn auto_iterations(top_left: Complex<f64>, bot_right: Complex<f64>) -> u32 {
+    let size = (bot_right.re - top_left.re).max(top_left.im - bot_right.im);
+    let zoom = 3.0 / size.abs();
+    (50.0 * zoom.sqrt()).clamp(255.0, 1_000_000.0) as u32
+}
+
+/// orbit(c, l) : `Z_0 = 0, Z_1 .. Z_n` of `c`, stopping after `l`
+/// iterations or once `Z_n` escapes, for plotting or orbit traps
+pub fn orbit(c: Complex<f64>, limit: u32) -> Vec<Complex<f64>> {
+    orbit_iter(c, limit).collect()
+}
+
+/// Find the escape times of the `bounds` image of `top_left` to
+/// `bot_right`, in whichever float type the corners are given in, on
+/// this thread
+pub fn escape_times<T: FloatCore>(times: &mut [u32],
+                                  bounds: (usize, usize),
+                                  top_left: Complex<T>,
+                                  bot_right: Complex<T>,
+                                  limit: u32)
+{
+    assert!(times.len() == bounds.0 * bounds.1);
+
+    let mut points = Vec::with_capacity(bounds.0);
+    for (row, row_times) in times.chunks_mut(bounds.0).enumerate() {
+        points.clear();
+        points.extend((0 .. bounds.0).map(|col| {
+            pixel_center(bounds, (col, row), top_left, bot_right)
+        }));
+        escape_time_batch(&points, limit, row_times);
+    }
+}
+
+/// Write `pixels` to `filename` as a grayscale PNG, recording `view`, the
+/// text from `pan::describe`, if given
+#[cfg(feature = "native")]
+pub fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize), view: Option<&str>)
+    -> Result<(), MandelError>
+{
+    let mut writer = ImageWriter::create(filename, bounds, view)?;
+    writer.write_rows(pixels)?;
+    writer.finish()
+}
+
+/// scale_bounds(b, s) : an image shaped like `bounds` with `side` pixels
+/// along its longer side, and at least one along the other however thin
+/// `bounds` is
+fn scale_bounds(bounds: (usize, usize), side: usize) -> (usize, usize) {
+    let other = |short: usize, long: usize| {
+        ((side as u64 * short as u64 / long as u64) as usize).max(1)
+    };
+    if bounds.0 >= bounds.1 {
+        (side, other(bounds.1, bounds.0))
+    } else {
+        (other(bounds.0, bounds.1), side)
+    }
+}
+
+/// Copy `tile`, the pixels of the `(x, y, width, height)` rectangle `rect`,
+/// into its place in the `bounds` image `pixels`
+fn copy_rect(pixels: &mut [u8],
+             bounds: (usize, usize),
+             rect: (usize, usize, usize, usize),
+             tile: &[u8])
+{
+    let (x, y, width, height) = rect;
+    for row in 0 .. height {
+        let start = (y + row) * bounds.0 + x;
+        pixels[start .. start + width]
+            .copy_from_slice(&tile[row * width .. (row + 1) * width]);
+    }
+}
+
+/// Side of the squares, lined up with the top left of an image, that each
+/// choose their own precision and, deep down, reference orbits, so every
+/// pixel comes out the same however the image is cut into bands or tiles
+const BLOCK_SIZE: usize = 64;
+
+/// Call `render` with each piece of the `(x, y, width, height)` rectangle
+/// `rect` of the `bounds` image that's in a block of its own, the whole
+/// block, and room for `per_pixel` values of each of the piece's pixels,
+/// which are then put in their place among `values`, those of `rect`
+fn in_blocks<F>(values: &mut [u32],
+                bounds: (usize, usize),
+                rect: (usize, usize, usize, usize),
+                per_pixel: usize,
+                mut render: F)
+    where F: FnMut(&mut [u32], (usize, usize, usize, usize), (usize, usize, usize, usize))
+{
+    let (x, y, width, height) = rect;
+    let mut piece_values = Vec::new();
+    for top in (y / BLOCK_SIZE * BLOCK_SIZE .. y + height).step_by(BLOCK_SIZE) {
+        for left in (x / BLOCK_SIZE * BLOCK_SIZE .. x + width).step_by(BLOCK_SIZE) {
+            let block = (left, top, BLOCK_SIZE.min(bounds.0 - left),
+                         BLOCK_SIZE.min(bounds.1 - top));
+            let (piece_x, piece_y) = (left.max(x), top.max(y));
+            let piece = (piece_x, piece_y,
+                         (left + BLOCK_SIZE).min(x + width) - piece_x,
+                         (top + BLOCK_SIZE).min(y + height) - piece_y);
+            piece_values.clear();
+            piece_values.resize(piece.2 * piece.3 * per_pixel, 0);
+            render(&mut piece_values, piece, block);
+
+            let row_len = piece.2 * per_pixel;
+            for (row, piece_row) in piece_values.chunks(row_len).enumerate() {
+                let start = ((piece_y - y + row) * width + piece_x - x) * per_pixel;
+                values[start .. start + row_len].copy_from_slice(piece_row);
+            }
+        }
+    }
+}
+
+/// Find the escape times of the `(x, y, width, height)` rectangle `rect`
+/// of the `bounds` image
+///
+/// `deep` holds the corners again in fixed point when `precision` is
+/// `Precision::Fixed`, and orbits have escaped once past `bailout`. Every
+/// pixel's escape time is found from the whole view, the same whichever
+/// rectangle it's found in. Once `cancel` is cancelled the rest of the
+/// times are left as they were.
+#[allow(clippy::too_many_arguments)]
+pub fn escape_rect(times: &mut [u32],
+                   bounds: (usize, usize),
+                   rect: (usize, usize, usize, usize),
+                   top_left: Complex<f64>,
+                   bot_right: Complex<f64>,
+                   deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+                   precision: Precision,
+                   limit: u32,
+                   bailout: f64,
+                   cancel: Option<&CancelToken>)
+{
+    in_blocks(times, bounds, rect, 1, |times, piece, block| {
+        // a view chosen for f32 or f64 can still have blocks that need more
+        let escalated = match deep {
+            None => precision::escalate(precision, bounds, block, top_left, bot_right),
+            Some(_) => precision,
+        };
+        let from_f64 = match escalated {
+            Precision::Fixed(bits) if deep.is_none() => {
+                let to_fixed = |z: Complex<f64>| Complex {
+                    re: Fixed::from_f64(z.re, bits),
+                    im: Fixed::from_f64(z.im, bits),
+                };
+                Some((to_fixed(top_left), to_fixed(bot_right)))
+            }
+            _ => None,
+        };
+
+        let (x, y, width, height) = piece;
+        let pixels: Vec<_> = (y .. y + height)
+            .flat_map(|row| (x .. x + width).map(move |col| (col, row)))
+            .collect();
+        times.copy_from_slice(&escape_points(&pixels, bounds, top_left, bot_right,
+                                             deep.or(from_f64.as_ref()), escalated, limit,
+                                             bailout, cancel));
+    });
+}
+
+/// Render the `(x, y, width, height)` rectangle `rect` of the `bounds`
+/// image into `pixels`, like `escape_rect` but shaded gray
+#[allow(clippy::too_many_arguments)]
+pub fn render_rect(pixels: &mut [u8],
+                   bounds: (usize, usize),
+                   rect: (usize, usize, usize, usize),
+                   top_left: Complex<f64>,
+                   bot_right: Complex<f64>,
+                   deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+                   precision: Precision,
+                   limit: u32)
+{
+    let mut times = vec![0; pixels.len()];
+    escape_rect(&mut times, bounds, rect, top_left, bot_right, deep, precision, limit, BAILOUT,
+                None);
+    Gray.colorize(&times, limit, pixels);
+}
+
+/// The escape times of each of `points`, given as `(col, row)` pixels of
+/// the `bounds` image, in the same order, with orbits escaping past
+/// `bailout`, or `NO_ESCAPE` for those not reached before `cancel` was
+/// cancelled
+#[allow(clippy::too_many_arguments)]
+pub fn escape_points(points: &[(usize, usize)],
+                     bounds: (usize, usize),
+                     top_left: Complex<f64>,
+                     bot_right: Complex<f64>,
+                     deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+                     precision: Precision,
+                     limit: u32,
+                     bailout: f64,
+                     cancel: Option<&CancelToken>)
+    -> Vec<u32>
+{
+    if let Some((top_left, bot_right)) = deep {
+        return perturb::escape_points(points, bounds, top_left, bot_right, limit, bailout,
+                                      cancel);
+    }
+
+    let points: Vec<_> = points.iter()
+        .map(|&pixel| pixel_center(bounds, pixel, top_left, bot_right))
+        .collect();
+    let mut times = vec![NO_ESCAPE; points.len()];
+    let cancelled = || cancel.is_some_and(CancelToken::is_cancelled);
+    if precision == Precision::F32 {
+        let points: Vec<_> = points.iter()
+            .map(|pt| Complex { re: pt.re as f32, im: pt.im as f32 })
+            .collect();
+        iterate_batch(&points, None, limit, bailout, &cancelled, &mut times);
+    } else {
+        iterate_batch(&points, None, limit, bailout, &cancelled, &mut times);
+    }
+    times
+}
+
+/// Shade each of `points` gray, like `escape_points`
+pub fn render_points(points: &[(usize, usize)],
+                     bounds: (usize, usize),
+                     top_left: Complex<f64>,
+                     bot_right: Complex<f64>,
+                     deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+                     precision: Precision,
+                     limit: u32)
+    -> Vec<u8>
+{
+    let times = escape_points(points, bounds, top_left, bot_right, deep, precision, limit,
+                              BAILOUT, None);
+    let mut shades = vec![0; times.len()];
+    Gray.colorize(&times, limit, &mut shades);
+    shades
+}
+
+/// Shade `points` like `render_points`, split between `threads` threads
+#[cfg(feature = "native")]
+#[allow(clippy::too_many_arguments)]
+pub fn render_points_parallel(points: &[(usize, usize)],
+                              bounds: (usize, usize),
+                              top_left: Complex<f64>,
+                              bot_right: Complex<f64>,
+                              deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+                              precision: Precision,
+                              limit: u32,
+                              threads: usize)
+    -> Vec<u8>
+{
+    let per_thread = points.len() / threads + 1;
+    crossbeam::scope(|spawner| {
+        let handles: Vec<_> = points.chunks(per_thread).enumerate()
+            .map(|(index, chunk)| spawner.spawn(move || {
+                affinity::pin(index);
+                render_points(chunk, bounds, top_left, bot_right,
+                              deep, precision, limit)
+            }))
+            .collect();
+        handles.into_iter().flat_map(|h| h.join()).collect()
+    })
+}
+
+/// Split the rows of an image held in `rows`, `width` to a row, into
+/// horizontal bands, calling `render` on `threads` threads with each band
+/// and the image rows it covers
+#[cfg(feature = "native")]
+fn in_bands<P, F>(rows: &mut [P], width: usize, threads: usize, render: F)
+    where P: Send, F: Fn(&mut [P], Range<usize>) + Sync
+{
+    let rows_per_band = rows.len() / width / threads + 1;
+    let render = &render;
+    crossbeam::scope(|spawner| {
+        for (i, band) in rows.chunks_mut(rows_per_band * width).enumerate() {
+            let top = rows_per_band * i;
+            spawner.spawn(move || {
+                affinity::pin(i);
+                let height = band.len() / width;
+                render(band, top .. top + height);
+            });
+        }
+    })
+}
+
+/// Split rows `first_row ..` of the image, held in `times`, into
+/// horizontal bands whose escape times are found on `threads` threads
+#[cfg(feature = "native")]
+#[allow(clippy::too_many_arguments)]
+pub fn escape_bands(times: &mut [u32],
+                    bounds: (usize, usize),
+                    first_row: usize,
+                    top_left: Complex<f64>,
+                    bot_right: Complex<f64>,
+                    deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+                    precision: Precision,
+                    limit: u32,
+                    threads: usize)
+{
+    in_bands(times, bounds.0, threads, |band, rows| {
+        let rect = (0, first_row + rows.start, bounds.0, rows.len());
+        escape_rect(band, bounds, rect, top_left, bot_right, deep, precision, limit, BAILOUT,
+                    None);
+    })
+}
+
+/// Split rows `first_row ..` of the image, held in `pixels`, into
+/// horizontal bands rendered on `threads` threads and shaded by
+/// `colorizer`
+#[cfg(feature = "native")]
+#[allow(clippy::too_many_arguments)]
+pub fn colorize_bands<P, C>(pixels: &mut [P],
+                            bounds: (usize, usize),
+                            first_row: usize,
+                            top_left: Complex<f64>,
+                            bot_right: Complex<f64>,
+                            deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+                            precision: Precision,
+                            limit: u32,
+                            threads: usize,
+                            colorizer: &C)
+    where P: Send, C: Colorizer<P> + ?Sized
+{
+    in_bands(pixels, bounds.0, threads, |band, rows| {
+        let rect = (0, first_row + rows.start, bounds.0, rows.len());
+        let mut times = vec![0; band.len()];
+        escape_rect(&mut times, bounds, rect, top_left, bot_right, deep, precision, limit,
+                    BAILOUT, None);
+        colorizer.colorize(&times, limit, band);
+    })
+}
+
+/// `colorize_bands` in gray
+#[cfg(feature = "native")]
+#[allow(clippy::too_many_arguments)]
+pub fn render_bands(pixels: &mut [u8],
+                    bounds: (usize, usize),
+                    first_row: usize,
+                    top_left: Complex<f64>,
+                    bot_right: Complex<f64>,
+                    deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+                    precision: Precision,
+                    limit: u32,
+                    threads: usize)
+{
+    colorize_bands(pixels, bounds, first_row, top_left, bot_right,
+                   deep, precision, limit, threads, &Gray)
+}
+
+/// Rows rendered per thread before a strip is handed to the encoder
+#[cfg(feature = "native")]
+const STRIP_ROWS_PER_THREAD: usize = 64;
+
+/// Render the image strip by strip, encoding each strip as soon as it's
+/// done instead of holding every pixel until the end
+///
+/// The image starts with the rows in `done`, left by an earlier,
+/// interrupted render. If Ctrl-C is pressed the render stops after the
+/// current strip and the rest of the image is left black.
+///
+/// Returns how many rows were completed.
+#[cfg(feature = "native")]
+#[allow(clippy::too_many_arguments)]
+pub fn render_streaming(filename: &str,
+                        bounds: (usize, usize),
+                        top_left: Complex<f64>,
+                        bot_right: Complex<f64>,
+                        deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+                        precision: Precision,
+                        limit: u32,
+                        threads: usize,
+                        view: Option<&str>,
+                        done: &[u8])
+    -> Result<usize, MandelError>
+{
+    let mut writer = ImageWriter::create(filename, bounds, view)?;
+    writer.write_rows(done)?;
+    let strip_rows = STRIP_ROWS_PER_THREAD.saturating_mul(threads);
+    let mut strip = vec![0; strip_rows.min(bounds.1) * bounds.0];
+
+    let mut rows = done.len() / bounds.0;
+    while rows < bounds.1 && !interrupt::requested() {
+        let height = strip_rows.min(bounds.1 - rows);
+        let strip = &mut strip[.. height * bounds.0];
+        render_bands(strip, bounds, rows, top_left, bot_right,
+                     deep, precision, limit, threads);
+        writer.write_rows(strip)?;
+        rows += height;
+    }
+
+    let blank = vec![0; bounds.0];
+    for _ in rows .. bounds.1 {
+        writer.write_rows(&blank)?;
+    }
+    writer.finish()?;
+    Ok(rows)
+}
+
+#[test]
+fn test_orbit() {
+    let c = Complex { re: -0.1, im: 0.1 };
+    let deep = Complex { re: Fixed::from_f64(c.re, 64), im: Fixed::from_f64(c.im, 64) };
+    let orbit = orbit(c, 50);
+    assert_eq!(orbit.len(), 51);
+    for (z, reference) in orbit.iter().zip(perturb::reference_orbit(&deep, 50, BAILOUT)) {
+        assert!((z - reference).norm() < 1e-9);
+    }
+}
+
+#[test]
+fn test_parse_pair() {
+    assert!(parse_pair::<i32>("",',').is_err());
+    assert!(parse_pair::<i32>("10",',').is_err());
+    assert!(parse_pair::<i32>("10,",',').is_err());
+    assert_eq!(parse_pair::<i32>("10,20",',').unwrap(), (10,20));
+    assert!(parse_pair::<i32>("10,20x",',').is_err());
+    assert_eq!(parse_pair::<i32>(" 10 ,\t20\n",',').unwrap(), (10,20));
+    assert_eq!(parse_pair::<usize>("1000X750",'x').unwrap(), (1000,750));
+    assert_eq!(parse_pair::<usize>("4×3",'×').unwrap(), (4,3));
+    assert_eq!(parse_pair::<i32>("1,2,3",',').unwrap_err().to_string(),
+               "'1,2,3' isn't a pair: more than one ','");
+    assert_eq!(parse_pair::<i32>("10",'x').unwrap_err().to_string(),
+               "'10' isn't a pair: no 'x' between the values");
+    assert_eq!(parse_pair::<i32>("10x2.5",'x').unwrap_err().to_string(),
+               "'10x2.5' isn't a pair: can't read '2.5'");
+}
+
+#[test]
+fn test_parse_complex() {
+    assert_eq!(parse_complex("1.25,-0.0625").unwrap(), Complex { re: 1.25, im: -0.0625 });
+    assert_eq!(parse_complex(" -0.75, 0.1 ").unwrap(), Complex { re: -0.75, im: 0.1 });
+    assert!(parse_complex(",1.0").is_err());
+}
+
+#[test]
+fn test_parse_complex_reads_back_formatted() {
+    use random::Rng;
+
+    let mut rng = Rng::new(179);
+    let mut cases = 0;
+    while cases < 10_000 {
+        // any finite f64, from subnormals to the largest, however it's spaced
+        let z = Complex { re: f64::from_bits(rng.next_u64()), im: f64::from_bits(rng.next_u64()) };
+        if !z.re.is_finite() || !z.im.is_finite() {
+            continue;
+        }
+        let written = format_complex(z);
+        let parsed = parse_complex(&written).unwrap();
+        assert_eq!((parsed.re.to_bits(), parsed.im.to_bits()), (z.re.to_bits(), z.im.to_bits()),
+                   "{}", written);
+        let spaced = written.replace(',', [",", " , ", ",\t", " ,"][rng.below(4)]);
+        assert_eq!(parse_complex(&spaced).unwrap(), z, "{:?}", spaced);
+        cases += 1;
+    }
+
+    // and strings of anything, near misses most of all, are turned down
+    // rather than panicking
+    let pieces = ["-", "+", "1", "0.5", "e", "E9", ",", ".", " ", "x", "×", "é", "inf", "NaN",
+                  ""];
+    for _ in 0 .. 10_000 {
+        let s: String = (0 .. rng.below(6)).map(|_| pieces[rng.below(pieces.len())]).collect();
+        if let Ok(z) = parse_complex(&s) {
+            assert_eq!(s.matches(',').count(), 1, "{:?}", s);
+            let _ = format_complex(z);
+        }
+        let _ = parse_pair::<usize>(&s, '×');
+        let _ = fixed::parse_complex(&s, 64);
+    }
+}
+
+#[test]
+fn test_auto_iterations() {
+    let full = auto_iterations(Complex { re: -2.0, im: 1.0 },
+                               Complex { re: 1.0, im: -1.0 });
+    assert_eq!(full, 255);
+    let deep = auto_iterations(Complex { re: -0.75, im: 0.1 },
+                               Complex { re: -0.75 + 3e-6, im: 0.1 - 2e-6 });
+    assert_eq!(deep, 50_000);
+    let deeper = auto_iterations(Complex { re: -0.75, im: 0.1 },
+                                 Complex { re: -0.75 + 3e-30, im: 0.1 });
+    assert_eq!(deeper, 1_000_000);
+}
+
+#[test]
+fn test_scale_bounds() {
+    assert_eq!(scale_bounds((800, 600), 48), (48, 36));
+    assert_eq!(scale_bounds((600, 800), 48), (36, 48));
+    assert_eq!(scale_bounds((1, 100_000), 48), (1, 48));
+    assert_eq!(scale_bounds((100_000, 1), 48), (48, 1));
+    assert_eq!(scale_bounds((1, 1), 48), (48, 48));
+}
+
+#[cfg(feature = "native")]
+#[test]
+fn test_thin_images() {
+    use viewport::Viewport;
+
+    // a row or column rendered on its own is that row or column of the
+    // whole image, however it's rendered
+    let (top_left, bot_right) = (Complex { re: -2.2, im: 1.2 }, Complex { re: 1.0, im: -1.2 });
+    let whole_bounds = (70, 50);
+    let mut whole = vec![0; whole_bounds.0 * whole_bounds.1];
+    render_bands(&mut whole, whole_bounds, 0, top_left, bot_right, None, Precision::F64, 100, 3);
+    let view = Viewport::new(whole_bounds, top_left, bot_right);
+    for &rect in &[(31, 0, 1, 50), (0, 17, 70, 1), (31, 17, 1, 1)] {
+        let (x, y, width, height) = rect;
+        let expected: Vec<u8> = (y .. y + height)
+            .flat_map(|row| whole[row * 70 + x .. row * 70 + x + width].to_vec())
+            .collect();
+        let sub = view.sub(rect);
+        let (tl, br) = (sub.top_left, sub.bot_right);
+        let bounds = (width, height);
+        for threads in [1, 4] {
+            let mut pixels = vec![0; width * height];
+            render_bands(&mut pixels, bounds, 0, tl, br, None, Precision::F64, 100, threads);
+            assert_eq!(pixels, expected, "{:?} in bands", rect);
+
+            progressive::render(&mut pixels, bounds, tl, br, None, Precision::F64, 100, threads,
+                                |_, _| true);
+            assert_eq!(pixels, expected, "{:?} progressively", rect);
+
+            let renderer = Renderer::new().bounds(width, height).viewport(tl, br)
+                .iterations(100).threads(threads);
+            assert_eq!(renderer.render().unwrap().pixels(), &expected[..], "{:?}", rect);
+            for renderer in [renderer.clone().antialias(3), renderer.clone().jitter(3),
+                             renderer.clone().rotation(1.0)] {
+                assert_eq!(renderer.render().unwrap().pixels().len(), width * height);
+            }
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use analysis::{atom_domain, fate, Fate};
+use core::{step, Real};
+use error::MandelError;
+use fixed::{self, Fixed};
+use num::Complex;
+use std::fmt;
+use std::str::FromStr;
+
+/// Bits points are found to, good for `PLACES` decimal places and a few
+/// bits to spare
+pub const BITS: usize = 192;
+
+/// Decimal places points are written out to
+pub const PLACES: usize = 50;
+
+/// Most Newton steps taken before giving up on converging
+const MAX_STEPS: usize = 100;
+
+/// How far an orbit can get from 0 before it's taken to have escaped,
+/// well past where it's sure to, before its numbers grow too long
+const ESCAPED: f64 = 1e6;
+
+/// What `find` converges to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// the center of a bulb or minibrot, whose orbit comes back to 0 after
+    /// `period` steps
+    Nucleus { period: u32 },
+    /// a point on the edge of the set whose orbit lands after `preperiod`
+    /// steps on a cycle of `period`, where spirals and branches meet
+    Misiurewicz { preperiod: u32, period: u32 },
+}
+
+/// Parsed from `PERIOD` for a nucleus or `PREPERIOD:PERIOD` for a
+/// Misiurewicz point
+impl FromStr for Target {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Target, MandelError> {
+        let error = |why: &str| MandelError::Parse(format!(
+            "bad period {:?}, expected PERIOD or PREPERIOD:PERIOD{}", s, why));
+        let count = |n: &str| n.parse::<u32>().ok().filter(|&n| n > 0);
+        match s.split_once(':') {
+            None => Ok(Target::Nucleus { period: count(s).ok_or_else(|| error(""))? }),
+            Some((preperiod, period)) => {
+                let (preperiod, period) = match (count(preperiod), count(period)) {
+                    (Some(preperiod), Some(period)) => (preperiod, period),
+                    _ => return Err(error("")),
+                };
+                // an orbit landing on a cycle at `Z_1 = c` was on it at 0
+                if preperiod < 2 {
+                    return Err(error(", with a preperiod of at least 2"));
+                }
+                Ok(Target::Misiurewicz { preperiod, period })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Target::Nucleus { period } => write!(f, "nucleus of period {}", period),
+            Target::Misiurewicz { preperiod, period } => {
+                write!(f, "Misiurewicz point of preperiod {} and period {}", preperiod, period)
+            }
+        }
+    }
+}
+
+/// period_near(c, l) : the period of the bulb or minibrot `c` is in, or
+/// else of the atom domain it's in, that of the largest one near it,
+/// following its orbit for up to `limit` iterations
+pub fn period_near(c: Complex<f64>, limit: u32) -> u32 {
+    match fate(c, limit) {
+        Fate::Cycle(period) => period,
+        _ => atom_domain(c, limit).0,
+    }
+}
+
+fn mul(a: &Complex<Fixed>, b: &Complex<Fixed>) -> Complex<Fixed> {
+    Complex {
+        re: a.re.mul(&b.re).sub(&a.im.mul(&b.im)),
+        im: a.re.mul(&b.im).add(&a.im.mul(&b.re)),
+    }
+}
+
+fn sub(a: &Complex<Fixed>, b: &Complex<Fixed>) -> Complex<Fixed> {
+    Complex { re: a.re.sub(&b.re), im: a.im.sub(&b.im) }
+}
+
+/// A point of an orbit with its derivative by `c`
+type Point = (Complex<Fixed>, Complex<Fixed>);
+
+/// orbit(c, n) : `Z_0 .. Z_n` of `c`, each with its derivative by `c`
+fn orbit(c: &Complex<Fixed>, steps: u32) -> Result<Vec<Point>, MandelError> {
+    let zero = Complex { re: c.re.like(0.0), im: c.re.like(0.0) };
+    let one = Complex { re: c.re.like(1.0), im: c.re.like(0.0) };
+    let mut orbit = vec![(zero.clone(), zero)];
+    for n in 0 .. steps as usize {
+        let (ref z, ref der) = orbit[n];
+        // dZ_n+1/dc = 2 Z_n dZ_n/dc + 1
+        let twice = mul(z, der);
+        let der = Complex {
+            re: twice.re.add(&twice.re).add(&one.re),
+            im: twice.im.add(&twice.im),
+        };
+        let z = step(z, c);
+        if fixed::to_f64(&z).norm() > ESCAPED {
+            return Err(MandelError::InvalidParams(format!(
+                "the orbit of {} escapes after {} steps, start closer to the point",
+                ::format_complex(fixed::to_f64(c)), n + 1)));
+        }
+        orbit.push((z, der));
+    }
+    Ok(orbit)
+}
+
+/// log_derivative(v, d) : `d / v`, the derivative of `log v` for a value
+/// `v` with derivative `d`, `None` if `v` is 0
+fn log_derivative(value: &Complex<Fixed>, der: &Complex<Fixed>) -> Option<Complex<f64>> {
+    let value = fixed::to_f64(value);
+    if value.norm_sqr() == 0.0 {
+        return None;
+    }
+    Some(fixed::to_f64(der) / value)
+}
+
+/// newton_step(t, c) : how far to move `c` towards `target`, `None` once
+/// it's reached it exactly
+///
+/// The function solved is `Z_p` for a nucleus and `Z_k+p - Z_k` for a
+/// Misiurewicz point, divided by the same for the shorter periods or
+/// preperiods whose points are roots of it too, so that Newton's method
+/// isn't drawn to them. The step is worked out from the log derivative,
+/// so it only takes the precision of `f64` however close `c` is.
+fn newton_step(target: Target, c: &Complex<Fixed>)
+    -> Result<Option<Complex<f64>>, MandelError>
+{
+    let (preperiod, period) = match target {
+        Target::Nucleus { period } => (0, period),
+        Target::Misiurewicz { preperiod, period } => (preperiod, period),
+    };
+    let orbit = orbit(c, preperiod + period)?;
+    let factor = |from: u32, period: u32| {
+        let (ref z, ref z_der) = orbit[(from + period) as usize];
+        let (ref w, ref w_der) = orbit[from as usize];
+        (sub(z, w), sub(z_der, w_der))
+    };
+    let (value, der) = factor(preperiod, period);
+    let mut total = match log_derivative(&value, &der) {
+        Some(total) => total,
+        None => return Ok(None),
+    };
+    let shorter = (1 .. period).filter(|q| period % q == 0).map(|q| (preperiod, q));
+    let earlier = (0 .. preperiod).map(|k| (k, period));
+    for (from, period) in shorter.chain(earlier) {
+        let (value, der) = factor(from, period);
+        if let Some(term) = log_derivative(&value, &der) {
+            total -= term;
+        }
+    }
+    Ok(Some(total.inv()))
+}
+
+/// find(t, g) : the point `target` describes nearest `guess`, to the bits
+/// of `guess`, by Newton's method
+pub fn find(target: Target, guess: &Complex<Fixed>) -> Result<Complex<Fixed>, MandelError> {
+    let bits = guess.re.bits();
+    let close_enough = 2f64.powi(8 - bits as i32);
+    let mut c = guess.clone();
+    for _ in 0 .. MAX_STEPS {
+        let delta = match newton_step(target, &c)? {
+            Some(delta) => delta,
+            None => return Ok(c),
+        };
+        if !delta.is_finite() {
+            break;
+        }
+        c = Complex { re: c.re.sub(&c.re.like(delta.re)), im: c.im.sub(&c.im.like(delta.im)) };
+        // the whole set is within `BAILOUT` of 0, with room for overshooting
+        if fixed::to_f64(&c).norm() > 2.0 * ::BAILOUT {
+            return Err(MandelError::InvalidParams(format!(
+                "no {} near {}, Newton's method wandered off the set", target,
+                ::format_complex(fixed::to_f64(guess)))));
+        }
+        if delta.norm() < close_enough {
+            return Ok(c);
+        }
+    }
+    Err(MandelError::InvalidParams(format!(
+        "no {} found near {}, Newton's method didn't settle", target,
+        ::format_complex(fixed::to_f64(guess)))))
+}
+
+#[test]
+fn test_target() {
+    assert_eq!("3".parse::<Target>().unwrap(), Target::Nucleus { period: 3 });
+    assert_eq!("2:1".parse::<Target>().unwrap(),
+               Target::Misiurewicz { preperiod: 2, period: 1 });
+    for bad in &["0", "x", "1:1", "3:0", "2:1:1", ""] {
+        assert!(bad.parse::<Target>().is_err(), "{}", bad);
+    }
+    assert_eq!(Target::Misiurewicz { preperiod: 3, period: 2 }.to_string(),
+               "Misiurewicz point of preperiod 3 and period 2");
+    assert_eq!(period_near(Complex { re: -0.12, im: 0.75 }, 1000), 3);
+    assert_eq!(period_near(Complex { re: -1.0, im: 0.4 }, 1000), 2);
+}
+
+#[test]
+fn test_find() {
+    let guess = |s: &str| fixed::parse_complex(s, BITS).unwrap();
+
+    // the rabbit's bulb, to more digits than f64 holds
+    let rabbit = find(Target::Nucleus { period: 3 }, &guess("-0.12,0.74")).unwrap();
+    assert_eq!(fixed::format_complex(&rabbit, 40),
+               "-0.1225611668766536199752455518207356540527,\
+                0.7448617666197442365931704286043923672402");
+    // the airplane's minibrot, not the period 1 cardioid nor any other
+    let airplane = find(Target::Nucleus { period: 3 }, &guess("-1.76,0")).unwrap();
+    assert_eq!(airplane.re.to_decimal(40), "-1.7548776662466927600495088963585286918946");
+
+    // -2, the tip of the antenna, and i, where 0 lands on a 2-cycle
+    let tip = find(Target::Misiurewicz { preperiod: 2, period: 1 }, &guess("-1.9,0.05"));
+    assert_eq!(fixed::format_complex(&tip.unwrap(), 45),
+               fixed::format_complex(&guess("-2,0"), 45));
+    let i = find(Target::Misiurewicz { preperiod: 2, period: 2 }, &guess("0.1,0.95")).unwrap();
+    assert_eq!(fixed::format_complex(&i, 45), fixed::format_complex(&guess("0,1"), 45));
+
+    // orbits that escape before the period is up are too far to start from
+    assert!(find(Target::Nucleus { period: 20 }, &guess("1.9,0")).is_err());
+}
@@ -1,169 +1,754 @@
-extern crate crossbeam;
-extern crate image;
+extern crate mandelbrot;
+#[cfg(feature = "native")]
 extern crate num;
 
-use image::ColorType;
-use image::png::PNGEncoder;
+#[cfg(feature = "native")]
+use mandelbrot::{affinity, analysis, animate, area, cache, camera, cli, compare, distributed,
+                 explore, filter, fixed, locate, output, overlay, ray, server, sound};
+#[cfg(feature = "gui")]
+use mandelbrot::gui;
+#[cfg(feature = "tui")]
+use mandelbrot::tui;
+#[cfg(feature = "native")]
+use mandelbrot::{format_complex, parse_complex, parse_pair};
+#[cfg(feature = "native")]
+use mandelbrot::{MandelError, Renderer, BAILOUT};
+#[cfg(feature = "native")]
+use mandelbrot::precision::Precision;
+#[cfg(feature = "native")]
+use mandelbrot::viewport::{Fit, Viewport};
+#[cfg(feature = "native")]
 use num::Complex;
-use std::io::Result;
-use std::io::Write;
-use std::fs::File;
-use std::str::FromStr;
 
-/// escape_time(c, l) : check if `c` in Mandelbrot with up to `l` iterations
+/// take_option(args, name) : remove `name VALUE` or `name=VALUE` from `args`
 ///
 /// Returns:
-///     `Some(i)` if `c` left within `i` iterations, `i` < `l`
+///     `Some(Ok(value))` if the option was given
+///     `Some(Err(..))` if it was given without a value
 ///     `None` otherwise
-fn escape_time(c: Complex<f64>, limit: u32) -> Option<u32> {
-    let mut z = Complex { re: 0.0, im: 0.0 };
-    for i in 0..limit {
-        z = z * z + c;
-        if z.norm_sqr() > 4.0 {
-            return Some(i);
-        }
+#[cfg(feature = "native")]
+fn take_option(args: &mut Vec<String>, name: &str)
+    -> Option<std::result::Result<String, String>>
+{
+    let prefix = format!("{}=", name);
+    let index = args.iter()
+        .position(|arg| arg == name || arg.starts_with(&prefix))?;
+    let arg = args.remove(index);
+    if arg != name {
+        return Some(Ok(arg[prefix.len()..].to_string()));
+    }
+    if index < args.len() {
+        Some(Ok(args.remove(index)))
+    } else {
+        Some(Err(format!("missing value for {}", name)))
+    }
+}
+
+/// Results the program can't go on without
+#[cfg(feature = "native")]
+trait OrExit<T> {
+    /// or_exit(what) : the value, or else print `what` went wrong and why,
+    /// and exit
+    fn or_exit(self, what: &str) -> T;
+}
+
+#[cfg(feature = "native")]
+impl<T, E: std::fmt::Display> OrExit<T> for Result<T, E> {
+    fn or_exit(self, what: &str) -> T {
+        self.unwrap_or_else(|e| {
+            eprintln!("{}: {}", what, e);
+            std::process::exit(1);
+        })
     }
-    None
 }
 
-fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T,T)> {
-    match s.find(separator) {
-        None => None,
+/// take_switch(args, name) : remove the flag `name` from `args`, if present
+#[cfg(feature = "native")]
+fn take_switch(args: &mut Vec<String>, name: &str) -> bool {
+    match args.iter().position(|arg| arg == name) {
         Some(index) => {
-            match (T::from_str(&s[..index]), T::from_str(&s[index+1..])) {
-                (Ok(l), Ok(r)) => Some ((l,r)),
-                _ => None
-            }
+            args.remove(index);
+            true
         }
+        None => false
     }
 }
 
-fn parse_complex(s: &str) -> Option<Complex<f64>> {
-    match parse_pair(s, ',') {
-        Some((re, im)) => Some(Complex { re, im }),
-        None => None
+/// flag(args, name) : `take_option`, exiting with usage if the value is missing
+#[cfg(feature = "native")]
+fn flag(args: &mut Vec<String>, name: &str) -> Option<String> {
+    take_option(args, name).map(|value| value.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        usage(&args[0]);
+    }))
+}
+
+#[cfg(feature = "tui")]
+fn run_tui(bounds: (usize, usize),
+           top_left: Complex<f64>,
+           bot_right: Complex<f64>,
+           precision: Precision,
+           limit: u32,
+           threads: usize)
+{
+    let precision = match precision {
+        Precision::Fixed(_) => {
+            eprintln!("note: the explorer renders in f64, deep zooms will be blocky");
+            Precision::F64
+        }
+        p => p,
+    };
+    if let Err(e) = tui::run(bounds, top_left, bot_right, precision, limit, threads) {
+        eprintln!("error running explorer: {}", e);
+        std::process::exit(1);
     }
 }
 
-fn pixel_to_point(bounds: (usize, usize),
-                  pixel: (usize, usize),
-                  top_left: Complex<f64>,
-                  bot_right: Complex<f64>)
-    -> Complex<f64>
+#[cfg(all(feature = "native", not(feature = "tui")))]
+fn run_tui(_: (usize, usize), _: Complex<f64>, _: Complex<f64>, _: Precision, _: u32, _: usize) {
+    eprintln!("this build has no terminal explorer, rebuild with --features tui");
+    std::process::exit(1);
+}
+
+#[cfg(feature = "native")]
+fn usage(program: &str) -> ! {
+    eprintln!("Usage: mandelbrot [--precision f32|f64] [--iterations N|auto] \
+               [--cache DIR] [--workers HOST:PORT,...] [--mmap] \
+               [--timing FILE.json] [--heatmap FILE.png] [--stats FILE.json] \
+               [--boundary FILE.csv|FILE.geojson] [--distance FILE.npy|FILE.exr] \
+               [--potential FILE.npy|FILE.exr] [--channels FILE.exr|FILE.npy] \
+               [--analysis period|atom] [--orbit RE,IM[;RE,IM...]] \
+               [--ray P/Q[;P/Q...]] [--axes] [--caption CORNER[,SIZE]] [--text TEXT] \
+               [--watermark FILE.png] [--stamp CORNER[,OPACITY[,SIZE]]] \
+               [--filter NAME:ARGS[;NAME:ARGS...]] [--progressive] [--guess] \
+               [--threads N] [--pin-threads [--skip-smt] | --numa] [--reuse OLD.png] \
+               [--resume] [--formula PLUGIN] [--antialias N | --jitter N [--seed N]] \
+               [--bailout R] \
+               [--fit contain|cover|stretch] FILE PIXELS TOP_LEFT BOT_RIGHT");
+    eprintln!("       mandelbrot worker HOST:PORT");
+    eprintln!("       mandelbrot [--precision f32|f64] [--iterations N|auto] serve HOST:PORT");
+    eprintln!("       mandelbrot [--precision f32|f64] [--iterations N|auto] \
+               [--fit contain|cover|stretch] gui|tui PIXELS TOP_LEFT BOT_RIGHT");
+    eprintln!("       mandelbrot [--seed N] screensaver");
+    eprintln!("       mandelbrot [--subframes N] [--ramp START:GROWTH] \
+               [--pacing even|constant|detail[:STRENGTH]] animate DIR PIXELS FRAMES \
+               RE,IM:ZOOM[:ITERATIONS[:PHASE[:EASING[:DEGREES]]]]...");
+    eprintln!("       mandelbrot [--subframes N] fly DIR PIXELS PATH.json");
+    eprintln!("       mandelbrot [--iterations N] morph DIR PIXELS FRAMES \
+               cardioid[:RADIUS]|RE,IM...");
+    eprintln!("       mandelbrot [--precision f32|f64] [--iterations N|auto] \
+               cycle DIR PIXELS FRAMES TOP_LEFT BOT_RIGHT");
+    eprintln!("       mandelbrot [--iterations N] [--seed N] area SAMPLES [TOP_LEFT BOT_RIGHT]");
+    eprintln!("       mandelbrot [--iterations N] find RE,IM [PERIOD|PREPERIOD:PERIOD]");
+    eprintln!("       mandelbrot [--iterations N] explore FILE COUNT [TOP_LEFT BOT_RIGHT]");
+    eprintln!("       mandelbrot diff A.png|A.npy B.png|B.npy [HEATMAP.png]");
+    eprintln!("       mandelbrot [--iterations N] [--note-length SECONDS] sonify FILE.wav \
+               RE,IM[;RE,IM...]");
+    eprintln!("       (animate, fly, morph and cycle also take \
+               [--frame-pattern PATTERN] [--start-number N])");
+    eprintln!("e.g. {} mandel.png 1000x750 -1.20,0.35 -1,0.20",
+              program);
+    std::process::exit(1);
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(bounds: (usize, usize),
+           top_left: Complex<f64>,
+           bot_right: Complex<f64>,
+           precision: Precision,
+           limit: u32,
+           threads: usize)
 {
-    let tl = top_left;
-    let br = bot_right;
-    let (width, height) = (br.re - tl.re, tl.im - br.im);
+    let precision = match precision {
+        Precision::Fixed(_) => {
+            eprintln!("note: the explorer renders in f64, deep zooms will be blocky");
+            Precision::F64
+        }
+        p => p,
+    };
+    if let Err(e) = gui::run(bounds, top_left, bot_right, precision, limit, threads) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(all(feature = "native", not(feature = "gui")))]
+fn run_gui(_: (usize, usize), _: Complex<f64>, _: Complex<f64>, _: Precision, _: u32, _: usize) {
+    eprintln!("this build has no explorer window, rebuild with --features gui");
+    std::process::exit(1);
+}
 
-    Complex {
-        re: tl.re + pixel.0 as f64 * width / bounds.0 as f64,
-        im: tl.im - pixel.1 as f64 * height / bounds.1 as f64
+#[cfg(feature = "gui")]
+fn run_screensaver(seed: Option<u64>, threads: usize) {
+    if let Err(e) = gui::screensaver::run(seed, threads) {
+        eprintln!("{}", e);
+        std::process::exit(1);
     }
 }
 
-fn render(pixels: &mut [u8],
-          bounds: (usize, usize),
-          top_left: Complex<f64>,
-          bot_right: Complex<f64>)
+#[cfg(all(feature = "native", not(feature = "gui")))]
+fn run_screensaver(_: Option<u64>, _: usize) {
+    eprintln!("this build has no screensaver, rebuild with --features gui");
+    std::process::exit(1);
+}
+
+/// Create the directory of `files` and write `count` frames into it with
+/// `write`, showing how many are done, and how many were there already
+#[cfg(feature = "native")]
+fn write_frames<W>(files: &animate::FrameFiles, count: usize, write: W)
+    where W: FnOnce(&mut dyn FnMut(usize)) -> Result<usize, MandelError>
 {
-    assert!(pixels.len() == bounds.0 * bounds.1);
+    std::fs::create_dir_all(&files.dir).or_exit("error creating frame directory");
+    let skipped = write(&mut |index| eprint!("\rframe {} of {}", index + 1, count))
+        .or_exit("error writing frames");
+    eprintln!();
+    if skipped > 0 {
+        eprintln!("{} frames were already rendered", skipped);
+    }
+}
 
-    for row in 0 .. bounds.1 {
-        for col in 0 .. bounds.0 {
-            let pt = pixel_to_point(bounds, (col, row), top_left, bot_right);
+/// Render the frames of a zoom through `keys` into `files`, PIXELS and
+/// FRAMES given as `bounds` and `count`
+#[cfg(feature = "native")]
+#[allow(clippy::too_many_arguments)]
+fn run_animate(files: &animate::FrameFiles, bounds: &str, count: &str, keys: &[String],
+               ramp: Option<animate::Ramp>, pacing: animate::Pacing, subframes: usize,
+               threads: usize)
+{
+    let bounds = parse_pair(bounds, 'x').or_exit("error parsing PIXELS");
+    let count: usize = count.parse().or_exit("error parsing FRAMES");
+    let keys: Vec<animate::Keyframe> = keys.iter()
+        .map(|key| key.parse().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }))
+        .collect();
+    let mut views = animate::paced(&keys, count, pacing, bounds, threads)
+        .or_exit("error measuring detail");
+    if let Some(ramp) = ramp {
+        ramp.apply(&mut views);
+    }
+    write_frames(files, count, |on_frame| {
+        animate::render_views(files, bounds, &views, subframes, threads, on_frame)
+    });
+}
 
-            pixels[row * bounds.0 + col] =
-                match escape_time(pt, 255) {
-                    None => 0,
-                    Some(i) => 255 - i as u8
-                }
+/// Render the frames of the camera path in the file `path` into `files`,
+/// PIXELS given as `bounds`
+#[cfg(feature = "native")]
+fn run_fly(files: &animate::FrameFiles, bounds: &str, path: &str, subframes: usize,
+           threads: usize)
+{
+    let bounds = parse_pair(bounds, 'x').or_exit("error parsing PIXELS");
+    let views = camera::CameraPath::read(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(1);
+    }).frames();
+    write_frames(files, views.len(), |on_frame| {
+        animate::render_views(files, bounds, &views, subframes, threads, on_frame)
+    });
+}
+
+/// Iterations of each frame of a Julia morph, unless given
+#[cfg(feature = "native")]
+const MORPH_ITERATIONS: u32 = 500;
+
+/// Render the frames of the Julia sets along `path` into `files`, PIXELS
+/// and FRAMES given as `bounds` and `count`
+#[cfg(feature = "native")]
+fn run_morph(files: &animate::FrameFiles, bounds: &str, count: &str, path: &str, limit: u32,
+             threads: usize)
+{
+    let bounds = parse_pair(bounds, 'x').or_exit("error parsing PIXELS");
+    let count: usize = count.parse().or_exit("error parsing FRAMES");
+    let path: animate::Morph = path.parse().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    write_frames(files, count, |on_frame| {
+        animate::morph(files, bounds, &path, count, limit, threads, on_frame)
+    });
+}
+
+/// Render the view from TOP_LEFT to BOT_RIGHT once and write FRAMES
+/// frames of its colors cycling into `files`, from `args` in that order
+/// with PIXELS first
+#[cfg(feature = "native")]
+fn run_cycle(files: &animate::FrameFiles, args: &[String], precision: Precision,
+             limit: Option<u32>, threads: usize)
+{
+    let (width, height) = parse_pair(&args[0], 'x').or_exit("error parsing PIXELS");
+    let count: usize = args[1].parse().or_exit("error parsing FRAMES");
+    let renderer = Renderer::new()
+        .bounds(width, height)
+        .corners(&args[2], &args[3])
+        .precision(precision)
+        .threads(threads);
+    let renderer = match limit {
+        Some(limit) => renderer.iterations(limit),
+        None => renderer,
+    };
+    let escapes = renderer.escapes().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    write_frames(files, count, |on_frame| {
+        animate::cycle(files, &escapes, count, threads, on_frame)
+    });
+}
+
+/// Iterations of each sample of an area estimate, unless given
+#[cfg(feature = "native")]
+const AREA_ITERATIONS: u32 = 10_000;
+
+/// Estimate the area of the set from SAMPLES random points, between the
+/// corners TOP_LEFT and BOT_RIGHT or over the whole set, from `args` in
+/// that order
+#[cfg(feature = "native")]
+fn run_area(args: &[String], limit: u32, seed: u64, threads: usize) {
+    let samples = args[0].parse().or_exit("error parsing SAMPLES");
+    let (top_left, bot_right) = match args {
+        [_, top_left, bot_right] => {
+            (parse_complex(top_left).or_exit("error parsing TOP_LEFT"),
+             parse_complex(bot_right).or_exit("error parsing BOT_RIGHT"))
+        }
+        _ => (area::TOP_LEFT, area::BOT_RIGHT),
+    };
+    if let Err(e) = Viewport::new((1, 1), top_left, bot_right).check(None) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    if samples == 0 {
+        eprintln!("there must be at least one sample");
+        std::process::exit(1);
+    }
+    let estimate = area::estimate(top_left, bot_right, samples, limit, seed, threads);
+    let (low, high) = estimate.interval(area::Z_95);
+    println!("area {:.6} ± {:.6}, {:.6} to {:.6} with 95% confidence; {} of {} samples \
+              in the set", estimate.area(), area::Z_95 * estimate.standard_error(), low, high,
+             estimate.inside, estimate.samples);
+}
+
+/// Find the nucleus or Misiurewicz point near RE,IM and print it, with
+/// its period, from `args` in that order, the period taken from the atom
+/// domain of RE,IM if it isn't given
+#[cfg(feature = "native")]
+fn run_find(args: &[String], limit: u32) {
+    if let Err(e) = parse_complex(&args[0]) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let guess = fixed::parse_complex(&args[0], locate::BITS).or_exit("error parsing RE,IM");
+    let target = match args.get(1) {
+        Some(target) => target.parse().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }),
+        None => {
+            let period = locate::period_near(fixed::to_f64(&guess), limit);
+            eprintln!("note: looking for period {}, the atom domain of {}", period, args[0]);
+            locate::Target::Nucleus { period }
+        }
+    };
+    match locate::find(target, &guess) {
+        Ok(c) => println!("{} at {}", target, fixed::format_complex(&c, locate::PLACES)),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
         }
     }
 }
 
-fn write_image(filename: &str, pixels: &[u8], bounds: (usize, usize))
-    -> Result<()>
-{
-    let output = File::create(filename)?;
+/// How many times `explore` narrows down each spot it finds, each time
+/// 12 times closer
+#[cfg(feature = "native")]
+const EXPLORE_DEPTH: usize = 2;
+
+/// Find COUNT spots with the most detail in the view from TOP_LEFT to
+/// BOT_RIGHT, or in the whole set, and write them to the bookmarks file
+/// FILE, from `args` in that order
+#[cfg(feature = "native")]
+fn run_explore(args: &[String], limit: u32) {
+    let count = args[1].parse().or_exit("error parsing COUNT");
+    let (top_left, bot_right) = match args {
+        [_, _, top_left, bot_right] => {
+            (parse_complex(top_left).or_exit("error parsing TOP_LEFT"),
+             parse_complex(bot_right).or_exit("error parsing BOT_RIGHT"))
+        }
+        _ => (explore::TOP_LEFT, explore::BOT_RIGHT),
+    };
+    if let Err(e) = Viewport::new((1, 1), top_left, bot_right).check(None) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    let spots = explore::spots(top_left, bot_right, limit, count, EXPLORE_DEPTH);
+    if spots.len() < count {
+        eprintln!("note: only found {} spots with detail", spots.len());
+    }
+    for (index, spot) in spots.iter().enumerate() {
+        println!("spot {}: {} {}, detail {:.0}", index + 1, format_complex(spot.top_left),
+                 format_complex(spot.bot_right), spot.score);
+    }
+    explore::write_spots(&args[0], &spots, limit, "spot").or_exit("error writing FILE");
+}
 
-    let encoder = PNGEncoder::new(output);
-    encoder.encode(&pixels,
-                   bounds.0 as u32, bounds.1 as u32,
-                   ColorType::Gray(8))?;
-    Ok(())
+/// Most differing pixels `diff` lists before leaving the rest to the
+/// heatmap
+#[cfg(feature = "native")]
+const DIFF_LISTED: usize = 10;
+
+/// Compare the renders A and B, gray PNGs or `.npy` dumps, listing the
+/// pixels that differ and writing a heatmap of them to HEATMAP if given,
+/// from `args` in that order, exiting with 1 if any differ and 2 if they
+/// can't be compared, as `diff` does
+#[cfg(feature = "native")]
+fn run_diff(args: &[String]) {
+    let load = |filename: &str| {
+        compare::load(filename).unwrap_or_else(|e| {
+            eprintln!("error reading {}: {}", filename, e);
+            std::process::exit(2);
+        })
+    };
+    let (a, b) = (load(&args[0]), load(&args[1]));
+    let comparison = compare::compare(&a, &b).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    });
+    for ((x, y), difference) in comparison.differing().take(DIFF_LISTED) {
+        println!("pixel {},{} differs by {}", x, y, difference);
+    }
+    println!("{}", comparison.summary());
+    if let Some(heatmap) = args.get(2) {
+        output::write_rgb(heatmap, &comparison.heatmap()).or_exit("error writing HEATMAP");
+    }
+    if comparison.most() > 0.0 {
+        std::process::exit(1);
+    }
 }
 
+/// Steps of each orbit played by `sonify`, unless given
+#[cfg(feature = "native")]
+const SONIFY_ITERATIONS: u32 = 64;
+
+/// Play the orbit of each of the points RE,IM, separated by `;`, into the
+/// WAV file FILE, each step `length` seconds long, from `args` in that
+/// order
+#[cfg(feature = "native")]
+fn run_sonify(args: &[String], limit: u32, length: f64) {
+    let points: Vec<_> = args[1].split(';')
+        .map(|point| parse_complex(point).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }))
+        .collect();
+    if !(length > 0.0 && length.is_finite()) {
+        eprintln!("--note-length must be a positive number of seconds");
+        std::process::exit(1);
+    }
+    let notes = sound::notes(&points, limit);
+    sound::write(&args[0], &sound::synthesize(&notes, length)).unwrap_or_else(|e| {
+        eprintln!("error writing {}: {}", args[0], e);
+        std::process::exit(1);
+    });
+    println!("{} notes, {:.1} seconds", notes.len(), notes.len() as f64 * length);
+}
+
+/// Built for the browser, where the page drives everything through the
+/// exports in `web`
+#[cfg(not(feature = "native"))]
+fn main() {}
+
+#[cfg(feature = "native")]
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<String> = std::env::args().collect();
+    let mode_flags = cli::Mode::given(&args);
 
-    if args.len() != 5 {
-        writeln!(std::io::stderr(),
-                 "Usage: mandelbrot FILE PIXELS TOP_LEFT BOT_RIGHT")
-            .unwrap();
-        writeln!(std::io::stderr(),
-                "e.g. {} mandel.png 1000x750 -1.20,0.35 -1,0.20",
-                args[0])
-            .unwrap();
+    if args.len() == 3 && args[1] == "worker" {
+        let listener = std::net::TcpListener::bind(&args[2])
+            .or_exit("error binding worker address");
+        distributed::serve(listener).or_exit("error accepting connection");
+        return;
+    }
+
+    let requested = match flag(&mut args, "--precision") {
+        None => Precision::F64,
+        Some(p) => p.parse().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            usage(&args[0]);
+        }),
+    };
+    let iterations = flag(&mut args, "--iterations");
+    let cache = flag(&mut args, "--cache").map(|dir| {
+        cache::TileCache::new(dir).or_exit("error creating tile cache")
+    });
+    let mmap = take_switch(&mut args, "--mmap");
+    // these only pick their mode, which `mode_flags` already saw
+    take_switch(&mut args, "--progressive");
+    take_switch(&mut args, "--guess");
+    let antialias = flag(&mut args, "--antialias")
+        .map_or(1, |n| n.parse().or_exit("error parsing --antialias"));
+    let jitter = flag(&mut args, "--jitter")
+        .map_or(1, |n| n.parse().or_exit("error parsing --jitter"));
+    let bailout = flag(&mut args, "--bailout")
+        .map_or(BAILOUT, |r| r.parse().or_exit("error parsing --bailout"));
+    let resume = take_switch(&mut args, "--resume");
+    let pin_threads = take_switch(&mut args, "--pin-threads");
+    let skip_smt = take_switch(&mut args, "--skip-smt");
+    let numa = take_switch(&mut args, "--numa");
+    let timing = flag(&mut args, "--timing");
+    let heatmap = flag(&mut args, "--heatmap");
+    let stats = flag(&mut args, "--stats");
+    let edge = flag(&mut args, "--boundary");
+    let field = flag(&mut args, "--distance");
+    let potential = flag(&mut args, "--potential");
+    let channels = flag(&mut args, "--channels");
+    let analysis = flag(&mut args, "--analysis").map(|mode| {
+        mode.parse::<analysis::Analysis>().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+    let orbits = flag(&mut args, "--orbit").map(|points| {
+        points.split(';')
+            .map(|point| parse_complex(point).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }))
+            .collect::<Vec<_>>()
+    });
+    let rays = flag(&mut args, "--ray").map(|angles| {
+        angles.split(';')
+            .map(|angle| angle.parse::<ray::Angle>().unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }))
+            .collect::<Vec<_>>()
+    });
+    let axes = take_switch(&mut args, "--axes");
+    let caption = flag(&mut args, "--caption").map(|placement| {
+        placement.parse::<overlay::Placement>().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+    let filters = flag(&mut args, "--filter").map(|filters| {
+        filters.split(';')
+            .map(|filter| filter.parse::<filter::Filter>().unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }))
+            .collect::<Vec<_>>()
+    }).unwrap_or_default();
+    let text = flag(&mut args, "--text");
+    let watermark = flag(&mut args, "--watermark").map(|filename| {
+        output::read_rgba(&filename).unwrap_or_else(|e| {
+            eprintln!("error reading --watermark {}: {}", filename, e);
+            std::process::exit(1);
+        })
+    });
+    let stamp = flag(&mut args, "--stamp").map(|stamp| {
+        stamp.parse::<overlay::Stamp>().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+    let stamped = text.is_some() || watermark.is_some();
+    if stamp.is_some() && !stamped {
+        eprintln!("note: --stamp places --text and --watermark, neither of which was given");
+    }
+    let reuse = flag(&mut args, "--reuse");
+    let formula = flag(&mut args, "--formula");
+    let seed = flag(&mut args, "--seed").map(|n| n.parse().or_exit("error parsing --seed"));
+    let ramp = flag(&mut args, "--ramp").map(|ramp| ramp.parse().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }));
+    let pacing = flag(&mut args, "--pacing").map_or(animate::Pacing::Even, |pacing| {
+        pacing.parse().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+    let fit = flag(&mut args, "--fit").map(|fit| fit.parse::<Fit>().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }));
+    let frame_pattern = flag(&mut args, "--frame-pattern");
+    let start_number = flag(&mut args, "--start-number")
+        .map_or(0, |n| n.parse().or_exit("error parsing --start-number"));
+    let frame_files = |dir: &str| {
+        let files = animate::FrameFiles::new(dir).start(start_number);
+        match frame_pattern {
+            None => files,
+            Some(ref pattern) => files.pattern(pattern).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }),
+        }
+    };
+    let subframes = flag(&mut args, "--subframes")
+        .map_or(1, |n| n.parse().or_exit("error parsing --subframes"));
+    let note_length = flag(&mut args, "--note-length")
+        .map_or(sound::NOTE_LENGTH, |n| n.parse().or_exit("error parsing --note-length"));
+    let workers = flag(&mut args, "--workers")
+        .map(|w| w.split(',').map(String::from).collect::<Vec<_>>());
+
+    let threads = flag(&mut args, "--threads")
+        .map_or_else(|| std::thread::available_parallelism().map_or(8, |n| n.get()),
+                     |n| n.parse().or_exit("error parsing --threads"));
+    if threads == 0 {
+        eprintln!("there must be at least one thread");
         std::process::exit(1);
     }
 
+    if args.len() == 3 && args[1] == "serve" {
+        let limit = match iterations {
+            Some(ref n) if n != "auto" => Some(n.parse().or_exit("error parsing --iterations")),
+            _ => None,
+        };
+        let listener = std::net::TcpListener::bind(&args[2])
+            .or_exit("error binding server address");
+        server::serve(listener, requested, limit, threads).or_exit("error accepting connection");
+        return;
+    }
+
+    if args.len() == 2 && args[1] == "screensaver" {
+        run_screensaver(seed, threads);
+        return;
+    }
+
+    if args.len() >= 6 && args[1] == "animate" {
+        run_animate(&frame_files(&args[2]), &args[3], &args[4], &args[5 ..], ramp, pacing,
+                    subframes, threads);
+        return;
+    }
+
+    if args.len() == 7 && args[1] == "cycle" {
+        let limit = match iterations {
+            Some(ref n) if n != "auto" => Some(n.parse().or_exit("error parsing --iterations")),
+            _ => None,
+        };
+        run_cycle(&frame_files(&args[2]), &args[3 ..], requested, limit, threads);
+        return;
+    }
+
+    if args.len() == 5 && args[1] == "fly" {
+        run_fly(&frame_files(&args[2]), &args[3], &args[4], subframes, threads);
+        return;
+    }
+
+    if args.len() >= 6 && args[1] == "morph" {
+        let limit = iterations.map_or(MORPH_ITERATIONS, |n| {
+            n.parse().or_exit("error parsing --iterations")
+        });
+        run_morph(&frame_files(&args[2]), &args[3], &args[4], &args[5 ..].join(";"), limit,
+                  threads);
+        return;
+    }
+
+    if (args.len() == 3 || args.len() == 5) && args[1] == "area" {
+        let limit = iterations.map_or(AREA_ITERATIONS, |n| {
+            n.parse().or_exit("error parsing --iterations")
+        });
+        run_area(&args[2 ..], limit, seed.unwrap_or(0), threads);
+        return;
+    }
+
+    if (args.len() == 4 || args.len() == 6) && args[1] == "explore" {
+        let limit = iterations.map_or(255, |n| n.parse().or_exit("error parsing --iterations"));
+        run_explore(&args[2 ..], limit);
+        return;
+    }
+
+    if (args.len() == 4 || args.len() == 5) && args[1] == "diff" {
+        run_diff(&args[2 ..]);
+        return;
+    }
+
+    if args.len() == 4 && args[1] == "sonify" {
+        let limit = iterations.map_or(SONIFY_ITERATIONS, |n| {
+            n.parse().or_exit("error parsing --iterations")
+        });
+        run_sonify(&args[2 ..], limit, note_length);
+        return;
+    }
+
+    if (args.len() == 3 || args.len() == 4) && args[1] == "find" {
+        let limit = iterations.map_or(AREA_ITERATIONS, |n| {
+            n.parse().or_exit("error parsing --iterations")
+        });
+        run_find(&args[2 ..], limit);
+        return;
+    }
+
+    if args.len() != 5 {
+        usage(&args[0]);
+    }
+
     let bounds = parse_pair(&args[2], 'x')
-        .expect("error parsing PIXELS");
-    let top_left = parse_complex(&args[3])
-        .expect("error parsing TOP_LEFT");
-    let bot_right = parse_complex(&args[4])
-        .expect("error parsing BOT_RIGHT");
-
-    let mut pixels = vec![0; bounds.0 * bounds.1];
-
-    let threads = 8;
-    let rows_per_band = bounds.1 / threads + 1;
-
-    {
-        let bands: Vec<&mut [u8]> =
-            pixels.chunks_mut(rows_per_band * bounds.0).collect();
-        crossbeam::scope(|spawner| {
-            for (i, band) in bands.into_iter().enumerate() {
-                let top = rows_per_band * i;
-                let height = band.len() / bounds.0;
-                let band_bounds = (bounds.0, height);
-                let band_top_left =
-                    pixel_to_point(bounds, (0, top), top_left, bot_right);
-                let band_bot_right =
-                    pixel_to_point(bounds, (bounds.0, top+height),
-                                   top_left, bot_right);
-
-                spawner.spawn(move || {
-                    render(band, band_bounds, band_top_left, band_bot_right);
-                });
+        .or_exit("error parsing PIXELS");
+    let view = cli::View::new(bounds, &args[3], &args[4], requested, iterations.as_deref(), fit)
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+
+    if numa {
+        if pin_threads || skip_smt {
+            eprintln!("note: --numa keeps threads on their node, ignoring --pin-threads");
+        }
+        match affinity::configure_numa() {
+            Ok(1) => eprintln!("note: only one NUMA node, --numa has no effect"),
+            Ok(_) => {}
+            Err(e) => eprintln!("warning: can't place threads on NUMA nodes: {}", e),
+        }
+    } else if pin_threads || skip_smt {
+        match affinity::configure(skip_smt) {
+            Ok(cores) if cores < threads => {
+                eprintln!("note: {} threads share {} pinned cores", threads, cores);
             }
-        })
+            Ok(_) => {}
+            Err(e) => eprintln!("warning: can't pin threads: {}", e),
+        }
     }
 
-    write_image(&args[1], &pixels, bounds)
-        .expect("error writing PNG file");
-}
+    if args[1] == "gui" {
+        run_gui(bounds, view.top_left, view.bot_right, view.precision, view.limit, threads);
+        return;
+    }
+    if args[1] == "tui" {
+        run_tui(bounds, view.top_left, view.bot_right, view.precision, view.limit, threads);
+        return;
+    }
 
-#[test]
-fn test_parse_pair() {
-    assert_eq!(parse_pair::<i32>("",','), None);
-    assert_eq!(parse_pair::<i32>("10",','), None);
-    assert_eq!(parse_pair::<i32>("10,",','), None);
-    assert_eq!(parse_pair::<i32>("10,20",','), Some((10,20)));
-    assert_eq!(parse_pair::<i32>("10,20x",','), None);
+    let flags = cli::Flags {
+        mmap, antialias, jitter, seed, bailout, resume, threads, timing, heatmap, stats,
+        boundary: edge, distance: field, potential, channels, analysis, orbits, rays, axes,
+        caption, filters, text, watermark, stamp, reuse, formula, workers, cache,
+    };
+    match cli::render(&args[1], &view, &flags, &mode_flags) {
+        Ok(()) => {}
+        Err(MandelError::Cancelled) => std::process::exit(130),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 }
 
+#[cfg(feature = "native")]
 #[test]
-fn test_parse_complex() {
-    assert_eq!(parse_complex("1.25,-0.0625"),
-               Some(Complex { re: 1.25, im: -0.0625 }));
-    assert_eq!(parse_complex(",1.0"), None)
-}
+fn test_take_option() {
+    let mut args: Vec<String> = ["prog", "--precision", "f32", "out.png"]
+        .iter().map(|s| s.to_string()).collect();
+    assert_eq!(take_option(&mut args, "--precision"), Some(Ok("f32".into())));
+    assert_eq!(args, ["prog", "out.png"]);
+    assert_eq!(take_option(&mut args, "--precision"), None);
 
-#[test]
-fn test_pixel_to_point() {
-    assert_eq!(pixel_to_point((100,100), (25,75),
-                              Complex { re: -1.0, im:  1.0 },
-                              Complex { re:  1.0, im: -1.0 }),
-               Complex { re: -0.5, im: -0.5 });
+    let mut args = vec!["--precision=f64".to_string()];
+    assert_eq!(take_option(&mut args, "--precision"), Some(Ok("f64".into())));
+    assert!(args.is_empty());
+
+    let mut args = vec!["--precision".to_string()];
+    assert!(take_option(&mut args, "--precision").unwrap().is_err());
 }
@@ -0,0 +1,274 @@
+use error::MandelError;
+use image::{ImageBuffer, Rgb, Rgba};
+use png;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use viewport::MAX_SIDE;
+
+/// Keyword of the text chunk recording which view an image shows
+pub const VIEW_KEYWORD: &str = "mandelbrot-view";
+
+/// png_bounds(b) : `bounds` as a PNG's width and height, if it can hold them
+fn png_bounds(bounds: (usize, usize)) -> Result<(u32, u32), MandelError> {
+    match (u32::try_from(bounds.0), u32::try_from(bounds.1)) {
+        (Ok(width), Ok(height)) if bounds.0 <= MAX_SIDE && bounds.1 <= MAX_SIDE => {
+            Ok((width, height))
+        }
+        _ => Err(MandelError::InvalidParams(
+            format!("a PNG can't be {}x{}, only up to {} pixels across or down",
+                    bounds.0, bounds.1, MAX_SIDE))),
+    }
+}
+
+/// Partial files started so far, to tell those of one process apart
+static PARTIALS: AtomicUsize = AtomicUsize::new(0);
+
+/// A file written under a temporary name beside where it's going and only
+/// renamed into place once it's whole, so nothing is ever found half
+/// written there, and removed if it never is
+struct Partial {
+    path: PathBuf,
+    temp: PathBuf,
+    file: File,
+    kept: bool,
+}
+
+impl Partial {
+    fn create(filename: &str) -> io::Result<Partial> {
+        let path = PathBuf::from(filename);
+        let name = path.file_name().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{} isn't a file", filename))
+        })?;
+        let temp = path.with_file_name(format!(".{}.{}-{}.partial", name.to_string_lossy(),
+                                               process::id(),
+                                               PARTIALS.fetch_add(1, Ordering::Relaxed)));
+        let file = File::create(&temp)?;
+        Ok(Partial { path, temp, file, kept: false })
+    }
+
+    /// Make sure the file is on disk, then move it into place
+    fn keep(mut self) -> io::Result<()> {
+        self.file.sync_all()?;
+        fs::rename(&self.temp, &self.path)?;
+        self.kept = true;
+        Ok(())
+    }
+}
+
+impl Drop for Partial {
+    fn drop(&mut self) {
+        if !self.kept {
+            let _ = fs::remove_file(&self.temp);
+        }
+    }
+}
+
+/// A file that notes whether writing it ever failed, since the encoder
+/// finishes the image as it's dropped, where errors are thrown away
+struct Noted {
+    file: File,
+    failed: Arc<AtomicBool>,
+}
+
+impl Write for Noted {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf);
+        self.failed.fetch_or(written.is_err(), Ordering::Relaxed);
+        written
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let flushed = self.file.flush();
+        self.failed.fetch_or(flushed.is_err(), Ordering::Relaxed);
+        flushed
+    }
+}
+
+/// Grayscale PNG written out row by row as the render progresses,
+/// so the whole image never has to be held in memory
+///
+/// The image only appears under its name once `finish` has written all
+/// of it; until then it's in a hidden `.partial` file beside it, which is
+/// removed if the writer is dropped unfinished.
+pub struct ImageWriter {
+    stream: png::StreamWriter<'static, BufWriter<Noted>>,
+    failed: Arc<AtomicBool>,
+    partial: Partial,
+}
+
+impl ImageWriter {
+    /// Start the image, tagging it with `view` if given so that later
+    /// renders can tell what it shows
+    pub fn create(filename: &str, bounds: (usize, usize), view: Option<&str>)
+        -> Result<ImageWriter, MandelError>
+    {
+        let (width, height) = png_bounds(bounds)?;
+        let partial = Partial::create(filename)?;
+        let failed = Arc::new(AtomicBool::new(false));
+        let output = BufWriter::new(Noted { file: partial.file.try_clone()?,
+                                            failed: failed.clone() });
+
+        let mut encoder = png::Encoder::new(output, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        if let Some(view) = view {
+            encoder.add_text_chunk(VIEW_KEYWORD.to_string(), view.to_string())?;
+        }
+        let stream = encoder.write_header()?.into_stream_writer()?;
+        Ok(ImageWriter { stream, failed, partial })
+    }
+
+    /// Append whole rows of pixels to the image
+    pub fn write_rows(&mut self, rows: &[u8]) -> io::Result<()> {
+        self.stream.write_all(rows)
+    }
+
+    /// Finish the image and put it in place
+    pub fn finish(self) -> Result<(), MandelError> {
+        self.stream.finish()?;
+        if self.failed.load(Ordering::Relaxed) {
+            return Err(io::Error::other("error writing the end of the image").into());
+        }
+        Ok(self.partial.keep()?)
+    }
+}
+
+/// encode(p, b) : `pixels` as a grayscale PNG in memory, for sending
+/// rather than saving
+pub fn encode(pixels: &[u8], bounds: (usize, usize)) -> Result<Vec<u8>, MandelError> {
+    let (width, height) = png_bounds(bounds)?;
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    writer.finish()?;
+    Ok(bytes)
+}
+
+/// write_rgb(f, i) : `image` saved to `filename` as an 8-bit color PNG
+pub fn write_rgb(filename: &str, image: &ImageBuffer<Rgb>) -> Result<(), MandelError> {
+    let (width, height) = png_bounds(image.bounds())?;
+    let partial = Partial::create(filename)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(&partial.file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    let bytes: Vec<u8> = image.pixels().iter().flat_map(|rgb| rgb.0).collect();
+    writer.write_image_data(&bytes)?;
+    writer.finish()?;
+    Ok(partial.keep()?)
+}
+
+/// An image read back in, with the view it was tagged with if any
+pub struct Image {
+    pub bounds: (usize, usize),
+    pub pixels: Vec<u8>,
+    pub view: Option<String>,
+}
+
+/// read_image(f) : the pixels of a grayscale image written by `ImageWriter`
+pub fn read_image(filename: &str) -> io::Result<Image> {
+    let decoder = png::Decoder::new(File::open(filename)?);
+    let mut reader = decoder.read_info()?;
+    let info = reader.info();
+    if info.color_type != png::ColorType::Grayscale || info.bit_depth != png::BitDepth::Eight {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("{} isn't an 8-bit grayscale image", filename)));
+    }
+    let bounds = (info.width as usize, info.height as usize);
+    let view = info.uncompressed_latin1_text.iter()
+        .find(|chunk| chunk.keyword == VIEW_KEYWORD)
+        .map(|chunk| chunk.text.clone());
+
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels)?;
+    Ok(Image { bounds, pixels, view })
+}
+
+/// read_rgba(f) : the pixels of any PNG, such as a logo to lay over a
+/// render, as 8-bit red, green, blue and alpha, opaque if it has no alpha
+pub fn read_rgba(filename: &str) -> io::Result<ImageBuffer<Rgba>> {
+    let mut decoder = png::Decoder::new(File::open(filename)?);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut bytes = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut bytes)?;
+    let bounds = (frame.width as usize, frame.height as usize);
+    let rgba = |pixel: &[u8]| match *pixel {
+        [value] => Rgba([value, value, value, 255]),
+        [value, alpha] => Rgba([value, value, value, alpha]),
+        [red, green, blue] => Rgba([red, green, blue, 255]),
+        [red, green, blue, alpha] => Rgba([red, green, blue, alpha]),
+        _ => unreachable!("8-bit PNGs have one to four channels"),
+    };
+    let channels = frame.color_type.samples();
+    let pixels = bytes[.. frame.buffer_size()].chunks(channels).map(rgba).collect();
+    Ok(ImageBuffer::from_pixels(bounds, pixels))
+}
+
+#[test]
+fn test_write_rows() {
+    let path = std::env::temp_dir()
+        .join(format!("mandelbrot-output-test-{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    let mut writer = ImageWriter::create(filename, (4, 3), Some("view")).unwrap();
+    writer.write_rows(&[0, 1, 2, 3, 4, 5, 6, 7]).unwrap();
+    // nothing's there under the name until the image is whole
+    assert!(!path.exists());
+    writer.write_rows(&[8, 9, 10, 11]).unwrap();
+    writer.finish().unwrap();
+
+    let image = read_image(filename).unwrap();
+    assert_eq!(image.bounds, (4, 3));
+    assert_eq!(image.pixels, (0..12).collect::<Vec<u8>>());
+    assert_eq!(image.view.as_deref(), Some("view"));
+
+    // an image given up on leaves the one before it, and nothing else
+    let mut writer = ImageWriter::create(filename, (4, 3), None).unwrap();
+    writer.write_rows(&[0; 4]).unwrap();
+    assert!(writer.finish().is_err());
+    assert_eq!(read_image(filename).unwrap().pixels, (0..12).collect::<Vec<u8>>());
+    let partial = format!(".{}.", path.file_name().unwrap().to_string_lossy());
+    assert!(!fs::read_dir(path.parent().unwrap()).unwrap()
+        .any(|entry| entry.unwrap().file_name().to_string_lossy().starts_with(&partial)));
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_read_rgba() {
+    let path = std::env::temp_dir()
+        .join(format!("mandelbrot-output-rgba-test-{}.png", std::process::id()));
+    let filename = path.to_str().unwrap();
+
+    let image = ImageBuffer::from_pixels((2, 1), vec![Rgb([255, 0, 0]), Rgb([0, 128, 255])]);
+    write_rgb(filename, &image).unwrap();
+    assert_eq!(read_rgba(filename).unwrap().into_pixels(),
+               [Rgba([255, 0, 0, 255]), Rgba([0, 128, 255, 255])]);
+
+    // grays come out as equal reds, greens and blues
+    ::write_image(filename, &[0, 64, 128, 255], (2, 2), None).unwrap();
+    let gray = read_rgba(filename).unwrap();
+    assert_eq!((gray.bounds(), gray[(1, 0)]), ((2, 2), Rgba([64, 64, 64, 255])));
+
+    fs::remove_file(&path).unwrap();
+    assert!(read_rgba(filename).is_err());
+}
+
+#[test]
+fn test_png_bounds() {
+    assert_eq!(png_bounds((640, 480)).unwrap(), (640, 480));
+    assert_eq!(png_bounds((MAX_SIDE, 1)).unwrap(), (MAX_SIDE as u32, 1));
+    for &bounds in &[(MAX_SIDE + 1, 1), (1, usize::MAX)] {
+        assert!(matches!(png_bounds(bounds), Err(MandelError::InvalidParams(_))));
+    }
+    assert!(encode(&[], (1 << 32, 0)).is_err());
+}
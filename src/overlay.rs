@@ -0,0 +1,757 @@
+use error::MandelError;
+use image::{ImageBuffer, Rgb, Rgba};
+use num::Complex;
+use orbit;
+use std::collections::HashMap;
+use std::str::FromStr;
+use viewport::Viewport;
+
+/// The color orbits are drawn in, bright against a gray render
+pub const ORBIT_COLOR: Rgb = Rgb([255, 64, 32]);
+
+/// The color external rays are drawn in, apart from orbits
+pub const RAY_COLOR: Rgb = Rgb([48, 160, 255]);
+
+/// The colors of gridlines, of the real and imaginary axes, and of tick
+/// labels and the halo around them that keeps them readable on the set
+/// and off it alike
+pub const GRID_COLOR: Rgb = Rgb([128, 128, 128]);
+pub const AXIS_COLOR: Rgb = Rgb([96, 96, 96]);
+pub const LABEL_COLOR: Rgb = Rgb([0, 0, 0]);
+pub const HALO_COLOR: Rgb = Rgb([255, 255, 255]);
+
+/// How opaque gridlines are drawn, faint enough to see the set through
+const GRID_OPACITY: f64 = 0.5;
+
+/// About how many gridlines `draw_axes` fits across the longer side
+pub const GRID_LINES: usize = 8;
+
+/// About what fraction of the image's width `draw_caption`'s scale bar
+/// spans
+pub const SCALE_BAR: usize = 5;
+
+/// A 3x5 bitmap font for tick labels, captions and stamped text, each row
+/// of a glyph three bits with the leftmost the highest
+const GLYPHS: [(char, [u8; 5]); 51] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('i', [0b010, 0b000, 0b010, 0b010, 0b010]),
+    ('+', [0b000, 0b010, 0b111, 0b010, 0b000]),
+    ('e', [0b111, 0b101, 0b111, 0b100, 0b111]),
+    ('x', [0b000, 0b101, 0b010, 0b101, 0b000]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('(', [0b001, 0b010, 0b010, 0b010, 0b001]),
+    (')', [0b100, 0b010, 0b010, 0b010, 0b100]),
+    ('!', [0b010, 0b010, 0b010, 0b000, 0b010]),
+    ('?', [0b110, 0b001, 0b010, 0b000, 0b010]),
+    ('\'', [0b010, 0b010, 0b000, 0b000, 0b000]),
+    ('_', [0b000, 0b000, 0b000, 0b000, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+];
+
+/// How opaque the last step of an orbit is drawn, fading from solid at
+/// its first
+pub const FADE_TO: f64 = 0.2;
+
+/// Most steps of an orbit worth drawing, past which one caught in a cycle
+/// only goes over itself again
+pub const ORBIT_STEPS: u32 = 100;
+
+/// blend(u, o, a) : `over` laid on `under` with opacity `alpha`
+fn blend(under: Rgb, over: Rgb, alpha: f64) -> Rgb {
+    let mut mixed = under;
+    for (channel, &over) in mixed.0.iter_mut().zip(&over.0) {
+        let under = *channel as f64;
+        *channel = (under + (over as f64 - under) * alpha).round() as u8;
+    }
+    mixed
+}
+
+/// clip(a, b, bounds) : how far along the line from `a` to `b` it enters
+/// and leaves a `bounds` image, as fractions of its length, `None` if it
+/// misses the image
+fn clip(a: (f64, f64), b: (f64, f64), bounds: (usize, usize)) -> Option<(f64, f64)> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut enter, mut leave) = (0.0f64, 1.0f64);
+    // each edge as the step towards it and the distance to it
+    let edges = [(-dx, a.0), (dx, bounds.0 as f64 - a.0), (-dy, a.1), (dy, bounds.1 as f64 - a.1)];
+    for &(step, distance) in &edges {
+        if step == 0.0 {
+            if distance < 0.0 {
+                return None;
+            }
+        } else if step < 0.0 {
+            enter = enter.max(distance / step);
+        } else {
+            leave = leave.min(distance / step);
+        }
+    }
+    if enter <= leave { Some((enter, leave)) } else { None }
+}
+
+/// Draw the line from `a` to `b` on `image` in `color`, its opacity going
+/// from `opacity.0` to `opacity.1` along it, skipping `last`, the pixel
+/// drawn before it, so that lines joined end to end don't darken the join
+fn draw_line(image: &mut ImageBuffer<Rgb>,
+             a: (f64, f64),
+             b: (f64, f64),
+             color: Rgb,
+             opacity: (f64, f64),
+             last: &mut Option<(usize, usize)>)
+{
+    let (width, height) = image.bounds();
+    let (enter, leave) = match clip(a, b, (width, height)) {
+        Some(along) => along,
+        None => return,
+    };
+    let at = |t: f64| (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+    let (start, end) = (at(enter), at(leave));
+    let steps = (end.0 - start.0).abs().max((end.1 - start.1).abs()).ceil().max(1.0) as usize;
+    for step in 0 ..= steps {
+        let t = enter + (leave - enter) * step as f64 / steps as f64;
+        let (x, y) = at(t);
+        let pixel = ((x.max(0.0) as usize).min(width - 1), (y.max(0.0) as usize).min(height - 1));
+        if *last != Some(pixel) {
+            image[pixel] = blend(image[pixel], color, opacity.0 + (opacity.1 - opacity.0) * t);
+            *last = Some(pixel);
+        }
+    }
+}
+
+/// Draw the orbit of `c` on `image`, a render of `view`, in `color`, as
+/// the line through `Z_0 = 0` and at most `steps` points after it, solid
+/// at the start and fading to `FADE_TO` by the end
+pub fn draw_orbit(image: &mut ImageBuffer<Rgb>,
+                  view: &Viewport,
+                  c: Complex<f64>,
+                  steps: u32,
+                  color: Rgb)
+{
+    if image.pixels().is_empty() {
+        return;
+    }
+    let points: Vec<_> = orbit(c, steps).into_iter().map(|z| view.position(z)).collect();
+    let lines = points.len().saturating_sub(1).max(1) as f64;
+    let fade = |step: usize| 1.0 - (1.0 - FADE_TO) * step as f64 / lines;
+    let mut last = None;
+    for (step, line) in points.windows(2).enumerate() {
+        draw_line(image, line[0], line[1], color, (fade(step), fade(step + 1)), &mut last);
+    }
+}
+
+/// Draw the line through `points` on `image`, a render of `view`, solid
+/// in `color`, such as an external ray from `ray::trace`
+pub fn draw_path(image: &mut ImageBuffer<Rgb>,
+                 view: &Viewport,
+                 points: &[Complex<f64>],
+                 color: Rgb)
+{
+    if image.pixels().is_empty() {
+        return;
+    }
+    let positions: Vec<_> = points.iter().map(|&point| view.position(point)).collect();
+    let mut last = None;
+    for line in positions.windows(2) {
+        draw_line(image, line[0], line[1], color, (1.0, 1.0), &mut last);
+    }
+}
+
+/// grid_step(s, n) : the round spacing, 1, 2 or 5 times a power of 10,
+/// that puts closest to `lines` gridlines across `span`
+pub fn grid_step(span: f64, lines: usize) -> f64 {
+    let rough = span.abs() / lines.max(1) as f64;
+    let power = 10f64.powf(rough.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter()
+        .map(|&mantissa| mantissa * power)
+        .min_by(|a, b| (a / rough).ln().abs().total_cmp(&(b / rough).ln().abs()))
+        .unwrap()
+}
+
+/// tick_label(n, s) : the label of the `n`th gridline of spacing `step`
+/// from 0, to as many decimal places as the spacing needs
+pub fn tick_label(index: i64, step: f64) -> String {
+    if index == 0 {
+        return "0".to_string();
+    }
+    let places = (-step.log10().floor()).max(0.0) as usize;
+    format!("{:.*}", places, index as f64 * step)
+}
+
+/// text_size(t, s) : how many pixels wide and high `text` is at `scale`
+fn text_size(text: &str, scale: usize) -> (usize, usize) {
+    ((4 * text.chars().count()).saturating_sub(1) * scale, 5 * scale)
+}
+
+/// Draw `text` on `image` in `color` with its top left at `at`, each pixel
+/// of the font `scale` pixels square, with a pixel of `HALO_COLOR` around
+/// it, leaving out characters the font doesn't have
+fn draw_text(image: &mut ImageBuffer<Rgb>, at: (i64, i64), text: &str, scale: usize, color: Rgb) {
+    draw_dots(image, &text_dots(at, text, scale), color, 1.0);
+}
+
+/// text_dots(a, t, s) : the pixels `draw_text` draws `text` on
+fn text_dots(at: (i64, i64), text: &str, scale: usize) -> Vec<(i64, i64)> {
+    let mut dots = Vec::new();
+    for (index, c) in text.chars().enumerate() {
+        let rows = match GLYPHS.iter().find(|&&(glyph, _)| glyph == c) {
+            Some(&(_, rows)) => rows,
+            None => continue,
+        };
+        for (y, row) in rows.iter().enumerate() {
+            for x in (0 .. 3).filter(|x| row & (0b100 >> x) != 0) {
+                for (dx, dy) in (0 .. scale * scale).map(|n| (n % scale, n / scale)) {
+                    dots.push((at.0 + ((4 * index + x) * scale + dx) as i64,
+                               at.1 + (y * scale + dy) as i64));
+                }
+            }
+        }
+    }
+    dots
+}
+
+/// Draw each of `dots` on `image` in `color`, over a pixel of `HALO_COLOR`
+/// around them all, with `opacity`, leaving out those off the image
+fn draw_dots(image: &mut ImageBuffer<Rgb>, dots: &[(i64, i64)], color: Rgb, opacity: f64) {
+    let (width, height) = image.bounds();
+    // each pixel once, so that overlapping halos aren't laid on twice
+    let mut colors = HashMap::new();
+    for &(x, y) in dots {
+        for (dx, dy) in (0 .. 9).map(|n| (n % 3 - 1, n / 3 - 1)) {
+            colors.insert((x + dx, y + dy), HALO_COLOR);
+        }
+    }
+    for &dot in dots {
+        colors.insert(dot, color);
+    }
+    for ((x, y), color) in colors {
+        if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+            let pixel = (x as usize, y as usize);
+            image[pixel] = blend(image[pixel], color, opacity);
+        }
+    }
+}
+
+/// Draw gridlines at round coordinates over `image`, a render of `view`
+/// that isn't turned, about `GRID_LINES` across its longer side, with the
+/// real and imaginary axes stronger where they're in view and the lines
+/// labelled, real parts along the bottom and imaginary ones down the left,
+/// leaving out labels that would run off the image or into each other
+pub fn draw_axes(image: &mut ImageBuffer<Rgb>, view: &Viewport) {
+    let (width, height) = image.bounds();
+    if width == 0 || height == 0 {
+        return;
+    }
+    let (span_re, span_im) = view.size();
+    let step = grid_step(span_re.abs().max(span_im.abs()), GRID_LINES);
+    let scale = 1 + width.min(height) / 500;
+    let (left, right) = (view.top_left.re.min(view.bot_right.re),
+                         view.top_left.re.max(view.bot_right.re));
+    let (bottom, top) = (view.top_left.im.min(view.bot_right.im),
+                         view.top_left.im.max(view.bot_right.im));
+    let indices = |from: f64, to: f64| (from / step).ceil() as i64 ..= (to / step).floor() as i64;
+    let style = |index: i64| {
+        if index == 0 { (AXIS_COLOR, 1.0) } else { (GRID_COLOR, GRID_OPACITY) }
+    };
+    let gap = 2 * scale as i64;
+    let (width_px, height_px) = (width as i64, height as i64);
+    // each label as where its top left goes and its text
+    let mut labels: Vec<((i64, i64), String)> = Vec::new();
+    let mut clear_of = i64::MIN;
+    for index in indices(left, right) {
+        let (x, _) = view.position(Complex { re: index as f64 * step, im: top });
+        let (color, opacity) = style(index);
+        draw_line(image, (x, 0.0), (x, height as f64), color, (opacity, opacity), &mut None);
+        let label = tick_label(index, step);
+        let (w, h) = text_size(&label, scale);
+        let (w, h) = (w as i64, h as i64);
+        let at = (x as i64 - w / 2, height_px - h - gap);
+        if at.0 >= 0 && at.0 + w <= width_px && at.1 >= 0 && at.0 > clear_of {
+            clear_of = at.0 + w + gap;
+            labels.push((at, label));
+        }
+    }
+    // the imaginary labels stop short of the real ones along the bottom
+    let floor = labels.iter().map(|&((_, y), _)| y - gap).min().unwrap_or(height_px);
+    let mut clear_of = i64::MIN;
+    for index in indices(bottom, top).rev() {
+        let (_, y) = view.position(Complex { re: left, im: index as f64 * step });
+        let (color, opacity) = style(index);
+        draw_line(image, (0.0, y), (width as f64, y), color, (opacity, opacity), &mut None);
+        let label = if index == 0 { "0".to_string() } else { tick_label(index, step) + "i" };
+        let (w, h) = text_size(&label, scale);
+        let (w, h) = (w as i64, h as i64);
+        let at = (gap, y as i64 - h / 2);
+        if at.1 >= 0 && at.1 + h <= floor && w + gap <= width_px && at.1 > clear_of {
+            clear_of = at.1 + h + gap;
+            labels.push((at, label));
+        }
+    }
+    for (at, label) in labels {
+        draw_text(image, at, &label, scale, LABEL_COLOR);
+    }
+}
+
+/// A corner of an image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Corner, MandelError> {
+        match s {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(MandelError::Parse(format!(
+                "bad corner {:?}, expected top-left, top-right, bottom-left or bottom-right",
+                s))),
+        }
+    }
+}
+
+impl Corner {
+    /// sides() : whether the corner is on the right and on the bottom
+    fn sides(self) -> (bool, bool) {
+        match self {
+            Corner::TopLeft => (false, false),
+            Corner::TopRight => (true, false),
+            Corner::BottomLeft => (false, true),
+            Corner::BottomRight => (true, true),
+        }
+    }
+}
+
+/// Where `draw_caption` stamps a caption and how big, `None` to scale it
+/// to the image as `draw_axes` does its labels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub corner: Corner,
+    pub scale: Option<usize>,
+}
+
+/// Parsed from `CORNER` or `CORNER,SCALE`, `SCALE` the pixels to a pixel
+/// of the font
+impl FromStr for Placement {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Placement, MandelError> {
+        let (corner, scale) = match s.split_once(',') {
+            Some((corner, scale)) => (corner, Some(scale)),
+            None => (s, None),
+        };
+        let scale = match scale.map(|scale| scale.parse::<usize>()) {
+            None => None,
+            Some(Ok(scale)) if scale > 0 => Some(scale),
+            Some(_) => return Err(MandelError::Parse(format!(
+                "bad caption size in {:?}, expected a whole number above 0", s))),
+        };
+        Ok(Placement { corner: corner.parse()?, scale })
+    }
+}
+
+/// What `draw_caption` writes of a view: its center, each part written
+/// out to as many places as tell its pixels apart, how many times it's
+/// magnified, and how wide it is in the plane
+#[derive(Clone, Debug, PartialEq)]
+pub struct Caption {
+    pub center: (String, String),
+    pub zoom: f64,
+    pub width: f64,
+}
+
+/// places(p) : the decimal places it takes to tell apart points `pixel`
+/// apart
+pub fn places(pixel: f64) -> usize {
+    (-pixel.abs().log10()).ceil().max(0.0) as usize
+}
+
+impl Caption {
+    /// lines() : the center, as `re+imi`, and the zoom, as `xZOOM`
+    pub fn lines(&self) -> [String; 2] {
+        let (ref re, ref im) = self.center;
+        let sign = if im.starts_with('-') { "" } else { "+" };
+        let zoom = if self.zoom < 1000.0 { format!("x{:.1}", self.zoom) }
+                   else { format!("x{:.2e}", self.zoom) };
+        [format!("{}{}{}i", re, sign, im), zoom]
+    }
+}
+
+/// bar_label(l) : the length of a scale bar, in decimals unless it's
+/// short enough to take more digits than an exponent does
+pub fn bar_label(length: f64) -> String {
+    if length >= 1e-3 { tick_label(1, length) } else { format!("{:.0e}", length) }
+}
+
+/// Stamp `caption` on `image` at `placement`: its center and zoom, and a
+/// scale bar of a round length about 1/`SCALE_BAR` of the image across,
+/// labelled with it, each lined up on the side of the corner
+pub fn draw_caption(image: &mut ImageBuffer<Rgb>, caption: &Caption, placement: Placement) {
+    let (width, height) = image.bounds();
+    if width == 0 || height == 0 {
+        return;
+    }
+    let scale = placement.scale.unwrap_or(1 + width.min(height) / 500);
+    let length = grid_step(caption.width, SCALE_BAR);
+    let bar = (length / caption.width.abs() * width as f64).round().max(1.0) as i64;
+    let label = bar_label(length);
+    let [center, zoom] = caption.lines();
+
+    // each row of text as its width and height, then the scale bar's with
+    // its label
+    let gap = 2 * scale as i64;
+    let size = |text: &str| {
+        let (w, h) = text_size(text, scale);
+        (w as i64, h as i64)
+    };
+    // a center too long for the image is split before its imaginary part
+    let split = center[1 ..].find(['+', '-']).map(|at| at + 1)
+        .filter(|_| size(&center).0 + 2 * gap > width as i64);
+    let texts = match split {
+        Some(at) => vec![center[.. at].to_string(), center[at ..].to_string(), zoom],
+        None => vec![center, zoom],
+    };
+    let (label_width, text_height) = size(&label);
+    let mut rows: Vec<_> = texts.iter().map(|text| size(text)).collect();
+    rows.push((bar + gap + label_width, text_height));
+    let block = (rows.iter().map(|row| row.0).max().unwrap(),
+                 rows.iter().map(|row| row.1).sum::<i64>() + gap * (rows.len() as i64 - 1));
+    let (right, bottom) = placement.corner.sides();
+    let top = if bottom { height as i64 - gap - block.1 } else { gap };
+    let left = |row_width: i64| if right { width as i64 - gap - row_width } else { gap };
+
+    let mut y = top;
+    for (text, row) in texts.iter().zip(&rows) {
+        draw_text(image, (left(row.0), y), text, scale, LABEL_COLOR);
+        y += row.1 + gap;
+    }
+    // the bar, a line `scale` thick with ticks up at its ends, then its
+    // length
+    let x = left(rows[texts.len()].0);
+    let thick = scale as i64;
+    let mut dots = Vec::new();
+    for dx in 0 .. bar {
+        let tick = dx < thick || dx >= bar - thick;
+        let from = if tick { y } else { y + text_height - thick };
+        dots.extend((from .. y + text_height).map(|dy| (x + dx, dy)));
+    }
+    draw_dots(image, &dots, LABEL_COLOR, 1.0);
+    draw_text(image, (x + bar + gap, y), &label, scale, LABEL_COLOR);
+}
+
+/// Where `draw_stamp` lays text or a watermark over an image, how opaque,
+/// and how big the text is, `None` to scale it as `draw_axes` does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stamp {
+    pub corner: Corner,
+    pub opacity: f64,
+    pub scale: Option<usize>,
+}
+
+/// Half see-through in the bottom right
+impl Default for Stamp {
+    fn default() -> Stamp {
+        Stamp { corner: Corner::BottomRight, opacity: 0.5, scale: None }
+    }
+}
+
+/// Parsed from `CORNER`, `CORNER,OPACITY` or `CORNER,OPACITY,SCALE`, the
+/// opacity from 0 to 1
+impl FromStr for Stamp {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Stamp, MandelError> {
+        let mut parts = s.split(',');
+        let corner = parts.next().unwrap_or("").parse()?;
+        let opacity = match parts.next().map(|opacity| opacity.parse::<f64>()) {
+            None => Stamp::default().opacity,
+            Some(Ok(opacity)) if (0.0 ..= 1.0).contains(&opacity) => opacity,
+            Some(_) => return Err(MandelError::Parse(format!(
+                "bad opacity in {:?}, expected a number from 0 to 1", s))),
+        };
+        let scale = match parts.next().map(|scale| scale.parse::<usize>()) {
+            None => None,
+            Some(Ok(scale)) if scale > 0 => Some(scale),
+            Some(_) => return Err(MandelError::Parse(format!(
+                "bad text size in {:?}, expected a whole number above 0", s))),
+        };
+        if parts.next().is_some() {
+            return Err(MandelError::Parse(format!(
+                "bad stamp {:?}, expected CORNER[,OPACITY[,SCALE]]", s)));
+        }
+        Ok(Stamp { corner, opacity, scale })
+    }
+}
+
+/// Lay `text`, in capitals, and `watermark`, blended by its alpha, over
+/// `image` at `stamp`, the watermark in the corner and the text between
+/// it and the middle, each lined up on the side of the corner, leaving out
+/// what runs off the image
+pub fn draw_stamp(image: &mut ImageBuffer<Rgb>,
+                  text: Option<&str>,
+                  watermark: Option<&ImageBuffer<Rgba>>,
+                  stamp: Stamp)
+{
+    let (width, height) = image.bounds();
+    if width == 0 || height == 0 {
+        return;
+    }
+    let scale = stamp.scale.unwrap_or(1 + width.min(height) / 500);
+    let gap = 2 * scale as i64;
+    let (right, bottom) = stamp.corner.sides();
+    // where a `size` block goes `from` pixels in from the corner's edge
+    let place = |size: (i64, i64), from: i64| {
+        (if right { width as i64 - gap - size.0 } else { gap },
+         if bottom { height as i64 - from - size.1 } else { from })
+    };
+
+    let mut from = gap;
+    if let Some(watermark) = watermark {
+        let (w, h) = watermark.bounds();
+        let at = place((w as i64, h as i64), from);
+        for (y, row) in watermark.rows().enumerate() {
+            for (x, &Rgba([red, green, blue, alpha])) in row.iter().enumerate() {
+                let (x, y) = (at.0 + x as i64, at.1 + y as i64);
+                if x >= 0 && y >= 0 && (x as usize) < width && (y as usize) < height {
+                    let pixel = (x as usize, y as usize);
+                    let alpha = stamp.opacity * alpha as f64 / 255.0;
+                    image[pixel] = blend(image[pixel], Rgb([red, green, blue]), alpha);
+                }
+            }
+        }
+        from += h as i64 + gap;
+    }
+    if let Some(text) = text {
+        let text = text.to_uppercase();
+        let (w, h) = text_size(&text, scale);
+        let at = place((w as i64, h as i64), from);
+        draw_dots(image, &text_dots(at, &text, scale), LABEL_COLOR, stamp.opacity);
+    }
+}
+
+#[test]
+fn test_clip() {
+    assert_eq!(clip((1.0, 1.0), (3.0, 2.0), (4, 4)), Some((0.0, 1.0)));
+    assert_eq!(clip((-2.0, 1.0), (6.0, 1.0), (4, 4)), Some((0.25, 0.75)));
+    assert_eq!(clip((2.0, 2.0), (2.0, 1e30), (4, 4)), Some((0.0, 2.0 / (1e30 - 2.0))));
+    assert_eq!(clip((5.0, 0.0), (5.0, 4.0), (4, 4)), None);
+    assert_eq!(clip((-1.0, 5.0), (3.0, 9.0), (4, 4)), None);
+}
+
+#[test]
+fn test_draw_orbit() {
+    let view = Viewport::new((8, 8), Complex { re: -2.0, im: 2.0 }, Complex { re: 2.0, im: -2.0 });
+    let black = ImageBuffer::new((8, 8));
+    let drawn = |image: &ImageBuffer<Rgb>| {
+        image.pixels().iter().filter(|&&pixel| pixel != Rgb::default()).count()
+    };
+
+    // zero stays put, so its orbit is a dot at the center
+    let mut image = black.clone();
+    draw_orbit(&mut image, &view, Complex { re: 0.0, im: 0.0 }, 50, ORBIT_COLOR);
+    assert_eq!(drawn(&image), 1);
+    assert_eq!(image[(4, 4)], ORBIT_COLOR);
+
+    // -1 goes back and forth between 0 and -1, fainter each time
+    let mut image = black.clone();
+    draw_orbit(&mut image, &view, Complex { re: -1.0, im: 0.0 }, 2, ORBIT_COLOR);
+    assert_eq!(drawn(&image), 3);
+    assert_eq!(image[(4, 4)], ORBIT_COLOR);
+    assert_eq!(image[(2, 4)], blend(Rgb::default(), ORBIT_COLOR, 0.6));
+    assert_eq!(image[(3, 4)], blend(blend(Rgb::default(), ORBIT_COLOR, 0.8), ORBIT_COLOR, 0.4));
+
+    // orbits leaving a deep view are cut off at its edges
+    let deep = Viewport::new((8, 8), Complex { re: 0.3, im: 1e-9 },
+                             Complex { re: 0.3 + 2e-9, im: -1e-9 });
+    let mut image = black.clone();
+    draw_orbit(&mut image, &deep, Complex { re: 0.3, im: 0.0 }, 50, ORBIT_COLOR);
+    assert!(drawn(&image) <= 8);
+    let mut empty = ImageBuffer::new((0, 0));
+    draw_orbit(&mut empty, &view, Complex { re: -1.0, im: 0.0 }, 2, ORBIT_COLOR);
+}
+
+#[test]
+fn test_draw_path() {
+    let view = Viewport::new((8, 8), Complex { re: -2.0, im: 2.0 }, Complex { re: 2.0, im: -2.0 });
+    let mut image = ImageBuffer::new((8, 8));
+    // from far off the left edge along the middle to the center, solid
+    let points = [Complex { re: -100.0, im: 0.1 }, Complex { re: -1.0, im: 0.1 },
+                  Complex { re: 0.1, im: 0.1 }];
+    draw_path(&mut image, &view, &points, RAY_COLOR);
+    let drawn: Vec<_> = (0 .. 8).filter(|&x| image[(x, 3)] == RAY_COLOR).collect();
+    assert_eq!(drawn, [0, 1, 2, 3, 4]);
+    assert_eq!(image.pixels().iter().filter(|&&pixel| pixel != Rgb::default()).count(), 5);
+}
+
+#[test]
+fn test_grid_step() {
+    assert_eq!(grid_step(3.0, 8), 0.5);
+    assert_eq!(grid_step(4.0, 8), 0.5);
+    assert_eq!(grid_step(1.6, 8), 0.2);
+    assert_eq!(grid_step(-80.0, 8), 10.0);
+    assert!((grid_step(1e-3, 5) - 2e-4).abs() < 1e-18);
+
+    assert_eq!((tick_label(0, 0.5), tick_label(-3, 0.5), tick_label(2, 0.5)),
+               ("0".to_string(), "-1.5".to_string(), "1.0".to_string()));
+    assert_eq!((tick_label(7, 2e-4), tick_label(-2, 10.0)),
+               ("0.0014".to_string(), "-20".to_string()));
+    assert_eq!(text_size("-1.5", 2), (30, 10));
+}
+
+#[test]
+fn test_draw_axes() {
+    let view = Viewport::new((80, 80), Complex { re: -2.0, im: 2.0 },
+                             Complex { re: 2.0, im: -2.0 });
+    let mut image = ImageBuffer::new((80, 80));
+    draw_axes(&mut image, &view);
+
+    // the axes through the middle, and faint gridlines every 0.5 between
+    assert_eq!((image[(40, 15)], image[(25, 40)]), (AXIS_COLOR, AXIS_COLOR));
+    let grid = blend(Rgb::default(), GRID_COLOR, GRID_OPACITY);
+    assert_eq!((image[(50, 15)], image[(25, 30)]), (grid, grid));
+    assert_eq!(image[(45, 15)], Rgb::default());
+
+    // labelled along the bottom and down the left, with halos to show up
+    // on black
+    let labels = |rows: std::ops::Range<usize>, cols: std::ops::Range<usize>| {
+        rows.flat_map(|y| cols.clone().map(move |x| (x, y)))
+            .filter(|&pixel| image[pixel] == HALO_COLOR)
+            .count()
+    };
+    assert!(labels(70 .. 80, 0 .. 80) > 0 && labels(0 .. 70, 0 .. 25) > 0);
+    assert_eq!(labels(0 .. 70, 25 .. 80), 0);
+
+    let mut empty = ImageBuffer::new((0, 0));
+    draw_axes(&mut empty, &view);
+}
+
+#[test]
+fn test_caption() {
+    assert_eq!("bottom-right".parse::<Placement>().unwrap(),
+               Placement { corner: Corner::BottomRight, scale: None });
+    assert_eq!("top-left,3".parse::<Placement>().unwrap(),
+               Placement { corner: Corner::TopLeft, scale: Some(3) });
+    for bad in &["", "left", "top-left,0", "top-left,x", "top-left,"] {
+        assert!(bad.parse::<Placement>().is_err(), "{}", bad);
+    }
+
+    assert_eq!((places(0.004), places(0.01), places(2.0)), (3, 2, 0));
+    assert_eq!((bar_label(0.5), bar_label(20.0), bar_label(2e-5)),
+               ("0.5".to_string(), "20".to_string(), "2e-5".to_string()));
+    let caption = |zoom: f64| Caption {
+        center: ("-0.750".to_string(), "-0.125".to_string()), zoom, width: 3.0,
+    };
+    assert_eq!(caption(1.5).lines(), ["-0.750-0.125i".to_string(), "x1.5".to_string()]);
+    let deep = Caption { center: ("0.25".to_string(), "0.00".to_string()), ..caption(2.5e6) };
+    assert_eq!(deep.lines(), ["0.25+0.00i".to_string(), "x2.50e6".to_string()]);
+
+    // in the corner asked for, with nothing drawn elsewhere
+    let drawn = |image: &ImageBuffer<Rgb>, xs: std::ops::Range<usize>, ys: std::ops::Range<usize>| {
+        ys.flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .filter(|&pixel| image[pixel] != Rgb::default())
+            .count()
+    };
+    let mut image = ImageBuffer::new((200, 100));
+    draw_caption(&mut image, &caption(1.5), "bottom-right".parse().unwrap());
+    assert!(drawn(&image, 100 .. 200, 50 .. 100) > 0);
+    assert_eq!(drawn(&image, 0 .. 200, 0 .. 50) + drawn(&image, 0 .. 100, 50 .. 100), 0);
+
+    // the bar is about a fifth of the image wide along the bottom of the
+    // third row, 0.5 of the 3 across being 200 / 6 pixels
+    let gray = Rgb([128, 128, 128]);
+    let mut image = ImageBuffer::from_pixels((200, 100), vec![gray; 200 * 100]);
+    draw_caption(&mut image, &caption(1.5), "top-left".parse().unwrap());
+    let row = 2 + 2 * (5 + 2) + 5 - 1;
+    assert!((2 .. 35).all(|x| image[(x, row)] == LABEL_COLOR));
+    assert_eq!((image[(1, row)], image[(35, row)], image[(18, row - 1)], image[(18, row - 2)]),
+               (HALO_COLOR, HALO_COLOR, HALO_COLOR, gray));
+
+    // a center too long for the image takes two rows, putting the bar a
+    // row lower
+    let mut image = ImageBuffer::from_pixels((50, 50), vec![gray; 50 * 50]);
+    draw_caption(&mut image, &caption(1.5), "top-left".parse().unwrap());
+    let row = 2 + 3 * (5 + 2) + 5 - 1;
+    assert!((2 .. 10).all(|x| image[(x, row)] == LABEL_COLOR));
+
+    let mut empty = ImageBuffer::new((0, 0));
+    draw_caption(&mut empty, &caption(1.5), "top-left".parse().unwrap());
+}
+
+#[test]
+fn test_stamp() {
+    assert_eq!("top-left".parse::<Stamp>().unwrap(),
+               Stamp { corner: Corner::TopLeft, ..Stamp::default() });
+    assert_eq!("bottom-left,0.25,3".parse::<Stamp>().unwrap(),
+               Stamp { corner: Corner::BottomLeft, opacity: 0.25, scale: Some(3) });
+    for bad in &["", "middle", "top-left,2", "top-left,x", "top-left,1,0", "top-left,1,2,3"] {
+        assert!(bad.parse::<Stamp>().is_err(), "{}", bad);
+    }
+    let letters: String = GLYPHS.iter().map(|&(glyph, _)| glyph).collect();
+    assert!(('A' ..= 'Z').all(|c| letters.contains(c)));
+
+    // a half see-through red square in the corner, the text above it
+    let gray = Rgb([100, 100, 100]);
+    let mut image = ImageBuffer::from_pixels((40, 40), vec![gray; 40 * 40]);
+    let mark = ImageBuffer::from_pixels((4, 4), vec![Rgba([255, 0, 0, 255]); 16]);
+    let stamp = Stamp { opacity: 0.5, ..Stamp::default() };
+    draw_stamp(&mut image, Some("hi"), Some(&mark), stamp);
+    let red = blend(gray, Rgb([255, 0, 0]), 0.5);
+    assert_eq!((image[(34, 34)], image[(37, 37)], image[(33, 37)]), (red, red, gray));
+    let changed = |ys: std::ops::Range<usize>| {
+        ys.flat_map(|y| (0 .. 40).map(move |x| (x, y))).filter(|&p| image[p] != gray).count()
+    };
+    assert!(changed(25 .. 32) > 0);
+    assert_eq!(changed(0 .. 24), 0);
+    // overlapping halos are laid on once
+    assert!(image.pixels().iter().all(|&p| [gray, red].contains(&p)
+        || p == blend(gray, HALO_COLOR, 0.5) || p == blend(gray, LABEL_COLOR, 0.5)));
+
+    // a watermark's own alpha lets the image through
+    let mut image = ImageBuffer::from_pixels((8, 8), vec![gray; 64]);
+    let clear = ImageBuffer::from_pixels((2, 2), vec![Rgba([255, 0, 0, 0]); 4]);
+    draw_stamp(&mut image, None, Some(&clear), Stamp { opacity: 1.0, ..stamp });
+    assert!(image.pixels().iter().all(|&p| p == gray));
+}
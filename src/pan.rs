@@ -0,0 +1,210 @@
+use affinity;
+use crossbeam;
+use num::Complex;
+use precision::Precision;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use {copy_rect, parse_complex, render_rect};
+
+/// `(x, y, width, height)` of a rectangle of pixels
+type Rect = (usize, usize, usize, usize);
+
+/// Rows in each piece of an exposed strip handed to a thread
+const BAND_ROWS: usize = 16;
+
+/// What a rendered image shows, as recorded in its PNG
+#[derive(Clone, Debug, PartialEq)]
+pub struct View {
+    pub bounds: (usize, usize),
+    pub limit: u32,
+    pub precision: Precision,
+    pub top_left: Complex<f64>,
+    pub bot_right: Complex<f64>,
+}
+
+/// describe(l, p, tl, br) : the text recorded for a view, with the corners
+/// as the user typed them
+pub fn describe(limit: u32, precision: Precision, top_left: &str, bot_right: &str)
+    -> String
+{
+    format!("{} {} {} {}", limit, precision.name(), top_left, bot_right)
+}
+
+impl View {
+    /// Parse the text written by `describe` for an image of `bounds`
+    ///
+    /// Fixed-point views aren't returned: their pixels can't be lined up
+    /// in f64.
+    pub fn parse(bounds: (usize, usize), text: &str) -> Option<View> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if words.len() != 4 {
+            return None;
+        }
+        Some(View {
+            bounds,
+            limit: words[0].parse().ok()?,
+            precision: words[1].parse().ok()?,
+            top_left: parse_complex(words[2]).ok()?,
+            bot_right: parse_complex(words[3]).ok()?,
+        })
+    }
+
+    fn scale(&self) -> (f64, f64) {
+        ((self.bot_right.re - self.top_left.re) / self.bounds.0 as f64,
+         (self.top_left.im - self.bot_right.im) / self.bounds.1 as f64)
+    }
+}
+
+/// offset(old, new) : where the top left pixel of `new` sits in `old`
+///
+/// Returns `None` unless both views were rendered the same way at the same
+/// scale, on pixel grids that line up.
+fn offset(old: &View, new: &View) -> Option<(i64, i64)> {
+    if old.limit != new.limit || old.precision != new.precision {
+        return None;
+    }
+    let (old_scale, new_scale) = (old.scale(), new.scale());
+    let same = |a: f64, b: f64| (a - b).abs() <= a.abs() * 1e-9;
+    if !same(old_scale.0, new_scale.0) || !same(old_scale.1, new_scale.1) {
+        return None;
+    }
+
+    let dx = (new.top_left.re - old.top_left.re) / old_scale.0;
+    let dy = (old.top_left.im - new.top_left.im) / old_scale.1;
+    let whole = |d: f64| (d - d.round()).abs() < 1e-3;
+    if !whole(dx) || !whole(dy) {
+        return None;
+    }
+    Some((dx.round() as i64, dy.round() as i64))
+}
+
+/// exposed(b, ob, o) : rectangles of an image of bounds `b` that aren't
+/// covered by an old image of bounds `ob` whose top left is at `-o`, and
+/// the rectangle that is
+fn exposed(bounds: (usize, usize), old_bounds: (usize, usize), offset: (i64, i64))
+    -> (Vec<Rect>, Option<Rect>)
+{
+    let clamp = |v: i64, max: usize| v.clamp(0, max as i64) as usize;
+    let (x0, x1) = (clamp(-offset.0, bounds.0),
+                    clamp(old_bounds.0 as i64 - offset.0, bounds.0));
+    let (y0, y1) = (clamp(-offset.1, bounds.1),
+                    clamp(old_bounds.1 as i64 - offset.1, bounds.1));
+    if x0 >= x1 || y0 >= y1 {
+        return (vec![(0, 0, bounds.0, bounds.1)], None);
+    }
+
+    let rects = vec![
+        (0, 0, bounds.0, y0),
+        (0, y1, bounds.0, bounds.1 - y1),
+        (0, y0, x0, y1 - y0),
+        (x1, y0, bounds.0 - x1, y1 - y0),
+    ];
+    let rects = rects.into_iter().filter(|r| r.2 > 0 && r.3 > 0).collect();
+    (rects, Some((x0, y0, x1 - x0, y1 - y0)))
+}
+
+/// Render `view` into `pixels`, taking whatever it shares with the `old`
+/// image from there instead of computing it again
+///
+/// Returns how many pixels were reused.
+pub fn render(pixels: &mut [u8],
+              view: &View,
+              old: &View,
+              old_pixels: &[u8],
+              threads: usize)
+    -> usize
+{
+    let bounds = view.bounds;
+    assert!(pixels.len() == bounds.0 * bounds.1);
+    assert!(old_pixels.len() == old.bounds.0 * old.bounds.1);
+
+    let offset = offset(old, view);
+    let (strips, kept) = match offset {
+        Some(offset) => exposed(bounds, old.bounds, offset),
+        None => (vec![(0, 0, bounds.0, bounds.1)], None),
+    };
+
+    let mut reused = 0;
+    if let (Some(offset), Some((x, y, width, height))) = (offset, kept) {
+        let (old_x, old_y) = ((x as i64 + offset.0) as usize, (y as i64 + offset.1) as usize);
+        for row in 0 .. height {
+            let from = (old_y + row) * old.bounds.0 + old_x;
+            let to = (y + row) * bounds.0 + x;
+            pixels[to .. to + width].copy_from_slice(&old_pixels[from .. from + width]);
+        }
+        reused = width * height;
+    }
+
+    let rects: Vec<_> = strips.iter()
+        .flat_map(|&(x, y, width, height)| {
+            (y .. y + height).step_by(BAND_ROWS)
+                .map(move |top| (x, top, width, BAND_ROWS.min(y + height - top)))
+        })
+        .collect();
+    let next = AtomicUsize::new(0);
+    let done = Mutex::new(Vec::with_capacity(rects.len()));
+    crossbeam::scope(|spawner| {
+        for index in 0..threads {
+            let (rects, next, done) = (&rects, &next, &done);
+            spawner.spawn(move || {
+                affinity::pin(index);
+                while let Some(&rect) = rects.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let mut tile = vec![0; rect.2 * rect.3];
+                    render_rect(&mut tile, bounds, rect, view.top_left, view.bot_right,
+                                None, view.precision, view.limit);
+                    done.lock().unwrap().push((rect, tile));
+                }
+            });
+        }
+    });
+
+    for (rect, tile) in done.into_inner().unwrap() {
+        copy_rect(pixels, bounds, rect, &tile);
+    }
+    reused
+}
+
+#[test]
+fn test_exposed() {
+    // panned right by 3 and up by 2
+    let (strips, kept) = exposed((10, 8), (10, 8), (3, -2));
+    assert_eq!(kept, Some((0, 2, 7, 6)));
+    assert_eq!(strips, [(0, 0, 10, 2), (7, 2, 3, 6)]);
+
+    let (strips, kept) = exposed((10, 8), (10, 8), (10, 0));
+    assert_eq!(kept, None);
+    assert_eq!(strips, [(0, 0, 10, 8)]);
+}
+
+#[test]
+fn test_render_matches_full_render() {
+    // a power-of-two scale keeps every pixel exact, so both renders agree
+    let bounds = (64, 48);
+    let old = View {
+        bounds,
+        limit: 255,
+        precision: Precision::F64,
+        top_left: Complex { re: -1.5, im: 0.25 },
+        bot_right: Complex { re: -1.25, im: 0.0625 },
+    };
+    let mut old_pixels = vec![0; bounds.0 * bounds.1];
+    render_rect(&mut old_pixels, bounds, (0, 0, bounds.0, bounds.1),
+                old.top_left, old.bot_right, None, old.precision, old.limit);
+
+    let shift = Complex { re: 7.0 / 256.0, im: 5.0 / 256.0 };
+    let view = View {
+        top_left: old.top_left + shift,
+        bot_right: old.bot_right + shift,
+        ..old.clone()
+    };
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    assert_eq!(render(&mut pixels, &view, &old, &old_pixels, 3), (64 - 7) * (48 - 5));
+
+    let mut full = vec![0; bounds.0 * bounds.1];
+    render_rect(&mut full, bounds, (0, 0, bounds.0, bounds.1),
+                view.top_left, view.bot_right, None, view.precision, view.limit);
+    assert!(pixels == full);
+
+    let view = View { limit: 100, ..view };
+    assert_eq!(render(&mut pixels, &view, &old, &old_pixels, 3), 0);
+}
@@ -0,0 +1,397 @@
+use cancel::CancelToken;
+use core;
+use fixed::{self, Fixed};
+use {INTERIOR_EPSILON, NO_ESCAPE};
+use num::Complex;
+use std::collections::BTreeMap;
+
+/// Side of the squares, lined up with the top left of the image, whose
+/// pixels share the reference orbit of the middle of the square, so which
+/// reference a pixel is iterated against doesn't depend on which other
+/// pixels are being found with it.
+const REFERENCE_BLOCK: usize = 256;
+
+/// Smallest pixel, across, of a view perturbation can render: offsets from
+/// the reference are `f64`, which can't hold much less than 1e-308, and
+/// would come out flat
+pub const MIN_PIXEL: f64 = 1e-300;
+
+/// Keep skipping iterations while the cubic term of the series is at least
+/// this many times smaller than the quadratic one.
+const SERIES_TOLERANCE: f64 = 1e3;
+
+/// reference_orbit(c, l, b) : high-precision orbit of `c`, rounded to `f64`
+///
+/// Returns `Z_0 .. Z_n`, stopping after `l` iterations or once `Z_n` is
+/// past `bailout`.
+pub fn reference_orbit(c: &Complex<Fixed>, limit: u32, bailout: f64) -> Vec<Complex<f64>> {
+    let bits = c.re.bits();
+    let mut z = Complex { re: Fixed::zero(bits), im: Fixed::zero(bits) };
+    let mut orbit = vec![Complex { re: 0.0, im: 0.0 }];
+    for _ in 0..limit {
+        z = core::step(&z, c);
+        let zf = fixed::to_f64(&z);
+        orbit.push(zf);
+        if zf.norm_sqr() > bailout * bailout {
+            break;
+        }
+    }
+    orbit
+}
+
+/// Series approximation `dz_n = A_n dc + B_n dc^2 + C_n dc^3`
+struct Series {
+    skip: u32,
+    coeffs: [Complex<f64>; 3],
+}
+
+impl Series {
+    /// Advance the coefficients along `orbit` for as long as the truncated
+    /// series stays accurate for every `|dc| <= max_dc`.
+    fn new(orbit: &[Complex<f64>], max_dc: f64) -> Series {
+        let one = Complex { re: 1.0, im: 0.0 };
+        let zero = Complex { re: 0.0, im: 0.0 };
+        let mut coeffs = [zero; 3];
+        let mut n = 0;
+
+        // stop one short of the end so the last step is always iterated
+        while n + 2 < orbit.len() {
+            let z2 = orbit[n] * 2.0;
+            let [a, b, c] = coeffs;
+            let next = [z2 * a + one,
+                        z2 * b + a * a,
+                        z2 * c + a * b * 2.0];
+            // coefficients grow like powers of the derivative, so deep down
+            // the cubic one can run past f64 long before the others
+            if !next[2].norm().is_finite()
+                || next[2].norm() * max_dc * SERIES_TOLERANCE > next[1].norm()
+            {
+                break;
+            }
+            coeffs = next;
+            n += 1;
+        }
+        Series { skip: n as u32, coeffs }
+    }
+
+    fn eval(&self, dc: Complex<f64>) -> Complex<f64> {
+        let [a, b, c] = self.coeffs;
+        ((c * dc + b) * dc + a) * dc
+    }
+}
+
+/// Iterate `dz_{n+1} = 2 Z_n dz_n + dz_n^2 + dc`, the offset of a pixel
+/// at `C + dc` from the reference orbit `Z` of `C`, starting from where
+/// `series` leaves off.
+///
+/// Where the pixel's orbit comes nearer 0 than the reference's, the offset
+/// would be all that's left of it and lose its precision, so it's rebased:
+/// the orbit carries on as an offset from the start of the reference
+/// instead, `Z_0 = 0`. It's rebased too if it outlasts the reference, so
+/// one reference does for every pixel, whatever their escape times.
+///
+/// Like `core::iterate_batch`, interior points are recognised by the
+/// derivative of their orbit shrinking towards zero.
+fn escape_time(orbit: &[Complex<f64>],
+               series: &Series,
+               dc: Complex<f64>,
+               limit: u32,
+               bailout: f64)
+    -> Option<u32>
+{
+    let mut dz = series.eval(dc);
+    let mut der = Complex { re: 1.0, im: 0.0 };
+    let mut n = series.skip as usize;
+    for i in series.skip..limit {
+        if i > 0 {
+            der = der * (orbit[n] + dz) * 2.0;
+        }
+        dz = orbit[n] * dz * 2.0 + dz * dz + dc;
+        n += 1;
+        let z = orbit[n] + dz;
+        if z.norm_sqr() > bailout * bailout {
+            return Some(i);
+        }
+        if der.norm_sqr() < INTERIOR_EPSILON * INTERIOR_EPSILON {
+            return None;
+        }
+        if z.norm_sqr() < dz.norm_sqr() || n + 1 >= orbit.len() {
+            dz = z;
+            n = 0;
+        }
+    }
+    None
+}
+
+/// Find the escape times of the `bounds` image, as `iterate_batch`
+/// records them with `bailout`, iterating only the reference orbits in
+/// full precision; every other pixel is a cheap `f64` delta against one.
+pub fn escape_times(times: &mut [u32],
+                    bounds: (usize, usize),
+                    top_left: &Complex<Fixed>,
+                    bot_right: &Complex<Fixed>,
+                    limit: u32,
+                    bailout: f64)
+{
+    assert!(times.len() == bounds.0 * bounds.1);
+
+    let points: Vec<(usize, usize)> = (0 .. bounds.1)
+        .flat_map(|row| (0 .. bounds.0).map(move |col| (col, row)))
+        .collect();
+    times.copy_from_slice(&escape_points(&points, bounds, top_left, bot_right, limit, bailout,
+                                         None));
+}
+
+/// The reference orbit of one `REFERENCE_BLOCK` square of an image, that
+/// of the pixel in its middle, for the points anywhere in the square
+pub struct Reference {
+    /// the reference point, in pixels across and down the image
+    center: (f64, f64),
+    /// the width and height of a pixel
+    step: Complex<f64>,
+    orbit: Vec<Complex<f64>>,
+    series: Series,
+    limit: u32,
+    bailout: f64,
+}
+
+impl Reference {
+    /// new(b, tl, br, s, l, r) : the reference of the square holding the
+    /// pixel `spot` of the `bounds` image of `top_left` to `bot_right`,
+    /// for orbits of up to `limit` iterations escaping past `bailout`
+    pub fn new(bounds: (usize, usize),
+               top_left: &Complex<Fixed>,
+               bot_right: &Complex<Fixed>,
+               spot: (usize, usize),
+               limit: u32,
+               bailout: f64)
+        -> Reference
+    {
+        let size = REFERENCE_BLOCK;
+        let (left, top) = (spot.0 / size * size, spot.1 / size * size);
+        let extent = (size.min(bounds.0 - left), size.min(bounds.1 - top));
+        let pixel = (left + extent.0 / 2, top + extent.1 / 2);
+        let c = ::pixel_center(bounds, pixel, top_left.clone(), bot_right.clone());
+        let orbit = reference_orbit(&c, limit, bailout);
+        let step = Complex {
+            re: (&bot_right.re - &top_left.re).to_f64() / bounds.0 as f64,
+            im: (&top_left.im - &bot_right.im).to_f64() / bounds.1 as f64,
+        };
+        let center = (pixel.0 as f64 + 0.5, pixel.1 as f64 + 0.5);
+        // the series has to hold for the whole square, not just the points
+        // wanted from it, the farthest of which is its top left corner
+        let reach = Complex {
+            re: (left as f64 - center.0) * step.re,
+            im: (center.1 - top as f64) * step.im,
+        };
+        let series = Series::new(&orbit, reach.norm());
+        Reference { center, step, orbit, series, limit, bailout }
+    }
+
+    /// offset(s) : how far the point at `spot` is from the reference point
+    fn offset(&self, spot: (f64, f64)) -> Complex<f64> {
+        Complex {
+            re: (spot.0 - self.center.0) * self.step.re,
+            im: (self.center.1 - spot.1) * self.step.im,
+        }
+    }
+
+    /// escape_time(s) : the escape time of the point at `spot`, in pixels
+    /// across and down the image and fractions of them, within the square
+    pub fn escape_time(&self, spot: (f64, f64)) -> u32 {
+        escape_time(&self.orbit, &self.series, self.offset(spot), self.limit, self.bailout)
+            .unwrap_or(NO_ESCAPE)
+    }
+}
+
+/// The references of the squares of an image, each worked out the first
+/// time a point in its square is wanted and kept for the rest, for finding
+/// a few points at a time, such as the samples of a pixel
+pub struct References<'a> {
+    bounds: (usize, usize),
+    top_left: &'a Complex<Fixed>,
+    bot_right: &'a Complex<Fixed>,
+    limit: u32,
+    bailout: f64,
+    squares: BTreeMap<(usize, usize), Reference>,
+}
+
+impl<'a> References<'a> {
+    /// References for the `bounds` image of `top_left` to `bot_right`, as
+    /// `Reference::new` makes them
+    pub fn new(bounds: (usize, usize),
+               top_left: &'a Complex<Fixed>,
+               bot_right: &'a Complex<Fixed>,
+               limit: u32,
+               bailout: f64)
+        -> References<'a>
+    {
+        References { bounds, top_left, bot_right, limit, bailout, squares: BTreeMap::new() }
+    }
+
+    /// escape_times(t, s, c) : find the escape times of the points at
+    /// `spots`, in pixels across and down the image and fractions of them,
+    /// stopping at the next point once `cancel` is cancelled
+    pub fn escape_times(&mut self, times: &mut [u32], spots: &[(f64, f64)],
+                        cancel: Option<&CancelToken>)
+    {
+        assert!(times.len() == spots.len());
+        for (time, &spot) in times.iter_mut().zip(spots) {
+            if cancel.is_some_and(CancelToken::is_cancelled) {
+                return;
+            }
+            // spots on the far edges belong to the last pixel
+            let pixel = ((spot.0 as usize).min(self.bounds.0 - 1),
+                         (spot.1 as usize).min(self.bounds.1 - 1));
+            let size = REFERENCE_BLOCK;
+            let square = (pixel.0 / size * size, pixel.1 / size * size);
+            let (bounds, top_left, bot_right) = (self.bounds, self.top_left, self.bot_right);
+            let (limit, bailout) = (self.limit, self.bailout);
+            let reference = self.squares.entry(square).or_insert_with(|| {
+                Reference::new(bounds, top_left, bot_right, pixel, limit, bailout)
+            });
+            *time = reference.escape_time(spot);
+        }
+    }
+}
+
+/// The escape times of each of `points`, given as `(col, row)` pixels of
+/// the `bounds` image, in the same order, stopping at the next point once
+/// `cancel` is cancelled
+pub fn escape_points(points: &[(usize, usize)],
+                     bounds: (usize, usize),
+                     top_left: &Complex<Fixed>,
+                     bot_right: &Complex<Fixed>,
+                     limit: u32,
+                     bailout: f64,
+                     cancel: Option<&CancelToken>)
+    -> Vec<u32>
+{
+    let mut times = vec![NO_ESCAPE; points.len()];
+
+    // a square at a time, so only one reference orbit is kept at once
+    let mut squares: BTreeMap<(usize, usize), Vec<usize>> = BTreeMap::new();
+    for (i, &(col, row)) in points.iter().enumerate() {
+        let size = REFERENCE_BLOCK;
+        squares.entry((col / size * size, row / size * size)).or_default().push(i);
+    }
+
+    for (square, members) in squares {
+        let reference = Reference::new(bounds, top_left, bot_right, square, limit, bailout);
+        for i in members {
+            if cancel.is_some_and(CancelToken::is_cancelled) {
+                return times;
+            }
+            let (col, row) = points[i];
+            times[i] = reference.escape_time((col as f64 + 0.5, row as f64 + 0.5));
+        }
+    }
+    times
+}
+
+#[test]
+fn test_reference_orbit() {
+    let c = Complex { re: Fixed::from_f64(-1.0, 64), im: Fixed::zero(64) };
+    let orbit = reference_orbit(&c, 4, ::BAILOUT);
+    assert_eq!(orbit.len(), 5);
+    assert_eq!(orbit[1], Complex { re: -1.0, im: 0.0 });
+    assert_eq!(orbit[2], Complex { re: 0.0, im: 0.0 });
+
+    let c = Complex { re: Fixed::from_f64(1.0, 64), im: Fixed::zero(64) };
+    assert_eq!(reference_orbit(&c, 255, ::BAILOUT).len(), 4);
+}
+
+#[test]
+fn test_series_matches_iteration() {
+    let orbit: Vec<_> = [(0.0, 0.0), (-0.75, 0.1), (-0.1975, -0.05),
+                         (-0.7135, 0.1198), (-0.2553, -0.0710)]
+        .iter()
+        .map(|&(re, im)| Complex { re, im })
+        .collect();
+    let dc = Complex { re: 1e-9, im: -2e-9 };
+    let series = Series::new(&orbit, dc.norm());
+    assert_eq!(series.skip, 3);
+
+    let mut dz = Complex { re: 0.0, im: 0.0 };
+    for z in &orbit[..3] {
+        dz = z * dz * 2.0 + dz * dz + dc;
+    }
+    assert!((series.eval(dc) - dz).norm() < 1e-12 * dz.norm());
+}
+
+#[test]
+fn test_render_matches_fixed() {
+    let bits = 128;
+    let top_left = fixed::parse_complex(
+        "-0.743643887037158704752,0.131825904205311970493", bits).unwrap();
+    let bot_right = fixed::parse_complex(
+        "-0.743643887037158604752,0.131825904205311870493", bits).unwrap();
+    let bounds = (12, 8);
+
+    let mut times = vec![0; bounds.0 * bounds.1];
+    escape_times(&mut times, bounds, &top_left, &bot_right, 255, ::BAILOUT);
+
+    for row in 0 .. bounds.1 {
+        for col in 0 .. bounds.0 {
+            let pt = ::pixel_center(bounds, (col, row), top_left.clone(), bot_right.clone());
+            assert_eq!(::escaped(times[row * bounds.0 + col]), ::escape_time(pt, 255));
+        }
+    }
+}
+
+#[test]
+fn test_perturb_bailout() {
+    // a bailout of its own gives the same times as iterating directly
+    let top_left = fixed::parse_complex("-0.75,0.1", 128).unwrap();
+    let bot_right = fixed::parse_complex("-0.7499,0.0999", 128).unwrap();
+    let bounds = (12, 8);
+    let points: Vec<_> = (0 .. bounds.1)
+        .flat_map(|row| (0 .. bounds.0).map(move |col| (col, row)))
+        .collect();
+    let near = escape_points(&points, bounds, &top_left, &bot_right, 500, ::BAILOUT, None);
+    let far = escape_points(&points, bounds, &top_left, &bot_right, 500, 1e6, None);
+    let centers: Vec<_> = points.iter()
+        .map(|&pixel| ::pixel_center(bounds, pixel, top_left.clone(), bot_right.clone()))
+        .collect();
+    let mut direct = vec![0; points.len()];
+    ::iterate_batch(&centers, None, 500, 1e6, &|| false, &mut direct);
+    assert_eq!(far, direct);
+    assert!(far.iter().zip(&near).any(|(far, near)| far > near && *far != NO_ESCAPE));
+}
+
+#[test]
+fn test_references() {
+    let bits = 128;
+    let top_left = fixed::parse_complex(
+        "-0.743643887037158704752,0.131825904205311970493", bits).unwrap();
+    let bot_right = fixed::parse_complex(
+        "-0.743643887037158604752,0.131825904205311870493", bits).unwrap();
+    let bounds = (6, 4);
+
+    // pixel centers come out as `escape_points` finds them
+    let points: Vec<_> = (0 .. bounds.1)
+        .flat_map(|row| (0 .. bounds.0).map(move |col| (col, row)))
+        .collect();
+    let spots: Vec<_> = points.iter()
+        .map(|&(col, row)| (col as f64 + 0.5, row as f64 + 0.5))
+        .collect();
+    let mut references = References::new(bounds, &top_left, &bot_right, 255, ::BAILOUT);
+    let mut times = vec![0; spots.len()];
+    references.escape_times(&mut times, &spots, None);
+    assert_eq!(times, escape_points(&points, bounds, &top_left, &bot_right, 255, ::BAILOUT,
+                                    None));
+
+    // and quarters of pixels as the pixels of an image twice the size
+    let fine = (bounds.0 * 2, bounds.1 * 2);
+    let spots: Vec<_> = (0 .. fine.1)
+        .flat_map(|row| (0 .. fine.0).map(move |col| (col, row)))
+        .map(|(col, row)| ((col as f64 + 0.5) / 2.0, (row as f64 + 0.5) / 2.0))
+        .collect();
+    let mut times = vec![0; spots.len()];
+    references.escape_times(&mut times, &spots, None);
+    for (i, &time) in times.iter().enumerate() {
+        let pt = ::pixel_center(fine, (i % fine.0, i / fine.0), top_left.clone(),
+                                bot_right.clone());
+        assert_eq!(::escaped(time), ::escape_time(pt, 255));
+    }
+}
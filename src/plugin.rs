@@ -0,0 +1,191 @@
+use color::{Colorizer, EscapeBuffer};
+use error::MandelError;
+use image::{ImageBuffer, Rgb};
+use libloading::Library;
+use num::Complex;
+use std::env::consts::DLL_EXTENSION;
+use std::ffi::CStr;
+use std::fs;
+use std::marker::PhantomData;
+use std::os::raw::c_char;
+use std::path::Path;
+use {in_bands, pixel_center};
+
+/// Version of the plugin interface in `include/mandelbrot_plugin.h`,
+/// which a plugin's `mandel_plugin_abi` must return
+pub const PLUGIN_ABI: u32 = 1;
+
+/// Where the command line looks for plugins
+pub const PLUGIN_DIR: &str = "plugins";
+
+type AbiFn = unsafe extern "C" fn() -> u32;
+type NameFn = unsafe extern "C" fn() -> *const c_char;
+type EscapeFn = unsafe extern "C" fn(f64, f64, u32) -> u32;
+type ColorFn = unsafe extern "C" fn(u32, u32) -> u32;
+
+/// A shared library of fractal formulas and colorizers, loaded at runtime
+///
+/// Plugins are native code run in-process, so only load ones you trust.
+pub struct Plugin {
+    name: String,
+    escape: Option<EscapeFn>,
+    color: Option<ColorFn>,
+    // kept loaded for as long as the functions above can be called
+    _library: Library,
+}
+
+/// A plugin's formula, from `Plugin::formula`
+#[derive(Clone, Copy)]
+pub struct Formula<'a> {
+    escape: EscapeFn,
+    plugin: PhantomData<&'a Plugin>,
+}
+
+/// A plugin's colorizer, from `Plugin::colorizer`
+#[derive(Clone, Copy)]
+pub struct Palette<'a> {
+    color: ColorFn,
+    plugin: PhantomData<&'a Plugin>,
+}
+
+impl Plugin {
+    /// Load the plugin at `path`, checking that it was built for this
+    /// `PLUGIN_ABI`
+    pub fn load(path: &Path) -> Result<Plugin, MandelError> {
+        let error = |e: &dyn ToString| {
+            MandelError::Plugin(format!("{}: {}", path.display(), e.to_string()))
+        };
+        // only the plugin's own initialisers run here, which are its
+        // author's to keep sound
+        let library = unsafe { Library::new(path) }.map_err(|e| error(&e))?;
+        unsafe {
+            let abi = library.get::<AbiFn>(b"mandel_plugin_abi\0").map_err(|e| error(&e))?;
+            if abi() != PLUGIN_ABI {
+                return Err(error(&format!("built for plugin ABI {}, not {}",
+                                          abi(), PLUGIN_ABI)));
+            }
+            let name = library.get::<NameFn>(b"mandel_plugin_name\0").map_err(|e| error(&e))?;
+            let name = CStr::from_ptr(name()).to_string_lossy().into_owned();
+            let escape = library.get::<EscapeFn>(b"mandel_plugin_escape\0").ok().map(|f| *f);
+            let color = library.get::<ColorFn>(b"mandel_plugin_color\0").ok().map(|f| *f);
+            Ok(Plugin { name, escape, color, _library: library })
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The plugin's formula, if it has one
+    pub fn formula(&self) -> Option<Formula<'_>> {
+        self.escape.map(|escape| Formula { escape, plugin: PhantomData })
+    }
+
+    /// The plugin's colorizer, if it has one
+    pub fn colorizer(&self) -> Option<Palette<'_>> {
+        self.color.map(|color| Palette { color, plugin: PhantomData })
+    }
+}
+
+impl<'a> Formula<'a> {
+    /// escape_time(c, l) : as `::escape_time`, but by the plugin's formula
+    pub fn escape_time(&self, c: Complex<f64>, limit: u32) -> Option<u32> {
+        ::escaped(unsafe { (self.escape)(c.re, c.im, limit) })
+    }
+
+    /// The escape time of every pixel of the `bounds` image of
+    /// `top_left` to `bot_right`, found on `threads` threads
+    pub fn escapes(&self,
+                   bounds: (usize, usize),
+                   top_left: Complex<f64>,
+                   bot_right: Complex<f64>,
+                   limit: u32,
+                   threads: usize)
+        -> EscapeBuffer
+    {
+        let mut times = vec![0; bounds.0 * bounds.1];
+        in_bands(&mut times, bounds.0, threads, |band, rows| {
+            for (row, times) in rows.zip(band.chunks_mut(bounds.0)) {
+                for (col, time) in times.iter_mut().enumerate() {
+                    let c = pixel_center(bounds, (col, row), top_left, bot_right);
+                    *time = unsafe { (self.escape)(c.re, c.im, limit) };
+                }
+            }
+        });
+        EscapeBuffer { limit, times: ImageBuffer::from_pixels(bounds, times) }
+    }
+}
+
+impl<'a> Colorizer<Rgb> for Palette<'a> {
+    fn color(&self, time: Option<u32>, limit: u32) -> Rgb {
+        let rgb = unsafe { (self.color)(time.unwrap_or(::NO_ESCAPE), limit) };
+        Rgb([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+    }
+}
+
+/// discover(d) : every plugin in the directory `dir`, or why each
+/// couldn't be loaded, none if there's no such directory
+///
+/// Plugins are the files named for shared libraries on this platform,
+/// `.so`, `.dylib` or `.dll`.
+pub fn discover(dir: &Path) -> Vec<Result<Plugin, MandelError>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) if !dir.exists() => return Vec::new(),
+        Err(e) => return vec![Err(e.into())],
+    };
+    let mut paths: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == DLL_EXTENSION))
+        .collect();
+    paths.sort();
+    paths.iter().map(|path| Plugin::load(path)).collect()
+}
+
+#[test]
+fn test_discover() {
+    let dir = ::std::env::temp_dir().join(format!("mandel-plugins-{}", ::std::process::id()));
+    assert!(discover(&dir).is_empty());
+
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("notes.txt"), "not a plugin").unwrap();
+    fs::write(dir.join("broken").with_extension(DLL_EXTENSION), "not a library").unwrap();
+    let found = discover(&dir);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(found.len(), 1);
+    assert!(matches!(found[0], Err(MandelError::Plugin(_))));
+}
+
+#[test]
+fn test_plugin() {
+    use std::process::Command;
+
+    // build examples/plugin.rs on its own, as a third party would
+    let dir = ::std::env::temp_dir().join(format!("mandel-plugin-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let status = Command::new(::std::env::var("RUSTC").unwrap_or("rustc".to_string()))
+        .args(["--crate-type", "cdylib", "--crate-name", "ship", "-O", "--out-dir"])
+        .arg(&dir)
+        .arg(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/plugin.rs"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let mut found = discover(&dir);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let plugin = found.pop().unwrap().unwrap();
+    assert_eq!(plugin.name(), "burning-ship");
+    let formula = plugin.formula().unwrap();
+    assert_eq!(formula.escape_time(Complex { re: -0.2, im: 0.1 }, 100), None);
+    assert_eq!(formula.escape_time(Complex { re: 2.0, im: 2.0 }, 100), Some(0));
+    let (top_left, bot_right) = (Complex { re: -2.2, im: 1.2 }, Complex { re: 1.2, im: -1.4 });
+    let escapes = formula.escapes((30, 20), top_left, bot_right, 100, 3);
+    for &pixel in &[(0, 0), (15, 10), (29, 19)] {
+        let c = pixel_center((30, 20), pixel, top_left, bot_right);
+        assert_eq!(escapes.time(pixel), formula.escape_time(c, 100));
+    }
+
+    let palette = plugin.colorizer().unwrap();
+    assert_eq!(palette.color(None, 100), Rgb([0, 0, 0]));
+    assert_eq!(palette.color(Some(100), 100), Rgb([255, 255, 255]));
+}
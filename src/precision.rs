@@ -0,0 +1,288 @@
+use error::MandelError;
+use fixed;
+use num::{Complex, Float};
+use perturb::MIN_PIXEL;
+#[cfg(feature = "native")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Most fractional bits fixed point can be asked for by name, enough for
+/// pixels of `MIN_PIXEL`, the deepest perturbation renders, with bits to
+/// spare
+pub const MAX_BITS: usize = 1088;
+
+/// Number type the escape-time kernel runs in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Precision {
+    F32,
+    F64,
+    /// arbitrary-precision fixed point with this many fractional bits
+    Fixed(usize),
+}
+
+impl FromStr for Precision {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Precision, MandelError> {
+        match s {
+            "f32" => Ok(Precision::F32),
+            "f64" => Ok(Precision::F64),
+            _ => Err(MandelError::Parse(format!("unknown precision '{}', expected f32 or f64",
+                                                s))),
+        }
+    }
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Precision::F32 => write!(f, "f32"),
+            Precision::F64 => write!(f, "f64"),
+            Precision::Fixed(bits) => write!(f, "{}-bit fixed point", bits),
+        }
+    }
+}
+
+impl Precision {
+    /// name() : the precision as written in view descriptions and JSON,
+    /// `fixed:bits` for fixed point
+    pub fn name(&self) -> String {
+        match *self {
+            Precision::Fixed(bits) => format!("fixed:{}", bits),
+            p => p.to_string(),
+        }
+    }
+
+    /// from_name(s) : the precision `name` gives as `s`, with at most
+    /// `MAX_BITS` bits of fixed point
+    pub fn from_name(s: &str) -> Result<Precision, MandelError> {
+        match s.strip_prefix("fixed:") {
+            Some(bits) => match bits.parse() {
+                Ok(bits) if bits > 0 && bits <= MAX_BITS => Ok(Precision::Fixed(bits)),
+                _ => Err(MandelError::Parse(format!("'{}' isn't a number of bits from 1 to {}",
+                                                    bits, MAX_BITS))),
+            },
+            None => s.parse(),
+        }
+    }
+}
+
+/// As its `name`
+#[cfg(feature = "native")]
+impl Serialize for Precision {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name())
+    }
+}
+
+#[cfg(feature = "native")]
+impl<'de> Deserialize<'de> for Precision {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Precision, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Precision::from_name(&name).map_err(de::Error::custom)
+    }
+}
+
+/// select(p, b, tl, br) : cheapest precision at least as good as `p`
+/// that can still tell adjacent pixels of the view apart
+///
+/// The corners are taken as decimal strings, since deep views may have
+/// corners that are equal once rounded to `f64`, and are an error if they
+/// can't be read in fixed point, as is a view with pixels smaller than
+/// `MIN_PIXEL`, past what perturbation can render.
+pub fn select(requested: Precision,
+              bounds: (usize, usize),
+              top_left: &str,
+              bot_right: &str)
+    -> Result<Precision, MandelError>
+{
+    // enough bits to hold every digit given
+    let exact = 4 * top_left.len().max(bot_right.len()) + 64;
+    let top_left = fixed::parse_complex(top_left, exact)?;
+    let bot_right = fixed::parse_complex(bot_right, exact)?;
+
+    let pixel = ((&bot_right.re - &top_left.re).to_f64() / bounds.0 as f64)
+        .min((&top_left.im - &bot_right.im).to_f64() / bounds.1 as f64)
+        .abs();
+    if pixel < MIN_PIXEL {
+        return Err(MandelError::InvalidParams(format!(
+            "the view is too deep, its pixels are {:e} across but can't be under {:e}",
+            pixel, MIN_PIXEL)));
+    }
+    let magnitude = [&top_left.re, &top_left.im, &bot_right.re, &bot_right.im]
+        .iter()
+        .fold(1.0, |m: f64, x| m.max(x.to_f64().abs()));
+
+    Ok(cheapest(requested, pixel, magnitude, exact))
+}
+
+/// escalate(p, b, r, tl, br) : precision to render the tile `r` of the
+/// view in, given that `p` was chosen for the view as a whole
+///
+/// Tiles far from the origin need more bits than those near it, so a tile
+/// whose adjacent pixels `p` can't tell apart is moved up to a precision
+/// that can rather than coming out blocky.
+pub fn escalate(precision: Precision,
+                bounds: (usize, usize),
+                rect: (usize, usize, usize, usize),
+                top_left: Complex<f64>,
+                bot_right: Complex<f64>)
+    -> Precision
+{
+    let pixel = ((bot_right.re - top_left.re) / bounds.0 as f64)
+        .min((top_left.im - bot_right.im) / bounds.1 as f64)
+        .abs();
+    if pixel == 0.0 {
+        // the corners themselves are indistinguishable, nothing to go on
+        return precision;
+    }
+
+    let (x, y, width, height) = rect;
+    let corner = |col: usize, row: usize| Complex {
+        re: top_left.re + (bot_right.re - top_left.re) * col as f64 / bounds.0 as f64,
+        im: top_left.im - (top_left.im - bot_right.im) * row as f64 / bounds.1 as f64,
+    };
+    let magnitude = [corner(x, y), corner(x + width, y + height)]
+        .iter()
+        .fold(1.0, |m: f64, c| m.max(c.re.abs()).max(c.im.abs()));
+
+    cheapest(precision, pixel, magnitude, f64::MANTISSA_DIGITS as usize + 1100)
+}
+
+/// too_deep_for_f64(tl, br) : whether the corners of a view, held in
+/// `f64`, are too close together for the view to be placed where it's
+/// meant to be
+///
+/// The explorers keep their views in `f64`, so zoomed in this far their
+/// corners are rounded by a good part of the view and, further in, to the
+/// same point, leaving nothing to render whatever the precision.
+pub fn too_deep_for_f64(top_left: Complex<f64>, bot_right: Complex<f64>) -> bool {
+    let side = (bot_right.re - top_left.re).abs().min((top_left.im - bot_right.im).abs());
+    let magnitude = [top_left, bot_right]
+        .iter()
+        .fold(1.0, |m: f64, c| m.max(c.re.abs()).max(c.im.abs()));
+    !fits::<f64>(side, magnitude)
+}
+
+/// cheapest(p, px, m, e) : the least precision at least as good as `p`
+/// that resolves pixels of size `px` at coordinates up to `m`, using no
+/// more than `e` bits
+fn cheapest(requested: Precision, pixel: f64, magnitude: f64, exact: usize) -> Precision {
+    if requested == Precision::F32 && fits::<f32>(pixel, magnitude) {
+        return Precision::F32;
+    }
+    let bits = ((-pixel.log2().floor()).min(exact as f64) as usize + 32).max(64);
+    match requested {
+        // too few bits is as blocky as too small a float
+        Precision::Fixed(given) => return Precision::Fixed(given.max(bits)),
+        _ if fits::<f64>(pixel, magnitude) => return Precision::F64,
+        _ => {}
+    }
+    Precision::Fixed(bits)
+}
+
+fn fits<T: Float>(pixel: f64, magnitude: f64) -> bool {
+    // leave ~10 bits of headroom for error accumulated while iterating
+    pixel > magnitude * T::epsilon().to_f64().unwrap() * 1024.0
+}
+
+#[test]
+fn test_parse_precision() {
+    assert_eq!("f32".parse::<Precision>().unwrap(), Precision::F32);
+    assert_eq!("f64".parse::<Precision>().unwrap(), Precision::F64);
+    assert!("f16".parse::<Precision>().is_err());
+}
+
+#[test]
+fn test_name() {
+    for &precision in &[Precision::F32, Precision::F64, Precision::Fixed(128)] {
+        assert_eq!(Precision::from_name(&precision.name()).unwrap(), precision);
+    }
+    assert_eq!(Precision::Fixed(96).name(), "fixed:96");
+    assert!(Precision::from_name("fixed:0").is_err());
+    assert_eq!(Precision::from_name(&format!("fixed:{}", MAX_BITS)).unwrap(),
+               Precision::Fixed(MAX_BITS));
+    for huge in [format!("fixed:{}", MAX_BITS + 1), "fixed:18446744073709551615".to_string(),
+                 "fixed:99999999999999999999999".to_string()] {
+        assert!(Precision::from_name(&huge).is_err(), "{}", huge);
+    }
+    assert_eq!(Precision::from_name("f16").unwrap_err().to_string(),
+               "unknown precision 'f16', expected f32 or f64");
+    assert!(Precision::from_name("f16").is_err());
+}
+
+#[test]
+fn test_select() {
+    let select = |p, tl, br| select(p, (1000, 1000), tl, br).unwrap();
+    assert_eq!(select(Precision::F32, "-1,1", "1,-1"), Precision::F32);
+    assert_eq!(select(Precision::F64, "-1,1", "1,-1"), Precision::F64);
+    assert_eq!(select(Precision::F32, "-0.75,0.1", "-0.749999,0.099999"),
+               Precision::F64);
+
+    // corners that collapse to the same f64
+    let tl = "-0.7436438870371587047,0.1318259042124218";
+    let br = "-0.7436438870371587040,0.1318259042124211";
+    match select(Precision::F32, tl, br) {
+        Precision::Fixed(bits) => assert!(bits > 96),
+        p => panic!("expected fixed point, got {}", p),
+    }
+
+    // pixels too small for perturbation's f64 offsets, or of no size at all
+    let view = |zeros| {
+        let side = format!("0.{}1", "0".repeat(zeros));
+        ::precision::select(Precision::F64, (1000, 1000), "0,0", &format!("{},-{}", side, side))
+    };
+    match view(295).unwrap() {
+        Precision::Fixed(bits) => assert!(bits > 960 && bits <= MAX_BITS),
+        p => panic!("expected fixed point, got {}", p),
+    }
+    assert!(matches!(view(297), Err(MandelError::InvalidParams(_))));
+    assert!(::precision::select(Precision::F64, (10, 10), tl, tl).is_err());
+
+    // fixed point keeps the bits asked for, or more if they'd be blocky
+    assert_eq!(select(Precision::Fixed(128), "-1,1", "1,-1"), Precision::Fixed(128));
+    match select(Precision::Fixed(64), tl, br) {
+        Precision::Fixed(bits) => assert!(bits > 96),
+        p => panic!("expected fixed point, got {}", p),
+    }
+
+    assert!(::precision::select(Precision::F64, (10, 10), "-1,1", "1,x").is_err());
+}
+
+#[test]
+fn test_too_deep_for_f64() {
+    let corners = |re: f64, width: f64| {
+        (Complex { re, im: 0.1 }, Complex { re: re + width, im: 0.1 - width * 0.75 })
+    };
+    let deep = |re, width| {
+        let (top_left, bot_right) = corners(re, width);
+        too_deep_for_f64(top_left, bot_right)
+    };
+    assert!(!deep(-2.2, 3.2));
+    assert!(!deep(-0.75, 1e-11));
+    assert!(deep(-0.75, 1e-14));
+    assert!(deep(-0.75, 0.0));
+    // far from the origin corners are rounded more
+    assert!(!deep(-0.75, 1e-10));
+    assert!(deep(-3e4, 1e-10));
+}
+
+#[test]
+fn test_escalate() {
+    // 1.8e-4 wide pixels, which f32 resolves near the origin but not at -2
+    let bounds = (12000, 1000);
+    let (top_left, bot_right) = (Complex { re: -2.16, im: 0.09 },
+                                 Complex { re: 0.0, im: -0.09 });
+    let tile = |p, x| escalate(p, bounds, (x, 0, 100, 100), top_left, bot_right);
+    assert_eq!(tile(Precision::F32, 0), Precision::F64);
+    assert_eq!(tile(Precision::F32, 11000), Precision::F32);
+    assert_eq!(tile(Precision::F64, 0), Precision::F64);
+    assert_eq!(tile(Precision::Fixed(128), 0), Precision::Fixed(128));
+
+    let bot_right = Complex { re: -2.16 + 1e-12, im: 0.09 - 1e-12 };
+    match escalate(Precision::F64, (1000, 1000), (0, 0, 10, 10), top_left, bot_right) {
+        Precision::Fixed(bits) => assert!(bits > 64),
+        p => panic!("expected fixed point, got {}", p),
+    }
+}
@@ -0,0 +1,88 @@
+use fixed::Fixed;
+use num::Complex;
+use precision::Precision;
+use render_points_parallel;
+
+/// Pixel spacing of each pass, coarsest first
+pub const PASSES: [usize; 4] = [8, 4, 2, 1];
+
+/// points(b, s, first) : pixels computed by the pass with spacing `s`
+///
+/// Every pass but the `first` skips the pixels the previous, twice as
+/// coarse pass already computed.
+fn points(bounds: (usize, usize), step: usize, first: bool) -> Vec<(usize, usize)> {
+    (0 .. bounds.1).step_by(step)
+        .flat_map(|row| (0 .. bounds.0).step_by(step).map(move |col| (col, row)))
+        .filter(|&(col, row)| first || col % (2 * step) != 0 || row % (2 * step) != 0)
+        .collect()
+}
+
+/// Render in successively finer passes, calling `on_pass` with each
+/// pass's spacing and the image so far; it returns whether to go on
+///
+/// After a pass with spacing `s`, every pixel holds the value of the
+/// nearest computed pixel above and to the left of it on the `s` grid, so
+/// each intermediate image is a blocky but complete preview.
+#[allow(clippy::too_many_arguments)]
+pub fn render<F>(pixels: &mut [u8],
+                 bounds: (usize, usize),
+                 top_left: Complex<f64>,
+                 bot_right: Complex<f64>,
+                 deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+                 precision: Precision,
+                 limit: u32,
+                 threads: usize,
+                 mut on_pass: F)
+    where F: FnMut(usize, &[u8]) -> bool
+{
+    assert!(pixels.len() == bounds.0 * bounds.1);
+
+    for (pass, &step) in PASSES.iter().enumerate() {
+        let points = points(bounds, step, pass == 0);
+        let shades = render_points_parallel(&points, bounds, top_left, bot_right,
+                                            deep, precision, limit, threads);
+
+        for (&(col, row), &shade) in points.iter().zip(&shades) {
+            let right = (col + step).min(bounds.0);
+            for y in row .. (row + step).min(bounds.1) {
+                for p in &mut pixels[y * bounds.0 + col .. y * bounds.0 + right] {
+                    *p = shade;
+                }
+            }
+        }
+        if !on_pass(step, pixels) {
+            return;
+        }
+    }
+}
+
+#[test]
+fn test_points_cover_image_once() {
+    let bounds = (21, 13);
+    let mut seen = vec![0; bounds.0 * bounds.1];
+    for (pass, &step) in PASSES.iter().enumerate() {
+        for (col, row) in points(bounds, step, pass == 0) {
+            seen[row * bounds.0 + col] += 1;
+        }
+    }
+    assert!(seen.iter().all(|&n| n == 1));
+}
+
+#[test]
+fn test_render_refines_to_full_image() {
+    let bounds = (37, 29);
+    let top_left = Complex { re: -1.2, im: 0.35 };
+    let bot_right = Complex { re: -1.0, im: 0.2 };
+
+    let mut passes = Vec::new();
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    render(&mut pixels, bounds, top_left, bot_right, None, Precision::F64, 255, 3,
+           |step, _| { passes.push(step); true });
+    assert_eq!(passes, PASSES);
+
+    let all: Vec<_> = (0 .. bounds.1)
+        .flat_map(|row| (0 .. bounds.0).map(move |col| (col, row)))
+        .collect();
+    assert_eq!(pixels, ::render_points(&all, bounds, top_left, bot_right,
+                                       None, Precision::F64, 255));
+}
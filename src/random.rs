@@ -0,0 +1,69 @@
+/// Step between the seeds of `Rng::stream`, the golden ratio in 64 bits
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// splitmix(x) : `x` scrambled so that nearby seeds give unrelated
+/// generators
+fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(GOLDEN_GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// A seeded source of random numbers for the stochastic renders, xorshift64*
+/// underneath, giving the same numbers for the same seed on every run and
+/// platform
+///
+/// Work split over threads should take its numbers from a `stream` per
+/// sample, pixel or tile rather than a generator per thread, so that the
+/// number of threads can't change what's drawn.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // xorshift never leaves zero
+        Rng(splitmix(seed) | 1)
+    }
+
+    /// stream(s, n) : the `n`th of the generators that can be drawn from
+    /// `seed` independently of each other
+    pub fn stream(seed: u64, n: u64) -> Rng {
+        Rng::new(seed.wrapping_add(n.wrapping_mul(GOLDEN_GAMMA)))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// below(n) : a number in `0 .. n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// unit() : a number in `0.0 .. 1.0`
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[test]
+fn test_rng() {
+    let mut rng = Rng::new(0);
+    let draws: Vec<usize> = (0 .. 100).map(|_| rng.below(3)).collect();
+    assert!(draws.iter().all(|&n| n < 3));
+    assert!((0 .. 3).all(|n| draws.contains(&n)));
+    assert!((0 .. 1000).map(|_| rng.unit()).all(|x| (0.0 .. 1.0).contains(&x)));
+
+    // the same seed draws the same numbers however it's reached
+    let first: Vec<u64> = (0 .. 10).map(|_| Rng::new(7).next_u64()).collect();
+    assert!(first.iter().all(|&x| x == first[0]));
+    let mut again = Rng::new(7);
+    assert_eq!(again.next_u64(), first[0]);
+    assert_eq!(Rng::stream(7, 3), Rng::stream(7, 3));
+    assert_ne!(Rng::stream(7, 3).next_u64(), Rng::stream(7, 4).next_u64());
+    assert_ne!(Rng::new(1).next_u64(), Rng::new(2).next_u64());
+}
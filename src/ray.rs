@@ -0,0 +1,162 @@
+use error::MandelError;
+use num::Complex;
+use std::f64::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// Points traced for each doubling of the angle, each a Newton solve from
+/// the one before, close enough together that it doesn't jump to another
+/// ray
+pub const SHARPNESS: u32 = 8;
+
+/// Radius rays are traced in from, where they're all but straight
+pub const ESCAPE_RADIUS: f64 = 65536.0;
+
+/// Doublings of the angle a ray is traced through, by when it's far closer
+/// to where it lands than `f64` can follow
+pub const DEPTH: u32 = 64;
+
+/// Most Newton steps taken towards each point of a ray
+const NEWTON_STEPS: usize = 64;
+
+/// An external angle, a fraction of a turn `num / den` as a rational, so
+/// that doubling it is exact however often it's done
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Angle {
+    num: u64,
+    den: u64,
+}
+
+impl Angle {
+    /// double() : twice the angle, less any whole turn
+    pub fn double(self) -> Angle {
+        Angle { num: 2 * self.num % self.den, den: self.den }
+    }
+
+    /// turns() : the angle as a fraction of a turn
+    pub fn turns(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+}
+
+/// Parsed from `P/Q` turns, with `0 <= P < Q`
+impl FromStr for Angle {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Angle, MandelError> {
+        let error = || MandelError::Parse(format!(
+            "bad angle {:?}, expected P/Q turns such as 1/3", s));
+        let (num, den) = s.split_once('/').ok_or_else(error)?;
+        let (num, den) = match (num.parse::<u64>(), den.parse::<u64>()) {
+            (Ok(num), Ok(den)) => (num, den),
+            _ => return Err(error()),
+        };
+        // doubling the numerator mustn't overflow
+        if num >= den || den > 1 << 62 {
+            return Err(error());
+        }
+        Ok(Angle { num, den })
+    }
+}
+
+impl fmt::Display for Angle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
+/// trace(a, d) : points along the external ray of `angle`, from
+/// `ESCAPE_RADIUS` in towards where it lands on the set, `SHARPNESS` to
+/// each of `depth` doublings, stopping short if they can't be followed
+///
+/// Where `Z_k+1` is about `Φ(c)^2^k`, the point at radius `r` of the ray
+/// solves `Z_k+1(c) = r e^2πi 2^k angle`, by Newton's method from the
+/// point before, `r` shrinking towards 1 from `ESCAPE_RADIUS` and halving
+/// its logarithm each doubling.
+pub fn trace(angle: Angle, depth: u32) -> Vec<Complex<f64>> {
+    let mut angle = angle;
+    let mut c = Complex::from_polar(ESCAPE_RADIUS, 2.0 * PI * angle.turns());
+    let mut ray = vec![c];
+    for k in 0 .. depth {
+        if k > 0 {
+            angle = angle.double();
+        }
+        for j in 0 .. SHARPNESS {
+            let r = ESCAPE_RADIUS.powf(0.5f64.powf((j as f64 + 0.5) / SHARPNESS as f64));
+            let target = Complex::from_polar(r, 2.0 * PI * angle.turns());
+            match solve(c, target, k + 1) {
+                Some(next) => c = next,
+                None => return ray,
+            }
+            ray.push(c);
+        }
+    }
+    ray
+}
+
+/// solve(c, t, n) : the `c` near `guess` where `Z_n(c) = target`, as near
+/// as `NEWTON_STEPS` of Newton's method get, `None` if they go off to
+/// infinity
+fn solve(guess: Complex<f64>, target: Complex<f64>, n: u32) -> Option<Complex<f64>> {
+    let mut c = guess;
+    for _ in 0 .. NEWTON_STEPS {
+        let (mut z, mut der) = (Complex { re: 0.0, im: 0.0 }, Complex { re: 0.0, im: 0.0 });
+        for _ in 0 .. n {
+            der = 2.0 * z * der + 1.0;
+            z = z * z + c;
+        }
+        let next = c - (z - target) / der;
+        if !next.is_finite() {
+            return None;
+        }
+        if next == c {
+            break;
+        }
+        c = next;
+    }
+    Some(c)
+}
+
+#[test]
+fn test_angle() {
+    let third: Angle = "1/3".parse().unwrap();
+    assert_eq!((third.double(), third.double().double()), ("2/3".parse().unwrap(), third));
+    assert_eq!(third.to_string(), "1/3");
+    assert_eq!("0/1".parse::<Angle>().unwrap().turns(), 0.0);
+    for bad in &["1/1", "3/2", "1", "x/3", "1/0", "-1/3", ""] {
+        assert!(bad.parse::<Angle>().is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_trace() {
+    let trace = |angle: &str| trace(angle.parse().unwrap(), DEPTH);
+
+    // the ray of 1/2 runs along the real axis to -2, the tip of the
+    // antenna, staying outside the set, until it's too close to follow
+    let half = trace("1/2");
+    assert!(half.len() > 20 * SHARPNESS as usize);
+    assert!(half.iter().all(|c| c.re < -2.0 && c.im.abs() < 1e-9));
+    assert!((half.last().unwrap() - Complex { re: -2.0, im: 0.0 }).norm() < 1e-9);
+
+    // 1/7 and 2/7 land together on the root of the rabbit's bulb, and 1/3
+    // and 2/3 on that of the period 2 disc, mirrored, each point escaping;
+    // rays come in slowly to roots, where the bulbs meet
+    let approaches = |ray: &[Complex<f64>], root: Complex<f64>| {
+        let distance = |c: Complex<f64>| (c - root).norm();
+        let last = distance(ray[ray.len() - 1]);
+        last < distance(ray[ray.len() / 2]) && last < 0.06
+    };
+    let rabbit_root = Complex { re: -0.125, im: 0.649519052838329 };
+    for angle in &["1/7", "2/7"] {
+        let ray = trace(angle);
+        assert!(approaches(&ray, rabbit_root), "{}", angle);
+        assert!(ray.iter().all(|&c| ::escape_time(c, 100_000).is_some()));
+    }
+    let (third, two_thirds) = (trace("1/3"), trace("2/3"));
+    assert!(approaches(&third, Complex { re: -0.75, im: 0.0 }));
+    for (a, b) in third.iter().zip(&two_thirds) {
+        assert!((a.conj() - b).norm() < 1e-9);
+    }
+}
+
@@ -0,0 +1,1271 @@
+use buffer;
+use cancel::CancelToken;
+use color::{Colorizer, EscapeBuffer, Gray};
+use core::{iterate_batch, Affine, Real, BAILOUT};
+use error::MandelError;
+use events::{EventHook, RenderEvent};
+use image::{Blend, ImageBuffer};
+use fixed::{self, Fixed};
+use num::Complex;
+use output::ImageWriter;
+use pan;
+use perturb::References;
+use precision::{self, Precision};
+use random::Rng;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+use std::mem;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use task::{self, ProgressStream, RenderFuture};
+use viewport::Viewport;
+use {auto_iterations, escape_rect, format_complex, in_bands, in_blocks, parse_complex,
+     STRIP_ROWS_PER_THREAD};
+
+/// invalid(r) : the error for settings that can't be rendered, and why
+fn invalid<S: Into<String>>(reason: S) -> MandelError {
+    MandelError::InvalidParams(reason.into())
+}
+
+/// room_for(b) : an empty vector with room for the pixels of a `bounds`
+/// image, or an error saying so if there's not the memory for them, rather
+/// than the allocation aborting
+///
+/// Memory the kernel says isn't free counts as not there, since it would
+/// only be promised and the render killed on using it.
+fn room_for<T>(bounds: (usize, usize)) -> Result<Vec<T>, MandelError> {
+    let len = bounds.0.saturating_mul(bounds.1);
+    let bytes = (len as u64).saturating_mul(mem::size_of::<T>() as u64);
+    let too_big = || invalid(format!("{}x{} pixels take {} bytes, more than the free memory",
+                                     bounds.0, bounds.1, bytes));
+    if buffer::available_memory().is_some_and(|free| bytes > free) {
+        return Err(too_big());
+    }
+    let mut pixels = Vec::new();
+    pixels.try_reserve_exact(len).map_err(|_| too_big())?;
+    Ok(pixels)
+}
+
+/// Rows a thread renders between checks that the render hasn't been
+/// cancelled
+const CANCEL_ROWS: usize = 8;
+
+/// An image to render, set up a setting at a time:
+///
+/// ```
+/// # extern crate mandelbrot;
+/// # extern crate num;
+/// # use mandelbrot::Renderer;
+/// # use num::Complex;
+/// # fn main() -> Result<(), mandelbrot::MandelError> {
+/// let image = Renderer::new()
+///     .bounds(100, 75)
+///     .viewport(Complex { re: -1.2, im: 0.35 }, Complex { re: -1.0, im: 0.2 })
+///     .iterations(500)
+///     .render()?;
+/// assert_eq!(image.bounds(), (100, 75));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Anything not set is left at its default: the whole set at 800x600,
+/// iterations chosen for the zoom, `f64` or as much more precision as
+/// the view needs, and a thread per core.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Renderer {
+    bounds: (usize, usize),
+    top_left: String,
+    bot_right: String,
+    /// `None` to choose from the zoom
+    limit: Option<u32>,
+    precision: Precision,
+    /// radians anticlockwise about the center
+    rotation: f64,
+    /// samples across and down each pixel on an edge, 1 for none
+    antialias: usize,
+    /// random samples of every pixel, 1 for one at its center
+    jitter: usize,
+    seed: u64,
+    /// radius past which orbits have escaped
+    bailout: f64,
+    threads: usize,
+    cancel: Option<CancelToken>,
+    events: Option<EventHook>,
+}
+
+/// Most iterations a render can be set to in JSON
+pub const MAX_ITERATIONS: u32 = 1_000_000;
+
+/// Most samples across a pixel a render can be anti-aliased with
+pub const MAX_ANTIALIAS: usize = 16;
+
+/// Most random samples of a pixel a render can be jittered with
+pub const MAX_JITTER: usize = 256;
+
+/// Largest bailout radius a render can be set to, well short of where its
+/// square would overflow an `f32`
+pub const MAX_BAILOUT: f64 = 1e12;
+
+/// How far apart a pixel and one beside it must look, as `Blend::contrast`
+/// measures it, for the pixel to be on an edge and be anti-aliased
+pub const EDGE_CONTRAST: f64 = 0.05;
+
+/// Settings that `to_json` writes and `from_json` reads
+const FIELDS: [&str; 11] = ["width", "height", "top_left", "bot_right", "iterations",
+                            "precision", "rotation", "antialias", "jitter", "seed", "bailout"];
+
+/// Side of the square tiles handed out by `Renderer::tiles`
+pub const TILE_SIZE: usize = 64;
+
+/// The `(x, y, width, height)` of a tile in its image
+pub type TileRect = (usize, usize, usize, usize);
+
+/// A render with everything worked out from its settings
+struct Plan {
+    top_left: Complex<f64>,
+    bot_right: Complex<f64>,
+    deep: Option<(Complex<Fixed>, Complex<Fixed>)>,
+    precision: Precision,
+    limit: u32,
+    /// the cosine and sine of the rotation, `None` for a view lined up
+    /// with the axes
+    turn: Option<(f64, f64)>,
+    antialias: usize,
+    jitter: usize,
+    seed: u64,
+    bailout: f64,
+    cancel: Option<CancelToken>,
+}
+
+/// jitter(p, n, r) : where `samples` samples of the pixel `pixel` go, in
+/// pixels across and down the image, drawn from `rng`
+///
+/// The pixel is cut into `samples` columns and as many rows, and each
+/// sample is somewhere random in a column and row of its own, so that the
+/// samples are spread out without lining up.
+fn jitter(pixel: (usize, usize), samples: usize, rng: &mut Rng) -> Vec<(f64, f64)> {
+    let mut rows: Vec<usize> = (0 .. samples).collect();
+    for i in (1 .. samples).rev() {
+        rows.swap(i, rng.below(i + 1));
+    }
+    let n = samples as f64;
+    rows.iter().enumerate().map(|(col, &row)| {
+        (pixel.0 as f64 + (col as f64 + rng.unit()) / n,
+         pixel.1 as f64 + (row as f64 + rng.unit()) / n)
+    }).collect()
+}
+
+/// Where in an image points are sampled, as pixels across and down, put
+/// in the plane by an `Affine` of whichever precision a block needs
+trait Samples {
+    /// points(a) : the points sampled, in order, where `affine` puts them
+    fn points<T: Real>(&self, affine: &Affine<T>) -> Vec<Complex<T>>;
+}
+
+/// The centers of each pixel of the rectangle, a row at a time
+impl Samples for TileRect {
+    fn points<T: Real>(&self, affine: &Affine<T>) -> Vec<Complex<T>> {
+        let (x, y, width, height) = *self;
+        (y .. y + height)
+            .flat_map(|row| (x .. x + width).map(move |col| affine.center((col, row))))
+            .collect()
+    }
+}
+
+/// Spots in pixels across and down, fractions of them included
+impl Samples for [(f64, f64)] {
+    fn points<T: Real>(&self, affine: &Affine<T>) -> Vec<Complex<T>> {
+        self.iter().map(|&(x, y)| affine.at(x, y)).collect()
+    }
+}
+
+impl Plan {
+    /// Whether the render has been cancelled through its `CancelToken`
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Find the escape times of the `(x, y, width, height)` rectangle
+    /// `rect` of the `bounds` image
+    fn escapes(&self, times: &mut [u32], bounds: (usize, usize),
+               rect: (usize, usize, usize, usize))
+    {
+        let turn = match self.turn {
+            None => {
+                return escape_rect(times, bounds, rect, self.top_left, self.bot_right,
+                                   self.deep.as_ref(), self.precision, self.limit,
+                                   self.bailout, self.cancel.as_ref());
+            }
+            Some(turn) => turn,
+        };
+        in_blocks(times, bounds, rect, 1, |times, piece, block| {
+            self.escape_samples(times, bounds, block, turn, &piece)
+        });
+    }
+
+    /// references(b) : for a deep view lined up with the axes, the reference
+    /// orbits of the `bounds` image, to find samples inside pixels against
+    /// without working out a reference for each pixel
+    fn references(&self, bounds: (usize, usize)) -> Option<References<'_>> {
+        match (&self.deep, self.turn) {
+            (Some((tl, br)), None) => {
+                Some(References::new(bounds, tl, br, self.limit, self.bailout))
+            }
+            _ => None,
+        }
+    }
+
+    /// Find the escape times of `points` with the plan's limit and bailout
+    fn iterate<T: Real>(&self, points: &[Complex<T>], times: &mut [u32]) {
+        iterate_batch(points, None, self.limit, self.bailout, &|| self.cancelled(), times)
+    }
+
+    /// Find the escape times of the points `samples` of the `bounds` image,
+    /// turned by `turn`, in the precision their `block` needs
+    ///
+    /// Turned rectangles and jittered spots aren't views of their own, so
+    /// their points come from the whole image's, and deep ones are iterated
+    /// directly.
+    fn escape_samples<S>(&self, times: &mut [u32], bounds: (usize, usize), block: TileRect,
+                         turn: (f64, f64), samples: &S)
+        where S: Samples + ?Sized
+    {
+        let (tl, br) = (self.top_left, self.bot_right);
+        let escalated = match self.deep {
+            None => precision::escalate(self.precision, bounds, block, tl, br),
+            Some(_) => self.precision,
+        };
+        match (&self.deep, escalated) {
+            (Some((tl, br)), _) => {
+                let affine = Affine::new(bounds, tl.clone(), br.clone(), turn);
+                self.iterate(&samples.points(&affine), times);
+            }
+            (None, Precision::Fixed(bits)) => {
+                let to_fixed = |z: Complex<f64>| Complex {
+                    re: Fixed::from_f64(z.re, bits),
+                    im: Fixed::from_f64(z.im, bits),
+                };
+                let affine = Affine::new(bounds, to_fixed(tl), to_fixed(br), turn);
+                self.iterate(&samples.points(&affine), times);
+            }
+            (None, Precision::F32) => {
+                let to_f32 = |z: Complex<f64>| Complex { re: z.re as f32, im: z.im as f32 };
+                let affine = Affine::new(bounds, to_f32(tl), to_f32(br), turn);
+                self.iterate(&samples.points(&affine), times);
+            }
+            (None, _) => {
+                let affine = Affine::new(bounds, tl, br, turn);
+                self.iterate(&samples.points(&affine), times);
+            }
+        }
+    }
+
+    /// Color `rect` of the `bounds` image into `pixels` with `colorizer`,
+    /// each pixel the mean of `jitter` samples put where `jitter` says
+    ///
+    /// The samples of a pixel are drawn from a stream of `seed` of its own,
+    /// so they're the same however the image is cut up.
+    fn shade_jittered<T, C>(&self, colorizer: &C, bounds: (usize, usize), rect: TileRect,
+                            pixels: &mut [T])
+        where T: Clone + Default + Blend, C: Colorizer<T> + ?Sized
+    {
+        let n = self.jitter;
+        let mut times = vec![0; rect.2 * rect.3 * n];
+        let mut references = self.references(bounds);
+        in_blocks(&mut times, bounds, rect, n, |times, piece, block| {
+            let (x, y, width, height) = piece;
+            let spots: Vec<(f64, f64)> = (y .. y + height)
+                .flat_map(|row| (x .. x + width).map(move |col| (col, row)))
+                .flat_map(|(col, row)| {
+                    let mut rng = Rng::stream(self.seed, (row * bounds.0 + col) as u64);
+                    jitter((col, row), n, &mut rng)
+                })
+                .collect();
+            match references {
+                Some(ref mut references) => {
+                    references.escape_times(times, &spots, self.cancel.as_ref())
+                }
+                None => {
+                    let turn = self.turn.unwrap_or((1.0, 0.0));
+                    self.escape_samples(times, bounds, block, turn, &spots[..]);
+                }
+            }
+        });
+        let mut sampled = vec![T::default(); times.len()];
+        colorizer.colorize(&times, self.limit, &mut sampled);
+        for (pixel, samples) in pixels.iter_mut().zip(sampled.chunks(n)) {
+            *pixel = T::mean(samples);
+        }
+    }
+
+    /// Color the `(x, y, width, height)` rectangle `rect` of the `bounds`
+    /// image into `pixels` with `colorizer`, anti-aliasing its edges
+    ///
+    /// A pixel is on an edge when it looks different enough from one beside
+    /// it, above or below, so its colors are found with a row and column
+    /// more all round; those are then each the mean of `antialias` by
+    /// `antialias` samples spread over the pixel, found as pixels of an
+    /// image that many times bigger.
+    fn shade<T, C>(&self, colorizer: &C, bounds: (usize, usize), rect: TileRect,
+                   pixels: &mut [T])
+        where T: Clone + Default + Blend, C: Colorizer<T> + ?Sized
+    {
+        if self.jitter > 1 {
+            return self.shade_jittered(colorizer, bounds, rect, pixels);
+        }
+        if self.antialias < 2 {
+            let mut times = vec![0; pixels.len()];
+            self.escapes(&mut times, bounds, rect);
+            colorizer.colorize(&times, self.limit, pixels);
+            return;
+        }
+
+        let (x, y, width, height) = rect;
+        let (left, top) = (x.saturating_sub(1), y.saturating_sub(1));
+        let around = (left, top, (x + width + 1).min(bounds.0) - left,
+                      (y + height + 1).min(bounds.1) - top);
+        let mut times = vec![0; around.2 * around.3];
+        self.escapes(&mut times, bounds, around);
+        let mut colors = vec![T::default(); times.len()];
+        colorizer.colorize(&times, self.limit, &mut colors);
+
+        let n = self.antialias;
+        let fine = (bounds.0 * n, bounds.1 * n);
+        let mut references = self.references(bounds);
+        let mut spots = vec![(0.0, 0.0); n * n];
+        let mut samples = vec![0; n * n];
+        let mut sampled = vec![T::default(); n * n];
+        for row in 0 .. height {
+            for col in 0 .. width {
+                // where the pixel and those beside it are among `colors`
+                let (i, j) = (x + col - left, y + row - top);
+                let at = |i, j| &colors[j * around.2 + i];
+                let pixel = at(i, j);
+                let edge = (i > 0 && pixel.contrast(at(i - 1, j)) > EDGE_CONTRAST)
+                    || (i + 1 < around.2 && pixel.contrast(at(i + 1, j)) > EDGE_CONTRAST)
+                    || (j > 0 && pixel.contrast(at(i, j - 1)) > EDGE_CONTRAST)
+                    || (j + 1 < around.3 && pixel.contrast(at(i, j + 1)) > EDGE_CONTRAST);
+                pixels[row * width + col] = if edge {
+                    match references {
+                        Some(ref mut references) => {
+                            // the centers of an n by n grid over the pixel
+                            for (k, spot) in spots.iter_mut().enumerate() {
+                                *spot = ((x + col) as f64 + ((k % n) as f64 + 0.5) / n as f64,
+                                         (y + row) as f64 + ((k / n) as f64 + 0.5) / n as f64);
+                            }
+                            references.escape_times(&mut samples, &spots, self.cancel.as_ref());
+                        }
+                        None => {
+                            self.escapes(&mut samples, fine,
+                                         ((x + col) * n, (y + row) * n, n, n));
+                        }
+                    }
+                    colorizer.colorize(&samples, self.limit, &mut sampled);
+                    T::mean(&sampled)
+                } else {
+                    pixel.clone()
+                };
+            }
+        }
+    }
+}
+
+/// As `to_json` writes it
+impl Serialize for Renderer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Renderer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Renderer, D::Error> {
+        Renderer::from_json(&Value::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// Tiles of a render, in the order they finish, from `Renderer::tiles`
+///
+/// Dropping it stops the render at the next tile.
+pub struct Tiles {
+    finished: mpsc::Receiver<(TileRect, Vec<u8>)>,
+}
+
+impl Iterator for Tiles {
+    type Item = (TileRect, Vec<u8>);
+
+    fn next(&mut self) -> Option<(TileRect, Vec<u8>)> {
+        self.finished.recv().ok()
+    }
+}
+
+/// A band of rows finished, as told to `Renderer::render_progress`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProgressEvent {
+    /// rows of the image the band covers
+    pub rows: Range<usize>,
+    /// rows finished so far, this band's included, of `height`
+    pub rows_done: usize,
+    pub height: usize,
+    /// how long the band took
+    pub elapsed: Duration,
+    /// how long since the render started
+    pub total: Duration,
+}
+
+impl Default for Renderer {
+    fn default() -> Renderer {
+        Renderer {
+            bounds: (800, 600),
+            top_left: "-2.2,1.2".to_string(),
+            bot_right: "1,-1.2".to_string(),
+            limit: None,
+            precision: Precision::F64,
+            rotation: 0.0,
+            antialias: 1,
+            jitter: 1,
+            seed: 0,
+            bailout: BAILOUT,
+            threads: thread::available_parallelism().map_or(8, |n| n.get()),
+            cancel: None,
+            events: None,
+        }
+    }
+}
+
+impl Renderer {
+    pub fn new() -> Renderer {
+        Renderer::default()
+    }
+
+    /// Render `width` by `height` pixels
+    pub fn bounds(self, width: usize, height: usize) -> Renderer {
+        Renderer { bounds: (width, height), ..self }
+    }
+
+    /// Show `top_left` to `bot_right`
+    pub fn viewport(self, top_left: Complex<f64>, bot_right: Complex<f64>) -> Renderer {
+        self.corners(&format_complex(top_left), &format_complex(bot_right))
+    }
+
+    /// Show the corners written as `re,im`, keeping every digit given for
+    /// views too deep for `f64`
+    pub fn corners(self, top_left: &str, bot_right: &str) -> Renderer {
+        Renderer { top_left: top_left.to_string(), bot_right: bot_right.to_string(), ..self }
+    }
+
+    /// Give up on points after `limit` iterations
+    pub fn iterations(self, limit: u32) -> Renderer {
+        Renderer { limit: Some(limit), ..self }
+    }
+
+    /// Render in at least `precision`
+    pub fn precision(self, precision: Precision) -> Renderer {
+        Renderer { precision, ..self }
+    }
+
+    /// Turn the view `radians` anticlockwise about its center, the corners
+    /// being those of the view before it's turned
+    pub fn rotation(self, radians: f64) -> Renderer {
+        Renderer { rotation: radians, ..self }
+    }
+
+    /// Anti-alias the edges of the image, where neighboring pixels look
+    /// different, with `samples` by `samples` samples of each pixel on them
+    /// in place of one, 1 for none
+    ///
+    /// Only the colored image is anti-aliased; escape times are left alone.
+    pub fn antialias(self, samples: usize) -> Renderer {
+        Renderer { antialias: samples, ..self }
+    }
+
+    /// Color every pixel as the mean of `samples` samples at random places
+    /// in it, spread out so no two share a column or row of its `samples`
+    /// by `samples` grid, 1 for one sample at its center
+    ///
+    /// This anti-aliases better than a regular grid and keeps moiré out of
+    /// the dense swirls near the boundary. It can't be combined with
+    /// `antialias`, and like it only changes the colored image.
+    pub fn jitter(self, samples: usize) -> Renderer {
+        Renderer { jitter: samples, ..self }
+    }
+
+    /// Draw `jitter`'s samples from `seed`, 0 by default, the same seed
+    /// always giving the same image
+    pub fn seed(self, seed: u64) -> Renderer {
+        Renderer { seed, ..self }
+    }
+
+    /// Count a point as escaped once its orbit is more than `radius` from
+    /// 0, `BAILOUT` by default
+    ///
+    /// Points take a little longer to escape past a bigger radius, which
+    /// renders aren't tuned for, so they're iterated directly rather than
+    /// through the faster paths.
+    pub fn bailout(self, radius: f64) -> Renderer {
+        Renderer { bailout: radius, ..self }
+    }
+
+    pub fn threads(self, threads: usize) -> Renderer {
+        Renderer { threads: threads.max(1), ..self }
+    }
+
+    /// Stop rendering, with an error, soon after `token` is cancelled
+    pub fn cancel_token(self, token: &CancelToken) -> Renderer {
+        Renderer { cancel: Some(token.clone()), ..self }
+    }
+
+    /// Call `hook` as each band or tile starts and finishes, from the
+    /// thread rendering it, whichever way the image is rendered
+    pub fn on_event<F>(self, hook: F) -> Renderer
+        where F: Fn(RenderEvent) + Send + Sync + 'static
+    {
+        Renderer { events: Some(EventHook::new(hook)), ..self }
+    }
+
+    /// The `(width, height)` the image is rendered at
+    pub fn image_bounds(&self) -> (usize, usize) {
+        self.bounds
+    }
+
+    /// The settings as a JSON object, the one form they're written in for
+    /// HTTP requests and settings files alike
+    ///
+    /// `width` and `height` are in pixels, `top_left` and `bot_right` are
+    /// `"re,im"` strings so deep views keep every digit, `iterations` is a
+    /// number or `"auto"`, `precision` is as `Precision::name` writes it,
+    /// `rotation` is in radians, `antialias` is the samples across each
+    /// pixel on an edge and `jitter` the random samples of every pixel,
+    /// drawn from `seed`, and `bailout` is the escape radius.
+    /// Threads and cancellation depend on where the render runs, so
+    /// they're left out.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "width": self.bounds.0,
+            "height": self.bounds.1,
+            "top_left": self.top_left,
+            "bot_right": self.bot_right,
+            "iterations": self.limit.map_or(json!("auto"), |limit| json!(limit)),
+            "precision": self.precision.name(),
+            "rotation": self.rotation,
+            "antialias": self.antialias,
+            "jitter": self.jitter,
+            "seed": self.seed,
+            "bailout": self.bailout,
+        })
+    }
+
+    /// The settings in the JSON object `json`, as `to_json` writes them,
+    /// with anything missing left at its default, or what's wrong with them
+    pub fn from_json(json: &Value) -> Result<Renderer, MandelError> {
+        let fields = json.as_object().ok_or_else(|| invalid("settings must be a JSON object"))?;
+        if let Some(unknown) = fields.keys().find(|key| !FIELDS.contains(&key.as_str())) {
+            return Err(invalid(format!("unknown field '{}'", unknown)));
+        }
+        let renderer = Renderer::new();
+
+        let side = |name: &str, default: usize| match fields.get(name).map(Value::as_u64) {
+            None => Ok(default),
+            Some(Some(n)) if n > 0 && n <= usize::MAX as u64 => Ok(n as usize),
+            Some(_) => Err(invalid(format!("'{}' must be a whole number of pixels", name))),
+        };
+        let bounds = (side("width", renderer.bounds.0)?, side("height", renderer.bounds.1)?);
+
+        let corner = |name: &str, default: &str| match fields.get(name).map(Value::as_str) {
+            None => Ok(default.to_string()),
+            Some(Some(s)) if parse_complex(s).is_ok() => Ok(s.to_string()),
+            Some(Some(_)) => Err(invalid(format!("'{}' must be \"re,im\"", name))),
+            Some(None) => Err(invalid(format!("'{}' must be a string", name))),
+        };
+        let top_left = corner("top_left", &renderer.top_left)?;
+        let bot_right = corner("bot_right", &renderer.bot_right)?;
+
+        let limit = match fields.get("iterations") {
+            None => None,
+            Some(Value::String(s)) if s == "auto" => None,
+            Some(n) => match n.as_u64() {
+                Some(n) if n > 0 && n <= MAX_ITERATIONS as u64 => Some(n as u32),
+                _ => return Err(invalid(format!("'iterations' must be \"auto\" or 1 to {}",
+                                               MAX_ITERATIONS))),
+            },
+        };
+        let precision = match fields.get("precision") {
+            None => renderer.precision,
+            Some(Value::String(s)) => Precision::from_name(s)?,
+            Some(_) => return Err(invalid("'precision' must be a string")),
+        };
+        let rotation = match fields.get("rotation") {
+            None => renderer.rotation,
+            Some(n) => n.as_f64().ok_or_else(|| invalid("'rotation' must be a number"))?,
+        };
+        let antialias = match fields.get("antialias").map(Value::as_u64) {
+            None => renderer.antialias,
+            Some(Some(n)) if n > 0 && n <= MAX_ANTIALIAS as u64 => n as usize,
+            Some(_) => return Err(invalid(format!("'antialias' must be 1 to {}",
+                                                  MAX_ANTIALIAS))),
+        };
+        let jitter = match fields.get("jitter").map(Value::as_u64) {
+            None => renderer.jitter,
+            Some(Some(n)) if n > 0 && n <= MAX_JITTER as u64 => n as usize,
+            Some(_) => return Err(invalid(format!("'jitter' must be 1 to {}", MAX_JITTER))),
+        };
+        let seed = match fields.get("seed") {
+            None => renderer.seed,
+            Some(n) => n.as_u64().ok_or_else(|| invalid("'seed' must be a whole number"))?,
+        };
+        let bailout = match fields.get("bailout") {
+            None => renderer.bailout,
+            Some(n) => n.as_f64().ok_or_else(|| invalid("'bailout' must be a number"))?,
+        };
+
+        let renderer = Renderer {
+            bounds, top_left, bot_right, limit, precision, rotation, antialias, jitter, seed,
+            bailout, ..renderer
+        };
+        // whatever else can't be rendered, such as corners the wrong way round
+        renderer.plan()?;
+        Ok(renderer)
+    }
+
+    /// Check the settings and work out what they leave open
+    fn plan(&self) -> Result<Plan, MandelError> {
+        let corner = |s: &str| parse_complex(s).map_err(|e| invalid(e.to_string()));
+        let (top_left, bot_right) = (corner(&self.top_left)?, corner(&self.bot_right)?);
+        let view = Viewport::new(self.bounds, top_left, bot_right);
+        let precision = match precision::select(self.precision, self.bounds,
+                                                &self.top_left, &self.bot_right) {
+            Ok(precision) => precision,
+            Err(e) => {
+                view.check(None)?;
+                return Err(invalid(format!("corners can't be read in fixed point: {}", e)));
+            }
+        };
+        let deep = match precision {
+            Precision::Fixed(bits) => {
+                let corner = |s: &str| fixed::parse_complex(s, bits)
+                    .map_err(|e| invalid(format!("corners can't be read in fixed point: {}", e)));
+                Some((corner(&self.top_left)?, corner(&self.bot_right)?))
+            }
+            _ => None,
+        };
+        view.check(deep.as_ref())?;
+        let limit = self.limit.unwrap_or_else(|| auto_iterations(top_left, bot_right));
+        if !self.rotation.is_finite() {
+            return Err(invalid("the rotation must be a number of radians"));
+        }
+        let turn = if self.rotation == 0.0 {
+            None
+        } else {
+            Some((self.rotation.cos(), self.rotation.sin()))
+        };
+        if !(1 ..= MAX_ANTIALIAS).contains(&self.antialias) {
+            return Err(invalid(format!("anti-aliasing takes 1 to {} samples across a pixel",
+                                       MAX_ANTIALIAS)));
+        }
+        // edges are re-sampled as pixels of an image `antialias` times the size
+        if self.bounds.0.checked_mul(self.antialias).is_none()
+            || self.bounds.1.checked_mul(self.antialias).is_none()
+        {
+            return Err(invalid(format!("a {}x{} image is too big to anti-alias {} times over",
+                                       self.bounds.0, self.bounds.1, self.antialias)));
+        }
+        if !(1 ..= MAX_JITTER).contains(&self.jitter) {
+            return Err(invalid(format!("jittering takes 1 to {} samples of a pixel",
+                                       MAX_JITTER)));
+        }
+        if self.jitter > 1 && self.antialias > 1 {
+            return Err(invalid("jittered samples and anti-aliased edges can't be combined"));
+        }
+        if !(BAILOUT ..= MAX_BAILOUT).contains(&self.bailout) {
+            return Err(invalid(format!("the bailout radius must be {} to {}",
+                                       BAILOUT, MAX_BAILOUT)));
+        }
+        Ok(Plan {
+            top_left, bot_right, deep, precision, limit, turn,
+            antialias: self.antialias,
+            jitter: self.jitter,
+            seed: self.seed,
+            bailout: self.bailout,
+            cancel: self.cancel.clone(),
+        })
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    fn event(&self, event: RenderEvent) {
+        if let Some(ref hook) = self.events {
+            hook.call(event);
+        }
+    }
+
+    /// Work through the image a strip of rows at a time, each split into
+    /// a band per thread, with `fill` called on a few rows of a band at a
+    /// time and each finished strip handed to `on_strip`
+    ///
+    /// The render stops with an error once it's cancelled.
+    fn run<T, F, S, P>(&self, fill: F, mut on_strip: S, on_progress: P) -> Result<(), MandelError>
+        where T: Clone + Default + Send,
+              F: Fn(&mut [T], (usize, usize, usize, usize)) + Sync,
+              S: FnMut(usize, usize, &[T]) -> Result<(), MandelError>,
+              P: Fn(ProgressEvent) + Sync
+    {
+        let bounds = self.bounds;
+        let strip_rows = STRIP_ROWS_PER_THREAD.saturating_mul(self.threads);
+        let strips = bounds.1.div_ceil(strip_rows);
+        let mut values = room_for((bounds.0, strip_rows.min(bounds.1)))?;
+        values.resize(strip_rows.min(bounds.1) * bounds.0, T::default());
+        let started = Instant::now();
+        let rows_done = AtomicUsize::new(0);
+        for i in 0 .. strips {
+            let strip = i * strip_rows .. (i + 1).saturating_mul(strip_rows).min(bounds.1);
+            let values = &mut values[.. strip.len() * bounds.0];
+            in_bands(values, bounds.0, self.threads, |band, rows| {
+                let band_started = Instant::now();
+                let rows = strip.start + rows.start .. strip.start + rows.end;
+                let rect = (0, rows.start, bounds.0, rows.len());
+                self.event(RenderEvent::Started { rect });
+                for (j, few) in band.chunks_mut(CANCEL_ROWS * bounds.0).enumerate() {
+                    if self.cancelled() {
+                        return;
+                    }
+                    fill(few, (0, rows.start + j * CANCEL_ROWS, bounds.0, few.len() / bounds.0));
+                }
+                self.event(RenderEvent::Finished {
+                    rect,
+                    elapsed: band_started.elapsed(),
+                    total: started.elapsed(),
+                });
+                on_progress(ProgressEvent {
+                    rows_done: rows_done.fetch_add(rows.len(), Ordering::SeqCst) + rows.len(),
+                    rows,
+                    height: bounds.1,
+                    elapsed: band_started.elapsed(),
+                    total: started.elapsed(),
+                });
+            });
+            if self.cancelled() {
+                return Err(MandelError::Cancelled);
+            }
+            on_strip(i + 1, strips, values)?;
+        }
+        Ok(())
+    }
+
+    /// Render colored by `colorizer`, calling `on_strip` with how many
+    /// strips are done, how many there are and the rows so far
+    fn shade<T, C, S, P>(&self, colorizer: &C, mut on_strip: S, on_progress: P)
+        -> Result<ImageBuffer<T>, MandelError>
+        where T: Clone + Default + Send + Blend,
+              C: Colorizer<T> + ?Sized,
+              S: FnMut(usize, usize, &[T]) -> Result<(), MandelError>,
+              P: Fn(ProgressEvent) + Sync
+    {
+        let plan = self.plan()?;
+        let mut pixels = room_for(self.bounds)?;
+        self.run(|few, rect| plan.shade(colorizer, self.bounds, rect, few), |done, strips, strip| {
+            pixels.extend_from_slice(strip);
+            on_strip(done, strips, &pixels)
+        }, on_progress)?;
+        Ok(ImageBuffer::from_pixels(self.bounds, pixels))
+    }
+
+    /// The image in 8-bit gray
+    ///
+    /// An image too big for the free memory is an error; `write` needs only
+    /// a strip of it at a time.
+    pub fn render(&self) -> Result<ImageBuffer<u8>, MandelError> {
+        self.render_with(&Gray)
+    }
+
+    /// The image as colored by `colorizer`, in whichever kind of pixel it
+    /// colors in
+    pub fn render_with<T, C>(&self, colorizer: &C) -> Result<ImageBuffer<T>, MandelError>
+        where T: Clone + Default + Send + Blend, C: Colorizer<T> + ?Sized
+    {
+        self.shade(colorizer, |_, _, _| Ok(()), |_| {})
+    }
+
+    /// The escape time of every pixel, to be shaded afterwards, as many
+    /// ways as wanted
+    pub fn escapes(&self) -> Result<EscapeBuffer, MandelError> {
+        let plan = self.plan()?;
+        let mut times = room_for(self.bounds)?;
+        self.run(|few, rect| plan.escapes(few, self.bounds, rect), |_, _, strip| {
+            times.extend_from_slice(strip);
+            Ok(())
+        }, |_| {})?;
+        Ok(EscapeBuffer { limit: plan.limit, times: ImageBuffer::from_pixels(self.bounds, times) })
+    }
+
+    /// Render, calling `on_progress` from the thread that rendered it as
+    /// each band of rows is finished
+    pub fn render_progress<P>(&self, on_progress: P) -> Result<ImageBuffer<u8>, MandelError>
+        where P: Fn(ProgressEvent) + Sync
+    {
+        self.shade(&Gray, |_, _, _| Ok(()), on_progress)
+    }
+
+    /// Render in strips of rows, calling `on_strip` after each with how
+    /// many strips are done, how many there are and the rows so far
+    ///
+    /// An error from `on_strip` stops the render and is returned.
+    pub fn render_strips<F>(&self, on_strip: F) -> Result<ImageBuffer<u8>, MandelError>
+        where F: FnMut(usize, usize, &[u8]) -> Result<(), MandelError>
+    {
+        self.shade(&Gray, on_strip, |_| {})
+    }
+
+    /// Render on a thread of its own, returning at once with a future of
+    /// the image and a stream of its `ProgressEvent`s, so async code can
+    /// await it without blocking its executor
+    ///
+    /// The rows are still split over `threads`. Dropping the future doesn't
+    /// stop the render; a `CancelToken` does.
+    pub fn render_async(&self) -> (RenderFuture, ProgressStream) {
+        task::spawn(self.clone())
+    }
+
+    /// Render in `TILE_SIZE` tiles on threads of their own, returning
+    /// the grayscale pixels of each as soon as it's done
+    ///
+    /// The tiles stop coming early if the render is cancelled. The hook
+    /// from `on_event` hears of each tile rather than of bands.
+    pub fn tiles(&self) -> Result<Tiles, MandelError> {
+        let plan = Arc::new(self.plan()?);
+        let bounds = self.bounds;
+        let view = Viewport::new(bounds, plan.top_left, plan.bot_right);
+        let rects = Arc::new(view.tiles(TILE_SIZE));
+        let next = Arc::new(AtomicUsize::new(0));
+        let started = Instant::now();
+        let (send, finished) = mpsc::channel();
+        for _ in 0 .. self.threads {
+            let (renderer, plan, rects, next) =
+                (self.clone(), plan.clone(), rects.clone(), next.clone());
+            let send = send.clone();
+            thread::spawn(move || {
+                while let Some(&rect) = rects.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    if renderer.cancelled() {
+                        return;
+                    }
+                    let tile_started = Instant::now();
+                    renderer.event(RenderEvent::Started { rect });
+                    let mut tile = vec![0; rect.2 * rect.3];
+                    plan.shade(&Gray, bounds, rect, &mut tile);
+                    renderer.event(RenderEvent::Finished {
+                        rect,
+                        elapsed: tile_started.elapsed(),
+                        total: started.elapsed(),
+                    });
+                    if send.send((rect, tile)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        Ok(Tiles { finished })
+    }
+
+    /// Render straight into the PNG `filename`, a strip at a time, recording
+    /// the view in it
+    pub fn write(&self, filename: &str) -> Result<(), MandelError> {
+        let plan = self.plan()?;
+        let view = pan::describe(plan.limit, plan.precision, &self.top_left, &self.bot_right);
+        let mut writer = ImageWriter::create(filename, self.bounds, Some(&view))?;
+        self.run(|few, rect| plan.shade(&Gray, self.bounds, rect, few),
+                 |_, _, strip| Ok(writer.write_rows(strip)?), |_| {})?;
+        writer.finish()
+    }
+}
+
+#[test]
+fn test_renderer() {
+    let top_left = Complex { re: -1.2, im: 0.35 };
+    let bot_right = Complex { re: -1.0, im: 0.2 };
+    let renderer = Renderer::new().bounds(40, 30).viewport(top_left, bot_right).threads(3);
+    assert_eq!(renderer, Renderer::new().threads(3).corners("-1.2,0.35", "-1,0.2").bounds(40, 30));
+
+    let mut expected = vec![0; 40 * 30];
+    ::render_bands(&mut expected, (40, 30), 0, top_left, bot_right, None, Precision::F64,
+                   auto_iterations(top_left, bot_right), 3);
+    assert_eq!(renderer.render().unwrap().pixels(), &expected[..]);
+
+    ::render_bands(&mut expected, (40, 30), 0, top_left, bot_right, None, Precision::F32, 100, 3);
+    let f32_render = renderer.clone().iterations(100).precision(Precision::F32).render();
+    assert_eq!(f32_render.unwrap().into_pixels(), expected);
+
+    let shallow = renderer.clone().iterations(100);
+    let escapes = shallow.escapes().unwrap();
+    assert_eq!(escapes.limit, 100);
+    assert_eq!(escapes.colorize(&Gray), shallow.render().unwrap());
+    assert_eq!(renderer.render_with(&Gray).unwrap(), renderer.render().unwrap());
+    let rgb = renderer.render_with::<::image::Rgb, _>(&Gray).unwrap();
+    assert_eq!(rgb[(20, 10)].0[0], renderer.render().unwrap()[(20, 10)]);
+
+    let events = ::std::sync::Mutex::new(Vec::new());
+    assert_eq!(renderer.render_progress(|event| events.lock().unwrap().push(event)).unwrap(),
+               renderer.render().unwrap());
+    let mut events = events.into_inner().unwrap();
+    events.sort_by_key(|event| event.rows.start);
+    assert_eq!(events.iter().map(|event| event.rows.len()).sum::<usize>(), 30);
+    assert!(events.windows(2).all(|pair| pair[0].rows.end == pair[1].rows.start));
+    assert!(events.iter().all(|event| event.height == 30 && event.elapsed <= event.total));
+    assert_eq!(events.iter().map(|event| event.rows_done).max(), Some(30));
+
+    let mut strips = Vec::new();
+    renderer.clone().threads(1).bounds(40, 130).render_strips(|done, of, pixels| {
+        strips.push((done, of, pixels.len()));
+        Ok(())
+    }).unwrap();
+    assert_eq!(strips, [(1, 3, 40 * 64), (2, 3, 40 * 128), (3, 3, 40 * 130)]);
+}
+
+#[test]
+fn test_renderer_tiles() {
+    let renderer = Renderer::new().bounds(150, 70).threads(3);
+    let (top_left, bot_right) = (Complex { re: -2.2, im: 1.2 }, Complex { re: 1.0, im: -1.2 });
+    let limit = auto_iterations(top_left, bot_right);
+    let mut rects = Vec::new();
+    for (rect, tile) in renderer.tiles().unwrap() {
+        let mut expected = vec![0; rect.2 * rect.3];
+        ::render_rect(&mut expected, (150, 70), rect, top_left, bot_right, None,
+                      Precision::F64, limit);
+        assert_eq!(tile, expected);
+        rects.push(rect);
+    }
+    rects.sort_by_key(|&(x, y, _, _)| (y, x));
+    assert_eq!(rects, [(0, 0, 64, 64), (64, 0, 64, 64), (128, 0, 22, 64),
+                       (0, 64, 64, 6), (64, 64, 64, 6), (128, 64, 22, 6)]);
+
+    let token = CancelToken::new();
+    token.cancel();
+    assert_eq!(renderer.cancel_token(&token).tiles().unwrap().count(), 0);
+}
+
+#[test]
+fn test_renderer_events() {
+    use std::sync::Mutex;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+        let events = events.clone();
+        move |event| events.lock().unwrap().push(event)
+    };
+    let plain = Renderer::new().bounds(40, 30).threads(3);
+    let renderer = plain.clone().on_event(hook);
+    assert_ne!(renderer, plain);
+    assert_eq!(renderer.render().unwrap(), plain.render().unwrap());
+
+    // every band starts before it finishes, and together they're the image
+    let taken: Vec<_> = events.lock().unwrap().drain(..).collect();
+    let mut bands = Vec::new();
+    for event in &taken {
+        match *event {
+            RenderEvent::Started { rect } => bands.push(rect),
+            RenderEvent::Finished { rect, elapsed, total } => {
+                assert!(bands.contains(&rect));
+                assert!(elapsed <= total);
+            }
+        }
+    }
+    assert_eq!(taken.len(), bands.len() * 2);
+    bands.sort();
+    assert!(bands.windows(2).all(|pair| pair[0].1 + pair[0].3 == pair[1].1));
+    assert_eq!(bands.iter().map(|rect| rect.3).sum::<usize>(), 30);
+    assert!(bands.iter().all(|rect| rect.0 == 0 && rect.2 == 40));
+
+    let tiles = renderer.bounds(100, 30).tiles().unwrap();
+    let mut tiles: Vec<_> = tiles.map(|(rect, _)| rect).collect();
+    let mut finished: Vec<_> = events.lock().unwrap().iter()
+        .filter(|event| matches!(event, RenderEvent::Finished { .. }))
+        .map(RenderEvent::rect)
+        .collect();
+    tiles.sort();
+    finished.sort();
+    assert_eq!(finished, tiles);
+}
+
+#[test]
+fn test_renderer_cancel() {
+    let token = CancelToken::new();
+    let renderer = Renderer::new().bounds(40, 200).threads(2).cancel_token(&token);
+    assert!(renderer.render().is_ok());
+    let strips = renderer.render_strips(|done, _, _| {
+        token.cancel();
+        assert_eq!(done, 1);
+        Ok(())
+    });
+    assert!(matches!(strips, Err(MandelError::Cancelled)));
+    assert!(matches!(renderer.escapes(), Err(MandelError::Cancelled)));
+}
+
+#[test]
+fn test_renderer_json() {
+    let renderer = Renderer::new().bounds(300, 200).corners("-1.2,0.35", "-1,0.2")
+        .iterations(500).precision(Precision::F32);
+    let json = json!({"width": 300, "height": 200, "top_left": "-1.2,0.35",
+                      "bot_right": "-1,0.2", "iterations": 500, "precision": "f32",
+                      "rotation": 0.0, "antialias": 1, "jitter": 1, "seed": 0,
+                      "bailout": 2.0});
+    assert_eq!(renderer.to_json(), json);
+    assert_eq!(Renderer::from_json(&json).unwrap(), renderer);
+    let text = ::serde_json::to_string(&renderer).unwrap();
+    assert_eq!(::serde_json::from_str::<Renderer>(&text).unwrap(), renderer);
+
+    let deep = Renderer::new().precision(Precision::Fixed(128));
+    assert_eq!(Renderer::from_json(&deep.to_json()).unwrap(), deep);
+    assert_eq!(Renderer::from_json(&json!({"iterations": "auto"})).unwrap(), Renderer::new());
+
+    for bad in [json!([1, 2]),
+                json!({"width": 0}),
+                json!({"top_left": "1,-2"}),
+                json!({"top_left": -2}),
+                json!({"colour": 1}),
+                json!({"iterations": -5}),
+                json!({"precision": "f16"}),
+                json!({"precision": "fixed:1000000000"}),
+                json!({"rotation": "90"}),
+                json!({"antialias": 0}),
+                json!({"jitter": 1000}),
+                json!({"seed": -1}),
+                json!({"bailout": 1.5}),
+                json!({"bailout": "far"})] {
+        assert!(Renderer::from_json(&bad).is_err(), "{}", bad);
+        assert!(::serde_json::from_value::<Renderer>(bad).is_err());
+    }
+}
+
+#[test]
+fn test_renderer_errors() {
+    assert!(Renderer::new().bounds(0, 10).render().is_err());
+    assert!(Renderer::new().corners("-1.2", "-1,0.2").render().is_err());
+    assert!(Renderer::new().corners("-1,0.2", "-1.2,0.35").render().is_err());
+    let stopped = Renderer::new().bounds(8, 8)
+        .render_strips(|_, _, _| Err(MandelError::InvalidParams("stop".to_string())));
+    assert_eq!(stopped.unwrap_err().to_string(), "stop");
+    assert!(matches!(Renderer::new().bounds(0, 10).render(),
+                     Err(MandelError::InvalidParams(_))));
+    for &(top_left, bot_right) in &[("-inf,1", "1,-1"), ("-2,NaN", "1,-1"), ("-1,1", "-1,-1"),
+                                    ("-1,-1", "1,1")] {
+        let corners = Renderer::new().bounds(8, 8).corners(top_left, bot_right);
+        assert!(matches!(corners.render(), Err(MandelError::InvalidParams(_))), "{}", top_left);
+        assert!(Renderer::from_json(&corners.to_json()).is_err());
+    }
+
+    // corners too close together for f64 are checked in fixed point
+    // images too big to hold fail before anything's allocated
+    let huge = Renderer::new().bounds(1 << 30, 1 << 30);
+    assert!(matches!(huge.render(), Err(MandelError::InvalidParams(_))));
+    assert!(matches!(huge.escapes(), Err(MandelError::InvalidParams(_))));
+    assert!(matches!(Renderer::new().bounds(usize::MAX, 2).render(),
+                     Err(MandelError::InvalidParams(_))));
+    assert!(room_for::<u8>((1 << 32, 1 << 31)).is_err());
+    assert_eq!(room_for::<u32>((30, 20)).unwrap().capacity(), 600);
+
+    let deep = Renderer::new().bounds(8, 6).iterations(200)
+        .corners("-0.74364388703715870475,0.13182590420531197050",
+                 "-0.74364388703715870474,0.13182590420531197049");
+    assert!(deep.render().is_ok());
+    assert!(Renderer::from_json(&deep.to_json()).is_ok());
+}
+
+#[test]
+fn test_renderer_rotation() {
+    use std::f64::consts::PI;
+
+    let renderer = Renderer::new().bounds(40, 30).corners("-1.5,0.6", "0.1,-0.6")
+        .iterations(100).threads(2);
+    let level = renderer.render().unwrap();
+    let turned = renderer.clone().rotation(PI).render().unwrap();
+    assert_ne!(turned, level);
+
+    // a half turn about the center puts the pixel that was in the top left
+    // in the bottom right, its center landing on the other's
+    let (width, height) = level.bounds();
+    let pixel = |image: &ImageBuffer<u8>, x: usize, y: usize| image.pixels()[y * width + x];
+    let flipped = (0 .. height).flat_map(|y| (0 .. width).map(move |x| (x, y)))
+        .filter(|&(x, y)| pixel(&level, x, y) == pixel(&turned, width - 1 - x, height - 1 - y))
+        .count();
+    assert!(flipped * 10 > width * height * 9, "{}", flipped);
+
+    // a full turn is no turn, near enough
+    let round = renderer.clone().rotation(2.0 * PI).render().unwrap();
+    let same = level.pixels().iter().zip(round.pixels()).filter(|(a, b)| a == b).count();
+    assert!(same * 10 > width * height * 9, "{}", same);
+    let deep = renderer.clone().rotation(PI).precision(Precision::Fixed(64)).render().unwrap();
+    assert_eq!(deep, turned);
+
+    assert!(renderer.clone().rotation(f64::NAN).render().is_err());
+    assert_eq!(Renderer::from_json(&json!({"rotation": 1.5})).unwrap(),
+               Renderer::new().rotation(1.5));
+}
+
+#[test]
+fn test_renderer_antialias() {
+    let renderer = Renderer::new().bounds(40, 30).corners("-1.5,0.6", "0.1,-0.6")
+        .iterations(100).threads(3);
+    let plain = renderer.render().unwrap();
+    assert_eq!(renderer.clone().antialias(1).render().unwrap(), plain);
+
+    // only pixels on edges change, the inside of the set staying black, and
+    // the result is near what sampling every pixel the same way gives
+    let smooth = renderer.clone().antialias(4).render().unwrap();
+    assert_ne!(smooth, plain);
+    let fine = renderer.clone().bounds(160, 120).render().unwrap();
+    let (mut changed, mut close) = (0, 0);
+    for y in 0 .. 30 {
+        for x in 0 .. 40 {
+            let (was, is) = (plain[(x, y)], smooth[(x, y)]);
+            if was != is {
+                changed += 1;
+                let beside = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)),
+                              (x, y + 1)];
+                assert!(beside.iter().any(|&(i, j)| i < 40 && j < 30
+                                          && was.contrast(&plain[(i, j)]) > EDGE_CONTRAST));
+            }
+            let block: Vec<u8> = (0 .. 16).map(|i| fine[(x * 4 + i % 4, y * 4 + i / 4)])
+                .collect();
+            if smooth[(x, y)].contrast(&u8::mean(&block)) <= EDGE_CONTRAST {
+                close += 1;
+            }
+        }
+    }
+    assert!(changed > 0 && changed < 40 * 30, "{}", changed);
+    assert!(close * 10 > 40 * 30 * 9, "{}", close);
+
+    // tiles and strips agree, as do colors
+    let tiled = renderer.clone().antialias(4);
+    for (rect, tile) in tiled.tiles().unwrap() {
+        assert_eq!(tile, smooth.tile(rect).to_image().into_pixels());
+    }
+    let rgb = tiled.render_with::<::image::Rgb, _>(&Gray).unwrap();
+    assert_eq!(rgb[(20, 10)].0[0], smooth[(20, 10)]);
+
+    assert!(renderer.clone().antialias(0).render().is_err());
+    assert!(renderer.antialias(MAX_ANTIALIAS + 1).render().is_err());
+}
+
+#[test]
+fn test_renderer_jitter() {
+    let mut rng = Rng::new(3);
+    let spots = jitter((5, 2), 4, &mut rng);
+    assert!(spots.iter().all(|&(x, y)| (5.0 .. 6.0).contains(&x) && (2.0 .. 3.0).contains(&y)));
+    for i in 0 .. 4 {
+        let slot = |v: f64| (v.fract() * 4.0) as usize == i;
+        assert_eq!(spots.iter().filter(|&&(x, _)| slot(x)).count(), 1);
+        assert_eq!(spots.iter().filter(|&&(_, y)| slot(y)).count(), 1);
+    }
+
+    let renderer = Renderer::new().bounds(40, 30).corners("-1.5,0.6", "0.1,-0.6")
+        .iterations(100).threads(3);
+    let plain = renderer.render().unwrap();
+    assert_eq!(renderer.clone().jitter(1).render().unwrap(), plain);
+
+    // the same seed gives the same image, on any number of threads and in
+    // tiles, and the interior stays black
+    let jittered = renderer.clone().jitter(8).seed(5);
+    let image = jittered.render().unwrap();
+    assert_ne!(image, plain);
+    assert_eq!(jittered.clone().threads(1).render().unwrap(), image);
+    assert_ne!(jittered.clone().seed(6).render().unwrap(), image);
+    for (rect, tile) in jittered.tiles().unwrap() {
+        assert_eq!(tile, image.tile(rect).to_image().into_pixels());
+    }
+    let black = renderer.clone().corners("-0.2,0.1", "0,-0.1").jitter(8).render().unwrap();
+    assert!(black.pixels().iter().all(|&p| p == 0));
+
+    // near enough to the mean of a fine grid over each pixel
+    let fine = renderer.clone().bounds(160, 120).render().unwrap();
+    let close = (0 .. 40 * 30).filter(|i| {
+        let (x, y) = (i % 40, i / 40);
+        let block: Vec<u8> = (0 .. 16).map(|j| fine[(x * 4 + j % 4, y * 4 + j / 4)]).collect();
+        image[(x, y)].contrast(&u8::mean(&block)) <= 0.1
+    }).count();
+    assert!(close * 10 > 40 * 30 * 9, "{}", close);
+
+    let turned = jittered.clone().rotation(1.0).render().unwrap();
+    assert_ne!(turned, image);
+    assert!(renderer.clone().jitter(0).render().is_err());
+    assert!(jittered.antialias(2).render().is_err());
+}
+
+#[test]
+fn test_renderer_centers() {
+    use std::f64::consts::PI;
+
+    // sampled at pixel centers, a view symmetric about the real axis
+    // renders symmetric, rows mirrored top to bottom, with an even number
+    // of rows as well as an odd one
+    for &height in &[30, 31] {
+        let renderer = Renderer::new().bounds(40, height).corners("-2,1.2", "0.6,-1.2")
+            .iterations(100).threads(2);
+        for image in &[renderer.render().unwrap(), renderer.clone().rotation(PI).render().unwrap(),
+                       renderer.clone().precision(Precision::Fixed(64)).render().unwrap()] {
+            for y in 0 .. height {
+                assert_eq!(image.row(y), image.row(height - 1 - y), "row {} of {}", y, height);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_renderer_bailout() {
+    let renderer = Renderer::new().bounds(40, 30).iterations(200).threads(2);
+    let plain = renderer.clone().escapes().unwrap();
+    let far = renderer.clone().bailout(1e6).escapes().unwrap();
+    let mut later = 0;
+    for (&near, &far) in plain.times.pixels().iter().zip(far.times.pixels()) {
+        // the same points escape, some a few iterations later
+        assert_eq!(near == ::NO_ESCAPE, far == ::NO_ESCAPE);
+        assert!(far >= near && far <= near.saturating_add(8));
+        later += (far > near) as usize;
+    }
+    assert!(later > 0);
+
+    // however the image is cut up
+    for (rect, tile) in renderer.clone().bailout(1e6).tiles().unwrap() {
+        let mut shades = vec![0; rect.2 * rect.3];
+        Gray.colorize(&far.times.tile(rect).to_image().into_pixels(), 200, &mut shades);
+        assert_eq!(tile, shades);
+    }
+
+    for bad in [1.0, f64::NAN, f64::INFINITY, MAX_BAILOUT * 2.0] {
+        assert!(matches!(renderer.clone().bailout(bad).render(),
+                         Err(MandelError::InvalidParams(_))), "{}", bad);
+    }
+}
+
+#[test]
+fn test_renderer_threads() {
+    let view = |top_left: &str, bot_right: &str| {
+        Renderer::new().bounds(150, 70).corners(top_left, bot_right).iterations(300)
+    };
+    let deep = view("-0.7436438870371587,0.1318259042053120",
+                    "-0.7436438870370587,0.1318259042052370");
+    for renderer in [view("-2.2,1.2", "1,-1.2"),
+                     view("-2.2,1.2", "1,-1.2").precision(Precision::F32),
+                     deep.clone().precision(Precision::Fixed(128)),
+                     deep,
+                     view("-1.5,0.6", "0.1,-0.6").rotation(0.5),
+                     view("-1.5,0.6", "0.1,-0.6").antialias(2),
+                     view("-1.5,0.6", "0.1,-0.6").jitter(3).seed(7)]
+    {
+        let expected = renderer.clone().threads(1).render().unwrap();
+        for threads in [2, 3, 7] {
+            let image = renderer.clone().threads(threads).render().unwrap();
+            assert!(image.pixels() == expected.pixels(), "{:?} on {} threads", renderer, threads);
+
+            let mut tiled = vec![0; expected.pixels().len()];
+            for (rect, tile) in renderer.clone().threads(threads).tiles().unwrap() {
+                for (row, pixels) in tile.chunks(rect.2).enumerate() {
+                    let start = (rect.1 + row) * 150 + rect.0;
+                    tiled[start .. start + rect.2].copy_from_slice(pixels);
+                }
+            }
+            assert!(tiled == expected.pixels(), "{:?} in tiles", renderer);
+        }
+    }
+}
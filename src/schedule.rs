@@ -0,0 +1,446 @@
+use crossbeam;
+use error::MandelError;
+use fixed::Fixed;
+use num::Complex;
+use precision::Precision;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use {copy_rect, render_rect};
+
+/// `(x, y, width, height)` of a rectangle of pixels
+type Rect = (usize, usize, usize, usize);
+
+/// Side of the square tiles handed out from the queue
+pub const TILE_SIZE: usize = 32;
+
+/// Tiles, spread over the image, each backend renders in a verification
+/// pass
+pub const VERIFY_TILES: usize = 8;
+
+/// What a backend's arithmetic is held to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Math {
+    /// whatever's fastest, fused multiply-adds and loose rounding included
+    Fast,
+    /// every operation rounded to nearest on its own, never fused, as
+    /// `core` does it, so the backend's escape times match the CPU's
+    Strict,
+}
+
+/// Something tiles of an image can be rendered on, such as the CPU's
+/// threads or, once there is one, a GPU
+pub trait Backend: Sync {
+    /// What to call it in reports
+    fn name(&self) -> &str;
+
+    /// How many tiles it renders at once, each from a thread of its own
+    fn lanes(&self) -> usize;
+
+    /// render(t, r, m) : render the rectangle `rect` of the image into
+    /// `tile`, a row at a time, with arithmetic held to `math`
+    fn render(&self, tile: &mut [u8], rect: Rect, math: Math) -> Result<(), MandelError>;
+}
+
+/// The escape-time kernel on CPU threads, a tile to a thread, as
+/// `render_rect` renders them, which is always `Math::Strict`
+pub struct Cpu<'a> {
+    pub bounds: (usize, usize),
+    pub top_left: Complex<f64>,
+    pub bot_right: Complex<f64>,
+    pub deep: Option<&'a (Complex<Fixed>, Complex<Fixed>)>,
+    pub precision: Precision,
+    pub limit: u32,
+    pub threads: usize,
+}
+
+impl<'a> Backend for Cpu<'a> {
+    fn name(&self) -> &str {
+        "cpu"
+    }
+
+    fn lanes(&self) -> usize {
+        self.threads
+    }
+
+    fn render(&self, tile: &mut [u8], rect: Rect, _: Math) -> Result<(), MandelError> {
+        render_rect(tile, self.bounds, rect, self.top_left, self.bot_right, self.deep,
+                    self.precision, self.limit);
+        Ok(())
+    }
+}
+
+/// How a backend fared in a render
+#[derive(Clone, Debug, PartialEq)]
+pub struct Throughput {
+    pub name: String,
+    pub tiles: usize,
+    pub pixels: usize,
+    /// the time its lanes spent rendering, added up
+    pub busy: Duration,
+}
+
+impl Throughput {
+    /// Pixels a second on one of its lanes, once it has rendered any
+    pub fn rate(&self) -> Option<f64> {
+        let seconds = self.busy.as_secs_f64();
+        if self.pixels > 0 && seconds > 0.0 {
+            Some(self.pixels as f64 / seconds)
+        } else {
+            None
+        }
+    }
+}
+
+/// How far a backend's render strays from a reference's
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deviation {
+    pub name: String,
+    /// the most any pixel is off, in gray levels
+    pub max: u8,
+    /// where that pixel is, `(col, row)`
+    pub worst: (usize, usize),
+    /// how many pixels are off at all, of how many compared
+    pub off: usize,
+    pub compared: usize,
+}
+
+/// tiles(b) : the tiles of the `bounds` image, a row of them at a time
+fn tiles(bounds: (usize, usize)) -> Vec<Rect> {
+    let mut rects = Vec::new();
+    for y in (0 .. bounds.1).step_by(TILE_SIZE) {
+        for x in (0 .. bounds.0).step_by(TILE_SIZE) {
+            rects.push((x, y, TILE_SIZE.min(bounds.0 - x), TILE_SIZE.min(bounds.1 - y)));
+        }
+    }
+    rects
+}
+
+/// verify(bs, r, b, m) : how far each of `backends` strays from
+/// `reference` on `VERIFY_TILES` tiles spread over the `bounds` image,
+/// both held to `math`
+pub fn verify(backends: &[&dyn Backend],
+              reference: &dyn Backend,
+              bounds: (usize, usize),
+              math: Math)
+    -> Result<Vec<Deviation>, MandelError>
+{
+    let rects = tiles(bounds);
+    let step = rects.len().div_ceil(VERIFY_TILES).max(1);
+    let sampled: Vec<Rect> = rects.into_iter().step_by(step).collect();
+
+    let mut expected = Vec::with_capacity(sampled.len());
+    for &rect in &sampled {
+        let mut tile = vec![0; rect.2 * rect.3];
+        reference.render(&mut tile, rect, Math::Strict)?;
+        expected.push(tile);
+    }
+
+    let mut deviations = Vec::with_capacity(backends.len());
+    for backend in backends {
+        let mut deviation = Deviation {
+            name: backend.name().to_string(),
+            max: 0,
+            worst: (0, 0),
+            off: 0,
+            compared: 0,
+        };
+        for (&rect, expected) in sampled.iter().zip(&expected) {
+            let mut tile = vec![0; rect.2 * rect.3];
+            backend.render(&mut tile, rect, math)?;
+            for (i, (&got, &want)) in tile.iter().zip(expected).enumerate() {
+                let off = got.abs_diff(want);
+                if off > 0 {
+                    deviation.off += 1;
+                }
+                if off > deviation.max {
+                    deviation.max = off;
+                    deviation.worst = (rect.0 + i % rect.2, rect.1 + i / rect.2);
+                }
+            }
+            deviation.compared += tile.len();
+        }
+        deviations.push(deviation);
+    }
+    Ok(deviations)
+}
+
+/// leave(s, b, l, t) : whether backend `backend` should leave the rest of
+/// the queue, `left` pixels, to the others, at the rates `stats` has seen,
+/// rather than take a `tile` more
+///
+/// It leaves them only if another backend is faster and, with all of
+/// them going, the queue would be done before its tile was. The fastest
+/// never does, so every tile is taken.
+fn leave(stats: &[Throughput], lanes: &[usize], backend: usize, left: usize, tile: usize)
+    -> bool
+{
+    let mine = match stats[backend].rate() {
+        Some(rate) => rate,
+        None => return false,
+    };
+    let rates = || stats.iter().zip(lanes).filter_map(|(stat, &n)| stat.rate().map(|r| (r, n)));
+    let faster = rates().any(|(rate, _)| rate > mine);
+    let together: f64 = rates().map(|(rate, lanes)| rate * lanes as f64).sum();
+    faster && tile as f64 / mine > left as f64 / together
+}
+
+/// render(p, b, bs) : render the `bounds` image into `pixels` on all of
+/// `backends` at once
+///
+/// Every lane of every backend takes the next tile from one queue as soon
+/// as it's done with its last, so each backend takes tiles in proportion
+/// to how fast it renders them, and none sits idle while another works.
+/// Near the end a slower backend leaves the last tiles to faster ones when
+/// they'd finish them sooner.
+///
+/// Returns how each backend fared, in order, or the first error one of
+/// them returned, once the lanes still rendering have finished.
+pub fn render(pixels: &mut [u8], bounds: (usize, usize), backends: &[&dyn Backend], math: Math)
+    -> Result<Vec<Throughput>, MandelError>
+{
+    assert!(pixels.len() == bounds.0 * bounds.1);
+
+    let rects = tiles(bounds);
+    let lanes: Vec<usize> = backends.iter().map(|backend| backend.lanes()).collect();
+
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let stats = Mutex::new(backends.iter()
+        .map(|backend| Throughput {
+            name: backend.name().to_string(),
+            tiles: 0,
+            pixels: 0,
+            busy: Duration::ZERO,
+        })
+        .collect::<Vec<_>>());
+    let done = Mutex::new(Vec::with_capacity(rects.len()));
+    let error = Mutex::new(None);
+    crossbeam::scope(|spawner| {
+        for (index, &backend) in backends.iter().enumerate() {
+            for _ in 0 .. lanes[index] {
+                let (rects, lanes, next, failed) = (&rects, &lanes, &next, &failed);
+                let (stats, done, error) = (&stats, &done, &error);
+                spawner.spawn(move || loop {
+                    let claimed = next.load(Ordering::SeqCst);
+                    let rect = match rects.get(claimed) {
+                        Some(&rect) if !failed.load(Ordering::SeqCst) => rect,
+                        _ => return,
+                    };
+                    let left = rects[claimed ..].iter().map(|r| r.2 * r.3).sum();
+                    if leave(&stats.lock().unwrap(), lanes, index, left, rect.2 * rect.3) {
+                        return;
+                    }
+                    if next.compare_exchange(claimed, claimed + 1, Ordering::SeqCst,
+                                             Ordering::SeqCst).is_err() {
+                        continue;
+                    }
+
+                    let mut tile = vec![0; rect.2 * rect.3];
+                    let start = Instant::now();
+                    if let Err(e) = backend.render(&mut tile, rect, math) {
+                        failed.store(true, Ordering::SeqCst);
+                        error.lock().unwrap().get_or_insert(e);
+                        return;
+                    }
+                    let mut stats = stats.lock().unwrap();
+                    let stat = &mut stats[index];
+                    stat.tiles += 1;
+                    stat.pixels += tile.len();
+                    stat.busy += start.elapsed();
+                    done.lock().unwrap().push((rect, tile));
+                });
+            }
+        }
+    });
+
+    if let Some(e) = error.into_inner().unwrap() {
+        return Err(e);
+    }
+    for (rect, tile) in done.into_inner().unwrap() {
+        copy_rect(pixels, bounds, rect, &tile);
+    }
+    Ok(stats.into_inner().unwrap())
+}
+
+/// render_strict(p, b, bs, r, t) : `render` held to `Math::Strict`, once a
+/// verification pass has found every one of `backends` within `tolerance`
+/// gray levels of `reference`
+///
+/// Returns how each backend fared and how far it strayed, or, for a
+/// backend that strays too far, an error saying by how much and where.
+pub fn render_strict(pixels: &mut [u8],
+                     bounds: (usize, usize),
+                     backends: &[&dyn Backend],
+                     reference: &dyn Backend,
+                     tolerance: u8)
+    -> Result<(Vec<Throughput>, Vec<Deviation>), MandelError>
+{
+    let deviations = verify(backends, reference, bounds, Math::Strict)?;
+    if let Some(worst) = deviations.iter().find(|deviation| deviation.max > tolerance) {
+        return Err(MandelError::InvalidParams(format!(
+            "{} is off by up to {} gray levels, at {:?}, past the {} allowed",
+            worst.name, worst.max, worst.worst, tolerance)));
+    }
+    let throughput = render(pixels, bounds, backends, Math::Strict)?;
+    Ok((throughput, deviations))
+}
+
+#[cfg(test)]
+struct Slow<'a> {
+    cpu: Cpu<'a>,
+    pause: Duration,
+}
+
+#[cfg(test)]
+impl<'a> Backend for Slow<'a> {
+    fn name(&self) -> &str {
+        "slow"
+    }
+
+    fn lanes(&self) -> usize {
+        1
+    }
+
+    fn render(&self, tile: &mut [u8], rect: Rect, math: Math) -> Result<(), MandelError> {
+        ::std::thread::sleep(self.pause);
+        self.cpu.render(tile, rect, math)
+    }
+}
+
+#[cfg(test)]
+struct Broken;
+
+#[cfg(test)]
+impl Backend for Broken {
+    fn name(&self) -> &str {
+        "broken"
+    }
+
+    fn lanes(&self) -> usize {
+        1
+    }
+
+    fn render(&self, _: &mut [u8], _: Rect, _: Math) -> Result<(), MandelError> {
+        Err(MandelError::InvalidParams("broken".to_string()))
+    }
+}
+
+#[cfg(test)]
+fn cpu<'a>(bounds: (usize, usize), threads: usize) -> Cpu<'a> {
+    Cpu {
+        bounds,
+        top_left: Complex { re: -2.2, im: 1.2 },
+        bot_right: Complex { re: 1.0, im: -1.2 },
+        deep: None,
+        precision: Precision::F64,
+        limit: 100,
+        threads,
+    }
+}
+
+#[test]
+fn test_render() {
+    let bounds = (200, 150);
+    let mut expected = vec![0; bounds.0 * bounds.1];
+    let whole = cpu(bounds, 1);
+    render_rect(&mut expected, bounds, (0, 0, bounds.0, bounds.1), whole.top_left,
+                whole.bot_right, None, Precision::F64, 100);
+
+    let fast = cpu(bounds, 2);
+    let slow = Slow { cpu: cpu(bounds, 1), pause: Duration::from_millis(50) };
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    let stats = render(&mut pixels, bounds, &[&fast, &slow], Math::Fast).unwrap();
+    assert!(pixels == expected);
+
+    assert_eq!((stats[0].name.as_str(), stats[1].name.as_str()), ("cpu", "slow"));
+    assert_eq!(stats[0].tiles + stats[1].tiles, 7 * 5);
+    assert_eq!(stats[0].pixels + stats[1].pixels, bounds.0 * bounds.1);
+    assert!(stats[0].tiles > stats[1].tiles);
+    assert!(stats[0].rate().unwrap() > stats[1].rate().unwrap());
+
+    assert!(render(&mut pixels, bounds, &[&fast, &Broken], Math::Fast).is_err());
+}
+
+#[test]
+fn test_leave() {
+    let stat = |pixels: usize, millis: u64| Throughput {
+        name: String::new(),
+        tiles: 1,
+        pixels,
+        busy: Duration::from_millis(millis),
+    };
+    // 1000 pixels a second against 10000 on each of 2 lanes
+    let stats = [stat(1000, 1000), stat(10000, 1000)];
+    let lanes = [1, 2];
+    // the fast lanes would take 1/21 s for what's left, the slow one 1 s
+    assert!(leave(&stats, &lanes, 0, 1000, 1000));
+    assert!(!leave(&stats, &lanes, 1, 1000, 1000));
+    // with plenty left, it pulls its weight
+    assert!(!leave(&stats, &lanes, 0, 100_000, 1000));
+    // nor does one that hasn't been timed yet
+    assert!(!leave(&[stat(0, 0), stat(10000, 1000)], &lanes, 0, 1000, 1000));
+}
+
+/// As a GPU rounding loosely unless told not to: pixels off by a few
+/// levels in `Math::Fast`
+#[cfg(test)]
+struct Loose<'a> {
+    cpu: Cpu<'a>,
+    /// how far off its pixels are when strict, too
+    always: u8,
+}
+
+#[cfg(test)]
+impl<'a> Backend for Loose<'a> {
+    fn name(&self) -> &str {
+        "loose"
+    }
+
+    fn lanes(&self) -> usize {
+        1
+    }
+
+    fn render(&self, tile: &mut [u8], rect: Rect, math: Math) -> Result<(), MandelError> {
+        self.cpu.render(tile, rect, math)?;
+        let off = if math == Math::Fast { 3 } else { self.always };
+        // only the odd pixels, so some are still right
+        for pixel in tile.iter_mut().skip(1).step_by(2) {
+            *pixel = pixel.saturating_add(off);
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_verify() {
+    let bounds = (200, 150);
+    let reference = cpu(bounds, 1);
+    let loose = Loose { cpu: cpu(bounds, 1), always: 0 };
+
+    let fast = verify(&[&reference, &loose], &reference, bounds, Math::Fast).unwrap();
+    assert_eq!(fast[0].max, 0);
+    assert_eq!(fast[0].off, 0);
+    assert_eq!(fast[1].name, "loose");
+    assert_eq!(fast[1].max, 3);
+    assert!(fast[1].off > 0 && fast[1].off < fast[1].compared);
+    assert_eq!(fast[1].worst.0 % 2, 1);
+    assert!(fast[1].compared >= VERIFY_TILES * TILE_SIZE * TILE_SIZE / 2);
+
+    let strict = verify(&[&loose], &reference, bounds, Math::Strict).unwrap();
+    assert_eq!(strict[0].max, 0);
+
+    // strict renders go ahead within the tolerance, and come out right
+    let mut expected = vec![0; bounds.0 * bounds.1];
+    render(&mut expected, bounds, &[&reference], Math::Fast).unwrap();
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    let (stats, deviations) = render_strict(&mut pixels, bounds, &[&reference, &loose],
+                                            &reference, 0).unwrap();
+    assert!(pixels == expected);
+    assert_eq!((stats.len(), deviations.len()), (2, 2));
+
+    let off = Loose { cpu: cpu(bounds, 1), always: 2 };
+    assert!(render_strict(&mut pixels, bounds, &[&off], &reference, 2).is_ok());
+    let e = render_strict(&mut pixels, bounds, &[&off], &reference, 1).unwrap_err();
+    assert!(e.to_string().starts_with("loose is off by up to 2 gray levels"), "{}", e);
+}
@@ -0,0 +1,873 @@
+use cancel::CancelToken;
+use num::Complex;
+use output;
+use precision::Precision;
+use renderer::Renderer;
+use serde_json::{self, json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::{Message, Role, WebSocket};
+use {auto_iterations, render_bands, scale_bounds};
+
+/// Side of the square tiles served, as web maps expect
+pub const TILE_SIZE: usize = 256;
+
+/// Deepest zoom level served; corners of tiles below this are still
+/// exact in f64, so deeper tiles escalate precision like any other
+const MAX_ZOOM: u32 = 48;
+
+/// Tiles kept in memory, at a few kilobytes of PNG each
+const CACHE_TILES: usize = 4096;
+
+/// Renders asked for through `/render` at once; more are turned away
+/// rather than queued, since each already uses every thread given
+const MAX_RENDERS: usize = 2;
+
+/// Connections served at once; more are closed unanswered
+const MAX_CONNECTIONS: usize = 256;
+
+/// Jobs kept around for their image to be fetched; the oldest finished
+/// one is forgotten to make room for another
+const MAX_JOBS: usize = 64;
+
+/// Width of the previews sent while a job renders, or height if they're
+/// taller than wide
+const PREVIEW_WIDTH: usize = 128;
+
+/// Largest image `/render` will make, and the largest request it reads
+pub const MAX_RENDER_PIXELS: usize = 16 << 20;
+const MAX_BODY: usize = 64 << 10;
+
+/// Most bytes and lines of request line and headers read before a
+/// request is turned away
+const MAX_HEADER_BYTES: usize = 16 << 10;
+const MAX_HEADERS: usize = 64;
+
+/// How long a connection may stall on a read or write before it's dropped
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How often a job's watcher checks it's still connected between
+/// strips, and how long it waits for the client to close its side
+const WATCH_POLL: Duration = Duration::from_secs(1);
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Top left corner and side of the square covered by the tile at zoom 0
+const WORLD_TOP_LEFT: Complex<f64> = Complex { re: -2.5, im: 2.0 };
+const WORLD_SIZE: f64 = 4.0;
+
+/// Page showing the tiles in Leaflet, served at `/`
+const INDEX: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>mandelbrot</title>
+<link rel="stylesheet" href="https://unpkg.com/leaflet@1.9.4/dist/leaflet.css">
+<script src="https://unpkg.com/leaflet@1.9.4/dist/leaflet.js"></script>
+<style>html, body, #map { height: 100%; margin: 0; }</style>
+</head>
+<body>
+<div id="map"></div>
+<script>
+const map = L.map('map', { crs: L.CRS.Simple }).setView([-128, 128], 1);
+L.tileLayer('/{z}/{x}/{y}.png', { maxZoom: 48, noWrap: true, bounds: [[0, 0], [-256, 256]] })
+    .addTo(map);
+</script>
+</body>
+</html>
+"#;
+
+/// A tile by zoom level and column and row within it
+type Tile = (u32, u64, u64);
+
+/// parse_tile(p) : the tile asked for by the path `/{z}/{x}/{y}.png`
+fn parse_tile(path: &str) -> Option<Tile> {
+    let mut parts = path.strip_prefix('/')?.strip_suffix(".png")?.split('/');
+    let zoom: u32 = parts.next()?.parse().ok()?;
+    let x: u64 = parts.next()?.parse().ok()?;
+    let y: u64 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() || zoom > MAX_ZOOM || x >> zoom != 0 || y >> zoom != 0 {
+        return None;
+    }
+    Some((zoom, x, y))
+}
+
+/// tile_corners(t) : the top left and bottom right corners of tile `t`
+fn tile_corners(tile: Tile) -> (Complex<f64>, Complex<f64>) {
+    let (zoom, x, y) = tile;
+    let side = WORLD_SIZE / (1u64 << zoom) as f64;
+    let top_left = Complex {
+        re: WORLD_TOP_LEFT.re + x as f64 * side,
+        im: WORLD_TOP_LEFT.im - y as f64 * side,
+    };
+    (top_left, Complex { re: top_left.re + side, im: top_left.im - side })
+}
+
+/// Least recently used tiles, dropped once there are more than `capacity`
+struct TileCache {
+    capacity: usize,
+    tiles: HashMap<Tile, (Arc<Vec<u8>>, u64)>,
+    clock: u64,
+}
+
+impl TileCache {
+    fn new(capacity: usize) -> TileCache {
+        TileCache { capacity, tiles: HashMap::new(), clock: 0 }
+    }
+
+    fn get(&mut self, tile: Tile) -> Option<Arc<Vec<u8>>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.tiles.get_mut(&tile).map(|entry| {
+            entry.1 = clock;
+            entry.0.clone()
+        })
+    }
+
+    fn insert(&mut self, tile: Tile, png: Arc<Vec<u8>>) {
+        if self.tiles.len() >= self.capacity && !self.tiles.contains_key(&tile) {
+            // a linear scan, which is nothing next to rendering a tile
+            let oldest = self.tiles.iter()
+                .min_by_key(|&(_, &(_, used))| used)
+                .map(|(&tile, _)| tile);
+            if let Some(oldest) = oldest {
+                self.tiles.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.tiles.insert(tile, (png, self.clock));
+    }
+}
+
+/// parse_render(b) : the render asked for by the JSON object `b`, or
+/// what's wrong with it
+///
+/// The object is as `Renderer::to_json` writes it, except that `width`,
+/// `height`, `top_left` and `bot_right` are required.
+fn parse_render(body: &[u8]) -> Result<Renderer, String> {
+    let json: Value = serde_json::from_slice(body)
+        .map_err(|e| format!("body isn't JSON: {}", e))?;
+    let fields = json.as_object().ok_or("body must be a JSON object")?;
+    if let Some(missing) = ["width", "height", "top_left", "bot_right"].iter()
+        .find(|&&name| !fields.contains_key(name))
+    {
+        return Err(format!("missing '{}'", missing));
+    }
+    let renderer = Renderer::from_json(&json).map_err(|e| e.to_string())?;
+    let bounds = renderer.image_bounds();
+    if bounds.0.saturating_mul(bounds.1) > MAX_RENDER_PIXELS {
+        return Err(format!("at most {} pixels can be rendered at once",
+                           MAX_RENDER_PIXELS));
+    }
+    Ok(renderer)
+}
+
+/// How far a job started by `POST /jobs` has got
+///
+/// The image is rendered in strips of rows, which count as its tiles.
+#[derive(Clone, Default)]
+struct Progress {
+    tiles_done: usize,
+    tiles: usize,
+    /// small PNG of the image so far
+    preview: Option<Arc<Vec<u8>>>,
+    /// the finished image, or why there isn't one
+    result: Option<Result<Arc<Vec<u8>>, String>>,
+}
+
+impl Progress {
+    /// The progress as sent to whoever is watching the job
+    fn event(&self) -> Value {
+        let mut event = json!({
+            "tiles_done": self.tiles_done,
+            "tiles": self.tiles,
+            "percent": 100.0 * self.tiles_done as f64 / self.tiles.max(1) as f64,
+        });
+        match self.result {
+            None => {}
+            Some(Ok(_)) => event["done"] = json!(true),
+            Some(Err(ref e)) => event["error"] = json!(e),
+        }
+        event
+    }
+}
+
+/// A job's progress, and a way to wait for it to change
+#[derive(Default)]
+struct Job {
+    progress: Mutex<Progress>,
+    changed: Condvar,
+    /// cancelled once the last of its watchers has gone
+    cancel: CancelToken,
+    watchers: AtomicUsize,
+}
+
+impl Job {
+    fn update<F: FnOnce(&mut Progress)>(&self, f: F) {
+        f(&mut self.progress.lock().unwrap());
+        self.changed.notify_all();
+    }
+
+    /// Count a watcher of the job until the `Watcher` is dropped
+    fn watch(&self) -> Watcher<'_> {
+        self.watchers.fetch_add(1, Ordering::SeqCst);
+        Watcher(self)
+    }
+}
+
+/// One of the clients watching a job; a job nobody was watching runs to
+/// the end to be fetched, but one whose every watcher has gone is
+/// cancelled
+struct Watcher<'a>(&'a Job);
+
+impl<'a> Drop for Watcher<'a> {
+    fn drop(&mut self) {
+        let job = self.0;
+        if job.watchers.fetch_sub(1, Ordering::SeqCst) == 1
+            && job.progress.lock().unwrap().result.is_none()
+        {
+            job.cancel.cancel();
+        }
+    }
+}
+
+/// Jobs by id, ids counting up from 0
+#[derive(Default)]
+struct Jobs {
+    next: u64,
+    jobs: BTreeMap<u64, Arc<Job>>,
+}
+
+/// How to render tiles, shared between connections
+struct Server {
+    precision: Precision,
+    /// fixed iteration limit, or `None` to pick one per zoom level
+    limit: Option<u32>,
+    threads: usize,
+    cache: Mutex<TileCache>,
+    /// `/render` requests and jobs being worked on
+    renders: Arc<AtomicUsize>,
+    jobs: Mutex<Jobs>,
+}
+
+/// Holds one of a limited number of places, counted in the shared
+/// counter, until dropped
+pub struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    /// take(c, m) : a place counted in `c`, if fewer than `max` are taken
+    pub fn take(counter: &Arc<AtomicUsize>, max: usize) -> Option<Slot> {
+        let taken = counter.fetch_add(1, Ordering::SeqCst);
+        let slot = Slot(counter.clone());
+        if taken < max { Some(slot) } else { None }
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// thumbnail(p, b) : `pixels` shrunk to at most `PREVIEW_WIDTH` along
+/// the longer side, as a PNG
+fn thumbnail(pixels: &[u8], bounds: (usize, usize)) -> io::Result<Vec<u8>> {
+    let (width, height) = scale_bounds(bounds, PREVIEW_WIDTH.min(bounds.0.max(bounds.1)));
+    let small: Vec<u8> = (0 .. height)
+        .flat_map(|row| (0 .. width).map(move |col| {
+            pixels[row * bounds.1 / height * bounds.0 + col * bounds.0 / width]
+        }))
+        .collect();
+    Ok(output::encode(&small, (width, height))?)
+}
+
+impl Server {
+    /// tile(t) : the PNG of tile `t`, or `None` if it isn't cached and
+    /// `MAX_RENDERS` are already under way
+    fn tile(&self, tile: Tile) -> io::Result<Option<Arc<Vec<u8>>>> {
+        if let Some(png) = self.cache.lock().unwrap().get(tile) {
+            return Ok(Some(png));
+        }
+        let _slot = match self.render_slot() {
+            Some(slot) => slot,
+            None => return Ok(None),
+        };
+        // rendered without holding the lock, so two requests for the same
+        // new tile may both render it
+        let (top_left, bot_right) = tile_corners(tile);
+        let limit = self.limit.unwrap_or_else(|| auto_iterations(top_left, bot_right));
+        let bounds = (TILE_SIZE, TILE_SIZE);
+        let mut pixels = vec![0; TILE_SIZE * TILE_SIZE];
+        render_bands(&mut pixels, bounds, 0, top_left, bot_right,
+                     None, self.precision, limit, self.threads);
+        let png = Arc::new(output::encode(&pixels, bounds)?);
+        self.cache.lock().unwrap().insert(tile, png.clone());
+        Ok(Some(png))
+    }
+
+    /// A place to render in, if fewer than `MAX_RENDERS` are under way
+    fn render_slot(&self) -> Option<Slot> {
+        Slot::take(&self.renders, MAX_RENDERS)
+    }
+
+    /// Render `request` strip by strip, calling `on_strip` with how many
+    /// strips of how many are done and the pixels so far after each
+    fn render<F>(&self, request: &Renderer, mut on_strip: F) -> io::Result<Vec<u8>>
+        where F: FnMut(usize, usize, &[u8]) -> io::Result<()>
+    {
+        let image = request.clone().threads(self.threads).render_strips(|done, strips, pixels| {
+            Ok(on_strip(done, strips, pixels)?)
+        })?;
+        Ok(output::encode(image.pixels(), image.bounds())?)
+    }
+
+    /// Start rendering `request` in the background in `slot`, returning
+    /// the id of its job
+    fn start_job(self: &Arc<Server>, slot: Slot, request: Renderer) -> u64 {
+        let job = Arc::new(Job::default());
+        let id = {
+            let mut jobs = self.jobs.lock().unwrap();
+            if jobs.jobs.len() >= MAX_JOBS {
+                let finished = jobs.jobs.iter()
+                    .find(|(_, job)| job.progress.lock().unwrap().result.is_some())
+                    .map(|(&id, _)| id);
+                if let Some(id) = finished {
+                    jobs.jobs.remove(&id);
+                }
+            }
+            let id = jobs.next;
+            jobs.next += 1;
+            jobs.jobs.insert(id, job.clone());
+            id
+        };
+
+        let server = self.clone();
+        std::thread::spawn(move || {
+            let _slot = slot;
+            let request = request.cancel_token(&job.cancel);
+            let bounds = request.image_bounds();
+            let result = server.render(&request, |tiles_done, tiles, pixels| {
+                // pad the rows still to come so the preview keeps its shape
+                let mut so_far = pixels.to_vec();
+                so_far.resize(bounds.0 * bounds.1, 0);
+                let preview = Arc::new(thumbnail(&so_far, bounds)?);
+                job.update(|progress| {
+                    progress.tiles_done = tiles_done;
+                    progress.tiles = tiles;
+                    progress.preview = Some(preview);
+                });
+                Ok(())
+            });
+            job.update(|progress| {
+                progress.result = Some(result.map(Arc::new).map_err(|e| e.to_string()));
+            });
+        });
+        id
+    }
+
+    fn job(&self, id: u64) -> Option<Arc<Job>> {
+        self.jobs.lock().unwrap().jobs.get(&id).cloned()
+    }
+}
+
+/// Serve XYZ tiles of the set at `/{z}/{x}/{y}.png` and whole images at
+/// `POST /render` until the listener fails, each rendered on `threads`
+/// threads
+///
+/// Zoom level 0 is a single tile covering `WORLD_SIZE` square around the
+/// whole set, and each level halves the side of a tile. `limit` fixes the
+/// iteration limit for tiles, otherwise it grows with the zoom like
+/// `--iterations auto`. See `parse_render` for what `/render` takes.
+/// Tiles not yet in the cache count against `MAX_RENDERS` like any other
+/// render, and are refused while that many are under way.
+///
+/// `POST /jobs` takes the same as `/render` but answers at once with the
+/// id of a job rendering in the background. Its progress is streamed
+/// over a WebSocket at `/jobs/{id}/progress`, as a JSON event after each
+/// strip followed by a PNG preview in a binary message, and the image
+/// can be fetched from `/jobs/{id}` once done. A job is cancelled if
+/// everyone watching it hangs up before it's done, and so is a `/render`
+/// whose client does.
+pub fn serve(listener: TcpListener, precision: Precision, limit: Option<u32>, threads: usize)
+    -> io::Result<()>
+{
+    let server = Arc::new(Server {
+        precision,
+        limit,
+        threads,
+        cache: Mutex::new(TileCache::new(CACHE_TILES)),
+        renders: Arc::new(AtomicUsize::new(0)),
+        jobs: Mutex::new(Jobs::default()),
+    });
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let connection = match Slot::take(&connections, MAX_CONNECTIONS) {
+            Some(connection) => connection,
+            None => continue,
+        };
+        let server = server.clone();
+        std::thread::spawn(move || {
+            let _connection = connection;
+            if let Err(e) = handle(stream, &server) {
+                eprintln!("serve: connection dropped: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// A request as read off the wire
+struct Request {
+    method: String,
+    path: String,
+    /// by lowercase name
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// A status and the reason to turn a request away with
+type Refusal = (&'static str, String);
+
+/// read_request(r) : the next request from `r`, or why it's refused
+///
+/// The request line and headers are read `MAX_HEADER_BYTES` at most, so
+/// a client can't make the server buffer without end.
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Result<Request, Refusal>> {
+    let too_big = || Ok(Err(("431 Request Header Fields Too Large",
+                             format!("headers are limited to {} bytes and {} lines",
+                                     MAX_HEADER_BYTES, MAX_HEADERS))));
+    let mut head = reader.by_ref().take(MAX_HEADER_BYTES as u64);
+    let mut line = String::new();
+    head.read_line(&mut line)?;
+    if !line.ends_with('\n') && head.limit() == 0 {
+        return too_big();
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (method, path) = match words[..] {
+        [method, path, _] => (method.to_string(), path.to_string()),
+        _ => return Ok(Err(("400 Bad Request", "bad request line".to_string()))),
+    };
+
+    let mut headers = HashMap::new();
+    let mut header = String::new();
+    let mut lines = 0;
+    while head.read_line(&mut header)? > 0 && !header.trim_end().is_empty() {
+        lines += 1;
+        if lines > MAX_HEADERS || !header.ends_with('\n') && head.limit() == 0 {
+            return too_big();
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+        header.clear();
+    }
+
+    let length = match headers.get("content-length").map(|n| n.parse()) {
+        None => 0,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => return Ok(Err(("400 Bad Request", "bad Content-Length".to_string()))),
+    };
+    if length > MAX_BODY {
+        return Ok(Err(("413 Payload Too Large",
+                       format!("requests are limited to {} bytes", MAX_BODY))));
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Ok(Request { method, path, headers, body }))
+}
+
+/// Answer one request, closing the connection after
+fn handle(stream: TcpStream, server: &Arc<Server>) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let request = read_request(&mut BufReader::new(stream.try_clone()?))?;
+    let mut writer = stream;
+    let request = match request {
+        Ok(request) => request,
+        Err((status, reason)) => return respond_error(&mut writer, status, &reason),
+    };
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => respond(&mut writer, "200 OK", "text/html", INDEX.as_bytes()),
+        ("POST", "/render") | ("POST", "/jobs") => {
+            // taken before the body is looked at, since planning a deep
+            // render can take a while itself
+            let slot = match server.render_slot() {
+                Some(slot) => slot,
+                None => return respond_error(&mut writer, "503 Service Unavailable",
+                                             "too many renders under way, try again later"),
+            };
+            let render = match parse_render(&request.body) {
+                Ok(render) => render,
+                Err(reason) => return respond_error(&mut writer, "400 Bad Request", &reason),
+            };
+            if request.path == "/jobs" {
+                let id = server.start_job(slot, render);
+                let body = json!({
+                    "id": id,
+                    "progress": format!("/jobs/{}/progress", id),
+                    "image": format!("/jobs/{}", id),
+                });
+                return respond(&mut writer, "202 Accepted", "application/json",
+                               format!("{}\n", body).as_bytes());
+            }
+            // stop rendering for a client that's gone
+            let png = server.render(&render, |_, _, _| {
+                if hung_up(&writer) {
+                    return Err(io::Error::other("client hung up"));
+                }
+                Ok(())
+            })?;
+            respond(&mut writer, "200 OK", "image/png", &png)
+        }
+        (_, "/render") | (_, "/jobs") => {
+            respond_error(&mut writer, "405 Method Not Allowed", "renders take POST")
+        }
+        ("GET", path) if path.starts_with("/jobs/") => {
+            let rest = &path["/jobs/".len() ..];
+            let (id, watch) = match rest.strip_suffix("/progress") {
+                Some(id) => (id, true),
+                None => (rest, false),
+            };
+            let job = match id.parse().ok().and_then(|id| server.job(id)) {
+                Some(job) => job,
+                None => return respond_error(&mut writer, "404 Not Found", "no such job"),
+            };
+            if watch {
+                return match request.headers.get("sec-websocket-key") {
+                    Some(key) => watch_job(writer, key, &job),
+                    None => respond_error(&mut writer, "400 Bad Request",
+                                          "progress is sent over a WebSocket"),
+                };
+            }
+            let progress = job.progress.lock().unwrap().clone();
+            match progress.result {
+                Some(Ok(ref png)) => respond(&mut writer, "200 OK", "image/png", png),
+                Some(Err(ref e)) => respond_error(&mut writer, "500 Internal Server Error", e),
+                None => respond(&mut writer, "202 Accepted", "application/json",
+                                format!("{}\n", progress.event()).as_bytes()),
+            }
+        }
+        ("GET", path) => match parse_tile(path) {
+            Some(tile) => match server.tile(tile)? {
+                Some(png) => respond(&mut writer, "200 OK", "image/png", &png),
+                None => respond_error(&mut writer, "503 Service Unavailable",
+                                      "too many renders under way, try again later"),
+            },
+            None => respond_error(&mut writer, "404 Not Found", "no such tile"),
+        },
+        _ => respond_error(&mut writer, "405 Method Not Allowed", "tiles take GET"),
+    }
+}
+
+fn respond(writer: &mut TcpStream, status: &str, content_type: &str, body: &[u8])
+    -> io::Result<()>
+{
+    write!(writer, "HTTP/1.1 {}\r\n\
+                    Content-Type: {}\r\n\
+                    Content-Length: {}\r\n\
+                    Access-Control-Allow-Origin: *\r\n\
+                    Connection: close\r\n\r\n",
+           status, content_type, body.len())?;
+    writer.write_all(body)
+}
+
+/// hung_up(s) : whether the client has closed its side of `stream`
+fn hung_up(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let closed = matches!(stream.peek(&mut [0]), Ok(0));
+    let _ = stream.set_nonblocking(false);
+    closed
+}
+
+/// Take the connection over as a WebSocket, sending `job`'s progress
+/// each time it changes until it's done
+///
+/// The job is cancelled if this was its last watcher and the client goes
+/// before the job is done.
+fn watch_job(mut stream: TcpStream, key: &str, job: &Job) -> io::Result<()> {
+    let _watcher = job.watch();
+    write!(stream, "HTTP/1.1 101 Switching Protocols\r\n\
+                    Upgrade: websocket\r\n\
+                    Connection: Upgrade\r\n\
+                    Sec-WebSocket-Accept: {}\r\n\r\n",
+           derive_accept_key(key.as_bytes()))?;
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+    let mut sent = None;
+    loop {
+        let progress = {
+            let mut progress = job.progress.lock().unwrap();
+            // wait for the first strip, then for each after
+            while (progress.tiles == 0 || sent == Some(progress.tiles_done))
+                && progress.result.is_none()
+            {
+                progress = job.changed.wait_timeout(progress, WATCH_POLL).unwrap().0;
+                if hung_up(socket.get_ref()) {
+                    return Err(io::Error::new(io::ErrorKind::ConnectionAborted,
+                                              "watcher hung up"));
+                }
+            }
+            progress.clone()
+        };
+        socket.send(Message::Text(progress.event().to_string())).map_err(io::Error::other)?;
+        if let Some(preview) = progress.preview {
+            socket.send(Message::Binary(preview.to_vec())).map_err(io::Error::other)?;
+        }
+        if progress.result.is_some() {
+            break;
+        }
+        sent = Some(progress.tiles_done);
+    }
+    socket.close(None).map_err(io::Error::other)?;
+    // wait a while for the client to close its side
+    socket.get_ref().set_read_timeout(Some(CLOSE_TIMEOUT))?;
+    while socket.read().is_ok() {}
+    Ok(())
+}
+
+/// Answer with `status` and `{"error": reason}`
+fn respond_error(writer: &mut TcpStream, status: &str, reason: &str) -> io::Result<()> {
+    let body = serde_json::json!({ "error": reason }).to_string() + "\n";
+    respond(writer, status, "application/json", body.as_bytes())
+}
+
+#[test]
+fn test_parse_tile() {
+    assert_eq!(parse_tile("/0/0/0.png"), Some((0, 0, 0)));
+    assert_eq!(parse_tile("/3/7/2.png"), Some((3, 7, 2)));
+    assert_eq!(parse_tile("/3/8/2.png"), None);
+    assert_eq!(parse_tile("/3/7/2"), None);
+    assert_eq!(parse_tile("/3/7/2/1.png"), None);
+    assert_eq!(parse_tile("/49/0/0.png"), None);
+
+    assert_eq!(tile_corners((0, 0, 0)),
+               (Complex { re: -2.5, im: 2.0 }, Complex { re: 1.5, im: -2.0 }));
+    assert_eq!(tile_corners((2, 1, 3)),
+               (Complex { re: -1.5, im: -1.0 }, Complex { re: -0.5, im: -2.0 }));
+}
+
+#[test]
+fn test_cache_drops_least_recently_used() {
+    let mut cache = TileCache::new(2);
+    let png = Arc::new(vec![0]);
+    cache.insert((0, 0, 0), png.clone());
+    cache.insert((1, 0, 0), png.clone());
+    assert!(cache.get((0, 0, 0)).is_some());
+    cache.insert((1, 1, 0), png);
+    assert!(cache.get((0, 0, 0)).is_some());
+    assert!(cache.get((1, 0, 0)).is_none());
+    assert!(cache.get((1, 1, 0)).is_some());
+}
+
+#[test]
+fn test_parse_render() {
+    let request = parse_render(br#"{"width": 300, "height": 200,
+                                     "top_left": "-1.2,0.35", "bot_right": "-1,0.2",
+                                     "iterations": 500, "precision": "f32"}"#);
+    assert_eq!(request, Ok(Renderer::new().bounds(300, 200).corners("-1.2,0.35", "-1,0.2")
+                               .iterations(500).precision(Precision::F32)));
+    let request = parse_render(br#"{"width": 3, "height": 2, "top_left": "-2,1",
+                                     "bot_right": "1,-1", "iterations": "auto"}"#);
+    assert_eq!(request, Ok(Renderer::new().bounds(3, 2).corners("-2,1", "1,-1")));
+
+    let view = r#""width": 3, "height": 2, "top_left": "-2,1", "bot_right": "1,-1""#;
+    assert!(parse_render(format!("{{{}}}", view).as_bytes()).is_ok());
+    for bad in ["[1, 2]".to_string(),
+                r#"{"width": 3, "height": 2, "top_left": "-2,1"}"#.to_string(),
+                format!("{{{}}}", view.replace("3", "0")),
+                format!("{{{}}}", view.replace("3", "100000").replace(" 2,", " 100000,")),
+                format!("{{{}}}", view.replace("-2,1", "1,-2")),
+                format!(r#"{{{}, "colour": 1}}"#, view),
+                format!(r#"{{{}, "iterations": -5}}"#, view),
+                format!(r#"{{{}, "precision": "f16"}}"#, view)] {
+        assert!(parse_render(bad.as_bytes()).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn test_read_request() {
+    let read = |bytes: &[u8]| read_request(&mut io::Cursor::new(bytes.to_vec())).unwrap();
+    let request = read(b"POST /render HTTP/1.1\r\nContent-Length: 2\r\nX-A: b\r\n\r\n{}").unwrap();
+    assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/render"));
+    assert_eq!((request.headers["x-a"].as_str(), &request.body[..]), ("b", &b"{}"[..]));
+
+    // an endless request line, a header too long or too many are refused
+    let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEADER_BYTES));
+    assert_eq!(read(long.as_bytes()).err().unwrap().0, "431 Request Header Fields Too Large");
+    let long = format!("GET / HTTP/1.1\r\nX-A: {}\r\n\r\n", "a".repeat(MAX_HEADER_BYTES));
+    assert_eq!(read(long.as_bytes()).err().unwrap().0, "431 Request Header Fields Too Large");
+    let many = format!("GET / HTTP/1.1\r\n{}\r\n", "X-A: b\r\n".repeat(MAX_HEADERS + 1));
+    assert_eq!(read(many.as_bytes()).err().unwrap().0, "431 Request Header Fields Too Large");
+    let enough = format!("GET / HTTP/1.1\r\n{}\r\n", "X-A: b\r\n".repeat(MAX_HEADERS));
+    assert!(read(enough.as_bytes()).is_ok());
+}
+
+#[test]
+fn test_render_slots_are_limited() {
+    let server = Server {
+        precision: Precision::F64,
+        limit: None,
+        threads: 1,
+        cache: Mutex::new(TileCache::new(1)),
+        renders: Arc::new(AtomicUsize::new(0)),
+        jobs: Mutex::new(Jobs::default()),
+    };
+    assert!(server.tile((0, 0, 0)).unwrap().is_some());
+    let slots: Vec<_> = (0 .. MAX_RENDERS).map(|_| server.render_slot().unwrap()).collect();
+    assert!(server.render_slot().is_none());
+    // tiles in the cache are still served, but new ones wait their turn
+    assert!(server.tile((0, 0, 0)).unwrap().is_some());
+    assert!(server.tile((1, 0, 0)).unwrap().is_none());
+    drop(slots);
+    assert!(server.render_slot().is_some());
+    assert!(server.tile((1, 0, 0)).unwrap().is_some());
+
+    // connections are counted the same way
+    let connections = Arc::new(AtomicUsize::new(0));
+    let first = Slot::take(&connections, 1);
+    assert!(first.is_some() && Slot::take(&connections, 1).is_none());
+    drop(first);
+    assert!(Slot::take(&connections, 1).is_some());
+}
+
+#[test]
+fn test_job_cancelled_when_watchers_go() {
+    let job = Job::default();
+    let first = job.watch();
+    let second = job.watch();
+    drop(first);
+    assert!(!job.cancel.is_cancelled());
+    drop(second);
+    assert!(job.cancel.is_cancelled());
+
+    // one that's done is left alone
+    let job = Job::default();
+    let watcher = job.watch();
+    job.update(|progress| progress.result = Some(Ok(Arc::new(Vec::new()))));
+    drop(watcher);
+    assert!(!job.cancel.is_cancelled());
+}
+
+#[test]
+fn test_hung_up() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    assert!(!hung_up(&server));
+    drop(client);
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(hung_up(&server));
+}
+
+#[test]
+fn test_serve() {
+    use std::io::Read;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || serve(listener, Precision::F64, Some(100), 2));
+
+    let send = |method: &str, path: &str, body: &str| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+               method, path, body.len(), body).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        response
+    };
+    let get = |path: &str| send("GET", path, "");
+
+    let response = get("/1/0/1.png");
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    let png = &response[split + 4 ..];
+
+    let (top_left, bot_right) = tile_corners((1, 0, 1));
+    let bounds = (TILE_SIZE, TILE_SIZE);
+    let mut pixels = vec![0; TILE_SIZE * TILE_SIZE];
+    render_bands(&mut pixels, bounds, 0, top_left, bot_right, None, Precision::F64, 100, 1);
+    assert_eq!(png, &output::encode(&pixels, bounds).unwrap()[..]);
+
+    assert!(get("/1/2/0.png").starts_with(b"HTTP/1.1 404"));
+
+    let response = send("POST", "/render", r#"{"width": 30, "height": 20, "iterations": 100,
+                                               "top_left": "-1.2,0.35", "bot_right": "-1,0.2"}"#);
+    assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let mut pixels = vec![0; 30 * 20];
+    render_bands(&mut pixels, (30, 20), 0, Complex { re: -1.2, im: 0.35 },
+                 Complex { re: -1.0, im: 0.2 }, None, Precision::F64, 100, 1);
+    assert_eq!(&response[split + 4 ..], &output::encode(&pixels, (30, 20)).unwrap()[..]);
+
+    let response = send("POST", "/render", r#"{"width": 30}"#);
+    assert!(response.starts_with(b"HTTP/1.1 400"));
+    assert!(response.ends_with(b"{\"error\":\"missing 'height'\"}\n"));
+    assert!(get("/render").starts_with(b"HTTP/1.1 405"));
+}
+
+#[test]
+fn test_job_progress() {
+    use std::io::Read;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || serve(listener, Precision::F64, None, 1));
+
+    let send = |method: &str, path: &str, body: &str| {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+               method, path, body.len(), body).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let split = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        (String::from_utf8_lossy(&response[.. split]).into_owned(),
+         response[split + 4 ..].to_vec())
+    };
+
+    // three strips of 64 rows on one thread
+    let (head, body) = send("POST", "/jobs", r#"{"width": 40, "height": 150, "iterations": 100,
+                                                 "top_left": "-1.2,0.35", "bot_right": "-1,0.2"}"#);
+    assert!(head.starts_with("HTTP/1.1 202"));
+    let started: Value = serde_json::from_slice(&body).unwrap();
+    let progress = started["progress"].as_str().unwrap();
+
+    let stream = TcpStream::connect(addr).unwrap();
+    let (mut socket, _) = tungstenite::client(format!("ws://{}{}", addr, progress), stream)
+        .unwrap();
+    let mut events = Vec::new();
+    let mut previews = 0;
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => events.push(serde_json::from_str::<Value>(&text).unwrap()),
+            Ok(Message::Binary(png)) => {
+                assert!(png.starts_with(b"\x89PNG"));
+                previews += 1;
+            }
+            Ok(_) => {}
+            Err(_) => break,
+        }
+    }
+    let last = events.last().unwrap();
+    assert_eq!((last["tiles_done"].as_u64(), last["tiles"].as_u64()), (Some(3), Some(3)));
+    assert_eq!(last["done"], json!(true));
+    assert_eq!(previews, events.len());
+
+    let (head, png) = send("GET", started["image"].as_str().unwrap(), "");
+    assert!(head.starts_with("HTTP/1.1 200"));
+    let mut pixels = vec![0; 40 * 150];
+    render_bands(&mut pixels, (40, 150), 0, Complex { re: -1.2, im: 0.35 },
+                 Complex { re: -1.0, im: 0.2 }, None, Precision::F64, 100, 1);
+    assert_eq!(png, output::encode(&pixels, (40, 150)).unwrap());
+
+    assert!(send("GET", "/jobs/99", "").0.starts_with("HTTP/1.1 404"));
+    assert!(send("GET", progress, "").0.starts_with("HTTP/1.1 400"));
+}
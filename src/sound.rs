@@ -0,0 +1,177 @@
+use error::MandelError;
+use num::Complex;
+use orbit;
+use std::convert::TryFrom;
+use std::f64::consts::PI;
+use std::fs;
+
+/// Samples a second of the sound written
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// Seconds each step of an orbit sounds for, unless given
+pub const NOTE_LENGTH: f64 = 0.1;
+
+/// Pitch, in Hz, of a point at an argument of -π, going up `OCTAVES` over
+/// a turn
+pub const LOWEST: f64 = 220.0;
+pub const OCTAVES: f64 = 2.0;
+
+/// Fraction of full scale the loudest note reaches, leaving headroom
+const VOLUME: f64 = 0.5;
+
+/// Seconds over which the loudness slides from one note to the next, short
+/// enough not to blur them and long enough not to click
+const GLIDE: f64 = 0.005;
+
+/// A tone: its pitch in Hz and its loudness from 0 to 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Note {
+    pub frequency: f64,
+    pub amplitude: f64,
+}
+
+/// note(z) : the note of a step `z` of an orbit, higher the further round
+/// `z` is from the negative real axis, and louder the further `z` is from
+/// 0, at full loudness once it's escaped
+pub fn note(z: Complex<f64>) -> Note {
+    let turn = (z.arg() + PI) / (2.0 * PI);
+    Note {
+        frequency: LOWEST * 2f64.powf(OCTAVES * turn),
+        amplitude: (z.norm() / 2.0).min(1.0),
+    }
+}
+
+/// notes(p, l) : the notes of the orbit of each of `points` in turn, at
+/// most `limit` iterations of each, with a rest between one and the next
+pub fn notes(points: &[Complex<f64>], limit: u32) -> Vec<Note> {
+    let rest = Note { frequency: LOWEST, amplitude: 0.0 };
+    let mut notes = Vec::new();
+    for (index, &c) in points.iter().enumerate() {
+        if index > 0 {
+            notes.push(rest);
+        }
+        notes.extend(orbit(c, limit).into_iter().map(note));
+    }
+    notes
+}
+
+/// synthesize(n, l) : `notes` played one after another as sine waves, each
+/// `length` seconds long, as 16-bit samples at `SAMPLE_RATE`
+///
+/// The wave carries on from one note to the next without jumping, and
+/// glides to each note's loudness over `GLIDE`, so notes change without
+/// clicking.
+pub fn synthesize(notes: &[Note], length: f64) -> Vec<i16> {
+    let per_note = (length * SAMPLE_RATE as f64).round() as usize;
+    let glide = ((GLIDE * SAMPLE_RATE as f64) as usize).clamp(1, per_note.max(1));
+    let mut samples = Vec::with_capacity(notes.len() * per_note);
+    let (mut phase, mut loudness) = (0.0f64, 0.0f64);
+    for note in notes {
+        let step = 2.0 * PI * note.frequency / SAMPLE_RATE as f64;
+        let from = loudness;
+        for n in 0 .. per_note {
+            loudness = from + (note.amplitude - from) * ((n + 1) as f64 / glide as f64).min(1.0);
+            samples.push((VOLUME * loudness * phase.sin() * i16::MAX as f64).round() as i16);
+            phase = (phase + step) % (2.0 * PI);
+        }
+    }
+    samples
+}
+
+/// data_size(n) : the bytes `n` samples take in a WAV file, if the file's
+/// 32-bit sizes can hold them along with its header
+fn data_size(samples: usize) -> Option<u32> {
+    samples.checked_mul(2)
+        .and_then(|data| u32::try_from(data).ok())
+        .filter(|&data| data.checked_add(36).is_some())
+}
+
+/// to_wav(s) : `samples` as a mono 16-bit PCM WAV file at `SAMPLE_RATE`,
+/// or an error if there are too many for one
+pub fn to_wav(samples: &[i16]) -> Result<Vec<u8>, MandelError> {
+    let data = data_size(samples.len()).ok_or_else(|| {
+        MandelError::InvalidParams(format!("{} samples are too many for a WAV file",
+                                           samples.len()))
+    })?;
+    let mut bytes = Vec::with_capacity(44 + data as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, one channel, then the bytes a second and to a sample, and 16
+    // bits to a sample
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    bytes.extend_from_slice(&(2 * SAMPLE_RATE).to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&16u16.to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data.to_le_bytes());
+    for sample in samples {
+        bytes.extend_from_slice(&sample.to_le_bytes());
+    }
+    Ok(bytes)
+}
+
+/// Write `samples` to `filename` as a WAV file
+pub fn write(filename: &str, samples: &[i16]) -> Result<(), MandelError> {
+    Ok(fs::write(filename, to_wav(samples)?)?)
+}
+
+#[test]
+fn test_note() {
+    // 0 is silent; the pitch climbs two octaves round from -π to π
+    assert_eq!(note(Complex { re: 0.0, im: 0.0 }).amplitude, 0.0);
+    let low = note(Complex { re: -1.0, im: -1e-12 });
+    let middle = note(Complex { re: 1.0, im: 0.0 });
+    let high = note(Complex { re: -1.0, im: 0.0 });
+    assert!((low.frequency - LOWEST).abs() < 1e-6 && (high.frequency - 4.0 * LOWEST).abs() < 1e-6);
+    assert_eq!((middle.frequency, middle.amplitude), (2.0 * LOWEST, 0.5));
+    assert_eq!(note(Complex { re: 0.0, im: 30.0 }).amplitude, 1.0);
+
+    // -1 goes 0, -1, 0, -1, ..., a tune of two notes, and a rest comes
+    // between one point's orbit and the next
+    let tune = notes(&[Complex { re: -1.0, im: 0.0 }], 5);
+    assert_eq!(tune.len(), 6);
+    assert_eq!((tune[1], tune[3]), (tune[5], tune[1]));
+    assert_eq!(tune[2].amplitude, 0.0);
+    let two = notes(&[Complex { re: -1.0, im: 0.0 }, Complex { re: 1.0, im: 0.0 }], 5);
+    assert_eq!(two[6].amplitude, 0.0);
+    // 1 escapes after a few steps, cutting its orbit short
+    assert_eq!(two.len(), 7 + orbit(Complex { re: 1.0, im: 0.0 }, 5).len());
+}
+
+#[test]
+fn test_synthesize() {
+    let loud = Note { frequency: 441.0, amplitude: 1.0 };
+    let silent = Note { frequency: 441.0, amplitude: 0.0 };
+    let samples = synthesize(&[silent, loud, silent], 0.1);
+    assert_eq!(samples.len(), 3 * 4410);
+    assert!(samples[.. 4410].iter().all(|&sample| sample == 0));
+    let peak = samples.iter().map(|sample| sample.unsigned_abs()).max().unwrap();
+    assert!((peak as f64 - VOLUME * i16::MAX as f64).abs() < 2.0);
+    // no sample jumps further than a full-loudness 441 Hz wave does
+    let most = VOLUME * i16::MAX as f64 * 2.0 * PI * 441.0 / SAMPLE_RATE as f64;
+    assert!(samples.windows(2).all(|pair| ((pair[1] - pair[0]) as f64).abs() <= most + 2.0));
+    // and it fades out again, to silence
+    assert_eq!(samples[samples.len() - 1], 0);
+}
+
+#[test]
+fn test_to_wav() {
+    let bytes = to_wav(&[0, 1, -1]).unwrap();
+    assert_eq!(bytes.len(), 44 + 6);
+    assert_eq!((&bytes[.. 4], &bytes[8 .. 16], &bytes[36 .. 40]),
+               (&b"RIFF"[..], &b"WAVEfmt "[..], &b"data"[..]));
+    assert_eq!(u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]), 42);
+    assert_eq!(u32::from_le_bytes([bytes[24], bytes[25], bytes[26], bytes[27]]), SAMPLE_RATE);
+    assert_eq!(u32::from_le_bytes([bytes[40], bytes[41], bytes[42], bytes[43]]), 6);
+    assert_eq!(&bytes[44 ..], &[0, 0, 1, 0, 255, 255]);
+
+    // the sizes are 32-bit, counting the 36 bytes of header after the first
+    assert_eq!(data_size(3), Some(6));
+    assert_eq!(data_size((u32::MAX as usize - 36) / 2), Some(u32::MAX - 37));
+    assert_eq!(data_size((u32::MAX as usize - 36) / 2 + 1), None);
+    assert_eq!(data_size(usize::MAX), None);
+}
@@ -0,0 +1,108 @@
+use color::EscapeBuffer;
+use escaped;
+use serde_json::{self, json, Value};
+use std::fs;
+use std::io;
+
+/// What a render's escape times add up to, for choosing an iteration
+/// limit or a palette
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    pub limit: u32,
+    pub pixels: usize,
+    /// pixels that never escaped
+    pub interior: usize,
+    /// how many pixels escaped after each number of iterations, up to the
+    /// most any took
+    pub histogram: Vec<usize>,
+    /// the fewest, most and mean iterations of those that escaped, `None`
+    /// if none did
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+    pub mean: Option<f64>,
+}
+
+impl Stats {
+    pub fn of(escapes: &EscapeBuffer) -> Stats {
+        let mut histogram = Vec::new();
+        let (mut interior, mut total) = (0, 0u64);
+        for &time in escapes.times.pixels() {
+            match escaped(time) {
+                None => interior += 1,
+                Some(t) => {
+                    if histogram.len() <= t as usize {
+                        histogram.resize(t as usize + 1, 0);
+                    }
+                    histogram[t as usize] += 1;
+                    total += t as u64;
+                }
+            }
+        }
+        let pixels = escapes.times.pixels().len();
+        let escaped = pixels - interior;
+        Stats {
+            limit: escapes.limit,
+            pixels,
+            interior,
+            min: histogram.iter().position(|&n| n > 0).map(|t| t as u32),
+            max: histogram.len().checked_sub(1).map(|t| t as u32),
+            mean: if escaped == 0 { None } else { Some(total as f64 / escaped as f64) },
+            histogram,
+        }
+    }
+
+    /// interior_fraction() : the share of pixels that never escaped
+    pub fn interior_fraction(&self) -> f64 {
+        self.interior as f64 / self.pixels.max(1) as f64
+    }
+
+    /// summary() : the statistics in a line, without the histogram
+    pub fn summary(&self) -> String {
+        match (self.min, self.max, self.mean) {
+            (Some(min), Some(max), Some(mean)) => {
+                format!("escaped after {} to {} of {} iterations, {:.1} on average; \
+                         {:.1}% interior", min, max, self.limit, mean,
+                        100.0 * self.interior_fraction())
+            }
+            _ => format!("nothing escaped in {} iterations", self.limit),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "iterations": self.limit,
+            "pixels": self.pixels,
+            "interior": self.interior,
+            "interior_fraction": self.interior_fraction(),
+            "min": self.min,
+            "max": self.max,
+            "mean": self.mean,
+            "histogram": self.histogram,
+        })
+    }
+
+    pub fn write_json(&self, filename: &str) -> io::Result<()> {
+        fs::write(filename, serde_json::to_string(&self.to_json()).unwrap() + "\n")
+    }
+}
+
+#[test]
+fn test_stats() {
+    use image::ImageBuffer;
+
+    let times = ImageBuffer::from_pixels((3, 2), vec![2, 5, 2, ::NO_ESCAPE, 3, ::NO_ESCAPE]);
+    let stats = Stats::of(&EscapeBuffer { limit: 10, times });
+    assert_eq!(stats.histogram, [0, 0, 2, 1, 0, 1]);
+    assert_eq!((stats.min, stats.max, stats.mean), (Some(2), Some(5), Some(3.0)));
+    assert_eq!(stats.interior, 2);
+    assert!((stats.interior_fraction() - 1.0 / 3.0).abs() < 1e-12);
+    assert_eq!(stats.summary(),
+               "escaped after 2 to 5 of 10 iterations, 3.0 on average; 33.3% interior");
+    assert_eq!(stats.to_json()["histogram"], json!([0, 0, 2, 1, 0, 1]));
+
+    let times = ImageBuffer::from_pixels((1, 1), vec![::NO_ESCAPE]);
+    let stats = Stats::of(&EscapeBuffer { limit: 10, times });
+    assert_eq!((stats.min, stats.max, stats.mean), (None, None, None));
+    assert_eq!(stats.summary(), "nothing escaped in 10 iterations");
+    assert_eq!(stats.to_json()["min"], Value::Null);
+}
@@ -0,0 +1,149 @@
+use error::MandelError;
+use image::ImageBuffer;
+use renderer::{ProgressEvent, Renderer};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// Values on their way from the render thread to whichever task is
+/// polling for them
+struct Queue<T> {
+    values: VecDeque<T>,
+    /// nothing more is coming
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Queue<T> {
+        Queue { values: VecDeque::new(), closed: false, waker: None }
+    }
+}
+
+type Shared<T> = Arc<Mutex<Queue<T>>>;
+
+/// The render thread's end of a `Queue`, which closes it when dropped,
+/// even by a panic
+struct Sender<T>(Shared<T>);
+
+impl<T> Sender<T> {
+    fn send(&self, value: T) {
+        let mut queue = self.0.lock().unwrap();
+        queue.values.push_back(value);
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut queue = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        queue.closed = true;
+        if let Some(waker) = queue.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// queue() : both ends of an empty `Queue`
+fn queue<T>() -> (Sender<T>, Shared<T>) {
+    let queue = Arc::new(Mutex::new(Queue::default()));
+    (Sender(queue.clone()), queue)
+}
+
+/// poll_queue(q, cx) : the next value in `queue`, `None` once it's closed
+/// and empty, or pending with `cx` to be woken when there's more
+fn poll_queue<T>(queue: &Mutex<Queue<T>>, cx: &mut Context) -> Poll<Option<T>> {
+    let mut queue = queue.lock().unwrap();
+    match queue.values.pop_front() {
+        Some(value) => Poll::Ready(Some(value)),
+        None if queue.closed => Poll::Ready(None),
+        None => {
+            queue.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// The image `Renderer::render_async` is rendering, ready once it's finished
+pub struct RenderFuture {
+    result: Shared<Result<ImageBuffer<u8>, MandelError>>,
+}
+
+impl Future for RenderFuture {
+    type Output = Result<ImageBuffer<u8>, MandelError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        poll_queue(&self.result, cx)
+            .map(|result| result.expect("render thread panicked"))
+    }
+}
+
+/// The `ProgressEvent`s of `Renderer::render_async`, as they happen
+///
+/// `poll_next` is shaped like `Stream::poll_next` from the `futures`
+/// crate, so wrapping it in one takes a line, and `event` awaits one.
+pub struct ProgressStream {
+    events: Shared<ProgressEvent>,
+}
+
+impl ProgressStream {
+    /// The next event, or `None` once the render is over
+    pub fn poll_next(&mut self, cx: &mut Context) -> Poll<Option<ProgressEvent>> {
+        poll_queue(&self.events, cx)
+    }
+
+    /// event() : a future of the next event, or `None` once the render is
+    /// over
+    pub fn event(&mut self) -> NextEvent<'_> {
+        NextEvent { stream: self }
+    }
+}
+
+/// The future `ProgressStream::event` returns
+pub struct NextEvent<'a> {
+    stream: &'a mut ProgressStream,
+}
+
+impl<'a> Future for NextEvent<'a> {
+    type Output = Option<ProgressEvent>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<ProgressEvent>> {
+        self.stream.poll_next(cx)
+    }
+}
+
+/// spawn(r) : `renderer`'s image, rendered on a thread of its own, and
+/// its progress, as `Renderer::render_async` returns them
+pub fn spawn(renderer: Renderer) -> (RenderFuture, ProgressStream) {
+    let (result, results) = queue();
+    let (event, events) = queue();
+    thread::spawn(move || {
+        let image = renderer.render_progress(|e| event.send(e));
+        drop(event);
+        result.send(image);
+    });
+    (RenderFuture { result: results }, ProgressStream { events })
+}
+
+#[test]
+fn test_render_async() {
+    let renderer = Renderer::new().bounds(60, 90).threads(2);
+    let (image, mut progress) = renderer.render_async();
+    let mut events = Vec::new();
+    while let Some(event) = ::pollster::block_on(progress.event()) {
+        events.push(event);
+    }
+    assert_eq!(::pollster::block_on(image).unwrap(), renderer.render().unwrap());
+    assert_eq!(events.iter().map(|e| e.rows.len()).sum::<usize>(), 90);
+    assert_eq!(events.iter().map(|e| e.rows_done).max(), Some(90));
+
+    let token = ::cancel::CancelToken::new();
+    token.cancel();
+    let (image, _) = renderer.cancel_token(&token).render_async();
+    assert!(matches!(::pollster::block_on(image), Err(MandelError::Cancelled)));
+}
@@ -0,0 +1,141 @@
+use affinity;
+use crossbeam;
+use fixed::Fixed;
+use num::Complex;
+use output::ImageWriter;
+use precision::Precision;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use viewport::Viewport;
+use {copy_rect, render_rect};
+
+/// Side of the square tiles timed individually
+pub const TILE_SIZE: usize = 32;
+
+pub struct TileTime {
+    pub rect: (usize, usize, usize, usize),
+    pub elapsed: Duration,
+}
+
+/// Render like `render_bands`, but hand out small tiles from a queue and
+/// time each of them
+#[allow(clippy::too_many_arguments)]
+pub fn render(pixels: &mut [u8],
+              bounds: (usize, usize),
+              top_left: Complex<f64>,
+              bot_right: Complex<f64>,
+              deep: Option<&(Complex<Fixed>, Complex<Fixed>)>,
+              precision: Precision,
+              limit: u32,
+              threads: usize)
+    -> Vec<TileTime>
+{
+    assert!(pixels.len() == bounds.0 * bounds.1);
+
+    let rects = Viewport::new(bounds, top_left, bot_right).tiles(TILE_SIZE);
+
+    let next = AtomicUsize::new(0);
+    let done = Mutex::new(Vec::with_capacity(rects.len()));
+    crossbeam::scope(|spawner| {
+        for index in 0..threads {
+            let (rects, next, done) = (&rects, &next, &done);
+            spawner.spawn(move || {
+                affinity::pin(index);
+                while let Some(&rect) = rects.get(next.fetch_add(1, Ordering::SeqCst)) {
+                    let mut tile = vec![0; rect.2 * rect.3];
+                    let start = Instant::now();
+                    render_rect(&mut tile, bounds, rect, top_left, bot_right,
+                                deep, precision, limit);
+                    let elapsed = start.elapsed();
+                    done.lock().unwrap().push((TileTime { rect, elapsed }, tile));
+                }
+            });
+        }
+    });
+
+    let mut times = Vec::with_capacity(rects.len());
+    for (time, tile) in done.into_inner().unwrap() {
+        copy_rect(pixels, bounds, time.rect, &tile);
+        times.push(time);
+    }
+    times.sort_by_key(|t| (t.rect.1, t.rect.0));
+    times
+}
+
+pub fn write_json(filename: &str, bounds: (usize, usize), times: &[TileTime])
+    -> io::Result<()>
+{
+    let mut out = BufWriter::new(File::create(filename)?);
+    let total: Duration = times.iter().map(|t| t.elapsed).sum();
+
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"width\": {},", bounds.0)?;
+    writeln!(out, "  \"height\": {},", bounds.1)?;
+    writeln!(out, "  \"total_micros\": {},", total.as_micros())?;
+    writeln!(out, "  \"tiles\": [")?;
+    for (i, t) in times.iter().enumerate() {
+        let (x, y, width, height) = t.rect;
+        writeln!(out, "    {{\"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}, \
+                       \"micros\": {}}}{}",
+                 x, y, width, height, t.elapsed.as_micros(),
+                 if i + 1 < times.len() { "," } else { "" })?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")?;
+    out.flush()
+}
+
+/// Image the size of the render where each tile is shaded by how long it
+/// took, white being the slowest
+pub fn heatmap(bounds: (usize, usize), times: &[TileTime]) -> Vec<u8> {
+    let slowest = times.iter().map(|t| t.elapsed).max().unwrap_or_default();
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    for t in times {
+        let shade = if slowest.as_nanos() == 0 {
+            0
+        } else {
+            (t.elapsed.as_nanos() * 255 / slowest.as_nanos()) as u8
+        };
+        let (x, y, width, height) = t.rect;
+        for row in y .. y + height {
+            for p in &mut pixels[row * bounds.0 + x .. row * bounds.0 + x + width] {
+                *p = shade;
+            }
+        }
+    }
+    pixels
+}
+
+pub fn write_heatmap(filename: &str, bounds: (usize, usize), times: &[TileTime])
+    -> io::Result<()>
+{
+    let mut writer = ImageWriter::create(filename, bounds, None)?;
+    writer.write_rows(&heatmap(bounds, times))?;
+    Ok(writer.finish()?)
+}
+
+#[test]
+fn test_render_covers_image() {
+    let bounds = (70, 40);
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    let times = render(&mut pixels, bounds,
+                       Complex { re: -1.2, im: 0.35 }, Complex { re: -1.0, im: 0.2 },
+                       None, Precision::F64, 255, 3);
+    assert_eq!(times.len(), 3 * 2);
+    assert_eq!(times[1].rect, (32, 0, 32, 32));
+    assert_eq!(times[5].rect, (64, 32, 6, 8));
+    assert_eq!(times.iter().map(|t| t.rect.2 * t.rect.3).sum::<usize>(),
+               bounds.0 * bounds.1);
+}
+
+#[test]
+fn test_heatmap() {
+    let times = [
+        TileTime { rect: (0, 0, 2, 1), elapsed: Duration::from_millis(10) },
+        TileTime { rect: (2, 0, 1, 1), elapsed: Duration::from_millis(5) },
+    ];
+    assert_eq!(heatmap((3, 1), &times), [255, 255, 127]);
+}
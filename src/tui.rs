@@ -0,0 +1,269 @@
+use history::History;
+use num::Complex;
+use pan;
+use precision::{self, Precision};
+use ratatui::buffer::Buffer;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::Paragraph;
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+use std::path::Path;
+use {format_complex, render_bands, render_streaming};
+
+/// Fraction of the view an arrow key pans by
+const PAN_STEP: f64 = 0.1;
+
+/// How much `+` zooms in
+const ZOOM_STEP: f64 = 0.5;
+
+const HELP: &str =
+    "arrows/hjkl pan  +/- zoom  [/] iterations  u/U undo/redo  r reset  e export  q quit";
+
+/// Shown instead of the help once the view is too deep to hold in `f64`
+const TOO_DEEP: &str = "zoomed in past what f64 can place, the view is off; zoom out with -";
+
+/// Where the explorer is looking, with square pixels so that the half
+/// blocks come out roughly square too
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct View {
+    center: Complex<f64>,
+    /// width of a pixel in the plane
+    scale: f64,
+}
+
+impl View {
+    /// The view showing all of `top_left` to `bot_right` in `bounds`
+    fn fit(top_left: Complex<f64>, bot_right: Complex<f64>, bounds: (usize, usize)) -> View {
+        View {
+            center: (top_left + bot_right) / 2.0,
+            scale: ((bot_right.re - top_left.re) / bounds.0 as f64)
+                .max((top_left.im - bot_right.im) / bounds.1 as f64),
+        }
+    }
+
+    /// corners(b) : the top left and bottom right of the view at `bounds`
+    fn corners(&self, bounds: (usize, usize)) -> (Complex<f64>, Complex<f64>) {
+        let half = Complex {
+            re: self.scale * bounds.0 as f64 / 2.0,
+            im: self.scale * bounds.1 as f64 / 2.0,
+        };
+        (Complex { re: self.center.re - half.re, im: self.center.im + half.im },
+         Complex { re: self.center.re + half.re, im: self.center.im - half.im })
+    }
+
+    /// Move the view by `(right, up)` times its width and height at `bounds`
+    fn pan(&self, bounds: (usize, usize), right: f64, up: f64) -> View {
+        View {
+            center: Complex {
+                re: self.center.re + right * self.scale * bounds.0 as f64,
+                im: self.center.im + up * self.scale * bounds.1 as f64,
+            },
+            ..*self
+        }
+    }
+
+    fn zoom(&self, factor: f64) -> View {
+        View { scale: self.scale * factor, ..*self }
+    }
+}
+
+/// image_bounds(a) : pixels shown in `area`, two to a character cell
+fn image_bounds(area: Rect) -> (usize, usize) {
+    (area.width.max(1) as usize, 2 * area.height.max(1) as usize)
+}
+
+/// Draw `pixels` into `area` as upper half blocks, the top pixel of each
+/// cell in the foreground and the bottom one in the background
+fn draw_pixels(buffer: &mut Buffer, area: Rect, pixels: &[u8]) {
+    let width = area.width as usize;
+    let gray = |shade: u8| Color::Rgb(shade, shade, shade);
+    for y in 0 .. area.height {
+        for x in 0 .. area.width {
+            let top = pixels[2 * y as usize * width + x as usize];
+            let bottom = pixels[(2 * y as usize + 1) * width + x as usize];
+            if let Some(cell) = buffer.cell_mut((area.x + x, area.y + y)) {
+                cell.set_char('▀').set_fg(gray(top)).set_bg(gray(bottom));
+            }
+        }
+    }
+}
+
+/// The explorer's state between key presses
+struct Explorer {
+    home: View,
+    view: View,
+    precision: Precision,
+    limit: u32,
+    threads: usize,
+    /// size of exported images
+    export_bounds: (usize, usize),
+    /// the last render and what it was of, redone when either changes
+    pixels: Vec<u8>,
+    rendered: Option<(View, (usize, usize), u32)>,
+    /// views and iteration limits gone through, for undo
+    history: History<(View, u32)>,
+    /// last thing worth telling the user, shown instead of the help
+    status: String,
+}
+
+impl Explorer {
+    fn draw(&mut self, frame: &mut Frame) {
+        let [image, status] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)])
+            .areas(frame.area());
+        let bounds = image_bounds(image);
+        if self.rendered != Some((self.view, bounds, self.limit)) {
+            let (top_left, bot_right) = self.view.corners(bounds);
+            self.pixels = vec![0; bounds.0 * bounds.1];
+            render_bands(&mut self.pixels, bounds, 0, top_left, bot_right,
+                         None, self.precision, self.limit, self.threads);
+            self.rendered = Some((self.view, bounds, self.limit));
+        }
+        draw_pixels(frame.buffer_mut(), image, &self.pixels);
+
+        let (top_left, bot_right) = self.view.corners(bounds);
+        let message = if !self.status.is_empty() {
+            &self.status
+        } else if precision::too_deep_for_f64(top_left, bot_right) {
+            TOO_DEEP
+        } else {
+            HELP
+        };
+        let line = format!("{},{}  zoom {:.3e}  {} iterations  {}  | {}",
+                           self.view.center.re, self.view.center.im,
+                           self.home.scale / self.view.scale, self.limit, self.precision,
+                           message);
+        let reversed = Style::new().add_modifier(Modifier::REVERSED);
+        frame.render_widget(Paragraph::new(line).style(reversed), status);
+    }
+
+    /// Render the view at `export_bounds` into the first `mandel-N.png`
+    /// that doesn't exist yet
+    fn export(&mut self) {
+        let filename = (1 ..)
+            .map(|n| format!("mandel-{}.png", n))
+            .find(|name| !Path::new(name).exists())
+            .unwrap();
+        let width = self.view.scale * self.rendered.map_or(1, |(_, bounds, _)| bounds.0) as f64;
+        let view = View { scale: width / self.export_bounds.0 as f64, ..self.view };
+        let (top_left, bot_right) = view.corners(self.export_bounds);
+
+        let description = pan::describe(self.limit, self.precision,
+                                        &format_complex(top_left), &format_complex(bot_right));
+        self.status = match render_streaming(&filename, self.export_bounds, top_left, bot_right,
+                                             None, self.precision, self.limit, self.threads,
+                                             Some(&description), &[]) {
+            Ok(_) => format!("exported {}x{} to {}",
+                             self.export_bounds.0, self.export_bounds.1, filename),
+            Err(e) => format!("error exporting {}: {}", filename, e),
+        };
+    }
+
+    /// Act on a key, returning false to quit
+    fn key(&mut self, terminal: &mut DefaultTerminal, code: KeyCode) -> io::Result<bool> {
+        let bounds = self.rendered.map_or((1, 1), |(_, bounds, _)| bounds);
+        self.status.clear();
+        let before = (self.view, self.limit);
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Left | KeyCode::Char('h') => self.view = self.view.pan(bounds, -PAN_STEP, 0.0),
+            KeyCode::Right | KeyCode::Char('l') => self.view = self.view.pan(bounds, PAN_STEP, 0.0),
+            KeyCode::Up | KeyCode::Char('k') => self.view = self.view.pan(bounds, 0.0, PAN_STEP),
+            KeyCode::Down | KeyCode::Char('j') => self.view = self.view.pan(bounds, 0.0, -PAN_STEP),
+            KeyCode::Char('+') | KeyCode::Char('=') => self.view = self.view.zoom(ZOOM_STEP),
+            KeyCode::Char('-') => self.view = self.view.zoom(1.0 / ZOOM_STEP),
+            KeyCode::Char(']') => self.limit = self.limit.saturating_mul(2),
+            KeyCode::Char('[') => self.limit = (self.limit / 2).max(1),
+            KeyCode::Char('r') => self.view = self.home,
+            KeyCode::Backspace | KeyCode::Char('u') | KeyCode::Char('U') => {
+                let (step, missing) = if code == KeyCode::Char('U') {
+                    (self.history.redo(before), "nothing to redo")
+                } else {
+                    (self.history.undo(before), "nothing to undo")
+                };
+                match step {
+                    Some((view, limit)) => {
+                        self.view = view;
+                        self.limit = limit;
+                    }
+                    None => self.status = missing.to_string(),
+                }
+                return Ok(true);
+            }
+            KeyCode::Char('e') => {
+                self.status = "exporting...".to_string();
+                terminal.draw(|frame| self.draw(frame))?;
+                self.export();
+            }
+            _ => {}
+        }
+        if (self.view, self.limit) != before {
+            self.history.push(before);
+        }
+        Ok(true)
+    }
+}
+
+/// Explore the set in the terminal, starting from the view, until `q`
+///
+/// Each character cell shows two pixels as a half block, so this works
+/// over plain SSH in any terminal with 24-bit colour. Exports are
+/// rendered at `bounds`.
+pub fn run(bounds: (usize, usize),
+           top_left: Complex<f64>,
+           bot_right: Complex<f64>,
+           precision: Precision,
+           limit: u32,
+           threads: usize)
+    -> io::Result<()>
+{
+    let mut terminal = ratatui::try_init()?;
+    let size = terminal.size()?;
+    let image = Rect::new(0, 0, size.width, size.height.saturating_sub(1));
+    let home = View::fit(top_left, bot_right, image_bounds(image));
+    let mut explorer = Explorer {
+        home,
+        view: home,
+        precision,
+        limit,
+        threads,
+        export_bounds: bounds,
+        pixels: Vec::new(),
+        rendered: None,
+        history: History::new(),
+        status: String::new(),
+    };
+
+    let result = (|| loop {
+        terminal.draw(|frame| explorer.draw(frame))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !explorer.key(&mut terminal, key.code)? {
+                return Ok(());
+            }
+        }
+    })();
+    ratatui::try_restore()?;
+    result
+}
+
+#[test]
+fn test_view() {
+    let view = View::fit(Complex { re: -2.0, im: 1.0 }, Complex { re: 1.0, im: -1.0 }, (60, 20));
+    assert_eq!(view, View { center: Complex { re: -0.5, im: 0.0 }, scale: 0.1 });
+    assert_eq!(view.corners((60, 20)),
+               (Complex { re: -3.5, im: 1.0 }, Complex { re: 2.5, im: -1.0 }));
+    assert_eq!(view.pan((60, 20), 0.5, -0.5).center, Complex { re: 2.5, im: -1.0 });
+    assert_eq!(view.zoom(0.5).corners((60, 20)).0, Complex { re: -2.0, im: 0.5 });
+}
+
+#[test]
+fn test_draw_pixels() {
+    let area = Rect::new(1, 1, 2, 1);
+    let mut buffer = Buffer::empty(Rect::new(0, 0, 3, 2));
+    draw_pixels(&mut buffer, area, &[10, 20, 30, 40]);
+    let cell = &buffer[(2, 1)];
+    assert_eq!(cell.symbol(), "▀");
+    assert_eq!((cell.fg, cell.bg), (Color::Rgb(20, 20, 20), Color::Rgb(40, 40, 40)));
+    assert_eq!(buffer[(0, 0)].symbol(), " ");
+}
@@ -0,0 +1,439 @@
+use error::MandelError;
+use fixed::Fixed;
+use num::Complex;
+use std::str::FromStr;
+use {pixel_center, pixel_to_point, Affine};
+
+/// Most pixels an image can be across or down, the most a PNG can hold
+pub const MAX_SIDE: usize = i32::MAX as usize;
+
+/// How a view is made to fit an image of another shape
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fit {
+    /// grow the view's shorter side, showing all of it and some around it
+    Contain,
+    /// shrink its longer side, filling the image with the middle of it
+    Cover,
+    /// leave it be, its pixels drawn squashed
+    Stretch,
+}
+
+/// Parsed from `contain`, `cover` or `stretch`
+impl FromStr for Fit {
+    type Err = MandelError;
+
+    fn from_str(s: &str) -> Result<Fit, MandelError> {
+        match s {
+            "contain" => Ok(Fit::Contain),
+            "cover" => Ok(Fit::Cover),
+            "stretch" => Ok(Fit::Stretch),
+            _ => Err(MandelError::Parse(format!(
+                "bad fit {:?}, expected contain, cover or stretch", s))),
+        }
+    }
+}
+
+/// A `bounds` image of the part of the plane from `top_left` to
+/// `bot_right`, turned about its center by `rotation`, with the ways of
+/// moving it about
+///
+/// The corners are those of the view before it's turned, so with no
+/// rotation they're the corners of the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub bounds: (usize, usize),
+    pub top_left: Complex<f64>,
+    pub bot_right: Complex<f64>,
+    /// radians anticlockwise
+    pub rotation: f64,
+}
+
+impl Viewport {
+    pub fn new(bounds: (usize, usize), top_left: Complex<f64>, bot_right: Complex<f64>)
+        -> Viewport
+    {
+        Viewport { bounds, top_left, bot_right, rotation: 0.0 }
+    }
+
+    /// Check the view can be rendered: that the image has pixels, but not
+    /// more than can be counted or saved, that the corners are finite
+    /// numbers and that the top left is above and left of the bottom right,
+    /// compared in `deep`'s fixed point instead when the corners are given
+    /// in it too, as views too deep for `f64` to tell them apart are
+    pub fn check(&self, deep: Option<&(Complex<Fixed>, Complex<Fixed>)>)
+        -> Result<(), MandelError>
+    {
+        let invalid = |reason: String| Err(MandelError::InvalidParams(reason));
+        let (tl, br) = (self.top_left, self.bot_right);
+        if self.bounds.0 == 0 || self.bounds.1 == 0 {
+            return invalid(format!("the image must be at least a pixel across, not {}x{}",
+                                   self.bounds.0, self.bounds.1));
+        }
+        if self.bounds.0 > MAX_SIDE || self.bounds.1 > MAX_SIDE {
+            return invalid(format!("a {}x{} image is more than the {} pixels across or down \
+                                    an image file can hold",
+                                   self.bounds.0, self.bounds.1, MAX_SIDE));
+        }
+        if self.bounds.0.checked_mul(self.bounds.1).is_none() {
+            return invalid(format!("a {}x{} image has more pixels than can be counted",
+                                   self.bounds.0, self.bounds.1));
+        }
+        if ![tl.re, tl.im, br.re, br.im].iter().all(|x| x.is_finite()) {
+            return invalid(format!("the corners {},{} and {},{} must be finite numbers",
+                                   tl.re, tl.im, br.re, br.im));
+        }
+        let ordered = match deep {
+            None => tl.re < br.re && tl.im > br.im,
+            Some((tl, br)) => tl.re < br.re && tl.im > br.im,
+        };
+        if !ordered {
+            return invalid(format!("the top left corner {},{} must be above and left of the \
+                                    bottom right {},{}", tl.re, tl.im, br.re, br.im));
+        }
+        Ok(())
+    }
+
+    /// The view turned a further `angle` radians anticlockwise about its
+    /// center
+    pub fn rotate(&self, angle: f64) -> Viewport {
+        Viewport { rotation: self.rotation + angle, ..*self }
+    }
+
+    /// turn() : the cosine and sine of the rotation, as `Affine` takes them
+    pub fn turn(&self) -> (f64, f64) {
+        (self.rotation.cos(), self.rotation.sin())
+    }
+
+    /// The map from the view's pixels to points, for rendering it
+    pub fn affine(&self) -> Affine<f64> {
+        Affine::new(self.bounds, self.top_left, self.bot_right, self.turn())
+    }
+
+    /// spin(z, a) : `z` turned `angle` radians about the center, exactly
+    /// `z` for no angle
+    fn spin(&self, z: Complex<f64>, angle: f64) -> Complex<f64> {
+        if angle == 0.0 {
+            return z;
+        }
+        let center = self.center();
+        center + (z - center) * Complex::from_polar(1.0, angle)
+    }
+
+    /// on_screen(v) : the step `v` along the image's axes as a step in the
+    /// plane
+    fn on_screen(&self, v: Complex<f64>) -> Complex<f64> {
+        if self.rotation == 0.0 { v } else { v * Complex::from_polar(1.0, self.rotation) }
+    }
+
+    /// size() : the width and height of the view in the plane
+    pub fn size(&self) -> (f64, f64) {
+        (self.bot_right.re - self.top_left.re, self.top_left.im - self.bot_right.im)
+    }
+
+    /// pixel_size() : the width and height of a pixel in the plane
+    pub fn pixel_size(&self) -> (f64, f64) {
+        let (width, height) = self.size();
+        (width / self.bounds.0 as f64, height / self.bounds.1 as f64)
+    }
+
+    /// distortion() : how many times wider than tall a pixel is in the
+    /// plane, so how squashed the image draws it, 1 for square
+    pub fn distortion(&self) -> f64 {
+        let (width, height) = self.pixel_size();
+        width / height
+    }
+
+    pub fn center(&self) -> Complex<f64> {
+        (self.top_left + self.bot_right) / 2.0
+    }
+
+    /// point(p) : the point at the top left of pixel `p`, which may be
+    /// off the image
+    pub fn point(&self, pixel: (usize, usize)) -> Complex<f64> {
+        let level = pixel_to_point(self.bounds, pixel, self.top_left, self.bot_right);
+        self.spin(level, self.rotation)
+    }
+
+    /// sample(p) : the point at the center of pixel `p`, where renders
+    /// sample it
+    pub fn sample(&self, pixel: (usize, usize)) -> Complex<f64> {
+        let level = pixel_center(self.bounds, pixel, self.top_left, self.bot_right);
+        self.spin(level, self.rotation)
+    }
+
+    /// position(p) : where `point` is on the image, in pixels across and
+    /// down from its top left corner, which may be off the image
+    pub fn position(&self, point: Complex<f64>) -> (f64, f64) {
+        let point = self.spin(point, -self.rotation);
+        let (width, height) = self.pixel_size();
+        ((point.re - self.top_left.re) / width, (self.top_left.im - point.im) / height)
+    }
+
+    /// pixel(p) : the pixel `point` falls in, `None` if it's off the image
+    pub fn pixel(&self, point: Complex<f64>) -> Option<(usize, usize)> {
+        let (col, row) = self.position(point);
+        let (col, row) = (col.floor(), row.floor());
+        if col >= 0.0 && row >= 0.0
+            && (col as usize) < self.bounds.0 && (row as usize) < self.bounds.1
+        {
+            Some((col as usize, row as usize))
+        } else {
+            None
+        }
+    }
+
+    /// Scale the view by `factor` about `fixed`, which stays where it is
+    /// on the image
+    pub fn zoom(&self, fixed: Complex<f64>, factor: f64) -> Viewport {
+        // the center closes in on `fixed`, and the corners on the center
+        let center = self.center();
+        let moved = fixed + (center - fixed) * factor;
+        Viewport {
+            top_left: moved + (self.top_left - center) * factor,
+            bot_right: moved + (self.bot_right - center) * factor,
+            ..*self
+        }
+    }
+
+    /// Move the view by `(right, up)` times its own width and height, as
+    /// the image shows them
+    pub fn pan(&self, right: f64, up: f64) -> Viewport {
+        let (width, height) = self.size();
+        self.shift(self.on_screen(Complex { re: width * right, im: height * up }))
+    }
+
+    /// Move the view `(right, down)` pixels, so that what was under one
+    /// pixel ends up that far the other way
+    pub fn pan_pixels(&self, right: f64, down: f64) -> Viewport {
+        let (width, height) = self.pixel_size();
+        self.shift(self.on_screen(Complex { re: width * right, im: -height * down }))
+    }
+
+    fn shift(&self, by: Complex<f64>) -> Viewport {
+        Viewport {
+            top_left: self.top_left + by,
+            bot_right: self.bot_right + by,
+            ..*self
+        }
+    }
+
+    /// Move the view so that `point` is in the middle
+    pub fn recenter(&self, point: Complex<f64>) -> Viewport {
+        self.shift(point - self.center())
+    }
+
+    /// The view zoomed in on the rectangle with corners `a` and `b`, as
+    /// the image shows them, widened to keep the image's aspect ratio
+    pub fn select(&self, a: Complex<f64>, b: Complex<f64>) -> Viewport {
+        let (a, b) = (self.spin(a, -self.rotation), self.spin(b, -self.rotation));
+        let level = Viewport {
+            top_left: Complex { re: a.re.min(b.re), im: a.im.max(b.im) },
+            bot_right: Complex { re: a.re.max(b.re), im: a.im.min(b.im) },
+            ..*self
+        }.fit();
+        level.shift(self.spin(level.center(), self.rotation) - level.center())
+    }
+
+    /// Stretch the shorter side of the view so that its pixels are square
+    pub fn fit(&self) -> Viewport {
+        self.fitted(Fit::Contain)
+    }
+
+    /// The view about the same center with square pixels, by `fit`
+    pub fn fitted(&self, fit: Fit) -> Viewport {
+        let (width, height) = self.size();
+        let aspect = self.bounds.0 as f64 / self.bounds.1 as f64;
+        let wide = width / height > aspect;
+        let (width, height) = match fit {
+            Fit::Stretch => return *self,
+            Fit::Contain if wide => (width, width / aspect),
+            Fit::Cover if !wide => (width, width / aspect),
+            _ => (height * aspect, height),
+        };
+        let center = self.center();
+        Viewport {
+            top_left: Complex { re: center.re - width / 2.0, im: center.im + height / 2.0 },
+            bot_right: Complex { re: center.re + width / 2.0, im: center.im - height / 2.0 },
+            ..*self
+        }
+    }
+
+    /// The view at `bounds` instead, keeping the centre and the size of a
+    /// pixel
+    pub fn resize(&self, bounds: (usize, usize)) -> Viewport {
+        let (width, height) = self.pixel_size();
+        let half = Complex {
+            re: width * bounds.0 as f64 / 2.0,
+            im: height * bounds.1 as f64 / 2.0,
+        };
+        let center = self.center();
+        Viewport {
+            bounds,
+            top_left: Complex { re: center.re - half.re, im: center.im + half.im },
+            bot_right: Complex { re: center.re + half.re, im: center.im - half.im },
+            ..*self
+        }
+    }
+
+    /// The `(x, y, width, height)` rectangle `rect` of the image, as a
+    /// view of its own
+    pub fn sub(&self, rect: (usize, usize, usize, usize)) -> Viewport {
+        let (x, y, width, height) = rect;
+        let level = Viewport {
+            bounds: (width, height),
+            top_left: pixel_to_point(self.bounds, (x, y), self.top_left, self.bot_right),
+            bot_right: pixel_to_point(self.bounds, (x + width, y + height),
+                                      self.top_left, self.bot_right),
+            rotation: self.rotation,
+        };
+        level.shift(self.spin(level.center(), self.rotation) - level.center())
+    }
+
+    /// tiles(s) : the image cut into `size` square rectangles, row by row,
+    /// smaller at the right and bottom edges where it doesn't divide
+    pub fn tiles(&self, size: usize) -> Vec<(usize, usize, usize, usize)> {
+        let mut rects = Vec::new();
+        for y in (0 .. self.bounds.1).step_by(size) {
+            for x in (0 .. self.bounds.0).step_by(size) {
+                rects.push((x, y, size.min(self.bounds.0 - x), size.min(self.bounds.1 - y)));
+            }
+        }
+        rects
+    }
+
+    /// Stretch and shift `pixels`, rendered for `old`, onto this view by
+    /// nearest neighbour, leaving `T::default()` what `old` didn't cover
+    pub fn resample<T: Clone + Default>(&self, old: &Viewport, pixels: &[T]) -> Vec<T> {
+        let mut resampled = vec![T::default(); self.bounds.0 * self.bounds.1];
+        for row in 0 .. self.bounds.1 {
+            for col in 0 .. self.bounds.0 {
+                if let Some((x, y)) = old.pixel(self.sample((col, row))) {
+                    resampled[row * self.bounds.0 + col] = pixels[y * old.bounds.0 + x].clone();
+                }
+            }
+        }
+        resampled
+    }
+}
+
+#[test]
+fn test_viewport_zoom_keeps_fixed_point() {
+    let view = Viewport::new((400, 300), Complex { re: -2.0, im: 1.5 },
+                             Complex { re: 2.0, im: -1.5 });
+    let fixed = view.point((100, 75));
+    let zoomed = view.zoom(fixed, 0.5);
+    assert_eq!(zoomed.top_left, Complex { re: -1.5, im: 1.125 });
+    assert_eq!(zoomed.bot_right, Complex { re: 0.5, im: -0.375 });
+
+    let resized = view.resize((800, 300));
+    assert_eq!(resized.top_left, Complex { re: -4.0, im: 1.5 });
+    assert_eq!(resized.center(), view.center());
+    assert_eq!(view.recenter(Complex { re: 1.0, im: 0.0 }).top_left,
+               Complex { re: -1.0, im: 1.5 });
+    assert_eq!(view.pan(0.25, -0.5).top_left, Complex { re: -1.0, im: 0.0 });
+    assert_eq!(view.pan_pixels(100.0, 150.0), view.pan(0.25, -0.5));
+
+    let tall = Viewport { bot_right: Complex { re: -1.0, im: -1.5 }, ..view };
+    assert_eq!(tall.distortion(), 0.25);
+    assert_eq!(tall.fit().top_left, Complex { re: -3.5, im: 1.5 });
+    let covered = tall.fitted(Fit::Cover);
+    assert_eq!((covered.top_left, covered.bot_right),
+               (Complex { re: -2.0, im: 0.375 }, Complex { re: -1.0, im: -0.375 }));
+    assert_eq!((covered.distortion(), tall.fit().distortion()), (1.0, 1.0));
+    assert_eq!(tall.fitted(Fit::Stretch), tall);
+    assert_eq!("cover".parse::<Fit>().unwrap(), Fit::Cover);
+    assert!("squash".parse::<Fit>().is_err());
+
+    // dragged up and left over a wide rectangle, which grows to 4:3
+    let selected = view.select(view.point((300, 150)), view.point((100, 120)));
+    assert_eq!(selected.top_left, Complex { re: -1.0, im: 0.9 });
+    assert_eq!(selected.bot_right, Complex { re: 1.0, im: -0.6 });
+}
+
+#[test]
+fn test_viewport_check() {
+    let (tl, br) = (Complex { re: -2.0, im: 1.0 }, Complex { re: 1.0, im: -1.0 });
+    assert!(Viewport::new((4, 3), tl, br).check(None).is_ok());
+    assert!(Viewport::new((MAX_SIDE, 1), tl, br).check(None).is_ok());
+    let nan = Complex { re: f64::NAN, im: 1.0 };
+    let far = Complex { re: -2.0, im: f64::INFINITY };
+    for view in &[Viewport::new((0, 3), tl, br), Viewport::new((4, 0), tl, br),
+                  Viewport::new((usize::MAX / 2, 3), tl, br),
+                  Viewport::new((MAX_SIDE + 1, 1), tl, br),
+                  Viewport::new((4, 3), br, tl), Viewport::new((4, 3), tl, tl),
+                  Viewport::new((4, 3), Complex { re: 2.0, im: 1.0 }, br),
+                  Viewport::new((4, 3), nan, br), Viewport::new((4, 3), far, br)] {
+        assert!(matches!(view.check(None), Err(MandelError::InvalidParams(_))), "{:?}", view);
+    }
+
+    // corners `f64` can't tell apart are checked in fixed point
+    let (tl, br) = ("-0.74364388703715870475,0.1318259042053119",
+                    "-0.74364388703715870474,0.1318259042053118");
+    let deep = (::fixed::parse_complex(tl, 128).unwrap(),
+                ::fixed::parse_complex(br, 128).unwrap());
+    let view = Viewport::new((4, 3), ::parse_complex(tl).unwrap(), ::parse_complex(br).unwrap());
+    assert!(view.check(None).is_err());
+    assert!(view.check(Some(&deep)).is_ok());
+    assert!(view.check(Some(&(deep.1.clone(), deep.0.clone()))).is_err());
+}
+
+#[test]
+fn test_viewport_pixels() {
+    let view = Viewport::new((4, 2), Complex { re: -2.0, im: 1.0 }, Complex { re: 2.0, im: -1.0 });
+    assert_eq!(view.pixel(view.point((3, 1))), Some((3, 1)));
+    assert_eq!(view.pixel(view.sample((3, 1))), Some((3, 1)));
+    assert_eq!(view.pixel(Complex { re: -0.5, im: 0.5 }), Some((1, 0)));
+    assert_eq!(view.pixel(Complex { re: 2.5, im: 0.0 }), None);
+    assert_eq!(view.position(Complex { re: 2.5, im: 0.0 }), (4.5, 1.0));
+
+    assert_eq!(view.tiles(3), [(0, 0, 3, 2), (3, 0, 1, 2)]);
+    let right = view.sub((3, 0, 1, 2));
+    assert_eq!(right, Viewport::new((1, 2), Complex { re: 1.0, im: 1.0 }, view.bot_right));
+}
+
+#[test]
+fn test_viewport_resample_moves_pixels() {
+    let view = Viewport::new((4, 2), Complex { re: -2.0, im: 1.0 }, Complex { re: 2.0, im: -1.0 });
+    let pixels = [1, 2, 3, 4, 5, 6, 7, 8];
+    assert_eq!(view.resample(&view, &pixels), pixels);
+
+    // one pixel to the right, exposing black on the right edge
+    let panned = view.pan(0.25, 0.0);
+    assert_eq!(panned.resample(&view, &pixels), [2, 3, 4, 0, 6, 7, 8, 0]);
+
+    // twice as close about the centre, so each old pixel covers two
+    let zoomed = view.zoom(view.center(), 0.5);
+    assert_eq!(zoomed.resample(&view, &pixels), [2, 2, 3, 3, 6, 6, 7, 7]);
+}
+
+#[test]
+fn test_viewport_rotation() {
+    use std::f64::consts::FRAC_PI_2;
+
+    let close = |a: Complex<f64>, b: Complex<f64>| (a - b).norm() < 1e-12;
+    let level = Viewport::new((4, 2), Complex { re: -2.0, im: 1.0 }, Complex { re: 2.0, im: -1.0 });
+    let turned = level.rotate(FRAC_PI_2);
+    assert_eq!(turned.center(), level.center());
+
+    // a quarter turn anticlockwise: the image's top left is the plane's
+    // bottom left, and going right on the image goes up the plane
+    assert!(close(turned.point((0, 0)), Complex { re: -1.0, im: -2.0 }));
+    assert!(close(turned.point((4, 0)), Complex { re: -1.0, im: 2.0 }));
+    assert!(close(turned.point((3, 1)), turned.affine().point((3, 1))));
+    assert_eq!(turned.pixel(Complex { re: -0.5, im: 1.5 }), Some((3, 0)));
+    assert_eq!(turned.pixel(Complex { re: 1.5, im: 0.0 }), None);
+
+    assert!(close(turned.pan(0.25, 0.0).center(), Complex { re: 0.0, im: 1.0 }));
+    assert!(close(turned.pan_pixels(0.0, 1.0).center(), Complex { re: 1.0, im: 0.0 }));
+    let fixed = turned.point((1, 1));
+    assert!(close(turned.zoom(fixed, 0.5).point((1, 1)), fixed));
+
+    // selecting the right half of the image, which is the top of the plane
+    let top = turned.select(turned.point((2, 0)), turned.point((4, 2)));
+    assert_eq!(top.rotation, FRAC_PI_2);
+    assert!(close(top.center(), Complex { re: 0.0, im: 1.0 }));
+    let right = turned.sub((2, 0, 2, 2));
+    assert!(close(right.point((0, 0)), turned.point((2, 0))));
+    assert!(close(right.point((2, 2)), turned.point((4, 2))));
+    assert_eq!(turned.resample(&turned, &[1, 2, 3, 4, 5, 6, 7, 8]), [1, 2, 3, 4, 5, 6, 7, 8]);
+}
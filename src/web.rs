@@ -0,0 +1,62 @@
+use num::Complex;
+use precision::Precision;
+use std::{ptr, slice};
+use {auto_iterations, render_rect};
+
+/// alloc(len) : a zeroed buffer of `len` bytes in the module's memory, for
+/// the page to pass to `render_rows`
+#[no_mangle]
+pub extern "C" fn alloc(len: usize) -> *mut u8 {
+    Box::into_raw(vec![0u8; len].into_boxed_slice()) as *mut u8
+}
+
+/// Give back a buffer from `alloc`
+///
+/// # Safety
+///
+/// `ptr` and `len` must be a buffer from `alloc` that isn't used again.
+#[no_mangle]
+pub unsafe extern "C" fn dealloc(ptr: *mut u8, len: usize) {
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+}
+
+/// iterations(tl, br) : `auto_iterations` for the view, corners split
+/// into their parts
+#[no_mangle]
+pub extern "C" fn iterations(top_left_re: f64, top_left_im: f64,
+                             bot_right_re: f64, bot_right_im: f64)
+    -> u32
+{
+    auto_iterations(Complex { re: top_left_re, im: top_left_im },
+                    Complex { re: bot_right_re, im: bot_right_im })
+}
+
+/// Render rows `first_row .. first_row + rows` of the `width` by `height`
+/// image into `pixels`, one gray byte per pixel
+///
+/// Each call is one band, so a page can share an image between web
+/// workers each holding an instance of the module. Bands too deep for
+/// f64 are escalated like any other tile.
+///
+/// # Safety
+///
+/// `pixels` must be a buffer from `alloc` of at least `width * rows` bytes.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn render_rows(pixels: *mut u8,
+                                     width: usize,
+                                     height: usize,
+                                     first_row: usize,
+                                     rows: usize,
+                                     top_left_re: f64,
+                                     top_left_im: f64,
+                                     bot_right_re: f64,
+                                     bot_right_im: f64,
+                                     limit: u32)
+{
+    let pixels = slice::from_raw_parts_mut(pixels, width * rows);
+    render_rect(pixels, (width, height), (0, first_row, width, rows),
+                Complex { re: top_left_re, im: top_left_im },
+                Complex { re: bot_right_re, im: bot_right_im },
+                None, Precision::F64, limit);
+}
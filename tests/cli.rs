@@ -0,0 +1,39 @@
+//! Runs of the `mandelbrot` program itself, for what only its command line
+//! decides.
+#![cfg(feature = "native")]
+
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+
+/// run(a) : the output of the program run with the arguments `args`
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_tutorial-mandelbrot"))
+        .args(args)
+        .output()
+        .expect("the program should run")
+}
+
+#[test]
+fn test_threads() {
+    let dir = env::temp_dir();
+    let image = |threads: &str| {
+        let file = dir.join(format!("mandelbrot-cli-threads-{}-{}.png", threads,
+                                    std::process::id()));
+        let file = file.to_str().unwrap().to_string();
+        let output = run(&["--threads", threads, &file, "64x48", "-2.2,1.2", "1,-1.2"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let pixels = fs::read(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        pixels
+    };
+    assert_eq!(image("1"), image("5"));
+
+    for threads in &["0", "many"] {
+        let file = dir.join(format!("mandelbrot-cli-threads-bad-{}.png", std::process::id()));
+        let output = run(&["--threads", threads, file.to_str().unwrap(),
+                           "64x48", "-2.2,1.2", "1,-1.2"]);
+        assert!(!output.status.success());
+        assert!(!file.exists());
+    }
+}
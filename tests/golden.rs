@@ -0,0 +1,129 @@
+//! Renders of a few small views, checked against reference images kept in
+//! `tests/golden`, so that a change to how the set is rendered can't
+//! change what's rendered without it being noticed.
+//!
+//! After a change that's meant to alter the images, look at them and then
+//! write new references with `MANDELBROT_BLESS=1 cargo test --test golden`.
+#![cfg(feature = "native")]
+
+extern crate mandelbrot;
+
+use mandelbrot::output::{self, ImageWriter};
+use mandelbrot::precision::Precision;
+use mandelbrot::{progressive, render_bands, Renderer};
+use std::env;
+use std::path::PathBuf;
+
+/// Most a pixel may be off its reference, in gray levels
+const TOLERANCE: u8 = 2;
+
+/// Pixels in a thousand that may be off by more, for points so close to
+/// the boundary that the last bit of a float can flip whether they escape
+const FLIPPED_PER_MILLE: usize = 2;
+
+/// The canonical views: a name, the corners, the iterations and anything
+/// else set on the renderer
+fn views() -> Vec<(&'static str, Renderer)> {
+    let view = |top_left: &str, bot_right: &str, limit: u32| {
+        Renderer::new().bounds(96, 72).corners(top_left, bot_right).iterations(limit).threads(3)
+    };
+    vec![
+        ("whole", view("-2.2,1.2", "1,-1.2", 100)),
+        ("seahorse", view("-0.76,0.14", "-0.72,0.11", 500)),
+        ("whole-f32", view("-2.2,1.2", "1,-1.2", 100).precision(Precision::F32)),
+        ("deep", view("-0.7436438870371587,0.1318259042053120",
+                      "-0.7436438870370587,0.1318259042052370", 5000)
+            .precision(Precision::Fixed(128))),
+        ("turned", view("-1.5,0.6", "0.1,-0.6", 200).rotation(0.5)),
+        ("antialiased", view("-1.5,0.6", "0.1,-0.6", 200).antialias(3)),
+        ("jittered", view("-1.5,0.6", "0.1,-0.6", 200).jitter(4).seed(1)),
+    ]
+}
+
+/// golden(n) : where the reference image for the view `name` is kept
+fn golden(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+        .join(format!("{}.png", name))
+}
+
+/// compare(n, p, b) : what's wrong with `pixels`, of the `bounds` image, as
+/// a render of the view `name`, if anything
+///
+/// A render that fails is written beside the system's temporary files to
+/// be looked at.
+fn compare(name: &str, pixels: &[u8], bounds: (usize, usize)) -> Result<(), String> {
+    let reference = output::read_image(golden(name).to_str().unwrap())
+        .map_err(|e| format!("{}: no reference image: {}", name, e))?;
+    if reference.bounds != bounds {
+        return Err(format!("{}: rendered {:?}, the reference is {:?}",
+                           name, bounds, reference.bounds));
+    }
+    let off = pixels.iter().zip(&reference.pixels)
+        .filter(|&(a, b)| a.abs_diff(*b) > TOLERANCE)
+        .count();
+    if off * 1000 <= pixels.len() * FLIPPED_PER_MILLE {
+        return Ok(());
+    }
+    let actual = env::temp_dir().join(format!("mandelbrot-golden-{}.png", name));
+    let mut writer = ImageWriter::create(actual.to_str().unwrap(), bounds, None)
+        .map_err(|e| e.to_string())?;
+    writer.write_rows(pixels).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())?;
+    Err(format!("{}: {} of {} pixels differ from the reference, see {}",
+                name, off, pixels.len(), actual.display()))
+}
+
+#[test]
+fn test_golden_images() {
+    if env::var_os("MANDELBROT_BLESS").is_some() {
+        for (name, renderer) in views() {
+            renderer.write(golden(name).to_str().unwrap()).unwrap();
+        }
+        return;
+    }
+
+    let mut failures = Vec::new();
+    for (name, renderer) in views() {
+        let image = renderer.render().unwrap();
+        failures.extend(compare(name, image.pixels(), image.bounds()).err());
+
+        // rendering in tiles, a thread at a time, agrees with it too
+        let mut tiled = vec![0; image.pixels().len()];
+        for (rect, tile) in renderer.clone().threads(1).tiles().unwrap() {
+            for (row, pixels) in tile.chunks(rect.2).enumerate() {
+                let start = (rect.1 + row) * image.bounds().0 + rect.0;
+                tiled[start .. start + rect.2].copy_from_slice(pixels);
+            }
+        }
+        failures.extend(compare(name, &tiled, image.bounds()).err().map(|e| e + ", in tiles"));
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_golden_images_by_other_renderers() {
+    // the free functions the command line renders with, for the views
+    // they cover, once there are references to compare with
+    if env::var_os("MANDELBROT_BLESS").is_some() {
+        return;
+    }
+    let corner = |s: &str| mandelbrot::parse_complex(s).unwrap();
+    let mut failures = Vec::new();
+    for &(name, top_left, bot_right, precision, limit) in
+        &[("whole", "-2.2,1.2", "1,-1.2", Precision::F64, 100),
+          ("seahorse", "-0.76,0.14", "-0.72,0.11", Precision::F64, 500),
+          ("whole-f32", "-2.2,1.2", "1,-1.2", Precision::F32, 100)]
+    {
+        let (top_left, bot_right) = (corner(top_left), corner(bot_right));
+        let bounds = (96, 72);
+        let mut pixels = vec![0; bounds.0 * bounds.1];
+        render_bands(&mut pixels, bounds, 0, top_left, bot_right, None, precision, limit, 4);
+        failures.extend(compare(name, &pixels, bounds).err().map(|e| e + ", in bands"));
+
+        let mut passes = vec![0; bounds.0 * bounds.1];
+        progressive::render(&mut passes, bounds, top_left, bot_right, None, precision, limit, 4,
+                            |_, _| true);
+        failures.extend(compare(name, &passes, bounds).err().map(|e| e + ", progressively"));
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
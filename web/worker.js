@@ -0,0 +1,22 @@
+// Renders bands of the image, each worker with an instance of the module
+// of its own.
+
+const exports = WebAssembly.instantiateStreaming(fetch('mandelbrot.wasm'))
+    .then(result => result.instance.exports);
+
+onmessage = async event => {
+    const wasm = await exports;
+    const { generation, width, height, firstRow, rows, topLeft, botRight } = event.data;
+    const limit = event.data.limit ||
+        wasm.iterations(topLeft.re, topLeft.im, botRight.re, botRight.im);
+
+    const len = width * rows;
+    const ptr = wasm.alloc(len);
+    wasm.render_rows(ptr, width, height, firstRow, rows,
+                     topLeft.re, topLeft.im, botRight.re, botRight.im, limit);
+    // the memory may have grown during the render, so look at it afresh
+    const pixels = new Uint8Array(wasm.memory.buffer, ptr, len).slice();
+    wasm.dealloc(ptr, len);
+
+    postMessage({ generation, firstRow, rows, limit, pixels }, [pixels.buffer]);
+};